@@ -0,0 +1,281 @@
+//! Possible hijack alerts raised on unicast updates
+use crate::bgpsvc::BgpSessionId;
+use crate::rpki::mask_addr;
+use crate::timestamp::Timestamp;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashSet, VecDeque};
+use std::io::{BufReader, BufWriter};
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use zettabgp::prelude::*;
+
+/// Alerts count to keep in log
+const ALERTS_MAX: usize = 10000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AlertKind {
+    /// origin AS differs from origins of the prefix from other peers
+    Moas,
+    /// new more specific prefix with origin different from covering prefix
+    SubPrefix,
+    /// peer changed origin AS of the prefix
+    OriginChange,
+    /// owned prefix or its more specific with foreign origin
+    Owned,
+}
+impl std::str::FromStr for AlertKind {
+    type Err = BgpError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "moas" => Ok(AlertKind::Moas),
+            "subprefix" => Ok(AlertKind::SubPrefix),
+            "originchange" => Ok(AlertKind::OriginChange),
+            "owned" => Ok(AlertKind::Owned),
+            _ => Err(BgpError::static_str("Invalid alert kind")),
+        }
+    }
+}
+impl std::fmt::Display for AlertKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            AlertKind::Moas => f.write_str("moas"),
+            AlertKind::SubPrefix => f.write_str("subprefix"),
+            AlertKind::OriginChange => f.write_str("originchange"),
+            AlertKind::Owned => f.write_str("owned"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Alert {
+    pub id: u64,
+    pub when: Timestamp,
+    pub kind: AlertKind,
+    pub session: BgpSessionId,
+    pub prefix: IpAddr,
+    pub prefixlen: u8,
+    pub origin: u32,
+    /// origins seen before, from other peers or allowed for owned prefix
+    #[serde(default)]
+    pub expected: Vec<u32>,
+    /// covering prefix of more specific
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub covering: Option<String>,
+    #[serde(default)]
+    pub acked: bool,
+}
+impl Alert {
+    pub fn new(
+        when: Timestamp,
+        kind: AlertKind,
+        session: BgpSessionId,
+        prefix: IpAddr,
+        prefixlen: u8,
+        origin: u32,
+    ) -> Alert {
+        Alert {
+            id: 0,
+            when,
+            kind,
+            session,
+            prefix,
+            prefixlen,
+            origin,
+            expected: Vec::new(),
+            covering: None,
+            acked: false,
+        }
+    }
+    fn key(&self) -> (AlertKind, IpAddr, u8, u32) {
+        (self.kind, self.prefix, self.prefixlen, self.origin)
+    }
+}
+
+/// Prefix declared as owned with allowed origins
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OwnedPrefix {
+    pub prefix: IpAddr,
+    pub prefixlen: u8,
+    pub origins: Vec<u32>,
+}
+impl OwnedPrefix {
+    pub fn covers(&self, addr: IpAddr, len: u8) -> bool {
+        addr.is_ipv4() == self.prefix.is_ipv4()
+            && len >= self.prefixlen
+            && mask_addr(addr, self.prefixlen) == self.prefix
+    }
+}
+impl std::str::FromStr for OwnedPrefix {
+    type Err = BgpError;
+    /// prefix followed by allowed origin ASes, like "192.0.2.0/24 65000 AS65001"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split_whitespace();
+        let (a, l) = match parts.next().and_then(|p| p.split_once('/')) {
+            Some(al) => al,
+            None => return Err(BgpError::static_str("Invalid owned prefix")),
+        };
+        let addr: IpAddr = match a.parse() {
+            Ok(a) => a,
+            Err(_) => return Err(BgpError::static_str("Invalid owned prefix address")),
+        };
+        let prefixlen: u8 = match l.parse() {
+            Ok(l) if l <= (if addr.is_ipv4() { 32 } else { 128 }) => l,
+            _ => return Err(BgpError::static_str("Invalid owned prefix length")),
+        };
+        let mut origins = Vec::new();
+        for o in parts {
+            match o.to_uppercase().trim_start_matches("AS").parse() {
+                Ok(n) => origins.push(n),
+                Err(_) => return Err(BgpError::static_str("Invalid owned prefix origin")),
+            }
+        }
+        Ok(OwnedPrefix {
+            prefix: mask_addr(addr, prefixlen),
+            prefixlen,
+            origins,
+        })
+    }
+}
+
+/// Bounded alert log, stored to file when changed
+#[derive(Debug)]
+pub struct AlertLog {
+    pub owned: Vec<OwnedPrefix>,
+    file: Option<String>,
+    items: VecDeque<Alert>,
+    /// unacknowledged alerts to skip repeated ones
+    open: HashSet<(AlertKind, IpAddr, u8, u32)>,
+    next_id: u64,
+    dirty: AtomicBool,
+}
+impl AlertLog {
+    pub fn new(owned: Vec<OwnedPrefix>, file: Option<String>) -> AlertLog {
+        let mut log = AlertLog {
+            owned,
+            file,
+            items: VecDeque::new(),
+            open: HashSet::new(),
+            next_id: 1,
+            dirty: AtomicBool::new(false),
+        };
+        if let Err(e) = log.load() {
+            warn!("Unable to load alerts: {}", e);
+        }
+        log
+    }
+    fn load(&mut self) -> std::io::Result<()> {
+        let fname = match self.file.as_ref() {
+            None => return Ok(()),
+            Some(f) => f,
+        };
+        if !std::path::Path::new(fname).exists() {
+            return Ok(());
+        }
+        let file = BufReader::new(std::fs::File::open(fname)?);
+        let items: VecDeque<Alert> = serde_json::from_reader(file)?;
+        for a in items.iter() {
+            if !a.acked {
+                self.open.insert(a.key());
+            }
+        }
+        self.next_id = items.iter().map(|a| a.id + 1).max().unwrap_or(1);
+        self.items = items;
+        Ok(())
+    }
+    /// Writes log to file if it was changed since last store
+    pub fn store(&self) -> std::io::Result<()> {
+        let fname = match self.file.as_ref() {
+            None => return Ok(()),
+            Some(f) => f,
+        };
+        if !self.dirty.swap(false, Ordering::Relaxed) {
+            return Ok(());
+        }
+        let ftmp = fname.clone() + ".tmp";
+        {
+            let file = BufWriter::new(std::fs::File::create(&ftmp)?);
+            serde_json::to_writer(file, &self.items)?;
+        }
+        std::fs::rename(&ftmp, fname)
+    }
+    /// Allowed origins of most specific owned prefix covering given one
+    pub fn owned_origins(&self, addr: IpAddr, len: u8) -> Option<&[u32]> {
+        self.owned
+            .iter()
+            .filter(|o| o.covers(addr, len))
+            .max_by_key(|o| o.prefixlen)
+            .map(|o| o.origins.as_slice())
+    }
+    /// Adds alert unless same one is still unacknowledged, returns added alert
+    pub fn raise(&mut self, mut alert: Alert) -> Option<Alert> {
+        if !self.open.insert(alert.key()) {
+            return None;
+        }
+        alert.id = self.next_id;
+        self.next_id += 1;
+        warn!(
+            "Alert {} {}/{} origin {} session {}",
+            alert.kind, alert.prefix, alert.prefixlen, alert.origin, alert.session
+        );
+        while self.items.len() >= ALERTS_MAX {
+            if let Some(a) = self.items.pop_front() {
+                if !a.acked {
+                    self.open.remove(&a.key());
+                }
+            }
+        }
+        self.items.push_back(alert.clone());
+        self.dirty.store(true, Ordering::Relaxed);
+        Some(alert)
+    }
+    /// Acknowledges alert by id, 0 acknowledges all
+    pub fn ack(&mut self, id: u64) -> usize {
+        let mut cnt = 0;
+        for a in self.items.iter_mut() {
+            if (id == 0 || a.id == id) && !a.acked {
+                a.acked = true;
+                self.open.remove(&(a.kind, a.prefix, a.prefixlen, a.origin));
+                cnt += 1;
+            }
+        }
+        if cnt > 0 {
+            self.dirty.store(true, Ordering::Relaxed);
+        }
+        cnt
+    }
+    /// Newest alerts first
+    pub fn list(&self, acked: Option<bool>, kind: Option<AlertKind>, limit: usize) -> Vec<&Alert> {
+        self.items
+            .iter()
+            .rev()
+            .filter(|a| acked.map(|v| a.acked == v).unwrap_or(true))
+            .filter(|a| kind.map(|k| a.kind == k).unwrap_or(true))
+            .take(limit)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_alert_log() {
+        let owned: OwnedPrefix = "192.0.2.1/24 AS65000 65001".parse().unwrap();
+        assert_eq!(owned.prefix, "192.0.2.0".parse::<IpAddr>().unwrap());
+        assert_eq!(owned.origins, vec![65000, 65001]);
+        let mut log = AlertLog::new(vec![owned], None);
+        let sub: IpAddr = "192.0.2.128".parse().unwrap();
+        assert_eq!(log.owned_origins(sub, 25), Some(&[65000, 65001][..]));
+        assert_eq!(log.owned_origins(sub, 23), None);
+        let alert = Alert::new(Timestamp::now(), AlertKind::Owned, 1, sub, 25, 65002);
+        assert_eq!(log.raise(alert.clone()).map(|a| a.id), Some(1));
+        assert!(log.raise(alert.clone()).is_none());
+        assert_eq!(log.list(Some(false), None, 10).len(), 1);
+        assert_eq!(log.ack(1), 1);
+        assert_eq!(log.list(Some(false), None, 10).len(), 0);
+        assert_eq!(log.raise(alert).map(|a| a.id), Some(2));
+        assert_eq!(log.list(None, Some(AlertKind::Owned), 10).len(), 2);
+    }
+}
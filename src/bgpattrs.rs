@@ -1,5 +1,9 @@
 use crate::bgprib::BgpRIB;
+use crate::bogon::BogonFlags;
+use crate::irr::IrrState;
+use crate::rpki::{AspaState, RpkiState};
 use serde::ser::SerializeStruct;
+use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use std::sync::Mutex;
 use zettabgp::prelude::*;
@@ -17,6 +21,161 @@ pub fn rib_get<'a>() -> std::sync::MutexGuard<'a, Option<BgpRIB>> {
     RIB.lock().unwrap()
 }
 
+/// Path attribute without dedicated decoder, stored as received
+#[derive(Hash, PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Serialize, Deserialize)]
+pub struct BgpRawAttr {
+    pub flags: u8,
+    pub typecode: u8,
+    pub value: Vec<u8>,
+}
+impl BgpRawAttr {
+    /// Extended length flag is an encoding detail, so it is cleared
+    pub fn from_unknown(a: &BgpAttrUnknown) -> BgpRawAttr {
+        BgpRawAttr {
+            flags: a.params.flags & !0x10,
+            typecode: a.params.typecode,
+            value: a.value.clone(),
+        }
+    }
+    /// Encodes back ATTR_SET (RFC 6368) and CONNECTOR path attributes, which are
+    /// decoded but not stored in dedicated fields. ATTR_SET carries 4-octet
+    /// AS numbers; nested ATTR_SET, PMSI tunnel and MP reach attributes are
+    /// not allowed inside and not encodable, so they are left out. Received
+    /// flags are not kept by decoder, both attributes are optional transitive.
+    pub fn from_attr(a: &BgpAttrItem) -> Option<BgpRawAttr> {
+        let params = BgpSessionParams::new(
+            0,
+            0,
+            BgpTransportMode::IPv4,
+            std::net::Ipv4Addr::UNSPECIFIED,
+            Vec::new(),
+        );
+        let mut buf = vec![0u8; 65536];
+        let (attr, value) = match a {
+            BgpAttrItem::Connector(n) => {
+                let len = n.encode_to(&params, &mut buf).ok()?;
+                (n.attr(), buf[0..len].to_vec())
+            }
+            BgpAttrItem::AttrSet(n) => {
+                let mut value = n.asn.to_be_bytes().to_vec();
+                for i in n.attrs.iter() {
+                    if matches!(
+                        i,
+                        BgpAttrItem::AttrSet(_)
+                            | BgpAttrItem::PMSITunnel(_)
+                            | BgpAttrItem::MPUpdates(_)
+                            | BgpAttrItem::MPWithdraws(_)
+                    ) {
+                        continue;
+                    }
+                    if let Ok(len) = i.encode_to(&params, &mut buf) {
+                        value.extend_from_slice(&buf[0..len]);
+                    }
+                }
+                (n.attr(), value)
+            }
+            _ => return None,
+        };
+        Some(BgpRawAttr {
+            flags: 0xc0,
+            typecode: attr.typecode,
+            value,
+        })
+    }
+}
+/// Unknown path attributes of the route, ordered by type code
+#[derive(Hash, PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct BgpRawAttrs {
+    pub items: Vec<BgpRawAttr>,
+}
+impl BgpRawAttrs {
+    pub fn contains(&self, typecode: u8) -> bool {
+        self.items.iter().any(|a| a.typecode == typecode)
+    }
+}
+
+/// Only-To-Customer path attribute type code, RFC 9234
+pub const BGP_ATTR_OTC: u8 = 35;
+/// BGP Role of the speaker, RFC 9234
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum BgpRole {
+    Provider,
+    RouteServer,
+    RouteServerClient,
+    Customer,
+    Peer,
+}
+impl BgpRole {
+    /// BGP Role capability value
+    pub fn code(&self) -> u8 {
+        match self {
+            BgpRole::Provider => 0,
+            BgpRole::RouteServer => 1,
+            BgpRole::RouteServerClient => 2,
+            BgpRole::Customer => 3,
+            BgpRole::Peer => 4,
+        }
+    }
+    pub fn from_code(code: u8) -> Option<BgpRole> {
+        match code {
+            0 => Some(BgpRole::Provider),
+            1 => Some(BgpRole::RouteServer),
+            2 => Some(BgpRole::RouteServerClient),
+            3 => Some(BgpRole::Customer),
+            4 => Some(BgpRole::Peer),
+            _ => None,
+        }
+    }
+    /// Role neighbor should advertise
+    pub fn expected_peer(&self) -> BgpRole {
+        match self {
+            BgpRole::Provider => BgpRole::Customer,
+            BgpRole::RouteServer => BgpRole::RouteServerClient,
+            BgpRole::RouteServerClient => BgpRole::RouteServer,
+            BgpRole::Customer => BgpRole::Provider,
+            BgpRole::Peer => BgpRole::Peer,
+        }
+    }
+    /// RFC 9234 ingress check for route received from neighbor with peer_as
+    pub fn is_leak(&self, otc: Option<u32>, peer_as: u32) -> bool {
+        match (self, otc) {
+            (_, None) => false,
+            (BgpRole::Provider, Some(_)) | (BgpRole::RouteServer, Some(_)) => true,
+            (BgpRole::Peer, Some(n)) => n != peer_as,
+            _ => false,
+        }
+    }
+    /// Routes from provider get downstream ASPA verification, others upstream
+    pub fn aspa_downstream(&self) -> bool {
+        *self == BgpRole::Customer
+    }
+}
+impl std::str::FromStr for BgpRole {
+    type Err = BgpError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "provider" => Ok(BgpRole::Provider),
+            "rs" => Ok(BgpRole::RouteServer),
+            "rs-client" => Ok(BgpRole::RouteServerClient),
+            "customer" => Ok(BgpRole::Customer),
+            "peer" => Ok(BgpRole::Peer),
+            _ => Err(BgpError::static_str("Invalid BGP role")),
+        }
+    }
+}
+impl std::fmt::Display for BgpRole {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            BgpRole::Provider => f.write_str("provider"),
+            BgpRole::RouteServer => f.write_str("rs"),
+            BgpRole::RouteServerClient => f.write_str("rs-client"),
+            BgpRole::Customer => f.write_str("customer"),
+            BgpRole::Peer => f.write_str("peer"),
+        }
+    }
+}
+
 #[derive(Hash, PartialEq, Eq, PartialOrd, Ord, Debug, Clone)]
 pub struct BgpAttrs {
     pub origin: BgpAttrOrigin,
@@ -32,6 +191,19 @@ pub struct BgpAttrs {
     pub originator: Option<std::net::IpAddr>,
     pub clusterlist: Option<Arc<BgpClusterList>>,
     pub pmsi_ta: Option<Arc<BgpPMSITunnel>>,
+    pub rawattrs: Option<Arc<BgpRawAttrs>>,
+    /// Only-To-Customer AS
+    pub otc: Option<u32>,
+    /// route violates RFC 9234 ingress rules of the receiving session
+    pub leak: bool,
+    /// route origin validation state, only for unicast when RPKI is configured
+    pub rpki: Option<RpkiState>,
+    /// AS path verification state, for sessions with configured role when ASPA is configured
+    pub aspa: Option<AspaState>,
+    /// IRR route object state, for unicast routes once whois lookup is done
+    pub irr: Option<IrrState>,
+    /// bogon prefix, AS path or next hop of unicast route when bogon check is configured
+    pub bogon: BogonFlags,
 }
 enum BgpAttrsField {
     Origin,
@@ -47,8 +219,15 @@ enum BgpAttrsField {
     Originator,
     Clusterlist,
     PmsiTa,
+    RawAttrs,
+    Otc,
+    Leak,
+    Rpki,
+    Aspa,
+    Irr,
+    Bogon,
 }
-const BA_VARS: [&str; 13] = [
+const BA_VARS: [&str; 20] = [
     "Origin",
     "Nexthop",
     "Aspath",
@@ -62,6 +241,13 @@ const BA_VARS: [&str; 13] = [
     "Originator",
     "Clusterlist",
     "PmsiTa",
+    "RawAttrs",
+    "OTC",
+    "Leak",
+    "RPKI",
+    "ASPA",
+    "IRR",
+    "Bogon",
 ];
 impl<'de> serde::de::Deserialize<'de> for BgpAttrsField {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
@@ -89,6 +275,13 @@ impl<'de> serde::de::Deserialize<'de> for BgpAttrsField {
                     "Originator" => Ok(BgpAttrsField::Originator),
                     "Clusterlist" => Ok(BgpAttrsField::Clusterlist),
                     "PmsiTa" => Ok(BgpAttrsField::PmsiTa),
+                    "RawAttrs" => Ok(BgpAttrsField::RawAttrs),
+                    "OTC" => Ok(BgpAttrsField::Otc),
+                    "Leak" => Ok(BgpAttrsField::Leak),
+                    "RPKI" => Ok(BgpAttrsField::Rpki),
+                    "ASPA" => Ok(BgpAttrsField::Aspa),
+                    "IRR" => Ok(BgpAttrsField::Irr),
+                    "Bogon" => Ok(BgpAttrsField::Bogon),
                     _ => Err(serde::de::Error::unknown_field(value, &BA_VARS)),
                 }
             }
@@ -145,6 +338,14 @@ impl<'de> serde::de::Visitor<'de> for BgpAttrsVisitor {
         let pmsi_ta: Option<BgpPMSITunnel> = seq
             .next_element()?
             .ok_or_else(|| serde::de::Error::invalid_length(12, &self))?;
+        // older snapshots have no raw attributes
+        let rawattrs: Option<BgpRawAttrs> = seq.next_element()?.unwrap_or(None);
+        let otc: Option<u32> = seq.next_element()?.unwrap_or(None);
+        let leak: bool = seq.next_element()?.unwrap_or(false);
+        let rpki: Option<RpkiState> = seq.next_element()?.unwrap_or(None);
+        let aspa: Option<AspaState> = seq.next_element()?.unwrap_or(None);
+        let irr: Option<IrrState> = seq.next_element()?.unwrap_or(None);
+        let bogon: BogonFlags = seq.next_element()?.unwrap_or_default();
         let mut mrib = rib_get();
         match (*mrib).as_mut() {
             None => Ok(BgpAttrs::new()),
@@ -162,6 +363,13 @@ impl<'de> serde::de::Visitor<'de> for BgpAttrsVisitor {
                 originator,
                 clusterlist: clusterlist.map(|x| rib.clusters.get(Arc::new(x)).unwrap()),
                 pmsi_ta: pmsi_ta.map(|x| rib.pmsi_ta_s.get(Arc::new(x)).unwrap()),
+                rawattrs: rawattrs.map(|x| rib.rawattrs.get(Arc::new(x)).unwrap()),
+                otc,
+                leak,
+                rpki,
+                aspa,
+                irr,
+                bogon,
             }),
         }
     }
@@ -183,6 +391,13 @@ impl<'de> serde::de::Visitor<'de> for BgpAttrsVisitor {
         let mut originator = None;
         let mut clusterlist: Option<Option<BgpClusterList>> = None;
         let mut pmsi_ta: Option<Option<BgpPMSITunnel>> = None;
+        let mut rawattrs: Option<Option<BgpRawAttrs>> = None;
+        let mut otc: Option<Option<u32>> = None;
+        let mut leak: Option<bool> = None;
+        let mut rpki: Option<Option<RpkiState>> = None;
+        let mut aspa: Option<Option<AspaState>> = None;
+        let mut irr: Option<Option<IrrState>> = None;
+        let mut bogon: Option<BogonFlags> = None;
         while let Some(key) = map.next_key()? {
             match key {
                 BgpAttrsField::Origin => {
@@ -263,6 +478,48 @@ impl<'de> serde::de::Visitor<'de> for BgpAttrsVisitor {
                     }
                     pmsi_ta = Some(map.next_value()?);
                 }
+                BgpAttrsField::RawAttrs => {
+                    if rawattrs.is_some() {
+                        return Err(serde::de::Error::duplicate_field(BA_VARS[13]));
+                    }
+                    rawattrs = Some(map.next_value()?);
+                }
+                BgpAttrsField::Otc => {
+                    if otc.is_some() {
+                        return Err(serde::de::Error::duplicate_field(BA_VARS[14]));
+                    }
+                    otc = Some(map.next_value()?);
+                }
+                BgpAttrsField::Leak => {
+                    if leak.is_some() {
+                        return Err(serde::de::Error::duplicate_field(BA_VARS[15]));
+                    }
+                    leak = Some(map.next_value()?);
+                }
+                BgpAttrsField::Rpki => {
+                    if rpki.is_some() {
+                        return Err(serde::de::Error::duplicate_field(BA_VARS[16]));
+                    }
+                    rpki = Some(map.next_value()?);
+                }
+                BgpAttrsField::Aspa => {
+                    if aspa.is_some() {
+                        return Err(serde::de::Error::duplicate_field(BA_VARS[17]));
+                    }
+                    aspa = Some(map.next_value()?);
+                }
+                BgpAttrsField::Irr => {
+                    if irr.is_some() {
+                        return Err(serde::de::Error::duplicate_field(BA_VARS[18]));
+                    }
+                    irr = Some(map.next_value()?);
+                }
+                BgpAttrsField::Bogon => {
+                    if bogon.is_some() {
+                        return Err(serde::de::Error::duplicate_field(BA_VARS[19]));
+                    }
+                    bogon = Some(map.next_value()?);
+                }
             }
         }
 
@@ -282,6 +539,13 @@ impl<'de> serde::de::Visitor<'de> for BgpAttrsVisitor {
         let clusterlist =
             clusterlist.ok_or_else(|| serde::de::Error::missing_field(BA_VARS[11]))?;
         let pmsi_ta = pmsi_ta.ok_or_else(|| serde::de::Error::missing_field(BA_VARS[12]))?;
+        let rawattrs = rawattrs.unwrap_or(None);
+        let otc = otc.unwrap_or(None);
+        let leak = leak.unwrap_or(false);
+        let rpki = rpki.unwrap_or(None);
+        let aspa = aspa.unwrap_or(None);
+        let irr = irr.unwrap_or(None);
+        let bogon = bogon.unwrap_or_default();
         let mut mrib = rib_get();
         match (*mrib).as_mut() {
             None => Ok(BgpAttrs::new()),
@@ -299,6 +563,13 @@ impl<'de> serde::de::Visitor<'de> for BgpAttrsVisitor {
                 originator,
                 clusterlist: clusterlist.map(|x| rib.clusters.get(Arc::new(x)).unwrap()),
                 pmsi_ta: pmsi_ta.map(|x| rib.pmsi_ta_s.get(Arc::new(x)).unwrap()),
+                rawattrs: rawattrs.map(|x| rib.rawattrs.get(Arc::new(x)).unwrap()),
+                otc,
+                leak,
+                rpki,
+                aspa,
+                irr,
+                bogon,
             }),
         }
     }
@@ -308,7 +579,7 @@ impl serde::Serialize for BgpAttrs {
     where
         S: serde::Serializer,
     {
-        let mut map = serializer.serialize_struct("BgpAttrs", 13)?;
+        let mut map = serializer.serialize_struct("BgpAttrs", 20)?;
         map.serialize_field(BA_VARS[0], &self.origin)?;
         map.serialize_field(BA_VARS[1], &self.nexthop)?;
         map.serialize_field(BA_VARS[2], self.aspath.as_ref())?;
@@ -322,6 +593,13 @@ impl serde::Serialize for BgpAttrs {
         map.serialize_field(BA_VARS[10], &self.originator)?;
         map.serialize_field(BA_VARS[11], &self.clusterlist.as_ref().map(|x| x.as_ref()))?;
         map.serialize_field(BA_VARS[12], &self.pmsi_ta.as_ref().map(|x| x.as_ref()))?;
+        map.serialize_field(BA_VARS[13], &self.rawattrs.as_ref().map(|x| x.as_ref()))?;
+        map.serialize_field(BA_VARS[14], &self.otc)?;
+        map.serialize_field(BA_VARS[15], &self.leak)?;
+        map.serialize_field(BA_VARS[16], &self.rpki)?;
+        map.serialize_field(BA_VARS[17], &self.aspa)?;
+        map.serialize_field(BA_VARS[18], &self.irr)?;
+        map.serialize_field(BA_VARS[19], &self.bogon)?;
         map.end()
     }
 }
@@ -354,6 +632,13 @@ impl BgpAttrs {
             originator: None,
             clusterlist: None,
             pmsi_ta: None,
+            rawattrs: None,
+            otc: None,
+            leak: false,
+            rpki: None,
+            aspa: None,
+            irr: None,
+            bogon: BogonFlags::default(),
         }
     }
 }
@@ -362,6 +647,8 @@ pub struct BgpAttrEntry {
     pub active: bool,
     pub attrs: Arc<BgpAttrs>,
     pub labels: Option<MplsLabels>,
+    /// active route retained after graceful restart of peer
+    pub stale: bool,
 }
 impl BgpAttrEntry {
     pub fn new(act: bool, atr: Arc<BgpAttrs>, lbl: Option<MplsLabels>) -> BgpAttrEntry {
@@ -369,6 +656,7 @@ impl BgpAttrEntry {
             active: act,
             attrs: atr,
             labels: lbl,
+            stale: false,
         }
     }
 }
@@ -377,10 +665,11 @@ impl serde::Serialize for BgpAttrEntry {
     where
         S: serde::Serializer,
     {
-        let mut map = serializer.serialize_struct("BgpAttrEntry", 3)?;
+        let mut map = serializer.serialize_struct("BgpAttrEntry", 4)?;
         map.serialize_field(BAE_VARS[0], &self.active)?;
         map.serialize_field(BAE_VARS[1], self.attrs.as_ref())?;
         map.serialize_field(BAE_VARS[2], &self.labels)?;
+        map.serialize_field(BAE_VARS[3], &self.stale)?;
         map.end()
     }
 }
@@ -388,8 +677,9 @@ enum BgpAttrEntryField {
     Active,
     Attrs,
     Labels,
+    Stale,
 }
-const BAE_VARS: [&str; 3] = ["Active", "Attrs", "Labels"];
+const BAE_VARS: [&str; 4] = ["Active", "Attrs", "Labels", "Stale"];
 impl<'de> serde::de::Deserialize<'de> for BgpAttrEntryField {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
@@ -406,6 +696,7 @@ impl<'de> serde::de::Deserialize<'de> for BgpAttrEntryField {
                     "Active" => Ok(BgpAttrEntryField::Active),
                     "Attrs" => Ok(BgpAttrEntryField::Attrs),
                     "Labels" => Ok(BgpAttrEntryField::Labels),
+                    "Stale" => Ok(BgpAttrEntryField::Stale),
                     _ => Err(serde::de::Error::unknown_field(value, &BAE_VARS)),
                 }
             }
@@ -432,13 +723,21 @@ impl<'de> serde::de::Visitor<'de> for BgpAttrEntryVisitor {
         let labels = seq
             .next_element()?
             .ok_or_else(|| serde::de::Error::invalid_length(2, &self))?;
+        // older snapshots have no stale flag
+        let stale = seq.next_element()?.unwrap_or(false);
         let mut mrib = rib_get();
         match (*mrib).as_mut() {
-            None => Ok(BgpAttrEntry::new(active, Arc::new(attrs), labels)),
+            None => Ok(BgpAttrEntry {
+                active,
+                attrs: Arc::new(attrs),
+                labels,
+                stale,
+            }),
             Some(rib) => Ok(BgpAttrEntry {
                 active,
                 attrs: rib.attrs.get(Arc::new(attrs)).unwrap(),
                 labels,
+                stale,
             }),
         }
     }
@@ -450,6 +749,7 @@ impl<'de> serde::de::Visitor<'de> for BgpAttrEntryVisitor {
         let mut active = None;
         let mut attrs = None;
         let mut labels = None;
+        let mut stale = None;
         while let Some(key) = map.next_key()? {
             match key {
                 BgpAttrEntryField::Active => {
@@ -470,19 +770,32 @@ impl<'de> serde::de::Visitor<'de> for BgpAttrEntryVisitor {
                     }
                     labels = Some(map.next_value()?);
                 }
+                BgpAttrEntryField::Stale => {
+                    if stale.is_some() {
+                        return Err(serde::de::Error::duplicate_field(BAE_VARS[3]));
+                    }
+                    stale = Some(map.next_value()?);
+                }
             }
         }
 
         let active = active.ok_or_else(|| serde::de::Error::missing_field(BA_VARS[0]))?;
         let attrs = attrs.ok_or_else(|| serde::de::Error::missing_field(BAE_VARS[1]))?;
         let labels = labels.ok_or_else(|| serde::de::Error::missing_field(BAE_VARS[2]))?;
+        let stale = stale.unwrap_or(false);
         let mut mrib = rib_get();
         match (*mrib).as_mut() {
-            None => Ok(BgpAttrEntry::new(active, Arc::new(attrs), labels)),
+            None => Ok(BgpAttrEntry {
+                active,
+                attrs: Arc::new(attrs),
+                labels,
+                stale,
+            }),
             Some(rib) => Ok(BgpAttrEntry {
                 active,
                 attrs: rib.attrs.get(Arc::new(attrs)).unwrap(),
                 labels,
+                stale,
             }),
         }
     }
@@ -1,4 +1,9 @@
+use crate::bgpattrs::BgpRole;
+use crate::bgprib::BgpRibKind;
 use crate::bgpsvc::*;
+use crate::flowspec::*;
+use crate::rtc::*;
+use crate::timestamp::Timestamp;
 use chrono::prelude::*;
 use std::net::{IpAddr, Ipv4Addr};
 use std::sync::Arc;
@@ -6,12 +11,79 @@ use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::*;
 use zettabgp::prelude::*;
 
+/// RFC 4271 maximum message size
+pub const BGP_MAX_MESSAGE: usize = 4096;
+/// RFC 8654 maximum extended message size
+pub const BGP_MAX_EXTENDED_MESSAGE: usize = 65535;
+/// RFC 8654 Extended Message capability code
+const CAP_EXTENDED_MESSAGE: u8 = 6;
+/// RFC 2918 ROUTE-REFRESH message type, unknown to zettabgp
+const BGP_MSG_ROUTE_REFRESH: u8 = 5;
+/// RFC 4724 Graceful Restart capability code
+const CAP_GRACEFUL_RESTART: u8 = 64;
+/// RFC 9234 BGP Role capability code
+const CAP_BGP_ROLE: u8 = 9;
+/// RFC 4760 Multiprotocol Extensions capability code
+const CAP_MULTIPROTOCOL: u8 = 1;
+/// Multiprotocol capability value for Route Target Constraint, unknown to zettabgp
+const CAP_MP_RTC: [u8; 4] = [0, RTC_AFI as u8, 0, RTC_SAFI];
+/// Multiprotocol capability value for VPNv6 FlowSpec, unknown to zettabgp
+const CAP_MP_FSV6U: [u8; 4] = [0, FSV6U_AFI as u8, 0, FSV6U_SAFI];
+/// RFC 4271 suggested large hold time while waiting for OPEN
+const OPEN_HOLD_TIME: std::time::Duration = std::time::Duration::from_secs(240);
+
+/// Received message kind
+#[derive(Debug, PartialEq)]
+enum BgpPeerMessage {
+    Bgp(BgpMessageType),
+    RouteRefresh,
+}
+
+/// Commands for established session
+#[derive(Debug)]
+pub enum BgpPeerCommand {
+    RouteRefresh(BgpRibKind),
+}
+
 pub struct BgpPeer<'a, H: BgpUpdateHandler> {
     pub params: BgpSessionParams,
     peersock: tokio::net::TcpStream,
     keepalive_sent: DateTime<Local>,
+    last_received: DateTime<Local>,
+    /// message header being received and its received size
+    head: [u8; 19],
+    headpos: usize,
     sessionid: BgpSessionId,
     update_handler: &'a H,
+    /// advertise extended messages capability
+    pub extended_message: bool,
+    max_message: usize,
+    /// expected neighbor AS in passive mode, 0 for any
+    pub remote_as: u32,
+    /// expected neighbor BGP identifier in passive mode
+    pub remote_router_id: Option<Ipv4Addr>,
+    /// capabilities to offer in passive mode instead of echoing neighbor ones
+    pub passive_caps: Option<Vec<BgpCapability>>,
+    /// advertise graceful restart capability as receiving speaker
+    pub graceful_restart: bool,
+    /// restart time and preserved RIBs advertised by neighbor
+    peer_restart: Option<(u16, Vec<BgpRibKind>)>,
+    /// RIBs with forwarding state preserved during neighbor restart
+    peer_forwarding: Option<Vec<BgpRibKind>>,
+    /// NOTIFICATION was sent or received, graceful restart is not applicable
+    notified: bool,
+    /// KEEPALIVE or UPDATE was received after OPEN, session left OpenConfirm state
+    established: bool,
+    /// local BGP Role to advertise and check neighbor one against
+    pub role: Option<BgpRole>,
+    /// neighbor rejected BGP Role capability
+    role_unsupported: bool,
+    /// advertise RFC 4684 Route Target Constraint address family
+    pub rtc: bool,
+    /// advertise VPNv6 FlowSpec address family
+    pub fsv6u: bool,
+    /// hold timer while waiting for neighbor OPEN
+    open_hold_time: std::time::Duration,
 }
 
 impl<'a, H: BgpUpdateHandler> BgpPeer<'a, H> {
@@ -25,8 +97,26 @@ impl<'a, H: BgpUpdateHandler> BgpPeer<'a, H> {
             params: pars,
             peersock: stream,
             keepalive_sent: Local::now(),
+            last_received: Local::now(),
+            head: [0u8; 19],
+            headpos: 0,
             update_handler: handler,
             sessionid: 0,
+            extended_message: false,
+            max_message: BGP_MAX_MESSAGE,
+            remote_as: 0,
+            remote_router_id: None,
+            passive_caps: None,
+            graceful_restart: false,
+            peer_restart: None,
+            peer_forwarding: None,
+            notified: false,
+            established: false,
+            role: None,
+            role_unsupported: false,
+            rtc: false,
+            fsv6u: false,
+            open_hold_time: OPEN_HOLD_TIME,
         };
         ret.params.peer_mode = if peerip.is_ipv4() {
             BgpTransportMode::IPv4
@@ -47,10 +137,183 @@ impl<'a, H: BgpUpdateHandler> BgpPeer<'a, H> {
             Err(e) => Err(e.into()),
         }
     }
-    async fn recv_message_head(&mut self) -> Result<(BgpMessageType, usize), BgpError> {
-        let mut buf = [0u8; 19];
-        self.read_socket(&mut buf).await?;
-        self.params.decode_message_head(&buf)
+    /// Reads message header. It is cancel safe, as partially read header is kept
+    /// until the next call, so it may be raced with timers and commands.
+    async fn recv_message_head(&mut self) -> Result<(BgpPeerMessage, usize), BgpError> {
+        while self.headpos < self.head.len() {
+            let n = self.peersock.read(&mut self.head[self.headpos..]).await?;
+            if n == 0 {
+                return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+            }
+            self.headpos += n;
+        }
+        self.headpos = 0;
+        let buf = self.head;
+        let msglen = getn_u16(&buf[16..18]) as usize;
+        if msglen < 19 || msglen > self.max_message {
+            // bad message length, data is the erroneous length field
+            self.notify(1, 2, &buf[16..18]).await;
+            return Err(BgpError::from_string(format!(
+                "Bad message length {}, maximum {}",
+                msglen, self.max_message
+            )));
+        }
+        if buf[18] == BGP_MSG_ROUTE_REFRESH {
+            if buf[0..16].iter().any(|q| *q != 255) {
+                return Err(BgpError::static_str("Invalid header content"));
+            }
+            return Ok((BgpPeerMessage::RouteRefresh, msglen - 19));
+        }
+        let (msgtype, len) = self.params.decode_message_head(&buf)?;
+        Ok((BgpPeerMessage::Bgp(msgtype), len))
+    }
+    /// Checks OPEN message body for capability, which is unknown to zettabgp
+    fn open_has_capability(buf: &[u8], code: u8) -> bool {
+        BgpPeer::<H>::open_get_capability(buf, code).is_some()
+    }
+    /// Finds capability value in OPEN message body
+    fn open_get_capability(buf: &[u8], code: u8) -> Option<&[u8]> {
+        BgpPeer::<H>::open_capabilities(buf)
+            .into_iter()
+            .find(|c| c.0 == code)
+            .map(|c| c.1)
+    }
+    /// All capability codes and values in OPEN message body
+    fn open_capabilities(buf: &[u8]) -> Vec<(u8, &[u8])> {
+        let mut ret = Vec::new();
+        if buf.len() < 10 {
+            return ret;
+        }
+        let optend = std::cmp::min(buf.len(), 10 + buf[9] as usize);
+        let mut pos = 10;
+        while pos + 2 <= optend {
+            let plen = buf[pos + 1] as usize;
+            if buf[pos] == 2 {
+                let capend = std::cmp::min(optend, pos + 2 + plen);
+                let mut cpos = pos + 2;
+                while cpos + 2 <= capend {
+                    let vend = std::cmp::min(capend, cpos + 2 + buf[cpos + 1] as usize);
+                    ret.push((buf[cpos], &buf[cpos + 2..vend]));
+                    cpos += 2 + buf[cpos + 1] as usize;
+                }
+            }
+            pos += 2 + plen;
+        }
+        ret
+    }
+    /// Checks OPEN message body for multiprotocol address family unknown to zettabgp
+    fn open_has_mp(buf: &[u8], afisafi: &[u8; 4]) -> bool {
+        BgpPeer::<H>::open_capabilities(buf)
+            .iter()
+            .any(|c| c.0 == CAP_MULTIPROTOCOL && c.1 == afisafi)
+    }
+    /// RTC and VPNv6 FlowSpec are used only when configured locally and advertised by neighbor
+    fn mp_negotiated(configured: bool, buf: &[u8], afisafi: &[u8; 4]) -> bool {
+        configured && BgpPeer::<H>::open_has_mp(buf, afisafi)
+    }
+    /// Appends RTC and VPNv6 FlowSpec multiprotocol capabilities, returns new body size
+    fn open_add_mp(&self, buf: &mut [u8], mut sz: usize) -> Result<usize, BgpError> {
+        if self.rtc {
+            sz = BgpPeer::<H>::open_add_capability(buf, sz, CAP_MULTIPROTOCOL, &CAP_MP_RTC)?;
+        }
+        if self.fsv6u {
+            sz = BgpPeer::<H>::open_add_capability(buf, sz, CAP_MULTIPROTOCOL, &CAP_MP_FSV6U)?;
+        }
+        Ok(sz)
+    }
+    /// Asks neighbor for VPN routes of any route target with default RT membership
+    async fn send_rtc_default(&mut self, ebgp: bool) -> Result<(), BgpError> {
+        let nexthop = self.peersock.local_addr()?.ip();
+        let body = encode_rtc_default(self.params.as_num, self.params.has_as32bit, ebgp, nexthop);
+        let mut buf = vec![255u8; 19 + body.len()];
+        buf[19..].copy_from_slice(&body);
+        self.send_message_buf(&mut buf, BgpMessageType::Update, body.len())
+            .await
+    }
+    /// Appends capability to encoded OPEN message body, returns new body size
+    fn open_add_capability(
+        buf: &mut [u8],
+        sz: usize,
+        code: u8,
+        value: &[u8],
+    ) -> Result<usize, BgpError> {
+        let len = 4 + value.len();
+        if sz < 10 || sz + len > buf.len() || buf[9] as usize + len > 255 {
+            return Err(BgpError::insufficient_buffer_size());
+        }
+        buf[sz..sz + 4].copy_from_slice(&[2, 2 + value.len() as u8, code, value.len() as u8]);
+        buf[sz + 4..sz + len].copy_from_slice(value);
+        buf[9] += len as u8;
+        Ok(sz + len)
+    }
+    /// Appends BGP Role capability if configured, returns new body size
+    fn open_add_role(&self, buf: &mut [u8], sz: usize) -> Result<usize, BgpError> {
+        match self.role {
+            Some(role) if !self.role_unsupported => {
+                BgpPeer::<H>::open_add_capability(buf, sz, CAP_BGP_ROLE, &[role.code()])
+            }
+            _ => Ok(sz),
+        }
+    }
+    /// RFC 9234 Role Mismatch check of neighbor OPEN message body
+    async fn check_role(&mut self, buf: &[u8]) -> Result<(), BgpError> {
+        let role = match self.role {
+            None => return Ok(()),
+            Some(r) => r,
+        };
+        let peer_role = match BgpPeer::<H>::open_get_capability(buf, CAP_BGP_ROLE) {
+            None => return Ok(()),
+            Some(v) if v.len() == 1 => BgpRole::from_code(v[0]),
+            Some(_) => None,
+        };
+        if peer_role == Some(role.expected_peer()) {
+            return Ok(());
+        }
+        self.notify(2, 11, &[]).await;
+        Err(BgpError::from_string(format!(
+            "Role mismatch, local {}, neighbor {}",
+            role,
+            peer_role
+                .map(|r| r.to_string())
+                .unwrap_or("invalid".to_string())
+        )))
+    }
+    /// Graceful restart capability of receiving speaker, which preserves no forwarding state
+    fn gr_helper_capability() -> BgpCapability {
+        BgpCapability::CapGR {
+            restart_state: false,
+            restart_time: 0,
+            afis: Vec::new(),
+        }
+    }
+    /// Records neighbor graceful restart capability and clears its restart-specific flags,
+    /// so the restarted session gets the same session id
+    fn take_peer_restart(&mut self, bom: &mut BgpOpenMessage) {
+        self.peer_restart = None;
+        self.peer_forwarding = None;
+        for cap in bom.caps.iter_mut() {
+            if let BgpCapability::CapGR {
+                restart_state,
+                restart_time,
+                afis,
+            } = cap
+            {
+                self.peer_restart = Some((
+                    *restart_time,
+                    afis.iter()
+                        .filter_map(|a| BgpRibKind::from_afi_safi(a.afi, a.safi))
+                        .collect(),
+                ));
+                self.peer_forwarding = Some(
+                    afis.iter()
+                        .filter(|a| a.forwarding_state)
+                        .filter_map(|a| BgpRibKind::from_afi_safi(a.afi, a.safi))
+                        .collect(),
+                );
+                *restart_state = false;
+                afis.iter_mut().for_each(|a| a.forwarding_state = false);
+            }
+        }
     }
     fn get_message_body_ref(buf: &mut [u8]) -> Result<&mut [u8], BgpError> {
         if buf.len() < 19 {
@@ -69,79 +332,229 @@ impl<'a, H: BgpUpdateHandler> BgpPeer<'a, H> {
             .prepare_message_buf(buf, messagetype, messagelen)?;
         self.write_socket(&buf[0..blen]).await
     }
+    /// Receives message head while waiting for neighbor OPEN, hold timer expiry is notified
+    async fn recv_open_head(&mut self) -> Result<(BgpPeerMessage, usize), BgpError> {
+        match tokio::time::timeout(self.open_hold_time, self.recv_message_head()).await {
+            Ok(r) => r,
+            Err(_) => {
+                self.notify(4, 0, &[]).await;
+                Err(BgpError::static_str("Hold timer expired waiting for OPEN"))
+            }
+        }
+    }
     pub async fn start_passive(&mut self) -> Result<(), BgpError> {
         let mut bom = BgpOpenMessage::new();
-        let mut buf = [255u8; 255];
-        let msg = match self.recv_message_head().await {
+        let mut buf = [255u8; BGP_MAX_MESSAGE];
+        let msg = match self.recv_open_head().await {
             Err(e) => return Err(e),
             Ok(msg) => msg,
         };
-        if msg.0 != BgpMessageType::Open {
+        if msg.0 != BgpPeerMessage::Bgp(BgpMessageType::Open) {
             return Err(BgpError::static_str("Invalid state to start_passive"));
         }
         self.read_socket(&mut buf[0..msg.1]).await?;
         bom.decode_from(&self.params, &buf[0..msg.1])?;
+        let extmsg = self.extended_message
+            && BgpPeer::<H>::open_has_capability(&buf[0..msg.1], CAP_EXTENDED_MESSAGE);
+        self.rtc = BgpPeer::<H>::mp_negotiated(self.rtc, &buf[0..msg.1], &CAP_MP_RTC);
+        self.fsv6u = BgpPeer::<H>::mp_negotiated(self.fsv6u, &buf[0..msg.1], &CAP_MP_FSV6U);
+        self.check_role(&buf[0..msg.1]).await?;
+        self.take_peer_restart(&mut bom);
         let remsess = BgpPeerDesc::new(self.peersock.peer_addr().unwrap().ip(), bom.clone());
+        let peer_as = bom
+            .caps
+            .iter()
+            .find_map(|c| match c {
+                BgpCapability::CapASN32(n) => Some(*n),
+                _ => None,
+            })
+            .unwrap_or(bom.as_num);
+        if self.remote_as != 0 && peer_as != self.remote_as {
+            // bad peer AS
+            self.notify(2, 2, &[]).await;
+            return Err(BgpError::from_string(format!(
+                "Bad peer AS {}, expected {}",
+                peer_as, self.remote_as
+            )));
+        }
+        if let Some(rid) = self.remote_router_id {
+            if bom.router_id != rid {
+                self.notify(2, 3, &[]).await;
+                return Err(BgpError::from_string(format!(
+                    "Bad BGP identifier {}, expected {}",
+                    bom.router_id, rid
+                )));
+            }
+        }
+        if bom.hold_time == 1 || bom.hold_time == 2 {
+            self.notify(2, 6, &[]).await;
+            return Err(BgpError::from_string(format!(
+                "Unacceptable hold time {}",
+                bom.hold_time
+            )));
+        }
+        self.params.hold_time = std::cmp::min(self.params.hold_time, bom.hold_time);
+        if let Some(caps) = self.passive_caps.take() {
+            self.params.caps = caps
+                .into_iter()
+                .map(|c| match c {
+                    BgpCapability::CapASN32(_) => BgpCapability::CapASN32(peer_as),
+                    c => c,
+                })
+                .collect();
+            self.params.match_caps(&bom.caps);
+            bom.caps = self.params.caps.clone();
+        } else {
+            self.params.match_caps(&bom.caps);
+        }
+        bom.caps
+            .retain(|c| !matches!(c, BgpCapability::CapGR { .. }));
+        if self.graceful_restart {
+            bom.caps.push(BgpPeer::<H>::gr_helper_capability());
+        }
         bom.router_id = self.params.router_id;
-        self.params.as_num = bom.as_num;
-        self.params.hold_time = bom.hold_time;
-        self.params.match_caps(&bom.caps);
-        let sz = match bom.encode_to(&self.params, BgpPeer::<H>::get_message_body_ref(&mut buf)?) {
-            Err(e) => return Err(e),
-            Ok(sz) => sz,
-        };
+        bom.hold_time = self.params.hold_time;
+        self.params.as_num = peer_as;
+        let mut sz =
+            match bom.encode_to(&self.params, BgpPeer::<H>::get_message_body_ref(&mut buf)?) {
+                Err(e) => return Err(e),
+                Ok(sz) => sz,
+            };
+        if extmsg {
+            sz = BgpPeer::<H>::open_add_capability(
+                BgpPeer::<H>::get_message_body_ref(&mut buf)?,
+                sz,
+                CAP_EXTENDED_MESSAGE,
+                &[],
+            )?;
+            self.max_message = BGP_MAX_EXTENDED_MESSAGE;
+        }
+        sz = self.open_add_mp(BgpPeer::<H>::get_message_body_ref(&mut buf)?, sz)?;
+        sz = self.open_add_role(BgpPeer::<H>::get_message_body_ref(&mut buf)?, sz)?;
         self.send_message_buf(&mut buf, BgpMessageType::Open, sz)
             .await?;
         let mysess = BgpPeerDesc::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), bom.clone());
-        self.sessionid = self
-            .update_handler
-            .register_session(Arc::new(BgpSessionDesc::new(mysess, remsess)))
-            .await;
+        let mut desc = BgpSessionDesc::new(mysess, remsess);
+        desc.role = self.role;
+        desc.gr_forwarding = self.peer_forwarding.take();
+        desc.direct = true;
+        self.sessionid = self.update_handler.register_session(Arc::new(desc)).await;
+        if self.rtc {
+            self.send_rtc_default(false).await?;
+        }
         Ok(())
     }
     pub async fn start_active(&mut self) -> Result<(), BgpError> {
         info!("start_active");
         loop {
-            let bom = self.params.open_message();
-            let mut buf = [255u8; 255];
-            let sz =
+            let mut bom = self.params.open_message();
+            if self.graceful_restart {
+                bom.caps.push(BgpPeer::<H>::gr_helper_capability());
+            }
+            let mut buf = [255u8; BGP_MAX_MESSAGE];
+            let mut sz =
                 match bom.encode_to(&self.params, BgpPeer::<H>::get_message_body_ref(&mut buf)?) {
                     Err(e) => {
                         return Err(e);
                     }
                     Ok(sz) => sz,
                 };
+            if self.extended_message {
+                sz = BgpPeer::<H>::open_add_capability(
+                    BgpPeer::<H>::get_message_body_ref(&mut buf)?,
+                    sz,
+                    CAP_EXTENDED_MESSAGE,
+                    &[],
+                )?;
+            }
+            sz = self.open_add_mp(BgpPeer::<H>::get_message_body_ref(&mut buf)?, sz)?;
+            sz = self.open_add_role(BgpPeer::<H>::get_message_body_ref(&mut buf)?, sz)?;
             let mysess = BgpPeerDesc::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), bom.clone());
             self.send_message_buf(&mut buf, BgpMessageType::Open, sz)
                 .await?;
-            let msg = match self.recv_message_head().await {
+            let msg = match self.recv_open_head().await {
                 Err(e) => {
                     return Err(e);
                 }
                 Ok(msg) => msg,
             };
             match msg.0 {
-                BgpMessageType::Open => {
+                BgpPeerMessage::Bgp(BgpMessageType::Open) => {
                     self.read_socket(&mut buf[0..msg.1]).await?;
                     let mut bomrcv = self.params.open_message();
                     bomrcv.decode_from(&self.params, &buf[0..msg.1])?;
+                    self.check_role(&buf[0..msg.1]).await?;
+                    self.take_peer_restart(&mut bomrcv);
+                    if bomrcv.hold_time == 1 || bomrcv.hold_time == 2 {
+                        self.notify(2, 6, &[]).await;
+                        return Err(BgpError::from_string(format!(
+                            "Unacceptable hold time {}",
+                            bomrcv.hold_time
+                        )));
+                    }
                     let remsess =
                         BgpPeerDesc::new(self.peersock.peer_addr().unwrap().ip(), bomrcv.clone());
-                    self.params.hold_time = bomrcv.hold_time;
+                    self.params.hold_time = std::cmp::min(self.params.hold_time, bomrcv.hold_time);
                     self.params.match_caps(&bomrcv.caps);
-                    self.sessionid = self
-                        .update_handler
-                        .register_session(Arc::new(BgpSessionDesc::new(mysess, remsess)))
-                        .await;
+                    if self.extended_message
+                        && BgpPeer::<H>::open_has_capability(&buf[0..msg.1], CAP_EXTENDED_MESSAGE)
+                    {
+                        self.max_message = BGP_MAX_EXTENDED_MESSAGE;
+                    }
+                    self.rtc = BgpPeer::<H>::mp_negotiated(self.rtc, &buf[0..msg.1], &CAP_MP_RTC);
+                    self.fsv6u =
+                        BgpPeer::<H>::mp_negotiated(self.fsv6u, &buf[0..msg.1], &CAP_MP_FSV6U);
+                    let ebgp = remsess.as_num() != self.params.as_num;
+                    let mut desc = BgpSessionDesc::new(mysess, remsess);
+                    desc.role = self.role;
+                    desc.gr_forwarding = self.peer_forwarding.take();
+                    desc.direct = true;
+                    self.sessionid = self.update_handler.register_session(Arc::new(desc)).await;
+                    if self.rtc {
+                        self.send_rtc_default(ebgp).await?;
+                    }
                     return Ok(());
                 }
-                BgpMessageType::Notification => {
+                BgpPeerMessage::Bgp(BgpMessageType::Notification) => {
                     self.read_socket(&mut buf[0..msg.1]).await?;
                     let mut bnrcv = BgpNotificationMessage::new();
                     bnrcv.decode_from(&self.params, &buf[0..msg.1])?;
                     warn!("Notification: {}", bnrcv.error_text());
                     if bnrcv.error_code == 2 && bnrcv.error_subcode == 7 {
                         //unsupported capability
+                        if self.extended_message && msg.1 > 2 && buf[2] == CAP_EXTENDED_MESSAGE {
+                            warn!("Unsupported extended message capability");
+                            self.extended_message = false;
+                            continue;
+                        }
+                        if self.graceful_restart && msg.1 > 2 && buf[2] == CAP_GRACEFUL_RESTART {
+                            warn!("Unsupported graceful restart capability");
+                            self.graceful_restart = false;
+                            continue;
+                        }
+                        if self.rtc
+                            && msg.1 >= 8
+                            && buf[2] == CAP_MULTIPROTOCOL
+                            && buf[4..8] == CAP_MP_RTC
+                        {
+                            warn!("Unsupported route target constraint address family");
+                            self.rtc = false;
+                            continue;
+                        }
+                        if self.fsv6u
+                            && msg.1 >= 8
+                            && buf[2] == CAP_MULTIPROTOCOL
+                            && buf[4..8] == CAP_MP_FSV6U
+                        {
+                            warn!("Unsupported VPNv6 FlowSpec address family");
+                            self.fsv6u = false;
+                            continue;
+                        }
+                        if self.role.is_some() && msg.1 > 2 && buf[2] == CAP_BGP_ROLE {
+                            warn!("Unsupported BGP role capability");
+                            self.role_unsupported = true;
+                            continue;
+                        }
                         let (cap, _) = BgpCapability::from_buffer(&buf[2..msg.1])?;
                         if let Ok(cap) = cap {
                             warn!("Unsupported capability: {:?} in {:?}", cap, self.params);
@@ -176,62 +589,196 @@ impl<'a, H: BgpUpdateHandler> BgpPeer<'a, H> {
             Err(e) => Err(e),
         }
     }
-    pub async fn lifecycle(&mut self, cancel: tokio_util::sync::CancellationToken) {
+    /// Sends NOTIFICATION with raw data field
+    pub async fn send_notification(
+        &mut self,
+        code: u8,
+        subcode: u8,
+        data: &[u8],
+    ) -> Result<(), BgpError> {
         let mut buf = [255u8; 4096];
+        let msglen = 2 + data.len();
+        if msglen + 19 > buf.len() {
+            return Err(BgpError::too_many_data());
+        }
+        buf[19] = code;
+        buf[20] = subcode;
+        buf[21..21 + data.len()].copy_from_slice(data);
+        self.send_message_buf(&mut buf, BgpMessageType::Notification, msglen)
+            .await
+    }
+    async fn notify(&mut self, code: u8, subcode: u8, data: &[u8]) {
+        self.notified = true;
+        if let Err(e) = self.send_notification(code, subcode, data).await {
+            warn!("Notification {}/{} send error: {:?}", code, subcode, e);
+        }
+    }
+    /// RFC 8203 shutdown communication, length-prefixed UTF-8
+    fn shutdown_communication(msg: &str) -> Vec<u8> {
+        let mut len = msg.len().min(255);
+        while !msg.is_char_boundary(len) {
+            len -= 1;
+        }
+        let mut ret = Vec::with_capacity(len + 1);
+        ret.push(len as u8);
+        ret.extend_from_slice(&msg.as_bytes()[0..len]);
+        ret
+    }
+    pub fn session_id(&self) -> BgpSessionId {
+        self.sessionid
+    }
+    /// Sends ROUTE-REFRESH request for AFI/SAFI
+    pub async fn send_route_refresh(&mut self, kind: BgpRibKind) -> Result<(), BgpError> {
+        if !self.params.check_capability(&BgpCapability::CapRR) {
+            return Err(BgpError::static_str("Route refresh was not negotiated"));
+        }
+        let (afi, safi) = kind.afi_safi();
+        let mut buf = [255u8; 23];
+        buf[16..18].copy_from_slice(&23u16.to_be_bytes());
+        buf[18] = BGP_MSG_ROUTE_REFRESH;
+        buf[19..21].copy_from_slice(&afi.to_be_bytes());
+        buf[21] = 0;
+        buf[22] = safi;
+        self.write_socket(&buf).await
+    }
+    /// Handles received ROUTE-REFRESH, passes Enhanced Route Refresh markers
+    async fn handle_route_refresh(&mut self, buf: &[u8]) -> Result<(), BgpError> {
+        if buf.len() != 4 {
+            // ROUTE-REFRESH message error, invalid message length
+            self.notify(7, 1, &[]).await;
+            return Err(BgpError::static_str("Invalid ROUTE-REFRESH length"));
+        }
+        let kind = match BgpRibKind::from_afi_safi(getn_u16(buf), buf[3]) {
+            None => {
+                warn!("ROUTE-REFRESH for unknown AFI/SAFI {:?}", buf);
+                return Ok(());
+            }
+            Some(k) => k,
+        };
+        match buf[2] {
+            0 => debug!("ROUTE-REFRESH request for {} ignored", kind),
+            1 | 2 => {
+                self.update_handler
+                    .route_refresh(self.sessionid, kind, buf[2] == 1, Timestamp::now())
+                    .await
+            }
+            n => warn!("ROUTE-REFRESH unknown subtype {}", n),
+        }
+        Ok(())
+    }
+    async fn recv_command(
+        commands: &mut Option<tokio::sync::mpsc::Receiver<BgpPeerCommand>>,
+    ) -> Option<BgpPeerCommand> {
+        match commands {
+            Some(rx) => rx.recv().await,
+            None => std::future::pending().await,
+        }
+    }
+    /// First KEEPALIVE or UPDATE in OpenConfirm state establishes the session
+    async fn set_established(&mut self) {
+        if !self.established {
+            self.established = true;
+            self.update_handler
+                .session_established(self.sessionid)
+                .await;
+        }
+    }
+    pub async fn lifecycle(
+        &mut self,
+        cancel: tokio_util::sync::CancellationToken,
+        mut commands: Option<tokio::sync::mpsc::Receiver<BgpPeerCommand>>,
+    ) {
+        let mut buf = vec![255u8; self.max_message];
+        let hold_time = chrono::Duration::seconds(self.params.hold_time as i64);
         let keep_interval = chrono::Duration::seconds((self.params.hold_time / 3) as i64);
-        loop {
-            let mut tosleep = Local::now() - self.keepalive_sent;
-            if tosleep >= keep_interval {
-                match self.send_keepalive().await {
-                    Ok(_) => {}
-                    Err(e) => {
+        self.last_received = Local::now();
+        let reason: String = loop {
+            let now = Local::now();
+            // hold time zero means no keepalives and no hold timer
+            if self.params.hold_time > 0 {
+                if now - self.last_received >= hold_time {
+                    warn!("Hold timer expired, session {}", self.sessionid);
+                    self.notify(4, 0, &[]).await;
+                    break "Hold timer expired".to_string();
+                }
+                if now - self.keepalive_sent >= keep_interval {
+                    if let Err(e) = self.send_keepalive().await {
                         error!("Keepalive send error: {:?}", e);
                     }
                 }
-                tosleep = Local::now() - self.keepalive_sent;
             }
-            tosleep = keep_interval - tosleep;
+            let tosleep = if self.params.hold_time > 0 {
+                let now = Local::now();
+                std::cmp::min(
+                    keep_interval - (now - self.keepalive_sent),
+                    hold_time - (now - self.last_received),
+                )
+            } else {
+                chrono::Duration::seconds(60)
+            };
             let tosleepstd = match tosleep.to_std() {
                 Ok(s) => s,
                 Err(_) => std::time::Duration::from_secs(1),
             };
             let msg = select! {
                 _ = cancel.cancelled() => {
-                    // The token was cancelled
-                    break;
+                    let comm = BgpPeer::<H>::shutdown_communication("bgpexplorer shutdown");
+                    self.notify(6, 2, &comm).await;
+                    break "Cancelled".to_string();
                 }
                 _ = tokio::time::sleep(tosleepstd) => {
-                    (BgpMessageType::Keepalive,0)
+                    continue;
+                }
+                cmd = BgpPeer::<H>::recv_command(&mut commands) => {
+                    match cmd {
+                        None => commands = None,
+                        Some(BgpPeerCommand::RouteRefresh(kind)) => {
+                            info!("Session {} sending ROUTE-REFRESH for {}", self.sessionid, kind);
+                            if let Err(e) = self.send_route_refresh(kind).await {
+                                warn!("ROUTE-REFRESH send error: {:?}", e);
+                            }
+                        }
+                    }
+                    continue;
                 }
                 msgin = self.recv_message_head() => {
                     match msgin {
                         Err(e) => {
                             error!("recv_message_head: {:?}", e);
-                            break;
+                            break format!("{:?}", e);
                         }
                         Ok(msg) => msg
                     }
                 }
             };
+            self.last_received = Local::now();
             if let Err(e) = self.read_socket(&mut buf[0..msg.1]).await {
                 warn!("recv_message: {:?}", e);
             };
             match msg.0 {
-                BgpMessageType::Open => {
+                BgpPeerMessage::Bgp(BgpMessageType::Open) => {
                     error!("Incorrect open message!");
-                    break;
+                    // RFC 6608 unexpected message in OpenConfirm or Established state
+                    self.notify(5, if self.established { 3 } else { 2 }, &[])
+                        .await;
+                    break "Incorrect open message".to_string();
                 }
-                BgpMessageType::Keepalive => match self.send_keepalive().await {
-                    Ok(_) => {}
-                    Err(e) => {
-                        warn!("Keepalive sending error: {:?}", e);
+                BgpPeerMessage::Bgp(BgpMessageType::Keepalive) => {
+                    self.set_established().await;
+                    match self.send_keepalive().await {
+                        Ok(_) => {}
+                        Err(e) => {
+                            warn!("Keepalive sending error: {:?}", e);
+                        }
                     }
-                },
-                BgpMessageType::Notification => {
+                }
+                BgpPeerMessage::Bgp(BgpMessageType::Notification) => {
+                    self.notified = true;
                     let mut msgnotification = BgpNotificationMessage::new();
                     match msgnotification.decode_from(&self.params, &buf[0..msg.1]) {
                         Err(e) => {
                             warn!("BGP notification decode error: {:?}", e);
+                            break format!("Notification: {:?}", e);
                         }
                         Ok(_) => {
                             info!(
@@ -239,22 +786,107 @@ impl<'a, H: BgpUpdateHandler> BgpPeer<'a, H> {
                                 msgnotification,
                                 msgnotification.error_text()
                             );
+                            break format!("Notification: {}", msgnotification.error_text());
                         }
                     };
-                    break;
                 }
-                BgpMessageType::Update => {
+                BgpPeerMessage::RouteRefresh => {
+                    if let Err(e) = self.handle_route_refresh(&buf[0..msg.1]).await {
+                        break format!("{:?}", e);
+                    }
+                }
+                BgpPeerMessage::Bgp(BgpMessageType::Update) => {
+                    self.set_established().await;
+                    // VPNv6 FlowSpec and RTC NLRI are unknown to zettabgp, so they are taken out before decoding
+                    let fsv6u = match extract_fsv6u(&buf[0..msg.1]) {
+                        Ok(r) => r,
+                        Err(e) => {
+                            error!("BGP update VPNv6 FlowSpec decode error: {:?}", e);
+                            self.notify(3, 1, &[]).await;
+                            break format!("Update decode error: {:?}", e);
+                        }
+                    };
+                    let body = match fsv6u {
+                        Some((_, ref b)) => b.as_slice(),
+                        None => &buf[0..msg.1],
+                    };
+                    let rtc = match extract_rtc(body) {
+                        Ok(r) => r,
+                        Err(e) => {
+                            error!("BGP update RTC decode error: {:?}", e);
+                            self.notify(3, 1, &[]).await;
+                            break format!("Update decode error: {:?}", e);
+                        }
+                    };
+                    let body = match rtc {
+                        Some((_, ref b)) => b.as_slice(),
+                        None => body,
+                    };
                     let mut msgupdate = BgpUpdateMessage::new();
-                    if let Err(e) = msgupdate.decode_from(&self.params, &buf[0..msg.1]) {
+                    if let Err(e) = msgupdate.decode_from(&self.params, body) {
                         error!("BGP update decode error: {:?}", e);
-                        continue;
+                        // malformed attribute list
+                        self.notify(3, 1, &[]).await;
+                        break format!("Update decode error: {:?}", e);
+                    }
+                    let when = Timestamp::now();
+                    match (fsv6u, rtc) {
+                        (None, None) => {
+                            self.update_handler
+                                .handle_update(self.sessionid, msgupdate, when)
+                                .await
+                        }
+                        (Some((f, _)), None) => {
+                            self.update_handler
+                                .handle_fsv6u(self.sessionid, msgupdate, f, when)
+                                .await
+                        }
+                        (None, Some((r, _))) => {
+                            self.update_handler
+                                .handle_rtc(self.sessionid, msgupdate, r, when)
+                                .await
+                        }
+                        (Some((f, _)), Some((r, _))) => {
+                            // FlowSpec rules share path attributes, other NLRI go with RTC
+                            self.update_handler
+                                .handle_fsv6u(
+                                    self.sessionid,
+                                    BgpPeer::<H>::attrs_only(&msgupdate),
+                                    f,
+                                    when,
+                                )
+                                .await;
+                            self.update_handler
+                                .handle_rtc(self.sessionid, msgupdate, r, when)
+                                .await
+                        }
                     }
-                    self.update_handler
-                        .handle_update(self.sessionid, msgupdate)
-                        .await;
                 }
             }
+        };
+        // RFC 4724 helper retains routes when session terminates without NOTIFICATION
+        if self.graceful_restart && !self.notified {
+            if let Some((restart_time, retain)) = self.peer_restart.take() {
+                self.update_handler
+                    .session_stale(self.sessionid, reason, restart_time, retain)
+                    .await;
+                return;
+            }
         }
+        self.update_handler
+            .session_down(self.sessionid, reason)
+            .await;
+    }
+    /// Copy of UPDATE path attributes without any NLRI
+    fn attrs_only(upd: &BgpUpdateMessage) -> BgpUpdateMessage {
+        let mut ret = BgpUpdateMessage::new();
+        ret.attrs = upd
+            .attrs
+            .iter()
+            .filter(|a| !matches!(a, BgpAttrItem::MPUpdates(_) | BgpAttrItem::MPWithdraws(_)))
+            .cloned()
+            .collect();
+        ret
     }
     pub async fn close(&mut self) {
         match self.peersock.shutdown().await {
@@ -265,3 +897,170 @@ impl<'a, H: BgpUpdateHandler> BgpPeer<'a, H> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{BgpPeer, CAP_MP_FSV6U, CAP_MP_RTC};
+    use crate::bgprib::BgpRibKind;
+    use crate::bgpsvc::{BgpSessionDesc, BgpSessionId, BgpSvr, BgpUpdateHandler};
+    use crate::bmpstats::BmpStationEvent;
+    use crate::flowspec::FsV6uUpdate;
+    use crate::rtc::RtcUpdate;
+    use crate::timestamp::Timestamp;
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+    use zettabgp::prelude::{BgpSessionParams, BgpTransportMode, BgpUpdateMessage};
+
+    struct NoHandler;
+    #[async_trait::async_trait]
+    impl BgpUpdateHandler for NoHandler {
+        async fn handle_update(&self, _: BgpSessionId, _: BgpUpdateMessage, _: Timestamp) {}
+        async fn handle_rtc(
+            &self,
+            _: BgpSessionId,
+            _: BgpUpdateMessage,
+            _: RtcUpdate,
+            _: Timestamp,
+        ) {
+        }
+        async fn handle_fsv6u(
+            &self,
+            _: BgpSessionId,
+            _: BgpUpdateMessage,
+            _: FsV6uUpdate,
+            _: Timestamp,
+        ) {
+        }
+        async fn register_session(&self, _: Arc<BgpSessionDesc>) -> BgpSessionId {
+            1
+        }
+        async fn session_established(&self, _: BgpSessionId) {}
+        async fn session_down(&self, _: BgpSessionId, _: String) {}
+        async fn session_stale(&self, _: BgpSessionId, _: String, _: u16, _: Vec<BgpRibKind>) {}
+        async fn bmp_event(&self, _: IpAddr, _: BmpStationEvent) {}
+        async fn route_refresh(&self, _: BgpSessionId, _: BgpRibKind, _: bool, _: Timestamp) {}
+    }
+
+    /// Connected pair of local BGP peer stream and remote neighbor stream
+    async fn socket_pair() -> (TcpStream, TcpStream) {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let remote = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (local, _) = listener.accept().await.unwrap();
+        (local, remote)
+    }
+
+    fn new_peer(stream: TcpStream, handler: &NoHandler) -> BgpPeer<'_, NoHandler> {
+        let params = BgpSessionParams::new(
+            65000,
+            180,
+            BgpTransportMode::IPv4,
+            Ipv4Addr::new(10, 0, 0, 1),
+            vec![],
+        );
+        BgpPeer::new(params, stream, handler)
+    }
+
+    /// Reads one message from neighbor side, returns type and body
+    async fn read_message(remote: &mut TcpStream) -> (u8, Vec<u8>) {
+        let mut head = [0u8; 19];
+        remote.read_exact(&mut head).await.unwrap();
+        let mut body = vec![0u8; u16::from_be_bytes([head[16], head[17]]) as usize - 19];
+        remote.read_exact(&mut body).await.unwrap();
+        (head[18], body)
+    }
+
+    /// OPEN of AS 65001 without optional parameters
+    fn open_message(hold_time: u16) -> Vec<u8> {
+        let mut ret = vec![255u8; 16];
+        ret.extend_from_slice(&29u16.to_be_bytes());
+        ret.extend_from_slice(&[1, 4, 0xfd, 0xe9]);
+        ret.extend_from_slice(&hold_time.to_be_bytes());
+        ret.extend_from_slice(&[10, 0, 0, 2, 0]);
+        ret
+    }
+
+    #[tokio::test]
+    async fn test_active_unacceptable_hold_time() {
+        let (local, mut remote) = socket_pair().await;
+        let handler = NoHandler;
+        let mut peer = new_peer(local, &handler);
+        let neighbor = async {
+            assert_eq!(read_message(&mut remote).await.0, 1);
+            remote.write_all(&open_message(2)).await.unwrap();
+            read_message(&mut remote).await
+        };
+        let (res, notification) = tokio::join!(peer.start_active(), neighbor);
+        assert!(res.is_err());
+        assert_eq!(notification, (3, vec![2, 6]));
+    }
+
+    #[tokio::test]
+    async fn test_open_hold_timer() {
+        let (local, mut remote) = socket_pair().await;
+        let handler = NoHandler;
+        let mut peer = new_peer(local, &handler);
+        peer.open_hold_time = Duration::from_millis(100);
+        let neighbor = async {
+            assert_eq!(read_message(&mut remote).await.0, 1);
+            // no OPEN from neighbor until hold timer expires
+            read_message(&mut remote).await
+        };
+        let (res, notification) = tokio::join!(peer.start_active(), neighbor);
+        assert!(res.is_err());
+        assert_eq!(notification, (3, vec![4, 0]));
+    }
+
+    #[tokio::test]
+    async fn test_open_in_open_confirm() {
+        let (local, mut remote) = socket_pair().await;
+        let handler = NoHandler;
+        let mut peer = new_peer(local, &handler);
+        let neighbor = async {
+            assert_eq!(read_message(&mut remote).await.0, 1);
+            remote.write_all(&open_message(90)).await.unwrap();
+        };
+        let (res, _) = tokio::join!(peer.start_active(), neighbor);
+        assert!(res.is_ok());
+        let cancel = tokio_util::sync::CancellationToken::new();
+        let neighbor = async {
+            remote.write_all(&open_message(90)).await.unwrap();
+            loop {
+                let msg = read_message(&mut remote).await;
+                if msg.0 == 3 {
+                    break msg;
+                }
+            }
+        };
+        let (_, notification) = tokio::join!(peer.lifecycle(cancel, None), neighbor);
+        // FSM error, unexpected message in OpenConfirm state
+        assert_eq!(notification, (3, vec![5, 2]));
+    }
+
+    #[test]
+    fn test_rtc_negotiated() {
+        // OPEN body with multiprotocol capabilities for IPv4 unicast and RTC
+        let open = [
+            4, 0xfd, 0xe8, 0, 90, 10, 0, 0, 1, 16, 2, 6, 1, 4, 0, 1, 0, 1, 2, 6, 1, 4, 0, 1, 0, 132,
+        ];
+        assert!(BgpPeer::<BgpSvr>::mp_negotiated(true, &open, &CAP_MP_RTC));
+        assert!(!BgpPeer::<BgpSvr>::mp_negotiated(false, &open, &CAP_MP_RTC));
+        assert!(!BgpPeer::<BgpSvr>::mp_negotiated(
+            true,
+            &open,
+            &CAP_MP_FSV6U
+        ));
+        // neighbor without RTC
+        let open = [4, 0xfd, 0xe8, 0, 90, 10, 0, 0, 1, 8, 2, 6, 1, 4, 0, 1, 0, 1];
+        assert!(!BgpPeer::<BgpSvr>::mp_negotiated(true, &open, &CAP_MP_RTC));
+        // neighbor with VPNv6 FlowSpec
+        let open = [
+            4, 0xfd, 0xe8, 0, 90, 10, 0, 0, 1, 8, 2, 6, 1, 4, 0, 2, 0, 134,
+        ];
+        assert!(BgpPeer::<BgpSvr>::mp_negotiated(true, &open, &CAP_MP_FSV6U));
+    }
+}
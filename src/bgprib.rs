@@ -1,20 +1,28 @@
+use crate::alerts::*;
 use crate::bgpattrs::*;
 use crate::bgpsvc::BgpSessionId;
+use crate::bogon::*;
 use crate::config::*;
+use crate::flaps::*;
+use crate::flowspec::*;
+use crate::irr::*;
 use crate::ribfilter::RouteFilter;
 use crate::ribservice::RibResponseFilter;
+use crate::rpki::*;
+use crate::rtc::*;
 use crate::timestamp::Timestamp;
 use serde::{Deserialize, Serialize};
 use std::cell::RefCell;
 use std::collections::{BTreeMap, BTreeSet, HashSet};
 use std::io::{BufReader, BufWriter};
 use std::iter::Iterator;
+use std::net::IpAddr;
 use std::ops::Deref;
 use std::sync::Arc;
 use tokio::sync::broadcast;
 use zettabgp::prelude::*;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum BgpRibKind {
     IpV4u,
     IpV4m,
@@ -22,6 +30,7 @@ pub enum BgpRibKind {
     VpnV4u,
     VpnV4m,
     IpV6u,
+    IpV6m,
     IpV6LU,
     VpnV6u,
     VpnV6m,
@@ -29,8 +38,12 @@ pub enum BgpRibKind {
     MVpn,
     EVpn,
     Fs4u,
+    Fs6u,
+    FsV4u,
     IpV4mdt,
     Ipv6mdt,
+    Rtc,
+    FsV6u,
 }
 impl BgpRibKind {
     pub fn from_bgp_addrs(addrs: &BgpAddrs) -> Option<BgpRibKind> {
@@ -50,8 +63,8 @@ impl BgpRibKind {
             BgpAddrs::IPV4MDTP(_) => Some(BgpRibKind::IpV4mdt),
             BgpAddrs::IPV6U(_) => Some(BgpRibKind::IpV6u),
             BgpAddrs::IPV6UP(_) => Some(BgpRibKind::IpV6u),
-            BgpAddrs::IPV6M(_) => None,
-            BgpAddrs::IPV6MP(_) => None,
+            BgpAddrs::IPV6M(_) => Some(BgpRibKind::IpV6m),
+            BgpAddrs::IPV6MP(_) => Some(BgpRibKind::IpV6m),
             BgpAddrs::IPV6LU(_) => Some(BgpRibKind::IpV6LU),
             BgpAddrs::IPV6LUP(_) => Some(BgpRibKind::IpV6LU),
             BgpAddrs::VPNV6U(_) => Some(BgpRibKind::VpnV6u),
@@ -60,12 +73,86 @@ impl BgpRibKind {
             BgpAddrs::VPNV6MP(_) => Some(BgpRibKind::VpnV6m),
             BgpAddrs::IPV6MDT(_) => Some(BgpRibKind::Ipv6mdt),
             BgpAddrs::IPV6MDTP(_) => Some(BgpRibKind::Ipv6mdt),
-            BgpAddrs::L2VPLS(_) => Some(BgpRibKind::Ipv6mdt),
+            BgpAddrs::L2VPLS(_) => Some(BgpRibKind::L2vpls),
             BgpAddrs::MVPN(_) => Some(BgpRibKind::MVpn),
             BgpAddrs::EVPN(_) => Some(BgpRibKind::EVpn),
             BgpAddrs::FS4U(_) => Some(BgpRibKind::Fs4u),
-            BgpAddrs::FS6U(_) => None,
-            BgpAddrs::FSV4U(_) => None,
+            BgpAddrs::FS6U(_) => Some(BgpRibKind::Fs6u),
+            BgpAddrs::FSV4U(_) => Some(BgpRibKind::FsV4u),
+        }
+    }
+}
+impl BgpRibKind {
+    pub const ALL: [BgpRibKind; 20] = [
+        BgpRibKind::IpV4u,
+        BgpRibKind::IpV4m,
+        BgpRibKind::IpV4LU,
+        BgpRibKind::VpnV4u,
+        BgpRibKind::VpnV4m,
+        BgpRibKind::IpV6u,
+        BgpRibKind::IpV6m,
+        BgpRibKind::IpV6LU,
+        BgpRibKind::VpnV6u,
+        BgpRibKind::VpnV6m,
+        BgpRibKind::L2vpls,
+        BgpRibKind::MVpn,
+        BgpRibKind::EVpn,
+        BgpRibKind::Fs4u,
+        BgpRibKind::Fs6u,
+        BgpRibKind::FsV4u,
+        BgpRibKind::IpV4mdt,
+        BgpRibKind::Ipv6mdt,
+        BgpRibKind::Rtc,
+        BgpRibKind::FsV6u,
+    ];
+    /// AFI and SAFI codes
+    pub fn afi_safi(&self) -> (u16, u8) {
+        match self {
+            BgpRibKind::IpV4u => (1, 1),
+            BgpRibKind::IpV4m => (1, 2),
+            BgpRibKind::IpV4LU => (1, 4),
+            BgpRibKind::VpnV4u => (1, 128),
+            BgpRibKind::VpnV4m => (1, 129),
+            BgpRibKind::IpV6u => (2, 1),
+            BgpRibKind::IpV6m => (2, 2),
+            BgpRibKind::IpV6LU => (2, 4),
+            BgpRibKind::VpnV6u => (2, 128),
+            BgpRibKind::VpnV6m => (2, 129),
+            BgpRibKind::L2vpls => (25, 65),
+            BgpRibKind::MVpn => (1, 5),
+            BgpRibKind::EVpn => (25, 70),
+            BgpRibKind::Fs4u => (1, 133),
+            BgpRibKind::Fs6u => (2, 133),
+            BgpRibKind::FsV4u => (1, 134),
+            BgpRibKind::IpV4mdt => (1, 66),
+            BgpRibKind::Ipv6mdt => (2, 66),
+            BgpRibKind::Rtc => (RTC_AFI, RTC_SAFI),
+            BgpRibKind::FsV6u => (FSV6U_AFI, FSV6U_SAFI),
+        }
+    }
+    pub fn from_afi_safi(afi: u16, safi: u8) -> Option<BgpRibKind> {
+        match (afi, safi) {
+            (1, 1) => Some(BgpRibKind::IpV4u),
+            (1, 2) => Some(BgpRibKind::IpV4m),
+            (1, 4) => Some(BgpRibKind::IpV4LU),
+            (1, 128) => Some(BgpRibKind::VpnV4u),
+            (1, 129) => Some(BgpRibKind::VpnV4m),
+            (2, 1) => Some(BgpRibKind::IpV6u),
+            (2, 2) => Some(BgpRibKind::IpV6m),
+            (2, 4) => Some(BgpRibKind::IpV6LU),
+            (2, 128) => Some(BgpRibKind::VpnV6u),
+            (2, 129) => Some(BgpRibKind::VpnV6m),
+            (25, 65) => Some(BgpRibKind::L2vpls),
+            (1, 5) => Some(BgpRibKind::MVpn),
+            (25, 70) => Some(BgpRibKind::EVpn),
+            (1, 133) => Some(BgpRibKind::Fs4u),
+            (2, 133) => Some(BgpRibKind::Fs6u),
+            (1, 134) => Some(BgpRibKind::FsV4u),
+            (1, 66) => Some(BgpRibKind::IpV4mdt),
+            (2, 66) => Some(BgpRibKind::Ipv6mdt),
+            (RTC_AFI, RTC_SAFI) => Some(BgpRibKind::Rtc),
+            (FSV6U_AFI, FSV6U_SAFI) => Some(BgpRibKind::FsV6u),
+            _ => None,
         }
     }
 }
@@ -84,6 +171,7 @@ impl std::str::FromStr for BgpRibKind {
             "vpnv4u" => Ok(BgpRibKind::VpnV4u),
             "vpnv4m" => Ok(BgpRibKind::VpnV4m),
             "ipv6u" => Ok(BgpRibKind::IpV6u),
+            "ipv6m" => Ok(BgpRibKind::IpV6m),
             "ipv6lu" => Ok(BgpRibKind::IpV6LU),
             "vpnv6u" => Ok(BgpRibKind::VpnV6u),
             "vpnv6m" => Ok(BgpRibKind::VpnV6m),
@@ -91,8 +179,12 @@ impl std::str::FromStr for BgpRibKind {
             "mvpn" => Ok(BgpRibKind::MVpn),
             "evpn" => Ok(BgpRibKind::EVpn),
             "fs4u" => Ok(BgpRibKind::Fs4u),
+            "fs6u" => Ok(BgpRibKind::Fs6u),
+            "fsv4u" => Ok(BgpRibKind::FsV4u),
             "ipv4mdt" => Ok(BgpRibKind::IpV4mdt),
             "ipv6mdt" => Ok(BgpRibKind::Ipv6mdt),
+            "rtc" => Ok(BgpRibKind::Rtc),
+            "fsv6u" => Ok(BgpRibKind::FsV6u),
             _ => Err(BgpError::static_str("Invalid RIB kind")),
         }
     }
@@ -106,6 +198,7 @@ impl std::fmt::Display for BgpRibKind {
             BgpRibKind::VpnV4u => f.write_str("vpnv4u"),
             BgpRibKind::VpnV4m => f.write_str("vpnv4m"),
             BgpRibKind::IpV6u => f.write_str("ipv6u"),
+            BgpRibKind::IpV6m => f.write_str("ipv6m"),
             BgpRibKind::IpV6LU => f.write_str("ipv6lu"),
             BgpRibKind::VpnV6u => f.write_str("vpnv6u"),
             BgpRibKind::VpnV6m => f.write_str("vpnv6m"),
@@ -113,8 +206,12 @@ impl std::fmt::Display for BgpRibKind {
             BgpRibKind::MVpn => f.write_str("mvpn"),
             BgpRibKind::EVpn => f.write_str("evpn"),
             BgpRibKind::Fs4u => f.write_str("fs4u"),
+            BgpRibKind::Fs6u => f.write_str("fs6u"),
+            BgpRibKind::FsV4u => f.write_str("fsv4u"),
             BgpRibKind::IpV4mdt => f.write_str("ipv4mdt"),
             BgpRibKind::Ipv6mdt => f.write_str("ipv6mdt"),
+            BgpRibKind::Rtc => f.write_str("rtc"),
+            BgpRibKind::FsV6u => f.write_str("fsv6u"),
         }
     }
 }
@@ -215,6 +312,10 @@ impl BgpRIBKey for BgpAddrV6 {}
 impl BgpRIBKey for BgpMVPN {}
 impl BgpRIBKey for BgpEVPN {}
 impl BgpRIBKey for BgpFlowSpec<BgpAddrV4> {}
+impl BgpRIBKey for BgpFlowSpec<FS6> {}
+impl BgpRIBKey for BgpFlowSpec<FSV4U> {}
+impl BgpRIBKey for BgpRtc {}
+impl BgpRIBKey for BgpFlowSpecVpn6 {}
 pub struct BgpRIBIndex<K: Eq + Ord + Clone, T: BgpRIBKey> {
     pub idx: BTreeMap<K, BTreeSet<T>>,
 }
@@ -258,6 +359,51 @@ macro_rules! clone_iter {
         ClonableIterator::new(Arc::new(std::cell::RefCell::new(Box::new($x))))
     };
 }
+/// Evaluates expression for every RIB table of BgpRIB bound to the name
+macro_rules! for_each_safi {
+    ( $rib:expr, $safi:ident => $e:expr ) => {{
+        let $safi = &mut $rib.ipv4u;
+        $e;
+        let $safi = &mut $rib.ipv4m;
+        $e;
+        let $safi = &mut $rib.ipv4lu;
+        $e;
+        let $safi = &mut $rib.vpnv4u;
+        $e;
+        let $safi = &mut $rib.vpnv4m;
+        $e;
+        let $safi = &mut $rib.ipv6u;
+        $e;
+        let $safi = &mut $rib.ipv6m;
+        $e;
+        let $safi = &mut $rib.ipv6lu;
+        $e;
+        let $safi = &mut $rib.vpnv6u;
+        $e;
+        let $safi = &mut $rib.vpnv6m;
+        $e;
+        let $safi = &mut $rib.l2vpls;
+        $e;
+        let $safi = &mut $rib.mvpn;
+        $e;
+        let $safi = &mut $rib.evpn;
+        $e;
+        let $safi = &mut $rib.fs4u;
+        $e;
+        let $safi = &mut $rib.fs6u;
+        $e;
+        let $safi = &mut $rib.fsv4u;
+        $e;
+        let $safi = &mut $rib.ipv4mdt;
+        $e;
+        let $safi = &mut $rib.ipv6mdt;
+        $e;
+        let $safi = &mut $rib.rtc;
+        $e;
+        let $safi = &mut $rib.fsv6u;
+        $e;
+    }};
+}
 impl<'a, K, V> std::iter::Iterator for ClonableIterator<'a, K, V> {
     type Item = (K, V);
     fn next(&mut self) -> Option<Self::Item> {
@@ -342,9 +488,29 @@ impl BgpAttrHistory {
     pub fn get_last_attr(&self) -> Option<BgpAttrEntry> {
         self.items.iter().last().map(|v| (*v.1).clone())
     }
+    /// Start of the latest run of active records, earliest kept one if history was shrunk
+    fn announced(&self) -> Option<Timestamp> {
+        self.items
+            .iter()
+            .rev()
+            .skip_while(|(_, e)| !e.active)
+            .take_while(|(_, e)| e.active)
+            .last()
+            .map(|(t, _)| *t)
+    }
     pub fn insert(&mut self, when: Timestamp, entry: BgpAttrEntry) {
         self.items.insert(when, entry);
     }
+    /// Changes stale flag of the last active record in place, without history event
+    pub fn set_stale(&mut self, stale: bool) -> bool {
+        match self.items.values_mut().last() {
+            Some(e) if e.active && e.stale != stale => {
+                e.stale = stale;
+                true
+            }
+            _ => false,
+        }
+    }
 }
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(transparent)]
@@ -371,6 +537,12 @@ impl BgpPathEntry {
             Some(x) => x.get_last_attr(),
         }
     }
+    pub fn set_stale(&mut self, path: BgpPathId, stale: bool) -> bool {
+        match self.items.get_mut(&path) {
+            None => false,
+            Some(x) => x.set_stale(stale),
+        }
+    }
     pub fn insert(&mut self, path: BgpPathId, when: Timestamp, atr: BgpAttrEntry) {
         let pe = match self.items.get_mut(&path) {
             Some(e) => e,
@@ -386,6 +558,9 @@ impl BgpPathEntry {
 #[serde(transparent)]
 pub struct BgpSessionEntry {
     pub items: BTreeMap<BgpSessionId, BgpPathEntry>,
+    /// flap counters per session, not kept in snapshot
+    #[serde(skip)]
+    pub flaps: BTreeMap<BgpSessionId, FlapStats>,
 }
 impl Default for BgpSessionEntry {
     fn default() -> Self {
@@ -396,6 +571,7 @@ impl BgpSessionEntry {
     pub fn new() -> BgpSessionEntry {
         BgpSessionEntry {
             items: BTreeMap::new(),
+            flaps: BTreeMap::new(),
         }
     }
     fn shrink_hist(&mut self, maxlen: usize) {
@@ -407,6 +583,12 @@ impl BgpSessionEntry {
             Some(x) => x.get_last_attr(path),
         }
     }
+    pub fn set_stale(&mut self, sess: BgpSessionId, path: BgpPathId, stale: bool) -> bool {
+        match self.items.get_mut(&sess) {
+            None => false,
+            Some(x) => x.set_stale(path, stale),
+        }
+    }
     pub fn insert(
         &mut self,
         sess: BgpSessionId,
@@ -423,6 +605,60 @@ impl BgpSessionEntry {
         };
         pe.insert(path, when, atr)
     }
+    /// Counts announcement or change of the route replaced by record, duplicates are not counted
+    fn count_update(
+        &mut self,
+        sess: BgpSessionId,
+        path: BgpPathId,
+        atr: &BgpAttrEntry,
+        when: &Timestamp,
+    ) {
+        let ev = match self.get_last_attr(sess, path) {
+            Some(l) if l.active && l.attrs == atr.attrs => return,
+            Some(l) if l.active => FlapEvent::Change,
+            _ => FlapEvent::Announce,
+        };
+        // stable routes have no counters, they are created with the first withdrawal or change
+        match self.flaps.get_mut(&sess) {
+            Some(st) => st.record(ev, when),
+            None if ev == FlapEvent::Change => self.flaps_mut(sess, path).record(ev, when),
+            None => {}
+        }
+    }
+    fn count_withdraw(&mut self, sess: BgpSessionId, path: BgpPathId, when: &Timestamp) {
+        self.flaps_mut(sess, path).record(FlapEvent::Withdraw, when);
+    }
+    /// Flap counters of the session, new ones count announcement of the route which began to flap
+    fn flaps_mut(&mut self, sess: BgpSessionId, path: BgpPathId) -> &mut FlapStats {
+        if !self.flaps.contains_key(&sess) {
+            let mut st = FlapStats::default();
+            let announced = self
+                .items
+                .get(&sess)
+                .and_then(|pe| pe.items.get(&path))
+                .and_then(|ah| ah.announced());
+            if let Some(t) = announced {
+                st.record(FlapEvent::Announce, &t);
+            }
+            self.flaps.insert(sess, st);
+        }
+        self.flaps.get_mut(&sess).unwrap()
+    }
+    /// Drops flap counters without events within the longest ranking window,
+    /// imported sessions keep theirs as history has no current time
+    fn prune_flaps(&mut self, now: i64, imported: &BTreeSet<BgpSessionId>) {
+        self.flaps
+            .retain(|sess, st| imported.contains(sess) || !st.expired(now));
+    }
+    /// Origin AS of last attributes of the session route
+    pub fn origin(&self, sess: BgpSessionId) -> Option<u32> {
+        self.items
+            .get(&sess)?
+            .items
+            .values()
+            .filter_map(|ah| ah.items.values().next_back())
+            .find_map(|e| origin_as(&e.attrs.aspath))
+    }
 }
 pub struct BgpRIBSafi<T: BgpRIBKey> {
     pub log_size: usize,
@@ -433,6 +669,8 @@ pub struct BgpRIBSafi<T: BgpRIBKey> {
     pub idx_community: BgpRIBIndex<BgpCommunity, T>,
     pub idx_extcommunity: BgpRIBIndex<BgpExtCommunity, T>,
     pub idx_changed: BgpRIBIndex<Timestamp, T>,
+    /// routes advertised since Enhanced Route Refresh BoRR, per session
+    pub refreshing: BTreeMap<BgpSessionId, BTreeSet<(T, BgpPathId)>>,
 }
 impl<T: BgpRIBKey> BgpRIBSafi<T> {
     pub fn new(logsize: usize, historymode: HistoryChangeMode) -> BgpRIBSafi<T> {
@@ -445,6 +683,7 @@ impl<T: BgpRIBKey> BgpRIBSafi<T> {
             idx_community: BgpRIBIndex::new(),
             idx_extcommunity: BgpRIBIndex::new(),
             idx_changed: BgpRIBIndex::new(),
+            refreshing: BTreeMap::new(),
         }
     }
     pub fn from_config(cfg: &SvcConfig) -> BgpRIBSafi<T> {
@@ -457,10 +696,12 @@ impl<T: BgpRIBKey> BgpRIBSafi<T> {
             idx_community: BgpRIBIndex::new(),
             idx_extcommunity: BgpRIBIndex::new(),
             idx_changed: BgpRIBIndex::new(),
+            refreshing: BTreeMap::new(),
         }
     }
     pub fn clear(&mut self) {
         self.items.clear();
+        self.refreshing.clear();
         self.idx_aspath.clear();
         self.idx_community.clear();
         self.idx_extcommunity.clear();
@@ -581,11 +822,150 @@ impl<T: BgpRIBKey> BgpRIBSafi<T> {
         }
         ret
     }
-    pub fn handle_withdraws_afi(&mut self, session: BgpSessionId, v: &[T]) {
+    /// Count prefixes with an active last entry per session
+    pub fn count_session_prefixes(&self) -> BTreeMap<BgpSessionId, usize> {
+        let mut ret: BTreeMap<BgpSessionId, usize> = BTreeMap::new();
+        for hist in self.items.values() {
+            for (sess, pe) in hist.items.iter() {
+                if pe.items.values().any(|ah| {
+                    ah.items
+                        .values()
+                        .next_back()
+                        .map(|e| e.active)
+                        .unwrap_or(false)
+                }) {
+                    *ret.entry(*sess).or_insert(0) += 1;
+                }
+            }
+        }
+        ret
+    }
+    /// Active routes which carry the route target, looked up by route target index
+    pub fn rt_routes(&self, rt: &BgpExtCommunity) -> Vec<&T> {
+        let keys = match self.idx_extcommunity.idx.get(rt) {
+            None => return Vec::new(),
+            Some(k) => k,
+        };
+        keys.iter()
+            .filter(|k| {
+                self.items
+                    .get(*k)
+                    .map(|hist| {
+                        hist.items.values().any(|pe| {
+                            pe.items.values().any(|ah| {
+                                ah.items
+                                    .values()
+                                    .next_back()
+                                    .map(|e| e.active && e.attrs.extcomms.value.contains(rt))
+                                    .unwrap_or(false)
+                            })
+                        })
+                    })
+                    .unwrap_or(false)
+            })
+            .collect()
+    }
+    /// Mark all active routes of the session as withdrawn, returns affected keys
+    pub fn withdraw_session(&mut self, session: BgpSessionId) -> Vec<T> {
+        self.refreshing.remove(&session);
+        self.withdraw_session_except(session, None, false, Timestamp::now())
+    }
+    /// Marks all active routes of the session as stale after graceful restart, returns count
+    pub fn mark_session_stale(&mut self, session: BgpSessionId) -> usize {
+        self.refreshing.remove(&session);
+        let mut cnt = 0;
+        for hist in self.items.values_mut() {
+            if let Some(pe) = hist.items.get_mut(&session) {
+                for ah in pe.items.values_mut() {
+                    if ah.set_stale(true) {
+                        cnt += 1;
+                    }
+                }
+            }
+        }
+        cnt
+    }
+    /// Withdraws routes of the session which are still stale, returns affected keys
+    pub fn withdraw_stale(&mut self, session: BgpSessionId, now: Timestamp) -> Vec<T> {
+        self.withdraw_session_except(session, None, true, now)
+    }
+    /// Starts Enhanced Route Refresh, routes are collected until refresh_end
+    pub fn refresh_begin(&mut self, session: BgpSessionId) {
+        self.refreshing.insert(session, BTreeSet::new());
+    }
+    /// Finishes Enhanced Route Refresh, withdraws stale routes and returns affected keys
+    pub fn refresh_end(&mut self, session: BgpSessionId, now: Timestamp) -> Vec<T> {
+        match self.refreshing.remove(&session) {
+            None => Vec::new(),
+            Some(seen) => self.withdraw_session_except(session, Some(&seen), false, now),
+        }
+    }
+    fn withdraw_session_except(
+        &mut self,
+        session: BgpSessionId,
+        keep: Option<&BTreeSet<(T, BgpPathId)>>,
+        only_stale: bool,
+        now: Timestamp,
+    ) -> Vec<T> {
+        let mut ret = Vec::new();
+        for (i, hist) in self.items.iter_mut() {
+            let pe = match hist.items.get_mut(&session) {
+                None => continue,
+                Some(x) => x,
+            };
+            let mut found = Vec::new();
+            for (pathid, ah) in pe.items.iter_mut() {
+                if let Some(k) = keep {
+                    if k.contains(&(i.clone(), *pathid)) {
+                        continue;
+                    }
+                }
+                let lrec = match ah.get_last_attr() {
+                    Some(x) if x.active && (x.stale || !only_stale) => x,
+                    _ => continue,
+                };
+                ah.shrink_hist(self.log_size - 1);
+                ah.insert(now, BgpAttrEntry::new(false, lrec.attrs, lrec.labels));
+                found.push(*pathid);
+            }
+            if !found.is_empty() {
+                // routes of the session which went down are not flaps of the routes
+                if keep.is_some() || only_stale {
+                    for pathid in found {
+                        hist.count_withdraw(session, pathid, &now);
+                    }
+                }
+                ret.push(i.clone());
+            }
+        }
+        ret
+    }
+    /// Drops expired flap counters, including ones of withdrawn routes and gone sessions
+    pub fn prune_flaps(&mut self, now: i64, imported: &BTreeSet<BgpSessionId>) {
+        self.items
+            .values_mut()
+            .for_each(|hist| hist.prune_flaps(now, imported));
+    }
+    /// Adds flap counters of every route to ranking
+    pub fn rank_flaps(&self, ranking: &mut FlapRanking)
+    where
+        T: std::fmt::Display,
+    {
+        for hist in self.items.values() {
+            for (sess, stats) in hist.flaps.iter() {
+                ranking.observe(*sess, stats);
+            }
+        }
+        for (k, hist) in self.items.iter() {
+            for (sess, stats) in hist.flaps.iter() {
+                ranking.add(k, *sess, hist.origin(*sess), stats);
+            }
+        }
+    }
+    pub fn handle_withdraws_afi(&mut self, session: BgpSessionId, v: &[T], now: Timestamp) {
         if v.is_empty() {
             return;
         }
-        let now = Timestamp::now();
         for i in v.iter() {
             //TODO: indexes cleanup
             match self.items.get_mut(i) {
@@ -596,6 +976,9 @@ impl<T: BgpRIBKey> BgpRIBSafi<T> {
                         None => continue,
                         Some(x) => x,
                     };
+                    if lrec.active {
+                        hist.count_withdraw(session, 0, &now);
+                    }
                     match self.history_mode {
                         HistoryChangeMode::EveryUpdate => {
                             hist.insert(
@@ -620,11 +1003,16 @@ impl<T: BgpRIBKey> BgpRIBSafi<T> {
             }
         }
     }
-    pub fn handle_updates_afi(&mut self, session: BgpSessionId, v: &[T], rattr: Arc<BgpAttrs>) {
+    pub fn handle_updates_afi(
+        &mut self,
+        session: BgpSessionId,
+        v: &[T],
+        rattr: Arc<BgpAttrs>,
+        now: Timestamp,
+    ) {
         if v.is_empty() {
             return;
         }
-        let now = Timestamp::now();
         for i in v.iter() {
             for aspathitem in rattr.aspath.value.iter() {
                 match aspathitem {
@@ -651,15 +1039,20 @@ impl<T: BgpRIBKey> BgpRIBSafi<T> {
             }
             let cnow = now.cut_millis(self.timeidx_granularity * 1000);
             self.idx_changed.set(&cnow, i);
+            if let Some(seen) = self.refreshing.get_mut(&session) {
+                seen.insert((i.clone(), 0));
+            }
             let histrec = BgpAttrEntry::new(true, rattr.clone(), i.getlabels());
             match self.items.get_mut(i) {
                 None => {
                     let mut hist = BgpSessionEntry::new();
+                    hist.count_update(session, 0, &histrec, &now);
                     hist.insert(session, 0, now, histrec);
                     self.items.insert(i.clone(), hist);
                 }
                 Some(hist) => {
                     hist.shrink_hist(self.log_size - 1);
+                    hist.count_update(session, 0, &histrec, &now);
                     match self.history_mode {
                         HistoryChangeMode::EveryUpdate => {
                             hist.insert(session, 0, now, histrec);
@@ -672,6 +1065,8 @@ impl<T: BgpRIBKey> BgpRIBSafi<T> {
                                 Some(lrec) => {
                                     if !lrec.active || lrec.attrs != histrec.attrs {
                                         hist.insert(session, 0, now, histrec);
+                                    } else if lrec.stale {
+                                        hist.set_stale(session, 0, false);
                                     }
                                 }
                             };
@@ -681,11 +1076,15 @@ impl<T: BgpRIBKey> BgpRIBSafi<T> {
             };
         }
     }
-    pub fn handle_withdraws_afi_pathid(&mut self, session: BgpSessionId, v: &[WithPathId<T>]) {
+    pub fn handle_withdraws_afi_pathid(
+        &mut self,
+        session: BgpSessionId,
+        v: &[WithPathId<T>],
+        now: Timestamp,
+    ) {
         if v.is_empty() {
             return;
         }
-        let now = Timestamp::now();
         for i in v.iter() {
             //TODO: indexes cleanup
             match self.items.get_mut(&i.nlri) {
@@ -696,6 +1095,9 @@ impl<T: BgpRIBKey> BgpRIBSafi<T> {
                         None => continue,
                         Some(x) => x,
                     };
+                    if lrec.active {
+                        hist.count_withdraw(session, i.pathid, &now);
+                    }
                     match self.history_mode {
                         HistoryChangeMode::EveryUpdate => {
                             hist.insert(
@@ -729,6 +1131,7 @@ impl<T: BgpRIBKey> BgpRIBSafi<T> {
         session: BgpSessionId,
         v: &[WithPathId<T>],
         rattr: Arc<BgpAttrs>,
+        now: Timestamp,
     ) {
         if v.is_empty() {
             return;
@@ -758,17 +1161,21 @@ impl<T: BgpRIBKey> BgpRIBSafi<T> {
                 }
             }
             let histrec = BgpAttrEntry::new(true, rattr.clone(), i.nlri.getlabels());
-            let now = Timestamp::now();
             let cnow = now.cut_millis(self.timeidx_granularity * 1000);
             self.idx_changed.set(&cnow, &i.nlri);
+            if let Some(seen) = self.refreshing.get_mut(&session) {
+                seen.insert((i.nlri.clone(), i.pathid));
+            }
             match self.items.get_mut(&i.nlri) {
                 None => {
                     let mut hist = BgpSessionEntry::new();
+                    hist.count_update(session, i.pathid, &histrec, &now);
                     hist.insert(session, i.pathid, now, histrec);
                     self.items.insert(i.nlri.clone(), hist);
                 }
                 Some(hist) => {
                     hist.shrink_hist(self.log_size - 1);
+                    hist.count_update(session, i.pathid, &histrec, &now);
                     match self.history_mode {
                         HistoryChangeMode::EveryUpdate => {
                             hist.insert(session, i.pathid, now, histrec);
@@ -781,6 +1188,8 @@ impl<T: BgpRIBKey> BgpRIBSafi<T> {
                                 Some(lrec) => {
                                     if !lrec.active || lrec.attrs != histrec.attrs {
                                         hist.insert(session, i.pathid, now, histrec);
+                                    } else if lrec.stale {
+                                        hist.set_stale(session, i.pathid, false);
                                     }
                                 }
                             };
@@ -791,10 +1200,39 @@ impl<T: BgpRIBKey> BgpRIBSafi<T> {
         }
     }
 }
+pub enum BgpRibUpdate {
+    Update(BgpSessionId, BgpUpdateMessage, Timestamp),
+    SessionDown(BgpSessionId),
+    /// Enhanced Route Refresh BoRR (true) or EoRR (false) marker
+    RouteRefresh(BgpSessionId, BgpRibKind, bool, Timestamp),
+    /// Graceful restart of the peer, routes of listed RIBs are retained as stale
+    SessionStale(BgpSessionId, Vec<BgpRibKind>, Timestamp),
+    /// Restart or stale path timer expired for graceful restart started at timestamp
+    StaleTimeout(BgpSessionId, Timestamp),
+    /// Session established with previous sessions of the peer and its preserved RIBs
+    SessionRestart(BgpSessionId, Vec<BgpSessionId>, Option<Vec<BgpRibKind>>),
+    /// Local BGP Role and neighbor AS of the session for route leak detection
+    SessionRole(BgpSessionId, BgpRole, u32),
+    /// Session imported from MRT file, its updates are not analyzed for alerts
+    SessionImported(BgpSessionId),
+    /// UPDATE with Route Target membership NLRI extracted
+    Rtc(BgpSessionId, BgpUpdateMessage, RtcUpdate, Timestamp),
+    /// UPDATE with VPNv6 FlowSpec NLRI extracted
+    FsV6u(BgpSessionId, BgpUpdateMessage, FsV6uUpdate, Timestamp),
+    /// Complete VRP set of RPKI source
+    Vrps(VrpSource, Vec<Vrp>),
+    /// Complete ASPA records set
+    Aspas(Vec<Aspa>),
+    /// IRR lookup result for prefix and origin AS
+    Irr(IrrKey, IrrState),
+    /// Bogon list file entries and excluded entries
+    Bogons(Vec<BogonEntry>, Vec<BogonEntry>),
+}
 #[derive(Clone)]
 pub enum BgpEvent {
     Update(BgpSessionId, Arc<BgpAttrs>, Arc<BgpAddrs>),
     Withdraw(BgpSessionId, Arc<BgpAddrs>),
+    Alert(Arc<Alert>),
 }
 pub struct BgpRIB {
     pub pathes: RibItemStore<BgpASpath>,
@@ -803,6 +1241,7 @@ pub struct BgpRIB {
     pub extcomms: RibItemStore<BgpExtCommunityList>,
     pub clusters: RibItemStore<BgpClusterList>,
     pub pmsi_ta_s: RibItemStore<BgpPMSITunnel>,
+    pub rawattrs: RibItemStore<BgpRawAttrs>,
     pub attrs: RibItemStore<BgpAttrs>,
     pub ipv4u: BgpRIBSafi<BgpAddrV4>,
     pub ipv4m: BgpRIBSafi<BgpAddrV4>,
@@ -810,6 +1249,7 @@ pub struct BgpRIB {
     pub vpnv4u: BgpRIBSafi<Labeled<WithRd<BgpAddrV4>>>,
     pub vpnv4m: BgpRIBSafi<Labeled<WithRd<BgpAddrV4>>>,
     pub ipv6u: BgpRIBSafi<BgpAddrV6>,
+    pub ipv6m: BgpRIBSafi<BgpAddrV6>,
     pub ipv6lu: BgpRIBSafi<Labeled<BgpAddrV6>>,
     pub vpnv6u: BgpRIBSafi<Labeled<WithRd<BgpAddrV6>>>,
     pub vpnv6m: BgpRIBSafi<Labeled<WithRd<BgpAddrV6>>>,
@@ -817,10 +1257,32 @@ pub struct BgpRIB {
     pub mvpn: BgpRIBSafi<BgpMVPN>,
     pub evpn: BgpRIBSafi<BgpEVPN>,
     pub fs4u: BgpRIBSafi<BgpFlowSpec<BgpAddrV4>>,
+    pub fs6u: BgpRIBSafi<BgpFlowSpec<FS6>>,
+    pub fsv4u: BgpRIBSafi<BgpFlowSpec<FSV4U>>,
     pub ipv4mdt: BgpRIBSafi<WithRd<BgpMdtV4>>,
     pub ipv6mdt: BgpRIBSafi<WithRd<BgpMdtV6>>,
+    pub rtc: BgpRIBSafi<BgpRtc>,
+    pub fsv6u: BgpRIBSafi<BgpFlowSpecVpn6>,
     pub cnt_updates: u64,
     pub cnt_withdraws: u64,
+    /// End-of-RIB receive time per session and RIB
+    pub synced: BTreeMap<BgpSessionId, BTreeMap<BgpRibKind, Timestamp>>,
+    /// Graceful restart start time for sessions with stale routes
+    pub stale: BTreeMap<BgpSessionId, Timestamp>,
+    /// Local BGP Role and neighbor AS per session
+    pub roles: BTreeMap<BgpSessionId, (BgpRole, u32)>,
+    /// sessions imported from MRT files, their updates are not analyzed
+    pub imported: BTreeSet<BgpSessionId>,
+    /// VRPs for origin validation of unicast routes
+    pub vrps: VrpTable,
+    /// ASPA records for AS path verification
+    pub aspas: AspaTable,
+    /// IRR validation results
+    pub irr: IrrTable,
+    /// Bogon prefixes and AS numbers for unicast routes check
+    pub bogons: BogonTable,
+    /// Possible hijacks detected on updates
+    pub alerts: AlertLog,
     pub events: broadcast::Sender<BgpEvent>,
     cnt_purge: u64,
     purge_after_withdraws: u64,
@@ -844,6 +1306,7 @@ impl BgpRIB {
             extcomms: RibItemStore::new(),
             clusters: RibItemStore::new(),
             pmsi_ta_s: RibItemStore::new(),
+            rawattrs: RibItemStore::new(),
             attrs: RibItemStore::new(),
             ipv4u: BgpRIBSafi::from_config(cfg),
             ipv4m: BgpRIBSafi::from_config(cfg),
@@ -851,6 +1314,7 @@ impl BgpRIB {
             vpnv4u: BgpRIBSafi::from_config(cfg),
             vpnv4m: BgpRIBSafi::from_config(cfg),
             ipv6u: BgpRIBSafi::from_config(cfg),
+            ipv6m: BgpRIBSafi::from_config(cfg),
             ipv6lu: BgpRIBSafi::from_config(cfg),
             vpnv6u: BgpRIBSafi::from_config(cfg),
             vpnv6m: BgpRIBSafi::from_config(cfg),
@@ -858,10 +1322,29 @@ impl BgpRIB {
             mvpn: BgpRIBSafi::from_config(cfg),
             evpn: BgpRIBSafi::from_config(cfg),
             fs4u: BgpRIBSafi::from_config(cfg),
+            fs6u: BgpRIBSafi::from_config(cfg),
+            fsv4u: BgpRIBSafi::from_config(cfg),
             ipv4mdt: BgpRIBSafi::from_config(cfg),
             ipv6mdt: BgpRIBSafi::from_config(cfg),
+            rtc: BgpRIBSafi::from_config(cfg),
+            fsv6u: BgpRIBSafi::from_config(cfg),
             cnt_updates: 0,
             cnt_withdraws: 0,
+            synced: BTreeMap::new(),
+            stale: BTreeMap::new(),
+            roles: BTreeMap::new(),
+            imported: BTreeSet::new(),
+            vrps: VrpTable::new(cfg.rpki_rtr.is_some() || cfg.rpki_file.is_some()),
+            aspas: AspaTable::new(cfg.aspa_file.is_some()),
+            irr: IrrTable::new(
+                cfg.irr_rate > 0,
+                std::time::Duration::from_secs(cfg.irr_recheck),
+            ),
+            bogons: BogonTable::new(
+                cfg.bogon_check || cfg.bogon_file.is_some(),
+                cfg.bogon_builtin,
+            ),
+            alerts: AlertLog::new(cfg.owned_prefixes.clone(), cfg.alerts_file.clone()),
             events: tx,
             cnt_purge: 0,
             purge_after_withdraws: cfg.purge_after_withdraws,
@@ -874,11 +1357,14 @@ impl BgpRIB {
     }
     pub fn purge(&mut self) {
         self.attrs.purge();
+        self.rawattrs.purge();
         self.clusters.purge();
         self.extcomms.purge();
         self.lcomms.purge();
         self.comms.purge();
         self.pathes.purge();
+        let now = Timestamp::now().timestamp();
+        for_each_safi!(self, safi => safi.prune_flaps(now, &self.imported));
         if self.purge_after_withdraws > 0 {
             self.cnt_purge = self.cnt_withdraws / self.purge_after_withdraws;
         };
@@ -919,12 +1405,20 @@ impl BgpRIB {
         ciborium::ser::into_writer(&self.fs4u.items, file.by_ref())?;
         ciborium::ser::into_writer(&self.ipv4mdt.items, file.by_ref())?;
         ciborium::ser::into_writer(&self.ipv6mdt.items, file.by_ref())?;
+        ciborium::ser::into_writer(&self.ipv6m.items, file.by_ref())?;
+        ciborium::ser::into_writer(&self.fs6u.items, file.by_ref())?;
+        ciborium::ser::into_writer(&self.fsv4u.items, file.by_ref())?;
+        ciborium::ser::into_writer(&self.rtc.items, file.by_ref())?;
+        ciborium::ser::into_writer(&self.fsv6u.items, file.by_ref())?;
         Ok(())
     }
     pub async fn shutdown(&self) {
         if let Err(e) = self.store_snapshot() {
             warn!("store_snapshot error on shutdown: {}", e);
         }
+        if let Err(e) = self.alerts.store() {
+            warn!("Alerts store error on shutdown: {}", e);
+        }
     }
     pub fn store_snapshot(&self) -> std::io::Result<()> {
         if self.snapshot_file.is_none() {
@@ -961,9 +1455,15 @@ impl BgpRIB {
         self.mvpn.clear();
         self.evpn.clear();
         self.fs4u.clear();
+        self.fs6u.clear();
+        self.fsv4u.clear();
+        self.ipv6m.clear();
         self.ipv4mdt.clear();
         self.ipv6mdt.clear();
+        self.rtc.clear();
+        self.fsv6u.clear();
         self.attrs.clear();
+        self.rawattrs.clear();
         self.clusters.clear();
         self.extcomms.clear();
         self.lcomms.clear();
@@ -995,6 +1495,12 @@ impl BgpRIB {
         let fs4u = ciborium::de::from_reader(&mut fl)?;
         let ipv4mdt = ciborium::de::from_reader(&mut fl)?;
         let ipv6mdt = ciborium::de::from_reader(&mut fl)?;
+        // older snapshots end here
+        let ipv6m = ciborium::de::from_reader(&mut fl).ok();
+        let fs6u = ciborium::de::from_reader(&mut fl).ok();
+        let fsv4u = ciborium::de::from_reader(&mut fl).ok();
+        let rtc = ciborium::de::from_reader(&mut fl).ok();
+        let fsv6u = ciborium::de::from_reader(&mut fl).ok();
         let mut rib = rib_take();
         rib.ipv4u.assign(ipv4u);
         rib.ipv4m.assign(ipv4m);
@@ -1011,35 +1517,371 @@ impl BgpRIB {
         rib.fs4u.assign(fs4u);
         rib.ipv4mdt.assign(ipv4mdt);
         rib.ipv6mdt.assign(ipv6mdt);
+        if let Some(v) = ipv6m {
+            rib.ipv6m.assign(v);
+        }
+        if let Some(v) = fs6u {
+            rib.fs6u.assign(v);
+        }
+        if let Some(v) = fsv4u {
+            rib.fsv4u.assign(v);
+        }
+        if let Some(v) = rtc {
+            rib.rtc.assign(v);
+            rib.index_rtc();
+        }
+        if let Some(v) = fsv6u {
+            rib.fsv6u.assign(v);
+        }
         Ok(rib)
     }
-    pub fn handle_withdraws(&mut self, session: BgpSessionId, withdraws: BgpAddrs) {
+    fn add_session_prefixes<T: BgpRIBKey>(
+        ret: &mut BTreeMap<BgpSessionId, BTreeMap<BgpRibKind, usize>>,
+        kind: BgpRibKind,
+        safi: &BgpRIBSafi<T>,
+    ) {
+        for (sess, cnt) in safi.count_session_prefixes().into_iter() {
+            ret.entry(sess).or_default().insert(kind, cnt);
+        }
+    }
+    /// Active prefixes count for each session and RIB
+    pub fn session_prefixes(&self) -> BTreeMap<BgpSessionId, BTreeMap<BgpRibKind, usize>> {
+        let mut ret = BTreeMap::new();
+        BgpRIB::add_session_prefixes(&mut ret, BgpRibKind::IpV4u, &self.ipv4u);
+        BgpRIB::add_session_prefixes(&mut ret, BgpRibKind::IpV4m, &self.ipv4m);
+        BgpRIB::add_session_prefixes(&mut ret, BgpRibKind::IpV4LU, &self.ipv4lu);
+        BgpRIB::add_session_prefixes(&mut ret, BgpRibKind::VpnV4u, &self.vpnv4u);
+        BgpRIB::add_session_prefixes(&mut ret, BgpRibKind::VpnV4m, &self.vpnv4m);
+        BgpRIB::add_session_prefixes(&mut ret, BgpRibKind::IpV6u, &self.ipv6u);
+        BgpRIB::add_session_prefixes(&mut ret, BgpRibKind::IpV6m, &self.ipv6m);
+        BgpRIB::add_session_prefixes(&mut ret, BgpRibKind::IpV6LU, &self.ipv6lu);
+        BgpRIB::add_session_prefixes(&mut ret, BgpRibKind::VpnV6u, &self.vpnv6u);
+        BgpRIB::add_session_prefixes(&mut ret, BgpRibKind::VpnV6m, &self.vpnv6m);
+        BgpRIB::add_session_prefixes(&mut ret, BgpRibKind::L2vpls, &self.l2vpls);
+        BgpRIB::add_session_prefixes(&mut ret, BgpRibKind::MVpn, &self.mvpn);
+        BgpRIB::add_session_prefixes(&mut ret, BgpRibKind::EVpn, &self.evpn);
+        BgpRIB::add_session_prefixes(&mut ret, BgpRibKind::Fs4u, &self.fs4u);
+        BgpRIB::add_session_prefixes(&mut ret, BgpRibKind::Fs6u, &self.fs6u);
+        BgpRIB::add_session_prefixes(&mut ret, BgpRibKind::FsV4u, &self.fsv4u);
+        BgpRIB::add_session_prefixes(&mut ret, BgpRibKind::IpV4mdt, &self.ipv4mdt);
+        BgpRIB::add_session_prefixes(&mut ret, BgpRibKind::Ipv6mdt, &self.ipv6mdt);
+        BgpRIB::add_session_prefixes(&mut ret, BgpRibKind::Rtc, &self.rtc);
+        BgpRIB::add_session_prefixes(&mut ret, BgpRibKind::FsV6u, &self.fsv6u);
+        ret
+    }
+    fn publish_withdraws(&mut self, session: BgpSessionId, withdraws: BgpAddrs) -> usize {
+        let cnt = withdraws.len();
+        if cnt > 0 && self.events.receiver_count() > 0 {
+            if let Err(e) = self
+                .events
+                .send(BgpEvent::Withdraw(session, Arc::new(withdraws)))
+            {
+                warn!("Publish withdraw event error: {}", e);
+            }
+        }
+        cnt
+    }
+    /// Implicit withdraw of everything learned from the session
+    pub fn handle_session_down(&mut self, session: BgpSessionId) {
+        let mut cnt: usize = 0;
+        let v = self.ipv4u.withdraw_session(session);
+        cnt += self.publish_withdraws(session, BgpAddrs::IPV4U(v));
+        let v = self.ipv4m.withdraw_session(session);
+        cnt += self.publish_withdraws(session, BgpAddrs::IPV4M(v));
+        let v = self.ipv4lu.withdraw_session(session);
+        cnt += self.publish_withdraws(session, BgpAddrs::IPV4LU(v));
+        let v = self.vpnv4u.withdraw_session(session);
+        cnt += self.publish_withdraws(session, BgpAddrs::VPNV4U(v));
+        let v = self.vpnv4m.withdraw_session(session);
+        cnt += self.publish_withdraws(session, BgpAddrs::VPNV4M(v));
+        let v = self.ipv6u.withdraw_session(session);
+        cnt += self.publish_withdraws(session, BgpAddrs::IPV6U(v));
+        let v = self.ipv6m.withdraw_session(session);
+        cnt += self.publish_withdraws(session, BgpAddrs::IPV6M(v));
+        let v = self.ipv6lu.withdraw_session(session);
+        cnt += self.publish_withdraws(session, BgpAddrs::IPV6LU(v));
+        let v = self.vpnv6u.withdraw_session(session);
+        cnt += self.publish_withdraws(session, BgpAddrs::VPNV6U(v));
+        let v = self.vpnv6m.withdraw_session(session);
+        cnt += self.publish_withdraws(session, BgpAddrs::VPNV6M(v));
+        let v = self.l2vpls.withdraw_session(session);
+        cnt += self.publish_withdraws(session, BgpAddrs::L2VPLS(v));
+        let v = self.mvpn.withdraw_session(session);
+        cnt += self.publish_withdraws(session, BgpAddrs::MVPN(v));
+        let v = self.evpn.withdraw_session(session);
+        cnt += self.publish_withdraws(session, BgpAddrs::EVPN(v));
+        let v = self.fs4u.withdraw_session(session);
+        cnt += self.publish_withdraws(session, BgpAddrs::FS4U(v));
+        let v = self.fs6u.withdraw_session(session);
+        cnt += self.publish_withdraws(session, BgpAddrs::FS6U(v));
+        let v = self.fsv4u.withdraw_session(session);
+        cnt += self.publish_withdraws(session, BgpAddrs::FSV4U(v));
+        let v = self.ipv4mdt.withdraw_session(session);
+        cnt += self.publish_withdraws(session, BgpAddrs::IPV4MDT(v));
+        let v = self.ipv6mdt.withdraw_session(session);
+        cnt += self.publish_withdraws(session, BgpAddrs::IPV6MDT(v));
+        // route target membership and VPNv6 FlowSpec have no BgpAddrs kind to publish
+        cnt += self.rtc.withdraw_session(session).len();
+        cnt += self.fsv6u.withdraw_session(session).len();
+        info!("Session {} down, {} routes withdrawn", session, cnt);
+        self.cnt_withdraws += cnt as u64;
+        self.synced.remove(&session);
+        self.stale.remove(&session);
+        self.roles.remove(&session);
+        self.imported.remove(&session);
+    }
+    pub fn handle_session_role(&mut self, session: BgpSessionId, role: BgpRole, peer_as: u32) {
+        self.roles.insert(session, (role, peer_as));
+    }
+    fn mark_stale(&mut self, session: BgpSessionId, kind: BgpRibKind) -> usize {
+        match kind {
+            BgpRibKind::IpV4u => self.ipv4u.mark_session_stale(session),
+            BgpRibKind::IpV4m => self.ipv4m.mark_session_stale(session),
+            BgpRibKind::IpV4LU => self.ipv4lu.mark_session_stale(session),
+            BgpRibKind::VpnV4u => self.vpnv4u.mark_session_stale(session),
+            BgpRibKind::VpnV4m => self.vpnv4m.mark_session_stale(session),
+            BgpRibKind::IpV6u => self.ipv6u.mark_session_stale(session),
+            BgpRibKind::IpV6m => self.ipv6m.mark_session_stale(session),
+            BgpRibKind::IpV6LU => self.ipv6lu.mark_session_stale(session),
+            BgpRibKind::VpnV6u => self.vpnv6u.mark_session_stale(session),
+            BgpRibKind::VpnV6m => self.vpnv6m.mark_session_stale(session),
+            BgpRibKind::L2vpls => self.l2vpls.mark_session_stale(session),
+            BgpRibKind::MVpn => self.mvpn.mark_session_stale(session),
+            BgpRibKind::EVpn => self.evpn.mark_session_stale(session),
+            BgpRibKind::Fs4u => self.fs4u.mark_session_stale(session),
+            BgpRibKind::Fs6u => self.fs6u.mark_session_stale(session),
+            BgpRibKind::FsV4u => self.fsv4u.mark_session_stale(session),
+            BgpRibKind::IpV4mdt => self.ipv4mdt.mark_session_stale(session),
+            BgpRibKind::Ipv6mdt => self.ipv6mdt.mark_session_stale(session),
+            BgpRibKind::Rtc => self.rtc.mark_session_stale(session),
+            BgpRibKind::FsV6u => self.fsv6u.mark_session_stale(session),
+        }
+    }
+    fn withdraw_stale(
+        &mut self,
+        session: BgpSessionId,
+        kind: BgpRibKind,
+        when: Timestamp,
+    ) -> usize {
+        let withdraws = match kind {
+            BgpRibKind::IpV4u => BgpAddrs::IPV4U(self.ipv4u.withdraw_stale(session, when)),
+            BgpRibKind::IpV4m => BgpAddrs::IPV4M(self.ipv4m.withdraw_stale(session, when)),
+            BgpRibKind::IpV4LU => BgpAddrs::IPV4LU(self.ipv4lu.withdraw_stale(session, when)),
+            BgpRibKind::VpnV4u => BgpAddrs::VPNV4U(self.vpnv4u.withdraw_stale(session, when)),
+            BgpRibKind::VpnV4m => BgpAddrs::VPNV4M(self.vpnv4m.withdraw_stale(session, when)),
+            BgpRibKind::IpV6u => BgpAddrs::IPV6U(self.ipv6u.withdraw_stale(session, when)),
+            BgpRibKind::IpV6m => BgpAddrs::IPV6M(self.ipv6m.withdraw_stale(session, when)),
+            BgpRibKind::IpV6LU => BgpAddrs::IPV6LU(self.ipv6lu.withdraw_stale(session, when)),
+            BgpRibKind::VpnV6u => BgpAddrs::VPNV6U(self.vpnv6u.withdraw_stale(session, when)),
+            BgpRibKind::VpnV6m => BgpAddrs::VPNV6M(self.vpnv6m.withdraw_stale(session, when)),
+            BgpRibKind::L2vpls => BgpAddrs::L2VPLS(self.l2vpls.withdraw_stale(session, when)),
+            BgpRibKind::MVpn => BgpAddrs::MVPN(self.mvpn.withdraw_stale(session, when)),
+            BgpRibKind::EVpn => BgpAddrs::EVPN(self.evpn.withdraw_stale(session, when)),
+            BgpRibKind::Fs4u => BgpAddrs::FS4U(self.fs4u.withdraw_stale(session, when)),
+            BgpRibKind::Fs6u => BgpAddrs::FS6U(self.fs6u.withdraw_stale(session, when)),
+            BgpRibKind::FsV4u => BgpAddrs::FSV4U(self.fsv4u.withdraw_stale(session, when)),
+            BgpRibKind::IpV4mdt => BgpAddrs::IPV4MDT(self.ipv4mdt.withdraw_stale(session, when)),
+            BgpRibKind::Ipv6mdt => BgpAddrs::IPV6MDT(self.ipv6mdt.withdraw_stale(session, when)),
+            BgpRibKind::Rtc | BgpRibKind::FsV6u => BgpAddrs::None,
+        };
+        let cnt = match kind {
+            BgpRibKind::Rtc => self.rtc.withdraw_stale(session, when).len(),
+            BgpRibKind::FsV6u => self.fsv6u.withdraw_stale(session, when).len(),
+            _ => self.publish_withdraws(session, withdraws),
+        };
+        self.cnt_withdraws += cnt as u64;
+        cnt
+    }
+    /// Graceful restart of the peer: routes of retained RIBs are marked stale, others withdrawn
+    pub fn handle_session_stale(
+        &mut self,
+        session: BgpSessionId,
+        retain: &[BgpRibKind],
+        since: Timestamp,
+    ) {
+        let mut cnt: usize = 0;
+        let mut wcnt: usize = 0;
+        for kind in BgpRibKind::ALL {
+            cnt += self.mark_stale(session, kind);
+            if !retain.contains(&kind) {
+                wcnt += self.withdraw_stale(session, kind, since);
+            }
+        }
+        info!(
+            "Session {} restarting, {} routes marked stale, {} withdrawn",
+            session,
+            cnt - wcnt,
+            wcnt
+        );
+        self.synced.remove(&session);
+        self.stale.insert(session, since);
+    }
+    /// Withdraws remaining stale routes if graceful restart started at since is still pending
+    pub fn handle_stale_timeout(&mut self, session: BgpSessionId, since: Timestamp) {
+        if self.stale.get(&session) != Some(&since) {
+            return;
+        }
+        self.stale.remove(&session);
+        let mut cnt: usize = 0;
+        for kind in BgpRibKind::ALL {
+            cnt += self.withdraw_stale(session, kind, Timestamp::now());
+        }
+        info!(
+            "Session {} graceful restart timeout, {} stale routes withdrawn",
+            session, cnt
+        );
+    }
+    /// RIBs with stale routes to flush when previous session is re-established,
+    /// all of them unless the same session has forwarding state preserved
+    pub fn restart_flush(same: bool, forwarding: Option<&[BgpRibKind]>) -> Vec<BgpRibKind> {
+        match forwarding {
+            Some(f) if same => BgpRibKind::ALL
+                .iter()
+                .filter(|k| !f.contains(k))
+                .cloned()
+                .collect(),
+            _ => BgpRibKind::ALL.to_vec(),
+        }
+    }
+    /// Re-established session, stale routes of previous sessions without preserved
+    /// forwarding state are withdrawn at once (RFC 4724 section 4.2)
+    pub fn handle_session_restart(
+        &mut self,
+        session: BgpSessionId,
+        previous: &[BgpSessionId],
+        forwarding: Option<&[BgpRibKind]>,
+    ) {
+        for prev in previous.iter() {
+            if !self.stale.contains_key(prev) {
+                continue;
+            }
+            let flush = BgpRIB::restart_flush(*prev == session, forwarding);
+            let mut cnt: usize = 0;
+            for kind in flush.iter() {
+                cnt += self.withdraw_stale(*prev, *kind, Timestamp::now());
+            }
+            if flush.len() == BgpRibKind::ALL.len() {
+                self.stale.remove(prev);
+            }
+            info!(
+                "Session {} re-established, {} stale routes of session {} withdrawn",
+                session, cnt, prev
+            );
+        }
+    }
+    /// Enhanced Route Refresh markers, EoRR withdraws routes not re-advertised since BoRR
+    pub fn handle_route_refresh(
+        &mut self,
+        session: BgpSessionId,
+        kind: BgpRibKind,
+        begin: bool,
+        when: Timestamp,
+    ) {
+        if begin {
+            match kind {
+                BgpRibKind::IpV4u => self.ipv4u.refresh_begin(session),
+                BgpRibKind::IpV4m => self.ipv4m.refresh_begin(session),
+                BgpRibKind::IpV4LU => self.ipv4lu.refresh_begin(session),
+                BgpRibKind::VpnV4u => self.vpnv4u.refresh_begin(session),
+                BgpRibKind::VpnV4m => self.vpnv4m.refresh_begin(session),
+                BgpRibKind::IpV6u => self.ipv6u.refresh_begin(session),
+                BgpRibKind::IpV6m => self.ipv6m.refresh_begin(session),
+                BgpRibKind::IpV6LU => self.ipv6lu.refresh_begin(session),
+                BgpRibKind::VpnV6u => self.vpnv6u.refresh_begin(session),
+                BgpRibKind::VpnV6m => self.vpnv6m.refresh_begin(session),
+                BgpRibKind::L2vpls => self.l2vpls.refresh_begin(session),
+                BgpRibKind::MVpn => self.mvpn.refresh_begin(session),
+                BgpRibKind::EVpn => self.evpn.refresh_begin(session),
+                BgpRibKind::Fs4u => self.fs4u.refresh_begin(session),
+                BgpRibKind::Fs6u => self.fs6u.refresh_begin(session),
+                BgpRibKind::FsV4u => self.fsv4u.refresh_begin(session),
+                BgpRibKind::IpV4mdt => self.ipv4mdt.refresh_begin(session),
+                BgpRibKind::Ipv6mdt => self.ipv6mdt.refresh_begin(session),
+                BgpRibKind::Rtc => self.rtc.refresh_begin(session),
+                BgpRibKind::FsV6u => self.fsv6u.refresh_begin(session),
+            }
+            return;
+        }
+        let withdraws = match kind {
+            BgpRibKind::IpV4u => BgpAddrs::IPV4U(self.ipv4u.refresh_end(session, when)),
+            BgpRibKind::IpV4m => BgpAddrs::IPV4M(self.ipv4m.refresh_end(session, when)),
+            BgpRibKind::IpV4LU => BgpAddrs::IPV4LU(self.ipv4lu.refresh_end(session, when)),
+            BgpRibKind::VpnV4u => BgpAddrs::VPNV4U(self.vpnv4u.refresh_end(session, when)),
+            BgpRibKind::VpnV4m => BgpAddrs::VPNV4M(self.vpnv4m.refresh_end(session, when)),
+            BgpRibKind::IpV6u => BgpAddrs::IPV6U(self.ipv6u.refresh_end(session, when)),
+            BgpRibKind::IpV6m => BgpAddrs::IPV6M(self.ipv6m.refresh_end(session, when)),
+            BgpRibKind::IpV6LU => BgpAddrs::IPV6LU(self.ipv6lu.refresh_end(session, when)),
+            BgpRibKind::VpnV6u => BgpAddrs::VPNV6U(self.vpnv6u.refresh_end(session, when)),
+            BgpRibKind::VpnV6m => BgpAddrs::VPNV6M(self.vpnv6m.refresh_end(session, when)),
+            BgpRibKind::L2vpls => BgpAddrs::L2VPLS(self.l2vpls.refresh_end(session, when)),
+            BgpRibKind::MVpn => BgpAddrs::MVPN(self.mvpn.refresh_end(session, when)),
+            BgpRibKind::EVpn => BgpAddrs::EVPN(self.evpn.refresh_end(session, when)),
+            BgpRibKind::Fs4u => BgpAddrs::FS4U(self.fs4u.refresh_end(session, when)),
+            BgpRibKind::Fs6u => BgpAddrs::FS6U(self.fs6u.refresh_end(session, when)),
+            BgpRibKind::FsV4u => BgpAddrs::FSV4U(self.fsv4u.refresh_end(session, when)),
+            BgpRibKind::IpV4mdt => BgpAddrs::IPV4MDT(self.ipv4mdt.refresh_end(session, when)),
+            BgpRibKind::Ipv6mdt => BgpAddrs::IPV6MDT(self.ipv6mdt.refresh_end(session, when)),
+            BgpRibKind::Rtc | BgpRibKind::FsV6u => BgpAddrs::None,
+        };
+        let cnt = match kind {
+            BgpRibKind::Rtc => self.rtc.refresh_end(session, when).len(),
+            BgpRibKind::FsV6u => self.fsv6u.refresh_end(session, when).len(),
+            _ => self.publish_withdraws(session, withdraws),
+        };
+        info!(
+            "Session {} {} route refresh done, {} stale routes withdrawn",
+            session, kind, cnt
+        );
+        self.cnt_withdraws += cnt as u64;
+    }
+    /// Detects End-of-RIB marker: empty UPDATE for IPv4 unicast or empty MP_UNREACH_NLRI
+    fn end_of_rib(upd: &BgpUpdateMessage) -> Option<BgpRibKind> {
+        if !upd.updates.is_empty() || !upd.withdraws.is_empty() {
+            return None;
+        }
+        match upd.attrs.as_slice() {
+            [] => Some(BgpRibKind::IpV4u),
+            [BgpAttrItem::MPWithdraws(n)] if n.addrs.is_empty() => {
+                BgpRibKind::from_bgp_addrs(&n.addrs)
+            }
+            _ => None,
+        }
+    }
+    pub fn handle_withdraws(
+        &mut self,
+        session: BgpSessionId,
+        withdraws: BgpAddrs,
+        when: Timestamp,
+    ) {
         match &withdraws {
-            BgpAddrs::IPV4U(v) => self.ipv4u.handle_withdraws_afi(session, v),
-            BgpAddrs::IPV4M(v) => self.ipv4m.handle_withdraws_afi(session, v),
-            BgpAddrs::IPV4LU(v) => self.ipv4lu.handle_withdraws_afi(session, v),
-            BgpAddrs::VPNV4U(v) => self.vpnv4u.handle_withdraws_afi(session, v),
-            BgpAddrs::VPNV4M(v) => self.vpnv4m.handle_withdraws_afi(session, v),
-            BgpAddrs::IPV6U(v) => self.ipv6u.handle_withdraws_afi(session, v),
-            BgpAddrs::IPV6LU(v) => self.ipv6lu.handle_withdraws_afi(session, v),
-            BgpAddrs::VPNV6U(v) => self.vpnv6u.handle_withdraws_afi(session, v),
-            BgpAddrs::VPNV6M(v) => self.vpnv6m.handle_withdraws_afi(session, v),
-            BgpAddrs::L2VPLS(v) => self.l2vpls.handle_withdraws_afi(session, v),
-            BgpAddrs::MVPN(v) => self.mvpn.handle_withdraws_afi(session, v),
-            BgpAddrs::EVPN(v) => self.evpn.handle_withdraws_afi(session, v),
-            BgpAddrs::FS4U(v) => self.fs4u.handle_withdraws_afi(session, v),
-            BgpAddrs::IPV4UP(v) => self.ipv4u.handle_withdraws_afi_pathid(session, v),
-            BgpAddrs::IPV4MP(v) => self.ipv4m.handle_withdraws_afi_pathid(session, v),
-            BgpAddrs::IPV4LUP(v) => self.ipv4lu.handle_withdraws_afi_pathid(session, v),
-            BgpAddrs::VPNV4UP(v) => self.vpnv4u.handle_withdraws_afi_pathid(session, v),
-            BgpAddrs::VPNV4MP(v) => self.vpnv4m.handle_withdraws_afi_pathid(session, v),
-            BgpAddrs::IPV6UP(v) => self.ipv6u.handle_withdraws_afi_pathid(session, v),
-            //BgpAddrs::IPV6MP(v) => self.ipv6m.handle_withdraws_afi_pathid(session, v),
-            BgpAddrs::IPV6LUP(v) => self.ipv6lu.handle_withdraws_afi_pathid(session, v),
-            BgpAddrs::VPNV6UP(v) => self.vpnv6u.handle_withdraws_afi_pathid(session, v),
-            BgpAddrs::VPNV6MP(v) => self.vpnv6m.handle_withdraws_afi_pathid(session, v),
-            BgpAddrs::IPV4MDT(v) => self.ipv4mdt.handle_withdraws_afi(session, v),
-            BgpAddrs::IPV6MDT(v) => self.ipv6mdt.handle_withdraws_afi(session, v),
+            BgpAddrs::IPV4U(v) => self.ipv4u.handle_withdraws_afi(session, v, when),
+            BgpAddrs::IPV4M(v) => self.ipv4m.handle_withdraws_afi(session, v, when),
+            BgpAddrs::IPV4LU(v) => self.ipv4lu.handle_withdraws_afi(session, v, when),
+            BgpAddrs::VPNV4U(v) => self.vpnv4u.handle_withdraws_afi(session, v, when),
+            BgpAddrs::VPNV4M(v) => self.vpnv4m.handle_withdraws_afi(session, v, when),
+            BgpAddrs::IPV6U(v) => self.ipv6u.handle_withdraws_afi(session, v, when),
+            BgpAddrs::IPV6M(v) => self.ipv6m.handle_withdraws_afi(session, v, when),
+            BgpAddrs::IPV6LU(v) => self.ipv6lu.handle_withdraws_afi(session, v, when),
+            BgpAddrs::VPNV6U(v) => self.vpnv6u.handle_withdraws_afi(session, v, when),
+            BgpAddrs::VPNV6M(v) => self.vpnv6m.handle_withdraws_afi(session, v, when),
+            BgpAddrs::L2VPLS(v) => self.l2vpls.handle_withdraws_afi(session, v, when),
+            BgpAddrs::MVPN(v) => self.mvpn.handle_withdraws_afi(session, v, when),
+            BgpAddrs::EVPN(v) => self.evpn.handle_withdraws_afi(session, v, when),
+            BgpAddrs::FS4U(v) => self.fs4u.handle_withdraws_afi(session, v, when),
+            BgpAddrs::FS6U(v) => self.fs6u.handle_withdraws_afi(session, v, when),
+            BgpAddrs::FSV4U(v) => self.fsv4u.handle_withdraws_afi(session, v, when),
+            BgpAddrs::IPV4UP(v) => self.ipv4u.handle_withdraws_afi_pathid(session, v, when),
+            BgpAddrs::IPV4MP(v) => self.ipv4m.handle_withdraws_afi_pathid(session, v, when),
+            BgpAddrs::IPV4LUP(v) => self.ipv4lu.handle_withdraws_afi_pathid(session, v, when),
+            BgpAddrs::VPNV4UP(v) => self.vpnv4u.handle_withdraws_afi_pathid(session, v, when),
+            BgpAddrs::VPNV4MP(v) => self.vpnv4m.handle_withdraws_afi_pathid(session, v, when),
+            BgpAddrs::IPV6UP(v) => self.ipv6u.handle_withdraws_afi_pathid(session, v, when),
+            BgpAddrs::IPV6MP(v) => self.ipv6m.handle_withdraws_afi_pathid(session, v, when),
+            BgpAddrs::IPV6LUP(v) => self.ipv6lu.handle_withdraws_afi_pathid(session, v, when),
+            BgpAddrs::VPNV6UP(v) => self.vpnv6u.handle_withdraws_afi_pathid(session, v, when),
+            BgpAddrs::VPNV6MP(v) => self.vpnv6m.handle_withdraws_afi_pathid(session, v, when),
+            BgpAddrs::IPV4MDT(v) => self.ipv4mdt.handle_withdraws_afi(session, v, when),
+            BgpAddrs::IPV6MDT(v) => self.ipv6mdt.handle_withdraws_afi(session, v, when),
             _ => {}
         };
         if self.events.receiver_count() > 0 {
@@ -1056,34 +1898,77 @@ impl BgpRIB {
         session: BgpSessionId,
         rattr: Arc<BgpAttrs>,
         updates: BgpAddrs,
+        when: Timestamp,
     ) {
         let ra = rattr.clone();
+        if !self.imported.contains(&session) {
+            self.analyze_updates(session, &rattr, &updates, &when);
+        }
         match &updates {
-            BgpAddrs::IPV4U(v) => self.ipv4u.handle_updates_afi(session, v, rattr),
-            BgpAddrs::IPV4M(v) => self.ipv4m.handle_updates_afi(session, v, rattr),
-            BgpAddrs::IPV4LU(v) => self.ipv4lu.handle_updates_afi(session, v, rattr),
-            BgpAddrs::VPNV4U(v) => self.vpnv4u.handle_updates_afi(session, v, rattr),
-            BgpAddrs::VPNV4M(v) => self.vpnv4m.handle_updates_afi(session, v, rattr),
-            BgpAddrs::IPV6U(v) => self.ipv6u.handle_updates_afi(session, v, rattr),
-            BgpAddrs::IPV6LU(v) => self.ipv6lu.handle_updates_afi(session, v, rattr),
-            BgpAddrs::VPNV6U(v) => self.vpnv6u.handle_updates_afi(session, v, rattr),
-            BgpAddrs::VPNV6M(v) => self.vpnv6m.handle_updates_afi(session, v, rattr),
-            BgpAddrs::L2VPLS(v) => self.l2vpls.handle_updates_afi(session, v, rattr),
-            BgpAddrs::MVPN(v) => self.mvpn.handle_updates_afi(session, v, rattr),
-            BgpAddrs::EVPN(v) => self.evpn.handle_updates_afi(session, v, rattr),
-            BgpAddrs::FS4U(v) => self.fs4u.handle_updates_afi(session, v, rattr),
-            BgpAddrs::IPV4UP(v) => self.ipv4u.handle_updates_afi_pathid(session, v, rattr),
-            BgpAddrs::IPV4MP(v) => self.ipv4m.handle_updates_afi_pathid(session, v, rattr),
-            BgpAddrs::IPV4LUP(v) => self.ipv4lu.handle_updates_afi_pathid(session, v, rattr),
-            BgpAddrs::VPNV4UP(v) => self.vpnv4u.handle_updates_afi_pathid(session, v, rattr),
-            BgpAddrs::VPNV4MP(v) => self.vpnv4m.handle_updates_afi_pathid(session, v, rattr),
-            BgpAddrs::IPV6UP(v) => self.ipv6u.handle_updates_afi_pathid(session, v, rattr),
-            //BgpAddrs::IPV6MP(v) => self.ipv6m.handle_updates_afi_pathid(session, v, rattr),
-            BgpAddrs::IPV6LUP(v) => self.ipv6lu.handle_updates_afi_pathid(session, v, rattr),
-            BgpAddrs::VPNV6UP(v) => self.vpnv6u.handle_updates_afi_pathid(session, v, rattr),
-            BgpAddrs::VPNV6MP(v) => self.vpnv6m.handle_updates_afi_pathid(session, v, rattr),
-            BgpAddrs::IPV4MDT(v) => self.ipv4mdt.handle_updates_afi(session, v, rattr),
-            BgpAddrs::IPV6MDT(v) => self.ipv6mdt.handle_updates_afi(session, v, rattr),
+            BgpAddrs::IPV4U(v) => {
+                for (a, g) in self.validation_split(v, rattr, |p| (IpAddr::V4(p.addr), p.prefixlen))
+                {
+                    self.ipv4u.handle_updates_afi(session, &g, a, when);
+                }
+            }
+            BgpAddrs::IPV4M(v) => self.ipv4m.handle_updates_afi(session, v, rattr, when),
+            BgpAddrs::IPV4LU(v) => self.ipv4lu.handle_updates_afi(session, v, rattr, when),
+            BgpAddrs::VPNV4U(v) => self.vpnv4u.handle_updates_afi(session, v, rattr, when),
+            BgpAddrs::VPNV4M(v) => self.vpnv4m.handle_updates_afi(session, v, rattr, when),
+            BgpAddrs::IPV6U(v) => {
+                for (a, g) in self.validation_split(v, rattr, |p| (IpAddr::V6(p.addr), p.prefixlen))
+                {
+                    self.ipv6u.handle_updates_afi(session, &g, a, when);
+                }
+            }
+            BgpAddrs::IPV6M(v) => self.ipv6m.handle_updates_afi(session, v, rattr, when),
+            BgpAddrs::IPV6LU(v) => self.ipv6lu.handle_updates_afi(session, v, rattr, when),
+            BgpAddrs::VPNV6U(v) => self.vpnv6u.handle_updates_afi(session, v, rattr, when),
+            BgpAddrs::VPNV6M(v) => self.vpnv6m.handle_updates_afi(session, v, rattr, when),
+            BgpAddrs::L2VPLS(v) => self.l2vpls.handle_updates_afi(session, v, rattr, when),
+            BgpAddrs::MVPN(v) => self.mvpn.handle_updates_afi(session, v, rattr, when),
+            BgpAddrs::EVPN(v) => self.evpn.handle_updates_afi(session, v, rattr, when),
+            BgpAddrs::FS4U(v) => self.fs4u.handle_updates_afi(session, v, rattr, when),
+            BgpAddrs::FS6U(v) => self.fs6u.handle_updates_afi(session, v, rattr, when),
+            BgpAddrs::FSV4U(v) => self.fsv4u.handle_updates_afi(session, v, rattr, when),
+            BgpAddrs::IPV4UP(v) => {
+                let pfx = |p: &WithPathId<BgpAddrV4>| (IpAddr::V4(p.nlri.addr), p.nlri.prefixlen);
+                for (a, g) in self.validation_split(v, rattr, pfx) {
+                    self.ipv4u.handle_updates_afi_pathid(session, &g, a, when);
+                }
+            }
+            BgpAddrs::IPV4MP(v) => self
+                .ipv4m
+                .handle_updates_afi_pathid(session, v, rattr, when),
+            BgpAddrs::IPV4LUP(v) => self
+                .ipv4lu
+                .handle_updates_afi_pathid(session, v, rattr, when),
+            BgpAddrs::VPNV4UP(v) => self
+                .vpnv4u
+                .handle_updates_afi_pathid(session, v, rattr, when),
+            BgpAddrs::VPNV4MP(v) => self
+                .vpnv4m
+                .handle_updates_afi_pathid(session, v, rattr, when),
+            BgpAddrs::IPV6UP(v) => {
+                let pfx = |p: &WithPathId<BgpAddrV6>| (IpAddr::V6(p.nlri.addr), p.nlri.prefixlen);
+                for (a, g) in self.validation_split(v, rattr, pfx) {
+                    self.ipv6u.handle_updates_afi_pathid(session, &g, a, when);
+                }
+            }
+            BgpAddrs::IPV6MP(v) => self
+                .ipv6m
+                .handle_updates_afi_pathid(session, v, rattr, when),
+            BgpAddrs::IPV6LUP(v) => self
+                .ipv6lu
+                .handle_updates_afi_pathid(session, v, rattr, when),
+            BgpAddrs::VPNV6UP(v) => self
+                .vpnv6u
+                .handle_updates_afi_pathid(session, v, rattr, when),
+            BgpAddrs::VPNV6MP(v) => self
+                .vpnv6m
+                .handle_updates_afi_pathid(session, v, rattr, when),
+            BgpAddrs::IPV4MDT(v) => self.ipv4mdt.handle_updates_afi(session, v, rattr, when),
+            BgpAddrs::IPV6MDT(v) => self.ipv6mdt.handle_updates_afi(session, v, rattr, when),
             _ => {}
         };
         if self.events.receiver_count() > 0 {
@@ -1095,17 +1980,573 @@ impl BgpRIB {
             }
         }
     }
+    /// Raises alerts for announced unicast prefixes before routes are updated
+    fn analyze_updates(
+        &mut self,
+        session: BgpSessionId,
+        rattr: &BgpAttrs,
+        updates: &BgpAddrs,
+        when: &Timestamp,
+    ) {
+        let origin = match origin_as(&rattr.aspath) {
+            Some(o) => o,
+            None => return,
+        };
+        let v4 = |a: IpAddr, l: u8| match a {
+            IpAddr::V4(a) => Some(BgpAddrV4::new(a, l)),
+            IpAddr::V6(_) => None,
+        };
+        let v6 = |a: IpAddr, l: u8| match a {
+            IpAddr::V6(a) => Some(BgpAddrV6::new(a, l)),
+            IpAddr::V4(_) => None,
+        };
+        // MOAS and sub-prefix alerts wait for initial table transfer to complete
+        let synced = |kind| {
+            self.synced
+                .get(&session)
+                .is_some_and(|s| s.contains_key(&kind))
+        };
+        let (synced4, synced6) = (synced(BgpRibKind::IpV4u), synced(BgpRibKind::IpV6u));
+        let mut found = Vec::new();
+        match updates {
+            BgpAddrs::IPV4U(v) => {
+                for p in v.iter() {
+                    let tmpl = Alert::new(
+                        *when,
+                        AlertKind::Owned,
+                        session,
+                        IpAddr::V4(p.addr),
+                        p.prefixlen,
+                        origin,
+                    );
+                    found.extend(BgpRIB::check_prefix(&self.ipv4u, 0, p, tmpl, synced4, v4));
+                }
+            }
+            BgpAddrs::IPV4UP(v) => {
+                for p in v.iter() {
+                    let tmpl = Alert::new(
+                        *when,
+                        AlertKind::Owned,
+                        session,
+                        IpAddr::V4(p.nlri.addr),
+                        p.nlri.prefixlen,
+                        origin,
+                    );
+                    found.extend(BgpRIB::check_prefix(
+                        &self.ipv4u,
+                        p.pathid,
+                        &p.nlri,
+                        tmpl,
+                        synced4,
+                        v4,
+                    ));
+                }
+            }
+            BgpAddrs::IPV6U(v) => {
+                for p in v.iter() {
+                    let tmpl = Alert::new(
+                        *when,
+                        AlertKind::Owned,
+                        session,
+                        IpAddr::V6(p.addr),
+                        p.prefixlen,
+                        origin,
+                    );
+                    found.extend(BgpRIB::check_prefix(&self.ipv6u, 0, p, tmpl, synced6, v6));
+                }
+            }
+            BgpAddrs::IPV6UP(v) => {
+                for p in v.iter() {
+                    let tmpl = Alert::new(
+                        *when,
+                        AlertKind::Owned,
+                        session,
+                        IpAddr::V6(p.nlri.addr),
+                        p.nlri.prefixlen,
+                        origin,
+                    );
+                    found.extend(BgpRIB::check_prefix(
+                        &self.ipv6u,
+                        p.pathid,
+                        &p.nlri,
+                        tmpl,
+                        synced6,
+                        v6,
+                    ));
+                }
+            }
+            _ => return,
+        }
+        for mut alert in found.into_iter() {
+            if alert.kind == AlertKind::Owned {
+                match self.alerts.owned_origins(alert.prefix, alert.prefixlen) {
+                    Some(o) if !o.contains(&alert.origin) => alert.expected = o.to_vec(),
+                    _ => continue,
+                }
+            }
+            if let Some(a) = self.alerts.raise(alert) {
+                if self.events.receiver_count() > 0 {
+                    if let Err(e) = self.events.send(BgpEvent::Alert(Arc::new(a))) {
+                        warn!("Publish alert event error: {}", e);
+                    }
+                }
+            }
+        }
+    }
+    /// Origins of active routes of prefix by session and path
+    fn active_origins<T: BgpRIBKey>(
+        safi: &BgpRIBSafi<T>,
+        key: &T,
+    ) -> Vec<(BgpSessionId, BgpPathId, u32)> {
+        let mut ret = Vec::new();
+        if let Some(hist) = safi.items.get(key) {
+            for (sess, pe) in hist.items.iter() {
+                for (pathid, ah) in pe.items.iter() {
+                    if let Some(e) = ah.items.values().next_back() {
+                        if e.active {
+                            if let Some(o) = origin_as(&e.attrs.aspath) {
+                                ret.push((*sess, *pathid, o));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        ret
+    }
+    /// Alerts for prefix announced with template origin, template itself is the last one
+    /// as owned prefix candidate which is checked by caller.
+    /// MOAS and sub-prefix are not checked until session End-of-RIB (synced)
+    fn check_prefix<T: BgpRIBKey + std::string::ToString>(
+        safi: &BgpRIBSafi<T>,
+        pathid: BgpPathId,
+        key: &T,
+        tmpl: Alert,
+        synced: bool,
+        prefix: impl Fn(IpAddr, u8) -> Option<T>,
+    ) -> Vec<Alert> {
+        let mut ret = Vec::new();
+        let mut own = None;
+        let mut others = BTreeSet::new();
+        for (sess, pid, o) in BgpRIB::active_origins(safi, key).into_iter() {
+            if sess == tmpl.session && pid == pathid {
+                own = Some(o);
+            } else {
+                others.insert(o);
+            }
+        }
+        match own {
+            Some(o) if o != tmpl.origin => {
+                let mut a = tmpl.clone();
+                a.kind = AlertKind::OriginChange;
+                a.expected = vec![o];
+                ret.push(a);
+            }
+            None if !synced => {}
+            None if !others.is_empty() && !others.contains(&tmpl.origin) => {
+                let mut a = tmpl.clone();
+                a.kind = AlertKind::Moas;
+                a.expected = others.into_iter().collect();
+                ret.push(a);
+            }
+            None if others.is_empty() => {
+                // new prefix, look for nearest covering one, default route is not counted
+                for len in (1..tmpl.prefixlen).rev() {
+                    let covering = match prefix(mask_addr(tmpl.prefix, len), len) {
+                        Some(c) => c,
+                        None => break,
+                    };
+                    let origins: BTreeSet<u32> = BgpRIB::active_origins(safi, &covering)
+                        .into_iter()
+                        .map(|(_, _, o)| o)
+                        .collect();
+                    if origins.is_empty() {
+                        continue;
+                    }
+                    if !origins.contains(&tmpl.origin) {
+                        let mut a = tmpl.clone();
+                        a.kind = AlertKind::SubPrefix;
+                        a.expected = origins.into_iter().collect();
+                        a.covering = Some(covering.to_string());
+                        ret.push(a);
+                    }
+                    break;
+                }
+            }
+            _ => {}
+        }
+        ret.push(tmpl);
+        ret
+    }
+    /// Attributes with RPKI, IRR and bogon states of each prefix, prefixes grouped by states
+    fn validation_split<T: Clone>(
+        &mut self,
+        v: &[T],
+        rattr: Arc<BgpAttrs>,
+        prefix: impl Fn(&T) -> (IpAddr, u8),
+    ) -> Vec<(Arc<BgpAttrs>, Vec<T>)> {
+        if !self.vrps.enabled && !self.irr.enabled && !self.bogons.enabled {
+            return vec![(rattr, v.to_vec())];
+        }
+        let origin = origin_as(&rattr.aspath);
+        let mut groups: BTreeMap<(Option<RpkiState>, Option<IrrState>, BogonFlags), Vec<T>> =
+            BTreeMap::new();
+        for i in v.iter() {
+            let (addr, len) = prefix(i);
+            let rpki = if self.vrps.enabled {
+                Some(self.vrps.validate(addr, len, origin))
+            } else {
+                None
+            };
+            let irr = origin.and_then(|o| {
+                self.irr.enqueue((addr, len, o));
+                self.irr.get(&(addr, len, o))
+            });
+            let bogon = if self.bogons.enabled {
+                self.bogons.check(addr, len, &rattr.aspath, &rattr.nexthop)
+            } else {
+                BogonFlags::default()
+            };
+            groups
+                .entry((rpki, irr, bogon))
+                .or_default()
+                .push(i.clone());
+        }
+        let mut ret = Vec::with_capacity(groups.len());
+        for ((rpki, irr, bogon), g) in groups.into_iter() {
+            if rattr.rpki == rpki && rattr.irr == irr && rattr.bogon == bogon {
+                ret.push((rattr.clone(), g));
+                continue;
+            }
+            let mut attr = rattr.as_ref().clone();
+            attr.rpki = rpki;
+            attr.irr = irr;
+            attr.bogon = bogon;
+            match BgpRIB::register_shared(&mut self.attrs, &attr) {
+                Ok(a) => ret.push((a, g)),
+                Err(e) => {
+                    warn!("Validation attributes register error: {}", e);
+                    ret.push((rattr.clone(), g));
+                }
+            }
+        }
+        ret
+    }
+    /// Updates validation state of last active attributes in place, withdrawn routes keep their state
+    fn revalidate<T: BgpRIBKey>(
+        safi: &mut BgpRIBSafi<T>,
+        attrs: &mut RibItemStore<BgpAttrs>,
+        vrps: &VrpTable,
+        prefix: impl Fn(&T) -> (IpAddr, u8),
+    ) -> usize {
+        let mut cnt: usize = 0;
+        for (k, hist) in safi.items.iter_mut() {
+            let (addr, len) = prefix(k);
+            for pe in hist.items.values_mut() {
+                for ah in pe.items.values_mut() {
+                    let e = match ah.items.values_mut().next_back() {
+                        Some(e) if e.active => e,
+                        _ => continue,
+                    };
+                    let state = Some(vrps.validate(addr, len, origin_as(&e.attrs.aspath)));
+                    if e.attrs.rpki == state {
+                        continue;
+                    }
+                    let mut attr = e.attrs.as_ref().clone();
+                    attr.rpki = state;
+                    match BgpRIB::register_shared(attrs, &attr) {
+                        Ok(a) => {
+                            e.attrs = a;
+                            cnt += 1;
+                        }
+                        Err(e) => warn!("RPKI attributes register error: {}", e),
+                    }
+                }
+            }
+        }
+        cnt
+    }
+    /// Replaces VRP set of the source and re-evaluates unicast routes
+    pub fn handle_vrps(&mut self, source: VrpSource, vrps: Vec<Vrp>) {
+        if !self.vrps.set(source, vrps) {
+            return;
+        }
+        let cnt = BgpRIB::revalidate(&mut self.ipv4u, &mut self.attrs, &self.vrps, |p| {
+            (IpAddr::V4(p.addr), p.prefixlen)
+        }) + BgpRIB::revalidate(&mut self.ipv6u, &mut self.attrs, &self.vrps, |p| {
+            (IpAddr::V6(p.addr), p.prefixlen)
+        });
+        info!(
+            "RPKI {:?} VRPs changed, {} VRPs total, {} routes revalidated",
+            source,
+            self.vrps.len(),
+            cnt
+        );
+    }
+    /// Updates AS path verification state of last active attributes in place
+    fn reverify<T: BgpRIBKey>(
+        safi: &mut BgpRIBSafi<T>,
+        attrs: &mut RibItemStore<BgpAttrs>,
+        aspas: &AspaTable,
+        roles: &BTreeMap<BgpSessionId, (BgpRole, u32)>,
+    ) -> usize {
+        let mut cnt: usize = 0;
+        for hist in safi.items.values_mut() {
+            for (sess, pe) in hist.items.iter_mut() {
+                let downstream = match roles.get(sess) {
+                    None => continue,
+                    Some((role, _)) => role.aspa_downstream(),
+                };
+                for ah in pe.items.values_mut() {
+                    let e = match ah.items.values_mut().next_back() {
+                        Some(e) if e.active => e,
+                        _ => continue,
+                    };
+                    let state = Some(aspas.verify(&e.attrs.aspath, downstream));
+                    if e.attrs.aspa == state {
+                        continue;
+                    }
+                    let mut attr = e.attrs.as_ref().clone();
+                    attr.aspa = state;
+                    match BgpRIB::register_shared(attrs, &attr) {
+                        Ok(a) => {
+                            e.attrs = a;
+                            cnt += 1;
+                        }
+                        Err(e) => warn!("ASPA attributes register error: {}", e),
+                    }
+                }
+            }
+        }
+        cnt
+    }
+    /// Replaces ASPA records and re-verifies routes of sessions with known role
+    pub fn handle_aspas(&mut self, aspas: Vec<Aspa>) {
+        if !self.aspas.set(aspas) {
+            return;
+        }
+        let mut cnt: usize = 0;
+        for_each_safi!(self, safi => {
+            cnt += BgpRIB::reverify(safi, &mut self.attrs, &self.aspas, &self.roles)
+        });
+        info!(
+            "ASPA records changed, {} customer ASes, {} routes re-verified",
+            self.aspas.len(),
+            cnt
+        );
+    }
+    /// Updates bogon flags of last active attributes in place, withdrawn routes keep their flags
+    fn recheck_bogons<T: BgpRIBKey>(
+        safi: &mut BgpRIBSafi<T>,
+        attrs: &mut RibItemStore<BgpAttrs>,
+        bogons: &BogonTable,
+        prefix: impl Fn(&T) -> (IpAddr, u8),
+    ) -> usize {
+        let mut cnt: usize = 0;
+        for (k, hist) in safi.items.iter_mut() {
+            let (addr, len) = prefix(k);
+            for pe in hist.items.values_mut() {
+                for ah in pe.items.values_mut() {
+                    let e = match ah.items.values_mut().next_back() {
+                        Some(e) if e.active => e,
+                        _ => continue,
+                    };
+                    let flags = bogons.check(addr, len, &e.attrs.aspath, &e.attrs.nexthop);
+                    if e.attrs.bogon == flags {
+                        continue;
+                    }
+                    let mut attr = e.attrs.as_ref().clone();
+                    attr.bogon = flags;
+                    match BgpRIB::register_shared(attrs, &attr) {
+                        Ok(a) => {
+                            e.attrs = a;
+                            cnt += 1;
+                        }
+                        Err(e) => warn!("Bogon attributes register error: {}", e),
+                    }
+                }
+            }
+        }
+        cnt
+    }
+    /// Replaces bogon list file entries and re-checks unicast routes
+    pub fn handle_bogons(&mut self, entries: Vec<BogonEntry>, excluded: Vec<BogonEntry>) {
+        if !self.bogons.set(entries, excluded) {
+            return;
+        }
+        let cnt =
+            BgpRIB::recheck_bogons(&mut self.ipv4u, &mut self.attrs, &self.bogons, |p| {
+                (IpAddr::V4(p.addr), p.prefixlen)
+            }) + BgpRIB::recheck_bogons(&mut self.ipv6u, &mut self.attrs, &self.bogons, |p| {
+                (IpAddr::V6(p.addr), p.prefixlen)
+            });
+        info!(
+            "Bogon list changed, {} entries total, {} routes re-checked",
+            self.bogons.len(),
+            cnt
+        );
+    }
+    /// Sets IRR state of active routes of the prefix with the origin
+    fn apply_irr(
+        hist: &mut BgpSessionEntry,
+        attrs: &mut RibItemStore<BgpAttrs>,
+        origin: u32,
+        state: IrrState,
+    ) -> usize {
+        let mut cnt: usize = 0;
+        for pe in hist.items.values_mut() {
+            for ah in pe.items.values_mut() {
+                let e = match ah.items.values_mut().next_back() {
+                    Some(e) if e.active => e,
+                    _ => continue,
+                };
+                if e.attrs.irr == Some(state) || origin_as(&e.attrs.aspath) != Some(origin) {
+                    continue;
+                }
+                let mut attr = e.attrs.as_ref().clone();
+                attr.irr = Some(state);
+                match BgpRIB::register_shared(attrs, &attr) {
+                    Ok(a) => {
+                        e.attrs = a;
+                        cnt += 1;
+                    }
+                    Err(e) => warn!("IRR attributes register error: {}", e),
+                }
+            }
+        }
+        cnt
+    }
+    /// Stores IRR lookup result and applies it to routes
+    pub fn handle_irr(&mut self, key: IrrKey, state: IrrState) {
+        self.irr.set(key, state);
+        let (addr, len, origin) = key;
+        let hist = match addr {
+            IpAddr::V4(a) => self.ipv4u.items.get_mut(&BgpAddrV4::new(a, len)),
+            IpAddr::V6(a) => self.ipv6u.items.get_mut(&BgpAddrV6::new(a, len)),
+        };
+        if let Some(hist) = hist {
+            let cnt = BgpRIB::apply_irr(hist, &mut self.attrs, origin, state);
+            debug!(
+                "IRR {}/{} AS{} {}, {} routes",
+                addr, len, origin, state, cnt
+            );
+        }
+    }
+    /// Queues active routes present before IRR validator start
+    fn queue_irr_routes<T: BgpRIBKey>(
+        safi: &BgpRIBSafi<T>,
+        irr: &mut IrrTable,
+        prefix: impl Fn(&T) -> (IpAddr, u8),
+    ) {
+        for k in safi.items.keys() {
+            let (addr, len) = prefix(k);
+            for (_, _, o) in BgpRIB::active_origins(safi, k) {
+                irr.enqueue((addr, len, o));
+            }
+        }
+    }
+    /// Route with the prefix and origin is still active
+    fn irr_route_active(&self, key: &IrrKey) -> bool {
+        let (addr, len, origin) = *key;
+        let origins = match addr {
+            IpAddr::V4(a) => BgpRIB::active_origins(&self.ipv4u, &BgpAddrV4::new(a, len)),
+            IpAddr::V6(a) => BgpRIB::active_origins(&self.ipv6u, &BgpAddrV6::new(a, len)),
+        };
+        origins.iter().any(|(_, _, o)| *o == origin)
+    }
+    /// Next prefixes with origins of active unicast routes to look up in IRR.
+    /// New routes are queued on update, expired results are dropped and
+    /// queued again when queue is empty, if route is still active.
+    pub fn irr_candidates(&mut self, limit: usize) -> Vec<IrrKey> {
+        if !self.irr.enabled {
+            return Vec::new();
+        }
+        if !self.irr.scanned {
+            self.irr.scanned = true;
+            BgpRIB::queue_irr_routes(&self.ipv4u, &mut self.irr, |p| {
+                (IpAddr::V4(p.addr), p.prefixlen)
+            });
+            BgpRIB::queue_irr_routes(&self.ipv6u, &mut self.irr, |p| {
+                (IpAddr::V6(p.addr), p.prefixlen)
+            });
+        }
+        if self.irr.is_idle() {
+            for key in self.irr.expire() {
+                if self.irr_route_active(&key) {
+                    self.irr.enqueue(key);
+                }
+            }
+        }
+        self.irr.take(limit)
+    }
+    fn add_rpki_counts<T: BgpRIBKey>(ret: &mut BTreeMap<RpkiState, usize>, safi: &BgpRIBSafi<T>) {
+        for hist in safi.items.values() {
+            for pe in hist.items.values() {
+                for ah in pe.items.values() {
+                    if let Some(e) = ah.items.values().next_back() {
+                        if let (true, Some(state)) = (e.active, e.attrs.rpki) {
+                            *ret.entry(state).or_default() += 1;
+                        }
+                    }
+                }
+            }
+        }
+    }
+    /// Active unicast routes count by origin validation state
+    pub fn rpki_counts(&self) -> BTreeMap<RpkiState, usize> {
+        let mut ret = BTreeMap::new();
+        BgpRIB::add_rpki_counts(&mut ret, &self.ipv4u);
+        BgpRIB::add_rpki_counts(&mut ret, &self.ipv6u);
+        ret
+    }
+    fn add_bogon_counts<T: BgpRIBKey>(ret: &mut BTreeMap<BogonKind, usize>, safi: &BgpRIBSafi<T>) {
+        for hist in safi.items.values() {
+            for pe in hist.items.values() {
+                for ah in pe.items.values() {
+                    if let Some(e) = ah.items.values().next_back() {
+                        if e.active {
+                            for k in e.attrs.bogon.kinds().into_iter() {
+                                *ret.entry(k).or_default() += 1;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+    /// Active unicast routes count by bogon kind
+    pub fn bogon_counts(&self) -> BTreeMap<BogonKind, usize> {
+        let mut ret = BTreeMap::new();
+        BgpRIB::add_bogon_counts(&mut ret, &self.ipv4u);
+        BgpRIB::add_bogon_counts(&mut ret, &self.ipv6u);
+        ret
+    }
     fn register_shared<T: Clone + Eq + Ord + std::hash::Hash + std::fmt::Debug>(
         hset: &mut RibItemStore<T>,
         item: &T,
     ) -> Result<Arc<T>, Box<dyn std::error::Error>> {
         hset.get(Arc::new(item.clone()))
     }
-    pub fn handle_update(
+    fn handle_end_of_rib(&mut self, sessionid: BgpSessionId, kind: BgpRibKind, when: Timestamp) {
+        let synced = self.synced.entry(sessionid).or_default();
+        if !synced.contains_key(&kind) {
+            info!("Session {} {} End-of-RIB received", sessionid, kind);
+        }
+        synced.insert(kind, when);
+        if self.stale.contains_key(&sessionid) {
+            let cnt = self.withdraw_stale(sessionid, kind, when);
+            info!(
+                "Session {} {} End-of-RIB, {} stale routes withdrawn",
+                sessionid, kind, cnt
+            );
+        }
+    }
+    /// Path attributes of UPDATE message with shared parts registered
+    fn make_attrs(
         &mut self,
         sessionid: BgpSessionId,
-        upd: BgpUpdateMessage,
-    ) -> Result<(), Box<dyn std::error::Error>> {
+        upd: &BgpUpdateMessage,
+    ) -> Result<BgpAttrs, Box<dyn std::error::Error>> {
         let mut attr = BgpAttrs {
             origin: match upd.get_attr_origin() {
                 None => {
@@ -1144,7 +2585,15 @@ impl BgpRIB {
             originator: None,
             clusterlist: None,
             pmsi_ta: None,
+            rawattrs: None,
+            otc: None,
+            leak: false,
+            rpki: None,
+            aspa: None,
+            irr: None,
+            bogon: BogonFlags::default(),
         };
+        let mut rawattrs = Vec::new();
         for i in upd.attrs.iter() {
             match i {
                 BgpAttrItem::MED(n) => {
@@ -1168,18 +2617,60 @@ impl BgpRIB {
                 BgpAttrItem::PMSITunnel(n) => {
                     attr.pmsi_ta = Some(self.pmsi_ta_s.get(Arc::new(n.clone()))?);
                 }
-                BgpAttrItem::Unknown(_) => {
-                    warn!("{}\tBGP Unknown: {:?}", Timestamp::now(), upd);
+                BgpAttrItem::Unknown(n)
+                    if n.params.typecode == BGP_ATTR_OTC && n.value.len() == 4 =>
+                {
+                    attr.otc = Some(getn_u32(&n.value));
+                }
+                BgpAttrItem::Unknown(n) => {
+                    rawattrs.push(BgpRawAttr::from_unknown(n));
+                }
+                BgpAttrItem::AttrSet(_) | BgpAttrItem::Connector(_) => {
+                    rawattrs.extend(BgpRawAttr::from_attr(i));
                 }
                 _ => {}
             }
         }
+        if !rawattrs.is_empty() {
+            rawattrs.sort();
+            attr.rawattrs = Some(
+                self.rawattrs
+                    .get(Arc::new(BgpRawAttrs { items: rawattrs }))?,
+            );
+        }
+        if let Some((role, peer_as)) = self.roles.get(&sessionid) {
+            attr.leak = role.is_leak(attr.otc, *peer_as);
+            if self.aspas.enabled {
+                attr.aspa = Some(self.aspas.verify(&attr.aspath, role.aspa_downstream()));
+            }
+        }
+        Ok(attr)
+    }
+    pub fn handle_update(
+        &mut self,
+        sessionid: BgpSessionId,
+        upd: BgpUpdateMessage,
+        when: Timestamp,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(kind) = BgpRIB::end_of_rib(&upd) {
+            self.handle_end_of_rib(sessionid, kind, when);
+            return Ok(());
+        }
+        if BgpRIB::malformed_otc(&upd) {
+            warn!(
+                "Session {} UPDATE with malformed OTC attribute, treated as withdraw",
+                sessionid
+            );
+            self.treat_as_withdraw(sessionid, upd, when);
+            return Ok(());
+        }
+        let mut attr = self.make_attrs(sessionid, &upd)?;
         //let adr=bgp::BgpAddrV4::new(std::net::Ipv4Addr::new(0,0,0,0),32);
         let rattr = BgpRIB::register_shared(&mut self.attrs, &attr)?;
         let mut updates_count: usize = upd.updates.len();
         let mut withdraws_count: usize = upd.withdraws.len();
-        self.handle_withdraws(sessionid, upd.withdraws);
-        self.handle_updates(sessionid, rattr.clone(), upd.updates);
+        self.handle_withdraws(sessionid, upd.withdraws, when);
+        self.handle_updates(sessionid, rattr.clone(), upd.updates, when);
         for i in upd.attrs.into_iter() {
             match i {
                 BgpAttrItem::MPUpdates(n) => {
@@ -1190,11 +2681,11 @@ impl BgpRIB {
                         BgpRIB::register_shared(&mut self.attrs, &attr)?
                     };
                     updates_count += n.addrs.len();
-                    self.handle_updates(sessionid, cattr.clone(), n.addrs);
+                    self.handle_updates(sessionid, cattr.clone(), n.addrs, when);
                 }
                 BgpAttrItem::MPWithdraws(n) => {
                     withdraws_count += n.addrs.len();
-                    self.handle_withdraws(sessionid, n.addrs);
+                    self.handle_withdraws(sessionid, n.addrs, when);
                 }
                 _ => {}
             }
@@ -1203,12 +2694,286 @@ impl BgpRIB {
         self.cnt_withdraws += withdraws_count as u64;
         Ok(())
     }
+    /// OTC attribute of length other than 4, RFC 9234 section 5
+    fn malformed_otc(upd: &BgpUpdateMessage) -> bool {
+        upd.attrs.iter().any(|a| {
+            matches!(a, BgpAttrItem::Unknown(n)
+                if n.params.typecode == BGP_ATTR_OTC && n.value.len() != 4)
+        })
+    }
+    /// Withdraws all NLRI of malformed UPDATE, RFC 7606 treat-as-withdraw
+    fn treat_as_withdraw(
+        &mut self,
+        sessionid: BgpSessionId,
+        upd: BgpUpdateMessage,
+        when: Timestamp,
+    ) {
+        let mut withdraws_count = upd.withdraws.len() + upd.updates.len();
+        self.handle_withdraws(sessionid, upd.withdraws, when);
+        self.handle_withdraws(sessionid, upd.updates, when);
+        for i in upd.attrs.into_iter() {
+            match i {
+                BgpAttrItem::MPUpdates(n) => {
+                    withdraws_count += n.addrs.len();
+                    self.handle_withdraws(sessionid, n.addrs, when);
+                }
+                BgpAttrItem::MPWithdraws(n) => {
+                    withdraws_count += n.addrs.len();
+                    self.handle_withdraws(sessionid, n.addrs, when);
+                }
+                _ => {}
+            }
+        }
+        self.cnt_withdraws += withdraws_count as u64;
+    }
+    /// UPDATE with Route Target membership NLRI, the rest of NLRI is handled as usual
+    pub fn handle_rtc(
+        &mut self,
+        sessionid: BgpSessionId,
+        upd: BgpUpdateMessage,
+        rtc: RtcUpdate,
+        when: Timestamp,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if rtc.end_of_rib {
+            self.handle_end_of_rib(sessionid, BgpRibKind::Rtc, when);
+            return Ok(());
+        }
+        if BgpRIB::malformed_otc(&upd) {
+            self.rtc
+                .handle_withdraws_afi(sessionid, &rtc.withdraws, when);
+            self.rtc.handle_withdraws_afi(sessionid, &rtc.updates, when);
+            self.cnt_withdraws += (rtc.withdraws.len() + rtc.updates.len()) as u64;
+            return self.handle_update(sessionid, upd, when);
+        }
+        let mut attr = self.make_attrs(sessionid, &upd)?;
+        attr.nexthop = rtc.nexthop;
+        let rattr = BgpRIB::register_shared(&mut self.attrs, &attr)?;
+        self.rtc
+            .handle_withdraws_afi(sessionid, &rtc.withdraws, when);
+        self.rtc
+            .handle_updates_afi(sessionid, &rtc.updates, rattr, when);
+        for r in rtc.updates.iter().filter(|r| r.is_exact()) {
+            self.rtc.idx_extcommunity.set(&r.rt, r);
+        }
+        self.cnt_updates += rtc.updates.len() as u64;
+        self.cnt_withdraws += rtc.withdraws.len() as u64;
+        if BgpRIB::has_nlri(&upd) {
+            self.handle_update(sessionid, upd, when)?;
+        }
+        Ok(())
+    }
+    /// UPDATE with VPNv6 FlowSpec NLRI, the rest of NLRI is handled as usual
+    pub fn handle_fsv6u(
+        &mut self,
+        sessionid: BgpSessionId,
+        upd: BgpUpdateMessage,
+        fs: FsV6uUpdate,
+        when: Timestamp,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if fs.end_of_rib {
+            self.handle_end_of_rib(sessionid, BgpRibKind::FsV6u, when);
+            return Ok(());
+        }
+        if BgpRIB::malformed_otc(&upd) {
+            self.fsv6u
+                .handle_withdraws_afi(sessionid, &fs.withdraws, when);
+            self.fsv6u
+                .handle_withdraws_afi(sessionid, &fs.updates, when);
+            self.cnt_withdraws += (fs.withdraws.len() + fs.updates.len()) as u64;
+            return self.handle_update(sessionid, upd, when);
+        }
+        let mut attr = self.make_attrs(sessionid, &upd)?;
+        attr.nexthop = fs.nexthop;
+        let rattr = BgpRIB::register_shared(&mut self.attrs, &attr)?;
+        self.fsv6u
+            .handle_withdraws_afi(sessionid, &fs.withdraws, when);
+        self.fsv6u
+            .handle_updates_afi(sessionid, &fs.updates, rattr, when);
+        self.cnt_updates += fs.updates.len() as u64;
+        self.cnt_withdraws += fs.withdraws.len() as u64;
+        if BgpRIB::has_nlri(&upd) {
+            self.handle_update(sessionid, upd, when)?;
+        }
+        Ok(())
+    }
+    /// UPDATE still has NLRI known to zettabgp
+    fn has_nlri(upd: &BgpUpdateMessage) -> bool {
+        !upd.updates.is_empty()
+            || !upd.withdraws.is_empty()
+            || upd
+                .attrs
+                .iter()
+                .any(|a| matches!(a, BgpAttrItem::MPUpdates(_) | BgpAttrItem::MPWithdraws(_)))
+    }
+    /// Indexes RTC RIB by its own route targets, so the rt: filter applies to memberships
+    fn index_rtc(&mut self) {
+        let keys: Vec<BgpRtc> = self
+            .rtc
+            .items
+            .keys()
+            .filter(|r| r.is_exact())
+            .cloned()
+            .collect();
+        for r in keys.iter() {
+            self.rtc.idx_extcommunity.set(&r.rt, r);
+        }
+    }
+    /// Active Route Target memberships which cover the route target
+    pub fn rt_members(
+        &self,
+        rt: &BgpExtCommunity,
+    ) -> Vec<(&BgpRtc, BgpSessionId, &Timestamp, &BgpAttrEntry)> {
+        let mut ret = Vec::new();
+        for (k, hist) in self.rtc.items.iter().filter(|(k, _)| k.covers(rt)) {
+            for (sess, pe) in hist.items.iter() {
+                for ah in pe.items.values() {
+                    if let Some((ts, e)) = ah.items.iter().next_back() {
+                        if e.active {
+                            ret.push((k, *sess, ts, e));
+                        }
+                    }
+                }
+            }
+        }
+        ret
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    /// RIB with default settings of minimal config file
+    fn test_rib(name: &str) -> BgpRIB {
+        let path =
+            std::env::temp_dir().join(format!("bgpexplorer-{}-{}.ini", name, std::process::id()));
+        let ini = format!(
+            "[main]\nwhoisjsonconfig={}/whois.json\n[peer]\nmode=bgppassive\n",
+            env!("CARGO_MANIFEST_DIR")
+        );
+        std::fs::write(&path, ini).unwrap();
+        let cfg = SvcConfig::from_inifile(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+        BgpRIB::new(&cfg.unwrap())
+    }
+
+    fn ipv4_update(nets: &[BgpAddrV4]) -> BgpUpdateMessage {
+        let mut upd = BgpUpdateMessage::new();
+        upd.updates = BgpAddrs::IPV4U(nets.to_vec());
+        upd.attrs
+            .push(BgpAttrItem::Origin(BgpOrigin::new(BgpAttrOrigin::Igp)));
+        upd.attrs
+            .push(BgpAttrItem::ASPath(BgpASpath::from(vec![65001])));
+        upd
+    }
+
+    #[test]
+    fn test_end_of_rib() {
+        let upd = BgpUpdateMessage::new();
+        assert_eq!(BgpRIB::end_of_rib(&upd), Some(BgpRibKind::IpV4u));
+        let mp_unreach = |addrs| {
+            let mut upd = BgpUpdateMessage::new();
+            upd.attrs
+                .push(BgpAttrItem::MPWithdraws(BgpMPWithdraws { addrs }));
+            BgpRIB::end_of_rib(&upd)
+        };
+        assert_eq!(mp_unreach(BgpAddrs::IPV6U(vec![])), Some(BgpRibKind::IpV6u));
+        // add-path family marker has no path identifiers
+        assert_eq!(
+            mp_unreach(BgpAddrs::IPV6UP(vec![])),
+            Some(BgpRibKind::IpV6u)
+        );
+        assert_eq!(
+            mp_unreach(BgpAddrs::VPNV4UP(vec![])),
+            Some(BgpRibKind::VpnV4u)
+        );
+        let net = BgpAddrV6::new(std::net::Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 0), 32);
+        assert_eq!(mp_unreach(BgpAddrs::IPV6U(vec![net])), None);
+        let net = BgpAddrV4::new(std::net::Ipv4Addr::new(10, 0, 0, 0), 8);
+        assert_eq!(
+            BgpRIB::end_of_rib(&ipv4_update(std::slice::from_ref(&net))),
+            None
+        );
+        let mut upd = BgpUpdateMessage::new();
+        upd.withdraws = BgpAddrs::IPV4U(vec![net]);
+        assert_eq!(BgpRIB::end_of_rib(&upd), None);
+    }
+
+    #[test]
+    fn test_route_refresh_handle_update() {
+        let mut rib = test_rib("refresh");
+        let nets = vec![
+            BgpAddrV4::new(std::net::Ipv4Addr::new(10, 0, 0, 0), 24),
+            BgpAddrV4::new(std::net::Ipv4Addr::new(10, 0, 1, 0), 24),
+        ];
+        rib.handle_update(1, ipv4_update(&nets), Timestamp::now())
+            .unwrap();
+        rib.handle_route_refresh(1, BgpRibKind::IpV4u, true, Timestamp::now());
+        rib.handle_update(1, ipv4_update(&nets[0..1]), Timestamp::now())
+            .unwrap();
+        // End-of-RIB is not EoRR, stale routes are kept
+        rib.handle_update(1, BgpUpdateMessage::new(), Timestamp::now())
+            .unwrap();
+        assert!(
+            rib.ipv4u.items[&nets[1]]
+                .get_last_attr(1, 0)
+                .unwrap()
+                .active
+        );
+        rib.handle_route_refresh(1, BgpRibKind::IpV4u, false, Timestamp::now());
+        assert!(
+            rib.ipv4u.items[&nets[0]]
+                .get_last_attr(1, 0)
+                .unwrap()
+                .active
+        );
+        assert!(
+            !rib.ipv4u.items[&nets[1]]
+                .get_last_attr(1, 0)
+                .unwrap()
+                .active
+        );
+    }
+
+    #[test]
+    fn test_handle_fsv6u() {
+        let mut rib = test_rib("fsv6u");
+        let rule = BgpFlowSpecVpn6 {
+            rd: BgpRD::new(65000, 1),
+            components: vec![BgpFlowSpec::PrefixDst(FS6::new(
+                0,
+                BgpAddrV6::new(std::net::Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 0), 32),
+            ))],
+        };
+        let net = BgpAddrV4::new(std::net::Ipv4Addr::new(10, 0, 0, 0), 24);
+        let fs = FsV6uUpdate {
+            nexthop: BgpAddr::None,
+            updates: vec![rule.clone()],
+            withdraws: vec![],
+            end_of_rib: false,
+        };
+        // IPv4 NLRI of the same UPDATE go to their own RIB
+        rib.handle_fsv6u(
+            1,
+            ipv4_update(std::slice::from_ref(&net)),
+            fs,
+            Timestamp::now(),
+        )
+        .unwrap();
+        assert!(rib.fsv6u.items[&rule].get_last_attr(1, 0).unwrap().active);
+        assert!(rib.ipv4u.items[&net].get_last_attr(1, 0).unwrap().active);
+        let fs = FsV6uUpdate {
+            nexthop: BgpAddr::None,
+            updates: vec![],
+            withdraws: vec![rule.clone()],
+            end_of_rib: false,
+        };
+        rib.handle_fsv6u(1, BgpUpdateMessage::new(), fs, Timestamp::now())
+            .unwrap();
+        assert!(!rib.fsv6u.items[&rule].get_last_attr(1, 0).unwrap().active);
+        assert!(rib.ipv4u.items[&net].get_last_attr(1, 0).unwrap().active);
+    }
+
     #[test]
     fn test_ribitemstore() {
         let mut teststore = RibItemStore::<u32>::new();
@@ -1222,4 +2987,271 @@ mod tests {
         teststore.purge();
         assert_eq!(teststore.len(), 0);
     }
+    #[test]
+    fn test_withdraw_session() {
+        let mut rib = BgpRIBSafi::<BgpAddrV4>::new(10, HistoryChangeMode::OnlyDiffer);
+        let nets = vec![
+            BgpAddrV4::new(std::net::Ipv4Addr::new(10, 0, 0, 0), 24),
+            BgpAddrV4::new(std::net::Ipv4Addr::new(10, 0, 1, 0), 24),
+        ];
+        let attrs = Arc::new(BgpAttrs::new());
+        rib.handle_updates_afi(1, &nets, attrs.clone(), Timestamp::now());
+        rib.handle_updates_afi(2, &nets[0..1], attrs, Timestamp::now());
+        let wd = rib.withdraw_session(1);
+        assert_eq!(wd.len(), 2);
+        for n in nets.iter() {
+            assert!(!rib.items[n].get_last_attr(1, 0).unwrap().active);
+        }
+        assert!(rib.items[&nets[0]].get_last_attr(2, 0).unwrap().active);
+        assert_eq!(rib.count_session_prefixes().get(&1), None);
+        assert_eq!(rib.count_session_prefixes().get(&2), Some(&1));
+        assert!(rib.withdraw_session(1).is_empty());
+    }
+
+    #[test]
+    fn test_raw_attr_from_attr() {
+        let params = BgpSessionParams::new(
+            65000,
+            180,
+            BgpTransportMode::IPv4,
+            std::net::Ipv4Addr::new(10, 0, 0, 1),
+            Vec::new(),
+        );
+        // ATTR_SET of AS65001 with ORIGIN and LOCAL_PREF
+        let value = vec![0, 0, 0xfd, 0xe9, 0x40, 1, 1, 0, 0x40, 5, 4, 0, 0, 0, 100];
+        let a = BgpAttrItem::decode_from(&params, 128, 0xc0, value.len(), &value).unwrap();
+        assert!(matches!(a, BgpAttrItem::AttrSet(_)));
+        let raw = BgpRawAttr::from_attr(&a).unwrap();
+        assert_eq!((raw.flags, raw.typecode), (0xc0, 128));
+        assert_eq!(raw.value, value);
+        let value = vec![0, 1, 0, 0, 0xfd, 0xe9, 10, 0, 0, 1, 10, 0, 0, 2];
+        let a = BgpAttrItem::decode_from(&params, 20, 0xc0, value.len(), &value).unwrap();
+        assert!(matches!(a, BgpAttrItem::Connector(_)));
+        let raw = BgpRawAttr::from_attr(&a).unwrap();
+        assert_eq!((raw.flags, raw.typecode), (0xc0, 20));
+        assert_eq!(raw.value, value);
+    }
+
+    #[test]
+    fn test_malformed_otc() {
+        let params = BgpSessionParams::new(
+            65000,
+            180,
+            BgpTransportMode::IPv4,
+            std::net::Ipv4Addr::new(10, 0, 0, 1),
+            Vec::new(),
+        );
+        let mut upd = BgpUpdateMessage::new();
+        let otc =
+            |v: &[u8]| BgpAttrItem::decode_from(&params, BGP_ATTR_OTC, 0xc0, v.len(), v).unwrap();
+        upd.attrs.push(otc(&[0, 0, 0xfd, 0xe9]));
+        assert!(!BgpRIB::malformed_otc(&upd));
+        upd.attrs.push(otc(&[0, 0, 0xfd]));
+        assert!(BgpRIB::malformed_otc(&upd));
+    }
+
+    #[test]
+    fn test_route_refresh_stale() {
+        let mut rib = BgpRIBSafi::<BgpAddrV4>::new(10, HistoryChangeMode::OnlyDiffer);
+        let nets = vec![
+            BgpAddrV4::new(std::net::Ipv4Addr::new(10, 0, 0, 0), 24),
+            BgpAddrV4::new(std::net::Ipv4Addr::new(10, 0, 1, 0), 24),
+        ];
+        let attrs = Arc::new(BgpAttrs::new());
+        rib.handle_updates_afi(1, &nets, attrs.clone(), Timestamp::now());
+        rib.refresh_begin(1);
+        rib.handle_updates_afi(1, &nets[0..1], attrs, Timestamp::now());
+        let wd = rib.refresh_end(1, Timestamp::now());
+        assert_eq!(wd, vec![nets[1].clone()]);
+        assert!(rib.items[&nets[0]].get_last_attr(1, 0).unwrap().active);
+        assert!(!rib.items[&nets[1]].get_last_attr(1, 0).unwrap().active);
+        assert!(rib.refresh_end(1, Timestamp::now()).is_empty());
+        // stable route has no flap counters, EoRR withdrawal is counted
+        assert!(rib.items[&nets[0]].flaps.is_empty());
+        assert_eq!(rib.items[&nets[1]].flaps[&1].withdraws, 1);
+        assert_eq!(rib.items[&nets[1]].flaps[&1].announces, 1);
+    }
+
+    #[test]
+    fn test_flap_counters() {
+        let mut rib = BgpRIBSafi::<BgpAddrV4>::new(10, HistoryChangeMode::OnlyDiffer);
+        let net = BgpAddrV4::new(std::net::Ipv4Addr::new(10, 0, 0, 0), 24);
+        let attrs = Arc::new(BgpAttrs::new());
+        let now = Timestamp::now();
+        let old: Timestamp = (*now - chrono::Duration::days(2)).into();
+        rib.handle_updates_afi(1, std::slice::from_ref(&net), attrs.clone(), old);
+        rib.handle_updates_afi(2, std::slice::from_ref(&net), attrs.clone(), old);
+        // first withdrawal counts the announcement which began the flapping
+        rib.handle_withdraws_afi(1, std::slice::from_ref(&net), now);
+        rib.handle_updates_afi(1, std::slice::from_ref(&net), attrs.clone(), now);
+        rib.handle_withdraws_afi(2, std::slice::from_ref(&net), old);
+        let st = &rib.items[&net].flaps[&1];
+        assert_eq!(st.counts(None), [2, 1, 0]);
+        assert_eq!(st.counts(Some(now.timestamp() - 3600)), [1, 1, 0]);
+        assert_eq!(rib.items[&net].flaps[&2].counts(None), [1, 1, 0]);
+        // counters of imported session survive purge
+        rib.prune_flaps(now.timestamp(), &BTreeSet::from([2]));
+        assert!(rib.items[&net].flaps.contains_key(&2));
+        rib.prune_flaps(now.timestamp(), &BTreeSet::new());
+        assert!(!rib.items[&net].flaps.contains_key(&2));
+        assert!(rib.items[&net].flaps.contains_key(&1));
+    }
+
+    #[test]
+    fn test_rpki_revalidate() {
+        let mut rib = BgpRIBSafi::<BgpAddrV4>::new(10, HistoryChangeMode::OnlyDiffer);
+        let mut store = RibItemStore::<BgpAttrs>::new();
+        let net = BgpAddrV4::new(std::net::Ipv4Addr::new(10, 0, 0, 0), 24);
+        let mut attrs = BgpAttrs::new();
+        attrs.aspath = Arc::new(BgpASpath::from(vec![65001]));
+        rib.handle_updates_afi(
+            1,
+            std::slice::from_ref(&net),
+            Arc::new(attrs),
+            Timestamp::now(),
+        );
+        let mut vrps = VrpTable::new(true);
+        let pfx = |p: &BgpAddrV4| (IpAddr::V4(p.addr), p.prefixlen);
+        vrps.set(
+            VrpSource::File,
+            vec![Vrp::new(IpAddr::V4(net.addr), 16, 24, 65002)],
+        );
+        assert_eq!(BgpRIB::revalidate(&mut rib, &mut store, &vrps, pfx), 1);
+        let last = rib.items[&net].get_last_attr(1, 0).unwrap();
+        assert_eq!(last.attrs.rpki, Some(RpkiState::Invalid));
+        assert_eq!(rib.items[&net].items[&1].items[&0].items.len(), 1);
+        vrps.set(
+            VrpSource::Rtr,
+            vec![Vrp::new(IpAddr::V4(net.addr), 24, 24, 65001)],
+        );
+        assert_eq!(BgpRIB::revalidate(&mut rib, &mut store, &vrps, pfx), 1);
+        let last = rib.items[&net].get_last_attr(1, 0).unwrap();
+        assert_eq!(last.attrs.rpki, Some(RpkiState::Valid));
+        assert_eq!(BgpRIB::revalidate(&mut rib, &mut store, &vrps, pfx), 0);
+        // withdrawn route keeps its state
+        rib.handle_withdraws_afi(1, std::slice::from_ref(&net), Timestamp::now());
+        vrps.set(VrpSource::Rtr, vec![]);
+        assert_eq!(BgpRIB::revalidate(&mut rib, &mut store, &vrps, pfx), 0);
+        let last = rib.items[&net].get_last_attr(1, 0).unwrap();
+        assert_eq!(last.attrs.rpki, Some(RpkiState::Valid));
+    }
+
+    #[test]
+    fn test_check_prefix() {
+        let mut rib = BgpRIBSafi::<BgpAddrV4>::new(10, HistoryChangeMode::OnlyDiffer);
+        let net = BgpAddrV4::new(std::net::Ipv4Addr::new(10, 0, 0, 0), 16);
+        let sub = BgpAddrV4::new(std::net::Ipv4Addr::new(10, 0, 1, 0), 24);
+        let mut attrs = BgpAttrs::new();
+        attrs.aspath = Arc::new(BgpASpath::from(vec![65001]));
+        rib.handle_updates_afi(
+            1,
+            std::slice::from_ref(&net),
+            Arc::new(attrs),
+            Timestamp::now(),
+        );
+        let v4 = |a: IpAddr, l: u8| match a {
+            IpAddr::V4(a) => Some(BgpAddrV4::new(a, l)),
+            IpAddr::V6(_) => None,
+        };
+        let kinds = |session, key: &BgpAddrV4, origin, synced| {
+            let tmpl = Alert::new(
+                Timestamp::now(),
+                AlertKind::Owned,
+                session,
+                IpAddr::V4(key.addr),
+                key.prefixlen,
+                origin,
+            );
+            BgpRIB::check_prefix(&rib, 0, key, tmpl, synced, v4)
+                .into_iter()
+                .map(|a| (a.kind, a.expected))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            kinds(1, &net, 65001, true),
+            vec![(AlertKind::Owned, vec![])]
+        );
+        assert_eq!(
+            kinds(1, &net, 65002, true),
+            vec![
+                (AlertKind::OriginChange, vec![65001]),
+                (AlertKind::Owned, vec![])
+            ]
+        );
+        assert_eq!(
+            kinds(2, &net, 65002, true),
+            vec![(AlertKind::Moas, vec![65001]), (AlertKind::Owned, vec![])]
+        );
+        assert_eq!(
+            kinds(2, &sub, 65002, true),
+            vec![
+                (AlertKind::SubPrefix, vec![65001]),
+                (AlertKind::Owned, vec![])
+            ]
+        );
+        assert_eq!(
+            kinds(2, &sub, 65001, true),
+            vec![(AlertKind::Owned, vec![])]
+        );
+        // before End-of-RIB only origin change of the same path is reported
+        assert_eq!(
+            kinds(2, &net, 65002, false),
+            vec![(AlertKind::Owned, vec![])]
+        );
+        assert_eq!(
+            kinds(2, &sub, 65002, false),
+            vec![(AlertKind::Owned, vec![])]
+        );
+        assert_eq!(
+            kinds(1, &net, 65002, false),
+            vec![
+                (AlertKind::OriginChange, vec![65001]),
+                (AlertKind::Owned, vec![])
+            ]
+        );
+    }
+
+    #[test]
+    fn test_graceful_restart_stale() {
+        let mut rib = BgpRIBSafi::<BgpAddrV4>::new(10, HistoryChangeMode::OnlyDiffer);
+        let nets = vec![
+            BgpAddrV4::new(std::net::Ipv4Addr::new(10, 0, 0, 0), 24),
+            BgpAddrV4::new(std::net::Ipv4Addr::new(10, 0, 1, 0), 24),
+        ];
+        let attrs = Arc::new(BgpAttrs::new());
+        rib.handle_updates_afi(1, &nets, attrs.clone(), Timestamp::now());
+        assert_eq!(rib.mark_session_stale(1), 2);
+        let last = rib.items[&nets[0]].get_last_attr(1, 0).unwrap();
+        assert!(last.active && last.stale);
+        // re-advertised route is refreshed in place, without history record
+        rib.handle_updates_afi(1, &nets[0..1], attrs, Timestamp::now());
+        assert_eq!(rib.items[&nets[0]].items[&1].items[&0].items.len(), 1);
+        assert!(!rib.items[&nets[0]].get_last_attr(1, 0).unwrap().stale);
+        let wd = rib.withdraw_stale(1, Timestamp::now());
+        assert_eq!(wd, vec![nets[1].clone()]);
+        assert!(!rib.items[&nets[1]].get_last_attr(1, 0).unwrap().active);
+        assert!(rib.withdraw_stale(1, Timestamp::now()).is_empty());
+    }
+
+    #[test]
+    fn test_restart_flush() {
+        // no graceful restart capability in new OPEN
+        assert_eq!(BgpRIB::restart_flush(true, None), BgpRibKind::ALL.to_vec());
+        // F bit set for IPv4 unicast only
+        let fwd = [BgpRibKind::IpV4u];
+        let flush = BgpRIB::restart_flush(true, Some(&fwd));
+        assert!(!flush.contains(&BgpRibKind::IpV4u));
+        assert!(flush.contains(&BgpRibKind::VpnV4u));
+        assert_eq!(flush.len(), BgpRibKind::ALL.len() - 1);
+        // F bit clear for all
+        assert_eq!(
+            BgpRIB::restart_flush(true, Some(&[])).len(),
+            BgpRibKind::ALL.len()
+        );
+        // other session of the same peer keeps nothing
+        assert_eq!(
+            BgpRIB::restart_flush(false, Some(&fwd)),
+            BgpRibKind::ALL.to_vec()
+        );
+    }
 }
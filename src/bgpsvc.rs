@@ -1,10 +1,18 @@
+use crate::bgpattrs::BgpRole;
 use crate::bgppeer::*;
 use crate::bgprib::*;
 use crate::bmppeer::*;
+use crate::bmpstats::*;
+use crate::flowspec::FsV6uUpdate;
+use crate::mrt::*;
 use crate::ribservice::*;
+use crate::rtc::RtcUpdate;
+use crate::tcpmd5::*;
+use crate::timestamp::Timestamp;
 use crate::*;
 use async_trait::async_trait;
-use hyper::{Body, Request, Response, StatusCode};
+use chrono::prelude::*;
+use hyper::{Body, Method, Request, Response, StatusCode};
 use serde::ser::{SerializeMap, SerializeStruct};
 use std::cmp::Ordering;
 use std::collections::{BTreeMap, BTreeSet};
@@ -19,11 +27,49 @@ use tokio::time::timeout;
 use zettabgp::bmp::prelude::*;
 use zettabgp::prelude::*;
 
+/// Time to wait for End-of-RIB from graceful restart peer restarted within restart time, seconds
+const BGP_STALE_PATH_TIME: u64 = 360;
+
 pub type BgpSessionId = u16;
 #[async_trait]
 pub trait BgpUpdateHandler {
-    async fn handle_update(&self, peerid: BgpSessionId, upd: BgpUpdateMessage);
+    async fn handle_update(&self, peerid: BgpSessionId, upd: BgpUpdateMessage, when: Timestamp);
+    /// UPDATE with Route Target membership NLRI, which are removed from upd
+    async fn handle_rtc(
+        &self,
+        peerid: BgpSessionId,
+        upd: BgpUpdateMessage,
+        rtc: RtcUpdate,
+        when: Timestamp,
+    );
+    /// UPDATE with VPNv6 FlowSpec NLRI, which are removed from upd
+    async fn handle_fsv6u(
+        &self,
+        peerid: BgpSessionId,
+        upd: BgpUpdateMessage,
+        fs: FsV6uUpdate,
+        when: Timestamp,
+    );
     async fn register_session(&self, sess: Arc<BgpSessionDesc>) -> BgpSessionId;
+    /// KEEPALIVE or UPDATE received in OpenConfirm state of BGP session
+    async fn session_established(&self, sessid: BgpSessionId);
+    async fn session_down(&self, sessid: BgpSessionId, reason: String);
+    /// Session terminated, but peer routes are retained until restart_time expires
+    async fn session_stale(
+        &self,
+        sessid: BgpSessionId,
+        reason: String,
+        restart_time: u16,
+        retain: Vec<BgpRibKind>,
+    );
+    async fn bmp_event(&self, station: IpAddr, ev: BmpStationEvent);
+    async fn route_refresh(
+        &self,
+        peerid: BgpSessionId,
+        kind: BgpRibKind,
+        begin: bool,
+        when: Timestamp,
+    );
 }
 #[derive(Hash, PartialEq, Eq, Debug, Clone)]
 pub struct BgpPeerDesc {
@@ -34,6 +80,17 @@ impl BgpPeerDesc {
     pub fn new(addr: IpAddr, bom: BgpOpenMessage) -> BgpPeerDesc {
         BgpPeerDesc { addr, bom }
     }
+    /// AS number, 4-octet one if advertised
+    pub fn as_num(&self) -> u32 {
+        self.bom
+            .caps
+            .iter()
+            .find_map(|c| match c {
+                BgpCapability::CapASN32(n) => Some(*n),
+                _ => None,
+            })
+            .unwrap_or(self.bom.as_num)
+    }
 }
 impl Ord for BgpPeerDesc {
     fn cmp(&self, other: &Self) -> Ordering {
@@ -55,19 +112,103 @@ impl PartialOrd for BgpPeerDesc {
         }
     }
 }
+/// Which RIB of the router the session routes come from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum BgpSessionView {
+    Bgp,
+    AdjRibInPre,
+    AdjRibInPost,
+    LocRib,
+    AdjRibOutPre,
+    AdjRibOutPost,
+}
+impl BgpSessionView {
+    pub fn from_bmp_peer(hdr: &BmpMessagePeerHeader) -> BgpSessionView {
+        if hdr.peertype == 3 {
+            return BgpSessionView::LocRib;
+        }
+        // O flag (RFC 8671) and L flag (RFC 7854)
+        match (hdr.flags & 0x10 != 0, hdr.flags & 0x40 != 0) {
+            (false, false) => BgpSessionView::AdjRibInPre,
+            (false, true) => BgpSessionView::AdjRibInPost,
+            (true, false) => BgpSessionView::AdjRibOutPre,
+            (true, true) => BgpSessionView::AdjRibOutPost,
+        }
+    }
+}
+impl std::str::FromStr for BgpSessionView {
+    type Err = BgpError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bgp" => Ok(BgpSessionView::Bgp),
+            "adj-rib-in-pre" | "pre" => Ok(BgpSessionView::AdjRibInPre),
+            "adj-rib-in-post" | "post" => Ok(BgpSessionView::AdjRibInPost),
+            "loc-rib" => Ok(BgpSessionView::LocRib),
+            "adj-rib-out-pre" => Ok(BgpSessionView::AdjRibOutPre),
+            "adj-rib-out-post" => Ok(BgpSessionView::AdjRibOutPost),
+            _ => Err(BgpError::static_str("Invalid session view")),
+        }
+    }
+}
+impl std::fmt::Display for BgpSessionView {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            BgpSessionView::Bgp => f.write_str("bgp"),
+            BgpSessionView::AdjRibInPre => f.write_str("adj-rib-in-pre"),
+            BgpSessionView::AdjRibInPost => f.write_str("adj-rib-in-post"),
+            BgpSessionView::LocRib => f.write_str("loc-rib"),
+            BgpSessionView::AdjRibOutPre => f.write_str("adj-rib-out-pre"),
+            BgpSessionView::AdjRibOutPost => f.write_str("adj-rib-out-post"),
+        }
+    }
+}
 #[derive(Eq, Debug, Clone)]
 pub struct BgpSessionDesc {
     pub peer1: BgpPeerDesc,
     pub peer2: BgpPeerDesc,
+    pub view: BgpSessionView,
+    /// local BGP Role, not a part of session identity
+    pub role: Option<BgpRole>,
+    /// RIBs with forwarding state preserved by restarting neighbor (F bit),
+    /// None without graceful restart capability, not a part of session identity
+    pub gr_forwarding: Option<Vec<BgpRibKind>>,
+    /// session with BGP speaker itself, not BMP or MRT feed, not a part of session identity
+    pub direct: bool,
+    /// bulk import from MRT file, its updates are not analyzed, not a part of session identity
+    pub imported: bool,
 }
 impl BgpSessionDesc {
     pub fn new(peer1: BgpPeerDesc, peer2: BgpPeerDesc) -> BgpSessionDesc {
-        BgpSessionDesc { peer1, peer2 }
+        BgpSessionDesc {
+            peer1,
+            peer2,
+            view: BgpSessionView::Bgp,
+            role: None,
+            gr_forwarding: None,
+            direct: false,
+            imported: false,
+        }
     }
-    pub fn from_bmppeerup(pu: &BmpMessagePeerUp) -> BgpSessionDesc {
+    pub fn from_bmppeerup(pu: &BmpMessagePeerUp, view: BgpSessionView) -> BgpSessionDesc {
         BgpSessionDesc {
             peer1: BgpPeerDesc::new(pu.localaddress, pu.msg1.clone()),
             peer2: BgpPeerDesc::new(pu.peer.peeraddress, pu.msg2.clone()),
+            view,
+            role: None,
+            gr_forwarding: None,
+            direct: false,
+            imported: false,
+        }
+    }
+    fn reversed(&self) -> BgpSessionDesc {
+        BgpSessionDesc {
+            peer1: self.peer2.clone(),
+            peer2: self.peer1.clone(),
+            view: self.view,
+            role: self.role,
+            gr_forwarding: self.gr_forwarding.clone(),
+            direct: self.direct,
+            imported: self.imported,
         }
     }
 }
@@ -86,7 +227,10 @@ impl Ord for BgpSessionDesc {
             op2 = &other.peer1;
         }
         match sp1.cmp(op1) {
-            Ordering::Equal => sp2.cmp(op2),
+            Ordering::Equal => match sp2.cmp(op2) {
+                Ordering::Equal => self.view.cmp(&other.view),
+                x => x,
+            },
             x => x,
         }
     }
@@ -110,15 +254,19 @@ impl PartialOrd for BgpSessionDesc {
             Some(pc) => match pc {
                 Ordering::Less => Some(Ordering::Less),
                 Ordering::Greater => Some(Ordering::Greater),
-                Ordering::Equal => sp2.partial_cmp(op2),
+                Ordering::Equal => match sp2.partial_cmp(op2) {
+                    Some(Ordering::Equal) => self.view.partial_cmp(&other.view),
+                    x => x,
+                },
             },
         }
     }
 }
 impl PartialEq for BgpSessionDesc {
     fn eq(&self, other: &Self) -> bool {
-        (self.peer1.eq(&other.peer1) && self.peer2.eq(&other.peer2))
-            || (self.peer1.eq(&other.peer2) && self.peer2.eq(&other.peer1))
+        self.view == other.view
+            && ((self.peer1.eq(&other.peer1) && self.peer2.eq(&other.peer2))
+                || (self.peer1.eq(&other.peer2) && self.peer2.eq(&other.peer1)))
     }
 }
 impl Hash for BgpSessionDesc {
@@ -130,38 +278,174 @@ impl Hash for BgpSessionDesc {
             self.peer2.hash(state);
             self.peer1.hash(state);
         }
+        self.view.hash(state);
+    }
+}
+#[derive(Debug, Clone, Default)]
+pub struct BgpAfiCounters {
+    pub updates: u64,
+    pub withdraws: u64,
+}
+/// Per-session FSM state and counters
+#[derive(Debug, Clone)]
+pub struct BgpSessionStats {
+    pub state: BgpSessionState,
+    pub established: Option<DateTime<Local>>,
+    pub changed: DateTime<Local>,
+    pub flaps: u64,
+    pub last_error: Option<String>,
+    pub afis: BTreeMap<BgpRibKind, BgpAfiCounters>,
+}
+impl BgpSessionStats {
+    pub fn new() -> BgpSessionStats {
+        BgpSessionStats {
+            state: BgpSessionState::Idle,
+            established: None,
+            changed: Local::now(),
+            flaps: 0,
+            last_error: None,
+            afis: BTreeMap::new(),
+        }
+    }
+    pub fn set_state(&mut self, state: BgpSessionState) {
+        if self.state == state {
+            return;
+        }
+        if state == BgpSessionState::Established {
+            self.established = Some(Local::now());
+        } else if self.state == BgpSessionState::Established {
+            self.flaps += 1;
+            self.established = None;
+        }
+        self.state = state;
+        self.changed = Local::now();
+    }
+    pub fn uptime(&self) -> Option<i64> {
+        self.established
+            .as_ref()
+            .map(|t| (Local::now() - *t).num_seconds())
+    }
+    fn count_addrs(&mut self, addrs: &BgpAddrs, withdraw: bool) {
+        if addrs.is_empty() {
+            return;
+        }
+        if let Some(kind) = BgpRibKind::from_bgp_addrs(addrs) {
+            let cnt = self.afis.entry(kind).or_default();
+            if withdraw {
+                cnt.withdraws += addrs.len() as u64;
+            } else {
+                cnt.updates += addrs.len() as u64;
+            }
+        }
+    }
+    pub fn count_rtc(&mut self, rtc: &RtcUpdate) {
+        if rtc.updates.is_empty() && rtc.withdraws.is_empty() {
+            return;
+        }
+        let cnt = self.afis.entry(BgpRibKind::Rtc).or_default();
+        cnt.updates += rtc.updates.len() as u64;
+        cnt.withdraws += rtc.withdraws.len() as u64;
+    }
+    pub fn count_fsv6u(&mut self, fs: &FsV6uUpdate) {
+        if fs.updates.is_empty() && fs.withdraws.is_empty() {
+            return;
+        }
+        let cnt = self.afis.entry(BgpRibKind::FsV6u).or_default();
+        cnt.updates += fs.updates.len() as u64;
+        cnt.withdraws += fs.withdraws.len() as u64;
+    }
+    pub fn count_update(&mut self, upd: &BgpUpdateMessage) {
+        self.count_addrs(&upd.updates, false);
+        self.count_addrs(&upd.withdraws, true);
+        for i in upd.attrs.iter() {
+            match i {
+                BgpAttrItem::MPUpdates(n) => self.count_addrs(&n.addrs, false),
+                BgpAttrItem::MPWithdraws(n) => self.count_addrs(&n.addrs, true),
+                _ => {}
+            }
+        }
+    }
+}
+impl Default for BgpSessionStats {
+    fn default() -> Self {
+        Self::new()
     }
 }
 struct BgpSessionStorage {
     pub ss_ids: BTreeMap<BgpSessionId, Arc<BgpSessionDesc>>,
     pub ss_addrs: BTreeMap<Arc<BgpSessionDesc>, BgpSessionId>,
+    pub ss_stats: BTreeMap<BgpSessionId, BgpSessionStats>,
 }
 impl BgpSessionStorage {
     fn new() -> BgpSessionStorage {
         BgpSessionStorage {
             ss_ids: BTreeMap::new(),
             ss_addrs: BTreeMap::new(),
+            ss_stats: BTreeMap::new(),
         }
     }
-    fn register_session(&mut self, sess: Arc<BgpSessionDesc>) -> BgpSessionId {
-        if let Some(x) = self.ss_addrs.get_key_value(&sess) {
-            return *x.1;
+    fn find_session(&self, sess: &Arc<BgpSessionDesc>) -> Option<BgpSessionId> {
+        if let Some(x) = self.ss_addrs.get_key_value(sess) {
+            return Some(*x.1);
         }
-        let sessdsc = Arc::new(BgpSessionDesc::new(sess.peer2.clone(), sess.peer1.clone()));
-        if let Some(x) = self.ss_addrs.get_key_value(&sessdsc) {
-            return *x.1;
-        }
-        let mut nid: BgpSessionId = (self.ss_ids.len() + 1) as BgpSessionId;
-        while self.ss_ids.get_key_value(&nid).is_some() {
-            nid += 1;
-        }
-        self.ss_addrs.insert(sessdsc.clone(), nid);
-        self.ss_ids.insert(nid, sessdsc);
+        let sessdsc = Arc::new(sess.reversed());
+        self.ss_addrs.get_key_value(&sessdsc).map(|x| *x.1)
+    }
+    fn register_session(&mut self, sess: Arc<BgpSessionDesc>) -> BgpSessionId {
+        let nid = match self.find_session(&sess) {
+            Some(id) => id,
+            None => {
+                let sessdsc = Arc::new(sess.reversed());
+                let mut nid: BgpSessionId = (self.ss_ids.len() + 1) as BgpSessionId;
+                while self.ss_ids.get_key_value(&nid).is_some() {
+                    nid += 1;
+                }
+                self.ss_addrs.insert(sessdsc.clone(), nid);
+                self.ss_ids.insert(nid, sessdsc);
+                nid
+            }
+        };
+        // BGP session waits for KEEPALIVE after OPEN, BMP and MRT sessions are up already
+        self.ss_stats
+            .entry(nid)
+            .or_default()
+            .set_state(if sess.direct {
+                BgpSessionState::OpenConfirm
+            } else {
+                BgpSessionState::Established
+            });
         nid
     }
+    /// Sessions between the same addresses, previous incarnations of the session
+    fn sessions_by_addrs(&self, sess: &BgpSessionDesc) -> Vec<BgpSessionId> {
+        let mut addrs = [sess.peer1.addr, sess.peer2.addr];
+        addrs.sort();
+        self.ss_ids
+            .iter()
+            .filter(|(_, d)| {
+                let mut a = [d.peer1.addr, d.peer2.addr];
+                a.sort();
+                d.view == sess.view && a == addrs
+            })
+            .map(|(id, _)| *id)
+            .collect()
+    }
+    fn sessions_by_view(&self, view: BgpSessionView) -> BTreeSet<BgpSessionId> {
+        self.ss_ids
+            .iter()
+            .filter(|(_, d)| d.view == view)
+            .map(|(id, _)| *id)
+            .collect()
+    }
+    fn session_down(&mut self, sessid: BgpSessionId, reason: String) {
+        if let Some(st) = self.ss_stats.get_mut(&sessid) {
+            st.set_state(BgpSessionState::Idle);
+            st.last_error = Some(reason);
+        }
+    }
 }
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone, Copy)]
 pub enum BgpSessionState {
     Idle,
     Connect,
@@ -171,30 +455,330 @@ pub enum BgpSessionState {
     Established,
     BMP,
 }
+/// Reconnect state of an active mode peer
+pub struct BgpPeerRetry {
+    pub peer: SocketAddr,
+    pub mode: PeerMode,
+    pub disabled: bool,
+    pub attempts: u32,
+    pub last_error: Option<String>,
+    pub last_attempt: Option<DateTime<Local>>,
+    pub next_attempt: Option<DateTime<Local>>,
+    wakeup: Arc<tokio::sync::Notify>,
+}
+impl BgpPeerRetry {
+    pub fn new(peer: SocketAddr, mode: PeerMode) -> BgpPeerRetry {
+        BgpPeerRetry {
+            peer,
+            mode,
+            disabled: false,
+            attempts: 0,
+            last_error: None,
+            last_attempt: None,
+            next_attempt: None,
+            wakeup: Arc::new(tokio::sync::Notify::new()),
+        }
+    }
+    /// Exponential backoff without jitter, initial doubled for every failed attempt up to max
+    fn backoff_limit(&self, initial: chrono::Duration, max: chrono::Duration) -> chrono::Duration {
+        let mut delay = initial;
+        for _ in 0..self.attempts.min(16) {
+            delay = delay * 2;
+            if delay >= max {
+                return max;
+            }
+        }
+        delay
+    }
+    /// Exponential backoff with RFC 4271 jitter 0.75..1.0
+    pub fn backoff(&self, initial: chrono::Duration, max: chrono::Duration) -> chrono::Duration {
+        self.backoff_limit(initial, max) * rand::random_range(750..=1000) / 1000
+    }
+}
+/// Transport connection to a peer, alive until its task finishes
+pub struct BgpConnection {
+    pub listen: Option<SocketAddr>,
+    pub mode: PeerMode,
+    pub since: DateTime<Local>,
+    pub state: BgpSessionState,
+    pub session: Option<BgpSessionId>,
+    cancel: tokio_util::sync::CancellationToken,
+    commands: Option<Sender<BgpPeerCommand>>,
+}
 pub struct BgpSvr {
     pub config: Arc<SvcConfig>,
     pub cancellation: tokio_util::sync::CancellationToken,
     pub rib: BgpRIBts,
-    pub session_state: std::sync::Mutex<BgpSessionState>,
+    connections: std::sync::Mutex<BTreeMap<SocketAddr, BgpConnection>>,
     sessions: Arc<RwLock<BgpSessionStorage>>,
-    upd: Option<Sender<Option<(BgpSessionId, BgpUpdateMessage)>>>,
+    bmp: RwLock<BmpStations>,
+    upd: Option<Sender<Option<BgpRibUpdate>>>,
     updater: Option<JoinHandle<()>>,
+    /// records for MRT archive writer thread
+    mrt_archive: Option<Sender<(Timestamp, Vec<u8>)>>,
+    retries: std::sync::Mutex<BTreeMap<SocketAddr, BgpPeerRetry>>,
 }
 #[async_trait]
 impl BgpUpdateHandler for BgpSvr {
-    async fn handle_update(&self, sid: BgpSessionId, upd: BgpUpdateMessage) {
+    async fn handle_update(&self, sid: BgpSessionId, upd: BgpUpdateMessage, when: Timestamp) {
+        let desc = {
+            let mut sess = self.sessions.write().await;
+            if let Some(st) = sess.ss_stats.get_mut(&sid) {
+                st.count_update(&upd);
+            }
+            sess.ss_ids.get(&sid).cloned()
+        };
+        if let (Some(arch), Some(desc)) = (self.mrt_archive.as_ref(), desc) {
+            match MrtArchive::encode_update(&when, &desc.peer2, &desc.peer1, &upd) {
+                Ok(rec) => {
+                    if let Err(e) = arch.send((when, rec)).await {
+                        warn!("MRT archive queue error: {:?}", e);
+                    }
+                }
+                Err(e) => warn!("MRT archive encode error: {}", e),
+            }
+        }
         match self.upd {
             None => warn!("Skip update"),
-            Some(ref updch) => match updch.send(Some((sid, upd))).await {
+            Some(ref updch) => match updch.send(Some(BgpRibUpdate::Update(sid, upd, when))).await {
                 Ok(_) => {}
                 Err(e) => warn!("Queued update error: {:?}", e),
             },
         };
     }
+    async fn handle_rtc(
+        &self,
+        sid: BgpSessionId,
+        upd: BgpUpdateMessage,
+        rtc: RtcUpdate,
+        when: Timestamp,
+    ) {
+        if let Some(st) = self.sessions.write().await.ss_stats.get_mut(&sid) {
+            st.count_rtc(&rtc);
+            st.count_update(&upd);
+        }
+        match self.upd {
+            None => warn!("Skip update"),
+            Some(ref updch) => {
+                if let Err(e) = updch
+                    .send(Some(BgpRibUpdate::Rtc(sid, upd, rtc, when)))
+                    .await
+                {
+                    warn!("Queued update error: {:?}", e);
+                }
+            }
+        };
+    }
+    async fn handle_fsv6u(
+        &self,
+        sid: BgpSessionId,
+        upd: BgpUpdateMessage,
+        fs: FsV6uUpdate,
+        when: Timestamp,
+    ) {
+        if let Some(st) = self.sessions.write().await.ss_stats.get_mut(&sid) {
+            st.count_fsv6u(&fs);
+            st.count_update(&upd);
+        }
+        match self.upd {
+            None => warn!("Skip update"),
+            Some(ref updch) => {
+                if let Err(e) = updch
+                    .send(Some(BgpRibUpdate::FsV6u(sid, upd, fs, when)))
+                    .await
+                {
+                    warn!("Queued update error: {:?}", e);
+                }
+            }
+        };
+    }
     async fn register_session(&self, sess: Arc<BgpSessionDesc>) -> BgpSessionId {
-        self.sessions.write().await.register_session(sess)
+        let role = sess.role.map(|r| (r, sess.peer2.as_num()));
+        let (sessid, previous) = {
+            let mut sessions = self.sessions.write().await;
+            let sessid = sessions.register_session(sess.clone());
+            (sessid, sessions.sessions_by_addrs(&sess))
+        };
+        // RFC 4724 4.2, stale routes not preserved by restarted neighbor are flushed now
+        if sess.view == BgpSessionView::Bgp {
+            if let Some(updch) = self.upd.as_ref() {
+                if let Err(e) = updch
+                    .send(Some(BgpRibUpdate::SessionRestart(
+                        sessid,
+                        previous,
+                        sess.gr_forwarding.clone(),
+                    )))
+                    .await
+                {
+                    warn!("Queued session restart error: {:?}", e);
+                }
+            }
+        }
+        // historical MRT imports are not analyzed for alerts
+        if sess.imported {
+            if let Some(updch) = self.upd.as_ref() {
+                if let Err(e) = updch
+                    .send(Some(BgpRibUpdate::SessionImported(sessid)))
+                    .await
+                {
+                    warn!("Queued session imported error: {:?}", e);
+                }
+            }
+        }
+        if let (Some((role, peer_as)), Some(updch)) = (role, self.upd.as_ref()) {
+            if let Err(e) = updch
+                .send(Some(BgpRibUpdate::SessionRole(sessid, role, peer_as)))
+                .await
+            {
+                warn!("Queued session role error: {:?}", e);
+            }
+        }
+        sessid
+    }
+    async fn session_established(&self, sessid: BgpSessionId) {
+        info!("Session {} established", sessid);
+        if let Some(st) = self.sessions.write().await.ss_stats.get_mut(&sessid) {
+            st.set_state(BgpSessionState::Established);
+        }
+        let conn = self
+            .connections
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|(_, c)| c.session == Some(sessid))
+            .map(|(a, _)| *a);
+        if let Some(conn) = conn {
+            self.set_state(&conn, BgpSessionState::Established);
+        }
+    }
+    async fn session_down(&self, sessid: BgpSessionId, reason: String) {
+        info!("Session {} down: {}", sessid, reason);
+        self.sessions.write().await.session_down(sessid, reason);
+        if let Some(ref updch) = self.upd {
+            if let Err(e) = updch.send(Some(BgpRibUpdate::SessionDown(sessid))).await {
+                warn!("Queued session down error: {:?}", e);
+            }
+        }
+    }
+    async fn session_stale(
+        &self,
+        sessid: BgpSessionId,
+        reason: String,
+        restart_time: u16,
+        retain: Vec<BgpRibKind>,
+    ) {
+        info!(
+            "Session {} down: {}, graceful restart in {}s",
+            sessid, reason, restart_time
+        );
+        self.sessions
+            .write()
+            .await
+            .session_down(sessid, format!("{} (graceful restart)", reason));
+        let updch = match self.upd {
+            None => return,
+            Some(ref updch) => updch.clone(),
+        };
+        let since = Timestamp::now();
+        if let Err(e) = updch
+            .send(Some(BgpRibUpdate::SessionStale(sessid, retain, since)))
+            .await
+        {
+            warn!("Queued session stale error: {:?}", e);
+            return;
+        }
+        let sessions = self.sessions.clone();
+        let cancel = self.cancellation.clone();
+        tokio::spawn(async move {
+            tokio::select! {
+                _ = cancel.cancelled() => return,
+                _ = tokio::time::sleep(std::time::Duration::from_secs(restart_time as u64)) => {}
+            }
+            let established = sessions
+                .read()
+                .await
+                .ss_stats
+                .get(&sessid)
+                .map(|st| st.state == BgpSessionState::Established)
+                .unwrap_or(false);
+            if established {
+                // restarted, stale routes are withdrawn on End-of-RIB or stale path timer
+                tokio::select! {
+                    _ = cancel.cancelled() => return,
+                    _ = tokio::time::sleep(std::time::Duration::from_secs(BGP_STALE_PATH_TIME)) => {}
+                }
+            }
+            if let Err(e) = updch
+                .send(Some(BgpRibUpdate::StaleTimeout(sessid, since)))
+                .await
+            {
+                warn!("Queued stale timeout error: {:?}", e);
+            }
+        });
+    }
+    async fn bmp_event(&self, station: IpAddr, ev: BmpStationEvent) {
+        self.bmp.write().await.handle_event(station, ev)
+    }
+    async fn route_refresh(
+        &self,
+        sessid: BgpSessionId,
+        kind: BgpRibKind,
+        begin: bool,
+        when: Timestamp,
+    ) {
+        info!(
+            "Session {} {} {}",
+            sessid,
+            kind,
+            if begin { "BoRR" } else { "EoRR" }
+        );
+        if let Some(ref updch) = self.upd {
+            if let Err(e) = updch
+                .send(Some(BgpRibUpdate::RouteRefresh(sessid, kind, begin, when)))
+                .await
+            {
+                warn!("Queued route refresh error: {:?}", e);
+            }
+        }
+    }
+}
+/// Prefixes per MRT dump chunk, RIB lock is released between chunks
+const MRT_DUMP_CHUNK: usize = 1000;
+
+/// Sends TABLE_DUMP_V2 of RIB table to HTTP body chunk by chunk
+async fn stream_dump<T: BgpRIBKey + MrtPrefix>(
+    rib: &RwLock<BgpRIB>,
+    locktimeout: std::time::Duration,
+    table: fn(&BgpRIB) -> &BgpRIBSafi<T>,
+    mut dump: TableDump,
+    header: Vec<u8>,
+    tx: &mut hyper::body::Sender,
+) -> Result<(), String> {
+    tx.send_data(header.into())
+        .await
+        .map_err(|e| e.to_string())?;
+    let mut after: Option<T> = None;
+    loop {
+        let (chunk, last) = {
+            let rib = match timeout(locktimeout, rib.read()).await {
+                Ok(r) => r,
+                Err(_) => return Err("RIB lock timed out".to_string()),
+            };
+            dump.chunk(table(&rib), after.as_ref(), MRT_DUMP_CHUNK)
+                .map_err(|e| format!("{:?}", e))?
+        };
+        if !chunk.is_empty() {
+            tx.send_data(chunk.into())
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+        match last {
+            None => return Ok(()),
+            Some(l) => after = Some(l),
+        }
     }
 }
+
 impl BgpSvr {
     pub fn new(cfg: Arc<SvcConfig>, cancel_token: tokio_util::sync::CancellationToken) -> BgpSvr {
         let rib = match cfg.snapshot_file {
@@ -212,14 +796,34 @@ impl BgpSvr {
             cancellation: cancel_token,
             rib: BgpRIBts::new(&cfg, rib),
             sessions: Arc::new(RwLock::new(BgpSessionStorage::new())),
-            session_state: std::sync::Mutex::new(BgpSessionState::Idle),
+            bmp: RwLock::new(BmpStations::new(cfg.bmp_stats_depth, cfg.bmp_mirror_depth)),
+            connections: std::sync::Mutex::new(BTreeMap::new()),
             upd: None,
             updater: None,
+            mrt_archive: cfg.mrt_archive.as_ref().map(|s| {
+                let (tx, rx) = channel(1000);
+                MrtArchive::new(s.clone(), cfg.mrt_archive_every).run(rx);
+                tx
+            }),
+            retries: std::sync::Mutex::new(
+                cfg.peers
+                    .iter()
+                    .filter(|p| p.mode == PeerMode::BgpActive || p.mode == PeerMode::BmpActive)
+                    .filter_map(|p| p.peer.map(|a| (a, BgpPeerRetry::new(a, p.mode.clone()))))
+                    .collect(),
+            ),
         }
     }
     pub async fn subscribe_bgp(&self) -> tokio::sync::broadcast::Receiver<BgpEvent> {
         self.rib.rib.read().await.events.subscribe()
     }
+    /// Queues update for RIB handler, false if it is stopped
+    pub async fn queue_rib_update(&self, upd: BgpRibUpdate) -> bool {
+        match self.upd.as_ref() {
+            Some(updch) => updch.send(Some(upd)).await.is_ok(),
+            None => false,
+        }
+    }
     pub async fn start_updates(&mut self) {
         if self.updater.is_some() {
             return;
@@ -228,9 +832,134 @@ impl BgpSvr {
         self.upd = Some(tx);
         self.updater = Some(self.rib.run(rx));
     }
-    pub fn set_state(&self, new_state: BgpSessionState) {
-        let mut wg = self.session_state.lock().unwrap();
-        *wg = new_state;
+    pub fn set_state(&self, conn: &SocketAddr, new_state: BgpSessionState) {
+        if let Some(c) = self.connections.lock().unwrap().get_mut(conn) {
+            c.state = new_state;
+        }
+        if new_state == BgpSessionState::Established || new_state == BgpSessionState::BMP {
+            if let Some(r) = self.retries.lock().unwrap().get_mut(conn) {
+                r.attempts = 0;
+                r.last_error = None;
+            }
+        }
+    }
+    /// Attaches established BGP session and its command channel to connection
+    fn set_session(&self, conn: &SocketAddr, session: BgpSessionId) -> Receiver<BgpPeerCommand> {
+        let (tx, rx) = channel(8);
+        if let Some(c) = self.connections.lock().unwrap().get_mut(conn) {
+            c.session = Some(session);
+            c.commands = Some(tx);
+        }
+        rx
+    }
+    /// Records connection attempt result and returns delay before the next one
+    fn retry_schedule(
+        &self,
+        fpeer: &ProtoPeer,
+        peeraddr: &SocketAddr,
+        err: Option<String>,
+    ) -> std::time::Duration {
+        let mut retries = self.retries.lock().unwrap();
+        let r = match retries.get_mut(peeraddr) {
+            Some(r) => r,
+            None => return std::time::Duration::from_secs(1),
+        };
+        let delay = r.backoff(fpeer.connect_retry, fpeer.connect_retry_max);
+        if err.is_some() {
+            r.last_error = err;
+        }
+        r.attempts += 1;
+        r.next_attempt = Some(Local::now() + delay);
+        info!(
+            "Peer {} reconnect attempt {} in {}s",
+            peeraddr,
+            r.attempts,
+            delay.num_seconds()
+        );
+        delay.to_std().unwrap_or(std::time::Duration::from_secs(1))
+    }
+    /// Administratively disables or enables active peer, returns false for unknown peer
+    pub fn set_peer_disabled(&self, peer: &IpAddr, disabled: bool) -> bool {
+        let mut found = false;
+        for r in self
+            .retries
+            .lock()
+            .unwrap()
+            .values_mut()
+            .filter(|r| r.peer.ip() == *peer)
+        {
+            found = true;
+            r.disabled = disabled;
+            if disabled {
+                r.next_attempt = None;
+                if let Some(c) = self.connections.lock().unwrap().get(&r.peer) {
+                    c.cancel.cancel();
+                }
+            } else {
+                r.attempts = 0;
+            }
+            r.wakeup.notify_waiters();
+        }
+        found
+    }
+    async fn run_peer_retry(self: Arc<Self>, fpeer: Arc<ProtoPeer>) {
+        let peeraddr = match fpeer.peer {
+            None => {
+                error!("No peer parameter");
+                return;
+            }
+            Some(l) => l,
+        };
+        let wakeup = match self.retries.lock().unwrap().get(&peeraddr) {
+            Some(r) => r.wakeup.clone(),
+            None => return,
+        };
+        let canceltok = self.cancellation.clone();
+        loop {
+            let notified = wakeup.notified();
+            let disabled = match self.retries.lock().unwrap().get_mut(&peeraddr) {
+                Some(r) => {
+                    if !r.disabled {
+                        r.last_attempt = Some(Local::now());
+                        r.next_attempt = None;
+                    }
+                    r.disabled
+                }
+                None => false,
+            };
+            if disabled {
+                select! {
+                    _ = canceltok.cancelled() => {
+                        return;
+                    }
+                    _ = notified => {
+                        continue;
+                    }
+                }
+            }
+            let res = select! {
+                _ = canceltok.cancelled() => {
+                    return;
+                }
+                res = self.clone().run_peer_active(fpeer.clone()) => res
+            };
+            let err = match res {
+                Ok(_) => None,
+                Err(e) => {
+                    warn!("Peer {} connection failed: {}", peeraddr, e);
+                    Some(e.to_string())
+                }
+            };
+            let notified = wakeup.notified();
+            let delay = self.retry_schedule(&fpeer, &peeraddr, err);
+            select! {
+                _ = canceltok.cancelled() => {
+                    return;
+                }
+                _ = tokio::time::sleep(delay) => {}
+                _ = notified => {}
+            }
+        }
     }
     pub async fn run_listen(self: Arc<Self>, sockaddr: SocketAddr) -> io::Result<()> {
         let socket = if sockaddr.is_ipv4() {
@@ -238,77 +967,154 @@ impl BgpSvr {
         } else {
             TcpSocket::new_v6()?
         };
+        for p in self.config.peers.iter().filter(|p| {
+            (p.mode == PeerMode::BgpPassive || p.mode == PeerMode::BmpPassive)
+                && p.protolisten == Some(sockaddr)
+        }) {
+            if let Some(ref password) = p.password {
+                let mut prefixes = p.neighbor_prefixes();
+                if prefixes.is_empty() {
+                    // key for any neighbor of listen address family
+                    prefixes.push((IpAddr::V4(std::net::Ipv4Addr::UNSPECIFIED), 0));
+                    if sockaddr.is_ipv6() {
+                        prefixes.push((IpAddr::V6(std::net::Ipv6Addr::UNSPECIFIED), 0));
+                    }
+                }
+                for (addr, len) in prefixes.iter() {
+                    let (addr, len) = match listen_key(&sockaddr, addr, *len) {
+                        Some(k) => k,
+                        None => {
+                            return Err(io::Error::new(
+                                io::ErrorKind::InvalidInput,
+                                format!(
+                                    "IPv6 neighbor {}/{} can not be keyed on IPv4 listen {}",
+                                    addr, len, sockaddr
+                                ),
+                            ));
+                        }
+                    };
+                    let hostlen = if addr.is_ipv4() { 32 } else { 128 };
+                    set_tcp_md5(
+                        &socket,
+                        &addr,
+                        if len == hostlen { None } else { Some(len) },
+                        password,
+                    )?;
+                }
+            }
+        }
         socket.bind(sockaddr)?;
         info!("Listening on {}", sockaddr);
-        self.set_state(BgpSessionState::Idle);
-        let listener = socket.listen(1)?;
+        let listener = socket.listen(32)?;
         loop {
             let client = match listener.accept().await {
                 Ok(acc) => acc,
                 Err(e) => return Err(e),
             };
-            self.set_state(BgpSessionState::Connect);
             info!("Incoming connected from {}", client.1);
-            let fpeer: Arc<ProtoPeer> = match self.config.peers.iter().find(|p| {
-                if p.mode == PeerMode::BgpPassive || p.mode == PeerMode::BmpPassive {
-                    if let Some(sa) = p.protolisten {
-                        if sa == sockaddr {
-                            return true;
-                        }
-                    }
-                };
-                false
-            }) {
-                Some(x) => x.clone(),
+            // most specific neighbor prefix wins
+            let fpeer: Arc<ProtoPeer> = match self
+                .config
+                .peers
+                .iter()
+                .filter(|p| {
+                    (p.mode == PeerMode::BgpPassive || p.mode == PeerMode::BmpPassive)
+                        && p.protolisten == Some(sockaddr)
+                })
+                .filter_map(|p| p.neighbor_match(&client.1.ip()).map(|l| (l, p)))
+                .max_by_key(|(l, _)| *l)
+            {
+                Some((_, x)) => x.clone(),
                 None => {
-                    error!(
-                        "Could not found matching peer for {} @{}",
+                    warn!(
+                        "Refused connection from unknown neighbor {} @{}",
                         client.1, sockaddr
                     );
                     continue;
                 }
             };
-            match fpeer.mode {
-                PeerMode::BmpPassive => {
-                    self.set_state(BgpSessionState::BMP);
-                    let mut peer = BmpPeer::new(client.0, fpeer, &*self);
-                    peer.lifecycle(self.cancellation.clone()).await;
-                    self.set_state(BgpSessionState::Idle);
-                    peer.close().await;
-                }
-                PeerMode::BgpPassive => {
-                    let mut peer = BgpPeer::new(
-                        BgpSessionParams::new(
-                            fpeer.bgppeeras,
-                            180,
-                            if client.1.is_ipv4() {
-                                BgpTransportMode::IPv4
-                            } else {
-                                BgpTransportMode::IPv6
-                            },
-                            fpeer.routerid,
-                            ProtoPeer::all_caps(fpeer.bgppeeras),
-                        ),
-                        client.0,
-                        &*self,
-                    );
-                    let mut scs: bool = true;
-                    self.set_state(BgpSessionState::OpenSent);
-                    if let Err(e) = peer.start_passive().await {
-                        error!("failed to create BGP peer; err = {:?}", e);
-                        scs = false;
+            let cancel = self.cancellation.child_token();
+            self.connections.lock().unwrap().insert(
+                client.1,
+                BgpConnection {
+                    listen: Some(sockaddr),
+                    mode: fpeer.mode.clone(),
+                    since: Local::now(),
+                    state: BgpSessionState::Connect,
+                    session: None,
+                    cancel: cancel.clone(),
+                    commands: None,
+                },
+            );
+            let slf = self.clone();
+            tokio::spawn(async move {
+                slf.run_incoming(client.0, client.1, fpeer, cancel).await;
+                slf.connections.lock().unwrap().remove(&client.1);
+            });
+        }
+    }
+    async fn run_incoming(
+        &self,
+        stream: tokio::net::TcpStream,
+        peeraddr: SocketAddr,
+        fpeer: Arc<ProtoPeer>,
+        cancel: tokio_util::sync::CancellationToken,
+    ) {
+        match fpeer.mode {
+            PeerMode::BmpPassive => {
+                let mut peer = match BmpPeer::new(stream, fpeer, self) {
+                    Ok(p) => p,
+                    Err(e) => {
+                        warn!("BMP session {} failed: {}", peeraddr, e);
+                        return;
                     }
-                    if scs {
-                        self.set_state(BgpSessionState::Established);
-                        peer.lifecycle(self.cancellation.clone()).await;
-                        info!("Session done {}", client.1);
-                    };
-                    self.set_state(BgpSessionState::Idle);
-                    peer.close().await;
+                };
+                self.set_state(&peeraddr, BgpSessionState::BMP);
+                peer.lifecycle(cancel).await;
+                info!("BMP session done {}", peeraddr);
+                peer.close().await;
+            }
+            PeerMode::BgpPassive => {
+                let mut peer = BgpPeer::new(
+                    BgpSessionParams::new(
+                        fpeer.bgppeeras,
+                        fpeer.hold_time,
+                        if peeraddr.is_ipv4() {
+                            BgpTransportMode::IPv4
+                        } else {
+                            BgpTransportMode::IPv6
+                        },
+                        fpeer.routerid,
+                        ProtoPeer::all_caps(fpeer.bgppeeras),
+                    ),
+                    stream,
+                    self,
+                );
+                peer.extended_message = fpeer.extended_message;
+                peer.graceful_restart = fpeer.graceful_restart;
+                peer.rtc = fpeer.rtc;
+                peer.fsv6u = fpeer.fsv6u;
+                peer.role = fpeer.role;
+                peer.remote_as = fpeer.bgppeeras;
+                peer.remote_router_id = fpeer.remote_routerid;
+                if fpeer.caps_configured {
+                    peer.passive_caps = Some(fpeer.def_caps(fpeer.bgppeeras));
                 }
-                _ => {}
+                let mut scs: bool = true;
+                self.set_state(&peeraddr, BgpSessionState::OpenSent);
+                if let Err(e) = peer.start_passive().await {
+                    error!("failed to create BGP peer; err = {:?}", e);
+                    scs = false;
+                }
+                if scs {
+                    self.set_state(&peeraddr, BgpSessionState::OpenConfirm);
+                    let commands = self.set_session(&peeraddr, peer.session_id());
+                    peer.lifecycle(cancel, Some(commands)).await;
+                    info!("Session done {}", peeraddr);
+                };
+                peer.close().await;
             }
-            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+            _ => {}
         }
     }
     pub async fn run_peer_active(self: Arc<Self>, fpeer: Arc<ProtoPeer>) -> io::Result<()> {
@@ -321,9 +1127,39 @@ impl BgpSvr {
             }
             Some(l) => l,
         };
-        self.set_state(BgpSessionState::Connect);
+        let cancel = self.cancellation.child_token();
+        self.connections.lock().unwrap().insert(
+            peeraddr,
+            BgpConnection {
+                listen: None,
+                mode: fpeer.mode.clone(),
+                since: Local::now(),
+                state: BgpSessionState::Connect,
+                session: None,
+                cancel: cancel.clone(),
+                commands: None,
+            },
+        );
+        let ret = self.run_outgoing(peeraddr, fpeer, cancel).await;
+        self.connections.lock().unwrap().remove(&peeraddr);
+        ret
+    }
+    async fn run_outgoing(
+        &self,
+        peeraddr: SocketAddr,
+        fpeer: Arc<ProtoPeer>,
+        cancel: tokio_util::sync::CancellationToken,
+    ) -> io::Result<()> {
         info!("Connecting to {}", peeraddr);
-        let peertcp = match tokio::net::TcpStream::connect(peeraddr).await {
+        let socket = if peeraddr.is_ipv4() {
+            TcpSocket::new_v4()?
+        } else {
+            TcpSocket::new_v6()?
+        };
+        if let Some(ref password) = fpeer.password {
+            set_tcp_md5(&socket, &peeraddr.ip(), None, password)?;
+        }
+        let peertcp = match socket.connect(peeraddr).await {
             Err(e) => {
                 return Err(e);
             }
@@ -332,30 +1168,33 @@ impl BgpSvr {
         info!("Connected to {}", peeraddr);
         match fpeer.mode {
             PeerMode::BmpActive => {
-                let mut peer = BmpPeer::new(peertcp, fpeer, &*self);
-                self.set_state(BgpSessionState::BMP);
-                peer.lifecycle(self.cancellation.clone()).await;
+                let mut peer = BmpPeer::new(peertcp, fpeer, self)?;
+                self.set_state(&peeraddr, BgpSessionState::BMP);
+                peer.lifecycle(cancel).await;
                 peer.close().await;
             }
             PeerMode::BgpActive => {
-                let mut peer = BgpPeer::new(fpeer.get_session_params(), peertcp, &*self);
-                let mut scs: bool = true;
-                self.set_state(BgpSessionState::OpenSent);
+                let mut peer = BgpPeer::new(fpeer.get_session_params(), peertcp, self);
+                peer.extended_message = fpeer.extended_message;
+                peer.graceful_restart = fpeer.graceful_restart;
+                peer.rtc = fpeer.rtc;
+                peer.fsv6u = fpeer.fsv6u;
+                peer.role = fpeer.role;
+                self.set_state(&peeraddr, BgpSessionState::OpenSent);
                 if let Err(e) = peer.start_active().await {
                     fpeer.set_session_params(peer.params.clone());
                     warn!("failed to create BGP peer; err = {:?}", e);
-                    scs = false;
+                    peer.close().await;
+                    return Err(std::io::Error::other(format!("{:?}", e)));
                 }
-                self.set_state(BgpSessionState::OpenConfirm);
-                if scs {
-                    peer.lifecycle(self.cancellation.clone()).await;
-                    info!("Session done {}", peeraddr);
-                };
+                self.set_state(&peeraddr, BgpSessionState::OpenConfirm);
+                let commands = self.set_session(&peeraddr, peer.session_id());
+                peer.lifecycle(cancel, Some(commands)).await;
+                info!("Session done {}", peeraddr);
                 peer.close().await;
             }
             _ => {}
         }
-        self.set_state(BgpSessionState::Idle);
         Ok(())
     }
     pub async fn run(self: Arc<Self>) {
@@ -388,27 +1227,68 @@ impl BgpSvr {
         }
         for p in self.config.peers.iter() {
             if p.mode == PeerMode::BgpActive || p.mode == PeerMode::BmpActive {
-                let _slf = self.clone();
-                let _p = p.clone();
+                tokio::spawn(self.clone().run_peer_retry(p.clone()));
+            }
+        }
+        for p in self.config.peers.iter() {
+            if p.mode == PeerMode::MrtFile {
+                let slf = self.clone();
+                let p = p.clone();
                 tokio::spawn(async move {
-                    let canceltok = _slf.cancellation.clone();
-                    let slf1 = _slf.clone();
-                    loop {
-                        let slf = slf1.clone();
-                        select! {
-                              _ = canceltok.cancelled() => {
-                                return;
-                              }
-                            _ = slf.run_peer_active(_p.clone()) => {
-                                tokio::time::sleep(std::time::Duration::from_millis(100)).await;
-                            }
-                        }
+                    let cancel = slf.cancellation.child_token();
+                    let mut mrt = MrtFile::new(p, slf.as_ref());
+                    if let Err(e) = mrt.lifecycle(cancel).await {
+                        error!("MRT import error: {}", e);
                     }
                 });
             }
         }
+        if let Some(upd) = self.upd.as_ref() {
+            if let Some(cache) = self.config.rpki_rtr.as_ref() {
+                tokio::spawn(rpki::run_rtr(
+                    cache.clone(),
+                    self.config.rpki_rtr_refresh,
+                    upd.clone(),
+                    self.cancellation.child_token(),
+                ));
+            }
+            let every = std::time::Duration::from_secs(self.config.rpki_file_reload.max(1));
+            if let Some(path) = self.config.rpki_file.as_ref() {
+                tokio::spawn(rpki::run_rpki_file(
+                    path.clone(),
+                    every,
+                    |buf| {
+                        let vrps = rpki::parse_vrp_json(buf)?;
+                        Ok(BgpRibUpdate::Vrps(rpki::VrpSource::File, vrps))
+                    },
+                    upd.clone(),
+                    self.cancellation.child_token(),
+                ));
+            }
+            if let Some(path) = self.config.aspa_file.as_ref() {
+                tokio::spawn(rpki::run_rpki_file(
+                    path.clone(),
+                    every,
+                    |buf| Ok(BgpRibUpdate::Aspas(rpki::parse_aspa_json(buf)?)),
+                    upd.clone(),
+                    self.cancellation.child_token(),
+                ));
+            }
+            if let Some(path) = self.config.bogon_file.as_ref() {
+                tokio::spawn(rpki::run_rpki_file(
+                    path.clone(),
+                    every,
+                    bogon::parse_bogon_list,
+                    upd.clone(),
+                    self.cancellation.child_token(),
+                ));
+            }
+        }
     }
     pub async fn shutdown(&self) {
+        for conn in self.connections.lock().unwrap().values() {
+            conn.cancel.cancel();
+        }
         self.rib.shutdown().await
     }
     pub async fn close(mut self) {
@@ -427,7 +1307,28 @@ impl BgpSvr {
         self.updater = None;
     }
     pub async fn say_state(&self) -> Result<Response<Body>, hyper::http::Error> {
-        let state = format!("{:?}", self.session_state.lock().unwrap());
+        let sess = match timeout(std::time::Duration::new(5, 0), self.sessions.read()).await {
+            Ok(r) => r,
+            Err(_) => {
+                return Response::builder()
+                    .status(StatusCode::from_u16(408).unwrap())
+                    .header("Content-type", "text/plain")
+                    .body("Operation timed out".into());
+            }
+        };
+        let mut states: BTreeMap<String, usize> = BTreeMap::new();
+        for st in sess.ss_stats.values() {
+            *states.entry(format!("{:?}", st.state)).or_insert(0) += 1;
+        }
+        let state = if states.is_empty() {
+            format!("{:?}", BgpSessionState::Idle)
+        } else {
+            states
+                .iter()
+                .map(|(k, v)| format!("{}:{}", k, v))
+                .collect::<Vec<String>>()
+                .join(" ")
+        };
         Response::builder()
             .status(StatusCode::OK)
             .header("Content-type", "text/plain")
@@ -443,7 +1344,196 @@ impl BgpSvr {
                     .body("Operation timed out".into());
             }
         };
-        match serde_json::to_vec(&*sess) {
+        let (prefixes, synced) = match timeout(self.rib.locktimeout, self.rib.rib.read()).await {
+            Ok(r) => (r.session_prefixes(), r.synced.clone()),
+            Err(_) => {
+                return Response::builder()
+                    .status(StatusCode::from_u16(408).unwrap())
+                    .header("Content-type", "text/plain")
+                    .body("Operation timed out".into());
+            }
+        };
+        let retries = self.retries.lock().unwrap();
+        let rsp = BgpSessionsResponse {
+            sessions: &sess,
+            prefixes: &prefixes,
+            synced: &synced,
+            retries: &retries,
+        };
+        match serde_json::to_vec(&rsp) {
+            Ok(v) => Response::builder()
+                .status(StatusCode::OK)
+                .header("Content-type", "text/json")
+                .body(v.into()),
+            Err(e) => Response::builder()
+                .status(StatusCode::from_u16(500).unwrap())
+                .header("Content-type", "text/plain")
+                .body(format!("Error: {:?}", e).into()),
+        }
+    }
+    pub async fn say_connections(&self) -> Result<Response<Body>, hyper::http::Error> {
+        let conns: Vec<BTreeMap<&str, String>> = self
+            .connections
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(peer, conn)| {
+                let mut m = BTreeMap::new();
+                m.insert("peer", peer.to_string());
+                if let Some(ref l) = conn.listen {
+                    m.insert("listen", l.to_string());
+                }
+                m.insert("mode", format!("{:?}", conn.mode));
+                m.insert("state", format!("{:?}", conn.state));
+                m.insert("since", conn.since.to_rfc3339());
+                m
+            })
+            .collect();
+        match serde_json::to_vec(&conns) {
+            Ok(v) => Response::builder()
+                .status(StatusCode::OK)
+                .header("Content-type", "text/json")
+                .body(v.into()),
+            Err(e) => Response::builder()
+                .status(StatusCode::from_u16(500).unwrap())
+                .header("Content-type", "text/plain")
+                .body(format!("Error: {:?}", e).into()),
+        }
+    }
+    pub async fn say_refresh(
+        &self,
+        sessid: &str,
+        ribname: &str,
+    ) -> Result<Response<Body>, hyper::http::Error> {
+        let kind: BgpRibKind = match ribname.parse() {
+            Ok(k) => k,
+            Err(_) => {
+                return Response::builder()
+                    .status(StatusCode::BAD_REQUEST)
+                    .header("Content-type", "text/plain")
+                    .body(format!("Invalid RIB: {}", ribname).into());
+            }
+        };
+        let sessid: BgpSessionId = match sessid.parse() {
+            Ok(s) => s,
+            Err(_) => return Ok(not_found()),
+        };
+        let commands = self
+            .connections
+            .lock()
+            .unwrap()
+            .values()
+            .find(|c| c.session == Some(sessid))
+            .and_then(|c| c.commands.clone());
+        let commands = match commands {
+            Some(c) => c,
+            None => {
+                return Response::builder()
+                    .status(StatusCode::NOT_FOUND)
+                    .header("Content-type", "text/plain")
+                    .body(format!("No established BGP session {}", sessid).into());
+            }
+        };
+        match commands.send(BgpPeerCommand::RouteRefresh(kind)).await {
+            Ok(_) => Response::builder()
+                .status(StatusCode::OK)
+                .header("Content-type", "text/plain")
+                .body(format!("Route refresh {} requested for session {}", kind, sessid).into()),
+            Err(e) => Response::builder()
+                .status(StatusCode::from_u16(500).unwrap())
+                .header("Content-type", "text/plain")
+                .body(format!("Error: {:?}", e).into()),
+        }
+    }
+    pub async fn say_peers(
+        &self,
+        urlparts: &[&str],
+        req: &Request<Body>,
+    ) -> Result<Response<Body>, hyper::http::Error> {
+        if urlparts.len() >= 5 {
+            if req.method() != Method::POST {
+                return Ok(method_not_allowed());
+            }
+            let peer: IpAddr = match urlparts[3].parse::<IpAddr>() {
+                Ok(a) => a,
+                Err(_) => match urlparts[3].parse::<SocketAddr>() {
+                    Ok(a) => a.ip(),
+                    Err(_) => {
+                        return Response::builder()
+                            .status(StatusCode::BAD_REQUEST)
+                            .header("Content-type", "text/plain")
+                            .body(format!("Invalid peer: {}", urlparts[3]).into());
+                    }
+                },
+            };
+            let disabled = match urlparts[4] {
+                "disable" => true,
+                "enable" => false,
+                _ => return Ok(not_found()),
+            };
+            if !self.set_peer_disabled(&peer, disabled) {
+                return Ok(not_found());
+            }
+        }
+        let retries = self.retries.lock().unwrap();
+        let peers: Vec<&BgpPeerRetry> = retries.values().collect();
+        match serde_json::to_vec(&peers) {
+            Ok(v) => Response::builder()
+                .status(StatusCode::OK)
+                .header("Content-type", "text/json")
+                .body(v.into()),
+            Err(e) => Response::builder()
+                .status(StatusCode::from_u16(500).unwrap())
+                .header("Content-type", "text/plain")
+                .body(format!("Error: {:?}", e).into()),
+        }
+    }
+    pub async fn say_bmp(&self, req: &Request<Body>) -> Result<Response<Body>, hyper::http::Error> {
+        let bmp = match timeout(std::time::Duration::new(5, 0), self.bmp.read()).await {
+            Ok(r) => r,
+            Err(_) => {
+                return Response::builder()
+                    .status(StatusCode::from_u16(408).unwrap())
+                    .header("Content-type", "text/plain")
+                    .body("Operation timed out".into());
+            }
+        };
+        let rsp = BmpStationsResponse {
+            stations: &bmp,
+            history: get_url_param(&get_url_params(req), "history").unwrap_or(0),
+        };
+        match serde_json::to_vec(&rsp) {
+            Ok(v) => Response::builder()
+                .status(StatusCode::OK)
+                .header("Content-type", "text/json")
+                .body(v.into()),
+            Err(e) => Response::builder()
+                .status(StatusCode::from_u16(500).unwrap())
+                .header("Content-type", "text/plain")
+                .body(format!("Error: {:?}", e).into()),
+        }
+    }
+    pub async fn say_bmp_mirror(
+        &self,
+        req: &Request<Body>,
+    ) -> Result<Response<Body>, hyper::http::Error> {
+        let bmp = match timeout(std::time::Duration::new(5, 0), self.bmp.read()).await {
+            Ok(r) => r,
+            Err(_) => {
+                return Response::builder()
+                    .status(StatusCode::from_u16(408).unwrap())
+                    .header("Content-type", "text/plain")
+                    .body("Operation timed out".into());
+            }
+        };
+        let params = get_url_params(req);
+        let rsp = BmpMirrorResponse {
+            stations: &bmp,
+            station: get_url_param(&params, "station"),
+            peer: get_url_param(&params, "peer"),
+            limit: get_url_param(&params, "limit").unwrap_or(usize::MAX),
+        };
+        match serde_json::to_vec(&rsp) {
             Ok(v) => Response::builder()
                 .status(StatusCode::OK)
                 .header("Content-type", "text/json")
@@ -454,6 +1544,68 @@ impl BgpSvr {
                 .body(format!("Error: {:?}", e).into()),
         }
     }
+    pub async fn say_mrt(&self, ribname: &str) -> Result<Response<Body>, hyper::http::Error> {
+        let peers: Vec<(BgpSessionId, MrtPeer)> =
+            match timeout(std::time::Duration::new(5, 0), self.sessions.read()).await {
+                Ok(r) => r
+                    .ss_ids
+                    .iter()
+                    .map(|(id, d)| (*id, MrtPeer::from_desc(&d.peer1)))
+                    .collect(),
+                Err(_) => {
+                    return Response::builder()
+                        .status(StatusCode::from_u16(408).unwrap())
+                        .header("Content-type", "text/plain")
+                        .body("Operation timed out".into());
+                }
+            };
+        let collector = match self.config.peers.first() {
+            Some(p) => p.routerid,
+            None => std::net::Ipv4Addr::new(0, 0, 0, 0),
+        };
+        let (body, mut tx) = match ribname {
+            "ipv4u" | "ipv4m" | "ipv6u" | "ipv6m" => {
+                let (tx, body) = Body::channel();
+                (body, tx)
+            }
+            _ => {
+                return Response::builder()
+                    .status(StatusCode::BAD_REQUEST)
+                    .header("Content-type", "text/plain")
+                    .body(format!("MRT dump is not supported for {}", ribname).into());
+            }
+        };
+        let (dump, header) = TableDump::new(ribname.ends_with('m'), ribname, collector, &peers);
+        let rib = self.rib.rib.clone();
+        let locktimeout = self.rib.locktimeout;
+        let name = ribname.to_string();
+        tokio::spawn(async move {
+            let res = match name.as_str() {
+                "ipv4u" => {
+                    stream_dump(&rib, locktimeout, |r| &r.ipv4u, dump, header, &mut tx).await
+                }
+                "ipv4m" => {
+                    stream_dump(&rib, locktimeout, |r| &r.ipv4m, dump, header, &mut tx).await
+                }
+                "ipv6u" => {
+                    stream_dump(&rib, locktimeout, |r| &r.ipv6u, dump, header, &mut tx).await
+                }
+                _ => stream_dump(&rib, locktimeout, |r| &r.ipv6m, dump, header, &mut tx).await,
+            };
+            if let Err(e) = res {
+                warn!("MRT dump {} aborted: {}", name, e);
+                tx.abort();
+            }
+        });
+        Response::builder()
+            .status(StatusCode::OK)
+            .header("Content-type", "application/octet-stream")
+            .header(
+                "Content-Disposition",
+                format!("attachment; filename=\"rib.{}.mrt\"", ribname),
+            )
+            .body(body)
+    }
     pub async fn handle_query(
         &self,
         req: &Request<Body>,
@@ -469,12 +1621,68 @@ impl BgpSvr {
         match urlparts[2] {
             "statistics" => self.rib.say_statistics().await,
             "sessions" => self.say_sessions().await,
+            "connections" => self.say_connections().await,
+            "peers" => self.say_peers(&urlparts, req).await,
+            "refresh" => {
+                if urlparts.len() < 5 {
+                    Ok(not_found())
+                } else if req.method() != Method::POST {
+                    Ok(method_not_allowed())
+                } else {
+                    self.say_refresh(urlparts[3], urlparts[4]).await
+                }
+            }
+            "bmp" => {
+                if urlparts.len() > 3 && urlparts[3] == "mirror" {
+                    self.say_bmp_mirror(req).await
+                } else {
+                    self.say_bmp(req).await
+                }
+            }
             "state" => self.say_state().await,
+            "alerts" => self.rib.say_alerts(&urlparts, req).await,
+            "mrt" => {
+                if urlparts.len() < 4 {
+                    Ok(not_found())
+                } else {
+                    self.say_mrt(urlparts[3]).await
+                }
+            }
+            "rtc" => {
+                if urlparts.len() < 4 {
+                    Ok(not_found())
+                } else {
+                    self.rib.say_rtc(urlparts[3], req).await
+                }
+            }
+            "top" => {
+                if urlparts.len() < 4 {
+                    Ok(not_found())
+                } else {
+                    self.rib.say_top(urlparts[3], req).await
+                }
+            }
             "json" => {
                 if urlparts.len() < 4 {
                     Ok(not_found())
                 } else {
-                    self.rib.say_jsonrib(urlparts[3], req).await
+                    let view = match get_url_param::<String>(&get_url_params(req), "view") {
+                        None => None,
+                        Some(v) => match v.parse::<BgpSessionView>() {
+                            Ok(v) => Some(v),
+                            Err(_) => {
+                                return Response::builder()
+                                    .status(StatusCode::BAD_REQUEST)
+                                    .header("Content-type", "text/plain")
+                                    .body(format!("Invalid view: {}", v).into());
+                            }
+                        },
+                    };
+                    let sessions = match view {
+                        None => None,
+                        Some(v) => Some(self.sessions.read().await.sessions_by_view(v)),
+                    };
+                    self.rib.say_jsonrib(urlparts[3], req, sessions).await
                 }
             }
             _ => Ok(not_found()),
@@ -572,6 +1780,9 @@ impl<'a, 'b> BSEItems<'a, 'b> {
     }
     pub fn is_empty(&self) -> bool {
         !self.bse.items.iter().any(|x| {
+            if !self.params.filter.filter_session(x.0) {
+                return false;
+            }
             let v = BPEItems::new(x.1, self.params);
             !v.is_empty()
         })
@@ -584,7 +1795,12 @@ impl<'a, 'b> serde::Serialize for BSEItems<'a, 'b> {
     {
         let mut state = serializer.serialize_map(Some(self.bse.items.len()))?;
 
-        for (k, v) in self.bse.items.iter() {
+        for (k, v) in self
+            .bse
+            .items
+            .iter()
+            .filter(|x| self.params.filter.filter_session(x.0))
+        {
             let v = BPEItems::new(v, self.params);
             if v.is_empty() {
                 continue;
@@ -602,7 +1818,7 @@ pub struct RibItems<'a, T: ribfilter::FilterMatchRoute + BgpRIBKey + std::string
 
 impl<'a, T: ribfilter::FilterMatchRoute + BgpRIBKey + std::string::ToString> RibItems<'a, T> {
     pub fn count(&self) -> usize {
-        if self.filter.terms.is_empty() {
+        if self.filter.terms.is_empty() && self.params.filter.sessions.is_none() {
             self.ribsafi.items.len()
         } else {
             //self.hashmap.iter().filter(|p|{!(self.filter.match_route(p.0, p.1) != ribfilter::FilterItemMatchResult::Yes)}).count()
@@ -723,23 +1939,255 @@ impl serde::Serialize for BgpSessionDesc {
     where
         S: serde::Serializer,
     {
-        let mut state = serializer.serialize_struct("BgpSessionDesc", 2)?;
+        let mut state = serializer.serialize_struct("BgpSessionDesc", 3)?;
         state.serialize_field("peer1", &self.peer1)?;
         state.serialize_field("peer2", &self.peer2)?;
+        state.serialize_field("view", &self.view.to_string())?;
         state.end()
     }
 }
 
-impl serde::Serialize for BgpSessionStorage {
+struct BgpSessionsResponse<'a> {
+    sessions: &'a BgpSessionStorage,
+    prefixes: &'a BTreeMap<BgpSessionId, BTreeMap<BgpRibKind, usize>>,
+    synced: &'a BTreeMap<BgpSessionId, BTreeMap<BgpRibKind, Timestamp>>,
+    retries: &'a BTreeMap<SocketAddr, BgpPeerRetry>,
+}
+struct BgpSessionItem<'a> {
+    desc: &'a BgpSessionDesc,
+    stats: Option<&'a BgpSessionStats>,
+    prefixes: Option<&'a BTreeMap<BgpRibKind, usize>>,
+    synced: Option<&'a BTreeMap<BgpRibKind, Timestamp>>,
+    retry: Option<&'a BgpPeerRetry>,
+}
+struct BgpSessionAfis<'a> {
+    stats: Option<&'a BgpSessionStats>,
+    prefixes: Option<&'a BTreeMap<BgpRibKind, usize>>,
+    synced: Option<&'a BTreeMap<BgpRibKind, Timestamp>>,
+}
+
+impl<'a> serde::Serialize for BgpSessionsResponse<'a> {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: serde::Serializer,
     {
-        let mut state = serializer.serialize_map(Some(self.ss_ids.len()))?;
-        for (k, v) in self.ss_ids.iter() {
-            let bssd: &BgpSessionDesc = v;
-            state.serialize_entry(k, bssd)?;
+        let mut state = serializer.serialize_map(Some(self.sessions.ss_ids.len()))?;
+        for (k, v) in self.sessions.ss_ids.iter() {
+            let itm = BgpSessionItem {
+                desc: v,
+                stats: self.sessions.ss_stats.get(k),
+                prefixes: self.prefixes.get(k),
+                synced: self.synced.get(k),
+                retry: if v.view == BgpSessionView::Bgp {
+                    self.retries
+                        .values()
+                        .find(|r| r.mode == PeerMode::BgpActive && r.peer.ip() == v.peer1.addr)
+                } else {
+                    None
+                },
+            };
+            state.serialize_entry(k, &itm)?;
         }
         state.end()
     }
 }
+
+impl<'a> serde::Serialize for BgpSessionItem<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let mut state = serializer.serialize_struct("BgpSessionItem", 11)?;
+        state.serialize_field("peer1", &self.desc.peer1)?;
+        state.serialize_field("peer2", &self.desc.peer2)?;
+        state.serialize_field("view", &self.desc.view.to_string())?;
+        state.serialize_field("role", &self.desc.role.map(|r| r.to_string()))?;
+        match self.stats {
+            None => {
+                state.serialize_field("state", &format!("{:?}", BgpSessionState::Idle))?;
+                state.serialize_field("established", &None::<String>)?;
+                state.serialize_field("uptime", &None::<i64>)?;
+                state.serialize_field("flaps", &0)?;
+                state.serialize_field("last_error", &None::<String>)?;
+            }
+            Some(st) => {
+                state.serialize_field("state", &format!("{:?}", st.state))?;
+                state.serialize_field("established", &st.established.map(|t| t.to_rfc3339()))?;
+                state.serialize_field("uptime", &st.uptime())?;
+                state.serialize_field("flaps", &st.flaps)?;
+                state.serialize_field("last_error", &st.last_error)?;
+            }
+        }
+        state.serialize_field(
+            "afis",
+            &BgpSessionAfis {
+                stats: self.stats,
+                prefixes: self.prefixes,
+                synced: self.synced,
+            },
+        )?;
+        state.serialize_field("retry", &self.retry)?;
+        state.end()
+    }
+}
+
+impl serde::Serialize for BgpPeerRetry {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let mut state = serializer.serialize_struct("BgpPeerRetry", 7)?;
+        state.serialize_field("peer", &self.peer.to_string())?;
+        state.serialize_field("mode", &format!("{:?}", self.mode))?;
+        state.serialize_field("disabled", &self.disabled)?;
+        state.serialize_field("attempts", &self.attempts)?;
+        state.serialize_field("last_error", &self.last_error)?;
+        state.serialize_field("last_attempt", &self.last_attempt.map(|t| t.to_rfc3339()))?;
+        state.serialize_field("next_attempt", &self.next_attempt.map(|t| t.to_rfc3339()))?;
+        state.end()
+    }
+}
+
+impl<'a> serde::Serialize for BgpSessionAfis<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let mut kinds: BTreeSet<BgpRibKind> = BTreeSet::new();
+        if let Some(st) = self.stats {
+            kinds.extend(st.afis.keys());
+        }
+        if let Some(pf) = self.prefixes {
+            kinds.extend(pf.keys());
+        }
+        if let Some(sc) = self.synced {
+            kinds.extend(sc.keys());
+        }
+        let mut state = serializer.serialize_map(Some(kinds.len()))?;
+        for kind in kinds.iter() {
+            let cnt = self.stats.and_then(|st| st.afis.get(kind));
+            let mut m: BTreeMap<&str, u64> = BTreeMap::new();
+            m.insert(
+                "prefixes",
+                self.prefixes
+                    .and_then(|pf| pf.get(kind))
+                    .copied()
+                    .unwrap_or(0) as u64,
+            );
+            m.insert("updates", cnt.map(|c| c.updates).unwrap_or(0));
+            m.insert("withdraws", cnt.map(|c| c.withdraws).unwrap_or(0));
+            // End-of-RIB time, initial sync complete
+            if let Some(ts) = self.synced.and_then(|sc| sc.get(kind)) {
+                m.insert("synced", ts.timestamp_millis() as u64);
+            }
+            state.serialize_entry(&kind.to_string(), &m)?;
+        }
+        state.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        BgpPeerDesc, BgpPeerRetry, BgpSessionDesc, BgpSessionState, BgpSessionStorage, BgpSvr,
+    };
+    use crate::config::{PeerMode, SvcConfig};
+    use hyper::{Body, Method, Request, StatusCode};
+    use std::net::{IpAddr, SocketAddr};
+    use std::sync::Arc;
+    use zettabgp::prelude::BgpOpenMessage;
+
+    #[test]
+    fn test_register_session_state() {
+        let mut storage = BgpSessionStorage::new();
+        let desc = |addr: [u8; 4], direct: bool| {
+            let mut d = BgpSessionDesc::new(
+                BgpPeerDesc::new(IpAddr::from([0, 0, 0, 0]), BgpOpenMessage::new()),
+                BgpPeerDesc::new(IpAddr::from(addr), BgpOpenMessage::new()),
+            );
+            d.direct = direct;
+            Arc::new(d)
+        };
+        let bgp = storage.register_session(desc([10, 0, 0, 1], true));
+        assert_eq!(storage.ss_stats[&bgp].state, BgpSessionState::OpenConfirm);
+        assert!(storage.ss_stats[&bgp].established.is_none());
+        let mrt = storage.register_session(desc([10, 0, 0, 2], false));
+        assert_eq!(storage.ss_stats[&mrt].state, BgpSessionState::Established);
+        storage.session_down(mrt, "Cancelled".to_string());
+        assert_eq!(storage.ss_stats[&mrt].flaps, 1);
+        // session closed before KEEPALIVE is not a flap
+        storage.session_down(bgp, "Hold timer expired".to_string());
+        assert_eq!(storage.ss_stats[&bgp].flaps, 0);
+    }
+
+    #[test]
+    fn test_backoff() {
+        let addr: SocketAddr = "10.0.0.1:179".parse().unwrap();
+        let mut r = BgpPeerRetry::new(addr, PeerMode::BgpActive);
+        let initial = chrono::Duration::seconds(30);
+        let max = chrono::Duration::seconds(600);
+        for attempts in 0..20u32 {
+            r.attempts = attempts;
+            let limit = std::cmp::min(initial * 2i32.pow(attempts.min(16)), max);
+            for _ in 0..100 {
+                let delay = r.backoff(initial, max);
+                assert!(delay <= limit, "{} > {}", delay, limit);
+                assert!(delay * 4 >= limit * 3, "{} < 0.75 * {}", delay, limit);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_peer_disable() {
+        let path =
+            std::env::temp_dir().join(format!("bgpexplorer-peers-{}.ini", std::process::id()));
+        let ini = format!(
+            "[main]\nwhoisjsonconfig={}/whois.json\n[s0]\nmode=bgpactive\npeer=10.0.0.1\npeeras=65001\n",
+            env!("CARGO_MANIFEST_DIR")
+        );
+        std::fs::write(&path, ini).unwrap();
+        let cfg = SvcConfig::from_inifile(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+        let svr = BgpSvr::new(
+            Arc::new(cfg.unwrap()),
+            tokio_util::sync::CancellationToken::new(),
+        );
+        let query = |method: Method, uri: &str| {
+            Request::builder()
+                .method(method)
+                .uri(uri)
+                .body(Body::empty())
+                .unwrap()
+        };
+        let addr: SocketAddr = "10.0.0.1:179".parse().unwrap();
+        let disabled = || svr.retries.lock().unwrap()[&addr].disabled;
+        let resp = svr
+            .handle_query(&query(Method::GET, "/api/peers/10.0.0.1/disable"))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert!(!disabled());
+        let resp = svr
+            .handle_query(&query(Method::POST, "/api/peers/10.0.0.1/disable"))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert!(disabled());
+        let resp = svr
+            .handle_query(&query(Method::POST, "/api/peers/10.0.0.1:179/enable"))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert!(!disabled());
+        let resp = svr
+            .handle_query(&query(Method::POST, "/api/peers/10.0.0.2/disable"))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        let resp = svr
+            .handle_query(&query(Method::GET, "/api/peers"))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+}
@@ -1,5 +1,8 @@
+use crate::bmpstats::*;
+use crate::timestamp::Timestamp;
 use crate::*;
 use std::collections::BTreeMap;
+use std::net::IpAddr;
 use std::sync::Arc;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use zettabgp::bmp::prelude::*;
@@ -7,9 +10,10 @@ use zettabgp::prelude::*;
 
 pub struct BmpPeer<'a, H: BgpUpdateHandler> {
     peersock: tokio::net::TcpStream,
+    station: IpAddr,
     peer: Arc<ProtoPeer>,
     sess: BMPSession,
-    sessids: BTreeMap<BgpSessionKey, BgpSessionId>,
+    sessids: BTreeMap<(BgpSessionKey, BgpSessionView), BgpSessionId>,
     update_handler: &'a H,
 }
 
@@ -18,14 +22,15 @@ impl<'a, H: BgpUpdateHandler> BmpPeer<'a, H> {
         sock: tokio::net::TcpStream,
         peer: Arc<ProtoPeer>,
         handler: &'a H,
-    ) -> BmpPeer<'a, H> {
-        BmpPeer {
+    ) -> std::io::Result<BmpPeer<'a, H>> {
+        Ok(BmpPeer {
+            station: sock.peer_addr()?.ip(),
             peersock: sock,
             peer,
             sess: Default::default(),
             sessids: BTreeMap::new(),
             update_handler: handler,
-        }
+        })
     }
     pub async fn processmsg(&mut self, msg: BmpMessage) -> Result<(), BgpError> {
         match msg {
@@ -37,26 +42,98 @@ impl<'a, H: BgpUpdateHandler> BmpPeer<'a, H> {
                         return Ok(());
                     }
                 };
+                let view = BgpSessionView::from_bmp_peer(&pu.peer);
                 let sessid = self
                     .update_handler
-                    .register_session(Arc::new(BgpSessionDesc::from_bmppeerup(&pu)))
+                    .register_session(Arc::new(BgpSessionDesc::from_bmppeerup(&pu, view)))
                     .await;
                 info!("Register session id {} for peer {:?}", sessid, pu);
-                self.sessids.insert(BgpSessionKey::from(&pu.peer), sessid);
+                self.sessids
+                    .insert((BgpSessionKey::from(&pu.peer), view), sessid);
+                self.update_handler
+                    .bmp_event(self.station, BmpStationEvent::PeerUp(pu.peer, sessid))
+                    .await;
             }
             BmpMessage::RouteMonitoring(rm) => {
-                let sessid = match self.sessids.get(&BgpSessionKey::from(&rm.peer)) {
+                let key = BgpSessionKey::from(&rm.peer);
+                let view = BgpSessionView::from_bmp_peer(&rm.peer);
+                let sessid = match self.sessids.get(&(BgpSessionKey::from(&rm.peer), view)) {
+                    Some(x) => *x,
                     None => {
-                        if let Some(ref filter_rd) = self.peer.flt_rd {
-                            if rm.peer.peerdistinguisher == *filter_rd {
-                                warn!("Skip update: {:?}", rm);
+                        // first route in another view of already known peer
+                        if !self.sessids.keys().any(|k| k.0 == key) {
+                            if let Some(ref filter_rd) = self.peer.flt_rd {
+                                if rm.peer.peerdistinguisher == *filter_rd {
+                                    warn!("Skip update: {:?}", rm);
+                                };
                             };
+                            return Ok(());
+                        }
+                        let pu = match self.sess.sessions.get(&key) {
+                            None => return Ok(()),
+                            Some(pu) => pu,
                         };
-                        return Ok(());
+                        let sessid = self
+                            .update_handler
+                            .register_session(Arc::new(BgpSessionDesc::from_bmppeerup(pu, view)))
+                            .await;
+                        info!(
+                            "Register session id {} for peer {:?} view {}",
+                            sessid, key, view
+                        );
+                        self.sessids.insert((key, view), sessid);
+                        sessid
                     }
-                    Some(x) => *x,
                 };
-                self.update_handler.handle_update(sessid, rm.update).await;
+                self.update_handler
+                    .handle_update(sessid, rm.update, Timestamp::now())
+                    .await;
+            }
+            BmpMessage::PeerDownNotification(pd) => {
+                let reason = match pd.reason {
+                    BmpMessagePeerDownReason::AdministrativelyClosed(ref n) => {
+                        format!("Administratively closed: {}", n.error_text())
+                    }
+                    BmpMessagePeerDownReason::LocalSystemState(c) => {
+                        format!("Local system state: {}", c)
+                    }
+                    BmpMessagePeerDownReason::RemoteNotification(ref n) => {
+                        format!("Remote notification: {}", n.error_text())
+                    }
+                    BmpMessagePeerDownReason::Remote => "Remote closed".to_string(),
+                    BmpMessagePeerDownReason::BmpDisabled => "BMP disabled".to_string(),
+                };
+                let key = BgpSessionKey::from(&pd.peer);
+                let views: Vec<BgpSessionView> = self
+                    .sessids
+                    .keys()
+                    .filter(|k| k.0 == key)
+                    .map(|k| k.1)
+                    .collect();
+                for view in views.into_iter() {
+                    if let Some(sessid) =
+                        self.sessids.remove(&(BgpSessionKey::from(&pd.peer), view))
+                    {
+                        self.update_handler
+                            .session_down(sessid, reason.clone())
+                            .await;
+                    }
+                }
+                self.update_handler
+                    .bmp_event(self.station, BmpStationEvent::PeerDown(pd.peer, reason))
+                    .await;
+            }
+            BmpMessage::Initiation(init) => {
+                info!("BMP station {} initiation: {:?}", self.station, init);
+                self.update_handler
+                    .bmp_event(self.station, BmpStationEvent::Initiation(init))
+                    .await;
+            }
+            BmpMessage::Termination(term) => {
+                info!("BMP station {} termination: {:?}", self.station, term);
+                self.update_handler
+                    .bmp_event(self.station, BmpStationEvent::Termination(term))
+                    .await;
             }
             _ => info!("BMP: {:?}", msg),
         };
@@ -64,6 +141,9 @@ impl<'a, H: BgpUpdateHandler> BmpPeer<'a, H> {
     }
     pub async fn lifecycle(&mut self, cancel: tokio_util::sync::CancellationToken) {
         let mut buf = Box::new([0u8; 65536]);
+        self.update_handler
+            .bmp_event(self.station, BmpStationEvent::Connected)
+            .await;
         loop {
             select! {
               _ = cancel.cancelled() => {
@@ -116,6 +196,30 @@ impl<'a, H: BgpUpdateHandler> BmpPeer<'a, H> {
                     }
                 }
             };
+            if buf[0] == 1 {
+                // statistics report is not decoded by BMPSession
+                match decode_stats_report(&buf[1..(bmph.0.msglength - 5)]) {
+                    Err(e) => warn!("BMP statistics decode error: {:?}", e),
+                    Ok((hdr, stats)) => {
+                        self.update_handler
+                            .bmp_event(self.station, BmpStationEvent::Statistics(hdr, stats))
+                            .await;
+                    }
+                }
+                continue;
+            }
+            if buf[0] == 6 {
+                // route mirroring is not decoded by BMPSession
+                match decode_route_mirroring(&buf[1..(bmph.0.msglength - 5)], &self.sess) {
+                    Err(e) => warn!("BMP route mirroring decode error: {:?}", e),
+                    Ok((hdr, entry)) => {
+                        self.update_handler
+                            .bmp_event(self.station, BmpStationEvent::Mirroring(hdr, entry))
+                            .await;
+                    }
+                }
+                continue;
+            }
             let msg = match self.sess.decode_from(&buf[0..(bmph.0.msglength - 5)]) {
                 Err(e) => {
                     warn!("BMP decode error: {:?}", e);
@@ -128,6 +232,14 @@ impl<'a, H: BgpUpdateHandler> BmpPeer<'a, H> {
                 break;
             };
         }
+        for sessid in self.sessids.values() {
+            self.update_handler
+                .session_down(*sessid, "BMP connection closed".to_string())
+                .await;
+        }
+        self.update_handler
+            .bmp_event(self.station, BmpStationEvent::Closed)
+            .await;
     }
     pub async fn close(&mut self) {
         if let Err(e) = self.peersock.shutdown().await {
@@ -0,0 +1,666 @@
+use crate::bgpsvc::BgpSessionId;
+use crate::timestamp::Timestamp;
+use serde::ser::{SerializeMap, SerializeStruct};
+use std::collections::{BTreeMap, VecDeque};
+use std::net::IpAddr;
+use zettabgp::bmp::prelude::*;
+use zettabgp::prelude::*;
+
+/// BMP station related events, reported by BmpPeer
+pub enum BmpStationEvent {
+    Connected,
+    Closed,
+    Initiation(BmpMessageInitiation),
+    Termination(BmpMessageTermination),
+    PeerUp(BmpMessagePeerHeader, BgpSessionId),
+    PeerDown(BmpMessagePeerHeader, String),
+    Statistics(BmpMessagePeerHeader, BTreeMap<String, u64>),
+    Mirroring(BmpMessagePeerHeader, BmpMirrorEntry),
+}
+
+fn stat_name(stattype: u16) -> String {
+    match stattype {
+        0 => "rejected_prefixes".to_string(),
+        1 => "duplicate_prefix_advertisements".to_string(),
+        2 => "duplicate_withdraws".to_string(),
+        3 => "invalid_cluster_list_loop".to_string(),
+        4 => "invalid_as_path_loop".to_string(),
+        5 => "invalid_originator_id".to_string(),
+        6 => "invalid_as_confed_loop".to_string(),
+        7 => "adj_rib_in_routes".to_string(),
+        8 => "loc_rib_routes".to_string(),
+        9 => "adj_rib_in_routes".to_string(),
+        10 => "loc_rib_routes".to_string(),
+        11 => "updates_treated_as_withdraw".to_string(),
+        12 => "prefixes_treated_as_withdraw".to_string(),
+        13 => "duplicate_update_messages".to_string(),
+        14 => "adj_rib_out_pre_routes".to_string(),
+        15 => "adj_rib_out_post_routes".to_string(),
+        16 => "adj_rib_out_pre_routes".to_string(),
+        17 => "adj_rib_out_post_routes".to_string(),
+        n => format!("stat{}", n),
+    }
+}
+
+fn afisafi_name(afi: u16, safi: u8) -> String {
+    match (afi, safi) {
+        (1, 1) => "ipv4u".to_string(),
+        (1, 2) => "ipv4m".to_string(),
+        (1, 4) => "ipv4lu".to_string(),
+        (1, 5) => "mvpn".to_string(),
+        (1, 66) => "ipv4mdt".to_string(),
+        (1, 128) => "vpnv4u".to_string(),
+        (1, 129) => "vpnv4m".to_string(),
+        (1, 132) => "rtc".to_string(),
+        (1, 133) => "fs4u".to_string(),
+        (1, 134) => "fsv4u".to_string(),
+        (2, 1) => "ipv6u".to_string(),
+        (2, 2) => "ipv6m".to_string(),
+        (2, 4) => "ipv6lu".to_string(),
+        (2, 66) => "ipv6mdt".to_string(),
+        (2, 133) => "fs6u".to_string(),
+        (2, 134) => "fsv6u".to_string(),
+        (2, 128) => "vpnv6u".to_string(),
+        (2, 129) => "vpnv6m".to_string(),
+        (25, 65) => "l2vpls".to_string(),
+        (25, 70) => "evpn".to_string(),
+        (a, s) => format!("afi{}safi{}", a, s),
+    }
+}
+
+/// Decodes BMP statistics report body (after message type octet)
+pub fn decode_stats_report(
+    buf: &[u8],
+) -> Result<(BmpMessagePeerHeader, BTreeMap<String, u64>), BgpError> {
+    let (peer, mut pos) = BmpMessagePeerHeader::decode_from(buf)?;
+    if buf.len() < pos + 4 {
+        return Err(BgpError::insufficient_buffer_size());
+    }
+    let count = getn_u32(&buf[pos..]);
+    pos += 4;
+    let mut ret = BTreeMap::new();
+    for _ in 0..count {
+        if buf.len() < pos + 4 {
+            return Err(BgpError::insufficient_buffer_size());
+        }
+        let stattype = getn_u16(&buf[pos..]);
+        let statlen = getn_u16(&buf[pos + 2..]) as usize;
+        pos += 4;
+        if buf.len() < pos + statlen {
+            return Err(BgpError::insufficient_buffer_size());
+        }
+        let v = &buf[pos..pos + statlen];
+        match statlen {
+            4 => {
+                ret.insert(stat_name(stattype), getn_u32(v) as u64);
+            }
+            8 => {
+                ret.insert(stat_name(stattype), getn_u64(v));
+            }
+            11 => {
+                ret.insert(
+                    format!(
+                        "{}.{}",
+                        stat_name(stattype),
+                        afisafi_name(getn_u16(v), v[2])
+                    ),
+                    getn_u64(&v[3..]),
+                );
+            }
+            _ => {
+                warn!("BMP stat type {} with unknown length {}", stattype, statlen);
+            }
+        }
+        pos += statlen;
+    }
+    Ok((peer, ret))
+}
+
+/// BGP PDU from route mirroring message
+pub struct BmpMirroredPdu {
+    pub raw: Vec<u8>,
+    /// decoded message text or decode error
+    pub decoded: Result<String, String>,
+}
+impl BmpMirroredPdu {
+    pub fn new(raw: &[u8], params: &BgpSessionParams) -> BmpMirroredPdu {
+        BmpMirroredPdu {
+            raw: raw.to_vec(),
+            decoded: decode_pdu(raw, params).map_err(|e| format!("{:?}", e)),
+        }
+    }
+}
+
+/// Route mirroring message, RFC 7854 section 4.7
+pub struct BmpMirrorEntry {
+    pub when: Timestamp,
+    /// information TLV codes
+    pub info: Vec<u16>,
+    pub pdus: Vec<BmpMirroredPdu>,
+}
+
+fn mirror_info_name(code: u16) -> String {
+    match code {
+        0 => "errored_pdu".to_string(),
+        1 => "messages_lost".to_string(),
+        n => format!("info{}", n),
+    }
+}
+
+fn decode_pdu(buf: &[u8], params: &BgpSessionParams) -> Result<String, BgpError> {
+    if buf.len() < 19 || (getn_u16(&buf[16..18]) as usize) < 19 {
+        return Err(BgpError::static_str("Invalid message header size"));
+    }
+    let (msgtype, len) = params.decode_message_head(buf)?;
+    if buf.len() < 19 + len {
+        return Err(BgpError::insufficient_buffer_size());
+    }
+    let body = &buf[19..19 + len];
+    match msgtype {
+        BgpMessageType::Open => {
+            let mut msg = BgpOpenMessage::new();
+            msg.decode_from(params, body)?;
+            Ok(format!("{:?}", msg))
+        }
+        BgpMessageType::Update => {
+            let mut msg = BgpUpdateMessage::new();
+            msg.decode_from(params, body)?;
+            Ok(format!("{:?}", msg))
+        }
+        BgpMessageType::Notification => {
+            let mut msg = BgpNotificationMessage::new();
+            msg.decode_from(params, body)?;
+            Ok(format!("Notification: {}", msg.error_text()))
+        }
+        BgpMessageType::Keepalive => Ok("Keepalive".to_string()),
+    }
+}
+
+/// Decodes BMP route mirroring body (after message type octet), PDUs are decoded
+/// with session parameters from peer up notification
+pub fn decode_route_mirroring(
+    buf: &[u8],
+    sess: &BMPSession,
+) -> Result<(BmpMessagePeerHeader, BmpMirrorEntry), BgpError> {
+    let (peer, mut pos) = BmpMessagePeerHeader::decode_from(buf)?;
+    let params: BgpSessionParams = match sess.sessions.get(&BgpSessionKey::from(&peer)) {
+        None => (&peer).into(),
+        Some(pu) => {
+            if pu.peer.routerid == pu.msg1.router_id {
+                BgpSessionParams::from(&pu.msg1)
+            } else {
+                BgpSessionParams::from(&pu.msg2)
+            }
+        }
+    };
+    let mut ret = BmpMirrorEntry {
+        when: Timestamp::now(),
+        info: Vec::new(),
+        pdus: Vec::new(),
+    };
+    while pos < buf.len() {
+        if buf.len() < pos + 4 {
+            return Err(BgpError::insufficient_buffer_size());
+        }
+        let tlvtype = getn_u16(&buf[pos..]);
+        let tlvlen = getn_u16(&buf[pos + 2..]) as usize;
+        pos += 4;
+        if buf.len() < pos + tlvlen {
+            return Err(BgpError::insufficient_buffer_size());
+        }
+        let v = &buf[pos..pos + tlvlen];
+        match tlvtype {
+            0 => ret.pdus.push(BmpMirroredPdu::new(v, &params)),
+            1 if tlvlen == 2 => ret.info.push(getn_u16(v)),
+            _ => warn!("BMP route mirroring TLV type {} length {}", tlvtype, tlvlen),
+        }
+        pos += tlvlen;
+    }
+    Ok((peer, ret))
+}
+
+pub struct BmpMonitoredPeer {
+    pub header: BmpMessagePeerHeader,
+    pub sessionid: Option<BgpSessionId>,
+    pub up: bool,
+    pub down_reason: Option<String>,
+    pub stats: VecDeque<(Timestamp, BTreeMap<String, u64>)>,
+    pub mirrors: VecDeque<BmpMirrorEntry>,
+}
+impl BmpMonitoredPeer {
+    pub fn new(header: BmpMessagePeerHeader) -> BmpMonitoredPeer {
+        BmpMonitoredPeer {
+            header,
+            sessionid: None,
+            up: false,
+            down_reason: None,
+            stats: VecDeque::new(),
+            mirrors: VecDeque::new(),
+        }
+    }
+}
+
+pub struct BmpStation {
+    pub connected: bool,
+    pub since: Timestamp,
+    pub sys_name: Option<String>,
+    pub sys_descr: Option<String>,
+    pub info: Option<String>,
+    pub termination: Option<String>,
+    pub peers: BTreeMap<BgpSessionKey, BmpMonitoredPeer>,
+}
+impl BmpStation {
+    pub fn new() -> BmpStation {
+        BmpStation {
+            connected: false,
+            since: Timestamp::now(),
+            sys_name: None,
+            sys_descr: None,
+            info: None,
+            termination: None,
+            peers: BTreeMap::new(),
+        }
+    }
+    fn get_peer(&mut self, hdr: &BmpMessagePeerHeader) -> &mut BmpMonitoredPeer {
+        self.peers
+            .entry(BgpSessionKey::from(hdr))
+            .or_insert_with(|| BmpMonitoredPeer::new(hdr.clone()))
+    }
+}
+impl Default for BmpStation {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// All BMP stations seen, with their monitored peers
+pub struct BmpStations {
+    pub stats_depth: usize,
+    pub mirror_depth: usize,
+    pub stations: BTreeMap<IpAddr, BmpStation>,
+}
+impl BmpStations {
+    pub fn new(stats_depth: usize, mirror_depth: usize) -> BmpStations {
+        BmpStations {
+            stats_depth,
+            mirror_depth,
+            stations: BTreeMap::new(),
+        }
+    }
+    pub fn handle_event(&mut self, station: IpAddr, ev: BmpStationEvent) {
+        let st = self.stations.entry(station).or_default();
+        match ev {
+            BmpStationEvent::Connected => {
+                st.connected = true;
+                st.since = Timestamp::now();
+                st.termination = None;
+            }
+            BmpStationEvent::Closed => {
+                st.connected = false;
+                st.since = Timestamp::now();
+                for p in st.peers.values_mut() {
+                    if p.up {
+                        p.up = false;
+                        p.down_reason = Some("BMP connection closed".to_string());
+                    }
+                }
+            }
+            BmpStationEvent::Initiation(init) => {
+                st.sys_name = init.sys_name;
+                st.sys_descr = init.sys_descr;
+                st.info = init.str0;
+            }
+            BmpStationEvent::Termination(term) => {
+                let reason = match term.reason {
+                    None => None,
+                    Some(0) => Some("Session administratively closed".to_string()),
+                    Some(1) => Some("Unspecified reason".to_string()),
+                    Some(2) => Some("Out of resources".to_string()),
+                    Some(3) => Some("Redundant connection".to_string()),
+                    Some(4) => Some("Session permanently administratively closed".to_string()),
+                    Some(n) => Some(format!("Reason code {}", n)),
+                };
+                st.termination = match (reason, term.str0) {
+                    (Some(r), Some(s)) => Some(format!("{}: {}", r, s)),
+                    (Some(r), None) => Some(r),
+                    (None, Some(s)) => Some(s),
+                    (None, None) => Some("Terminated".to_string()),
+                };
+            }
+            BmpStationEvent::PeerUp(hdr, sessid) => {
+                let p = st.get_peer(&hdr);
+                p.header = hdr;
+                p.sessionid = Some(sessid);
+                p.up = true;
+                p.down_reason = None;
+            }
+            BmpStationEvent::PeerDown(hdr, reason) => {
+                let p = st.get_peer(&hdr);
+                p.up = false;
+                p.down_reason = Some(reason);
+            }
+            BmpStationEvent::Statistics(hdr, stats) => {
+                let p = st.get_peer(&hdr);
+                while p.stats.len() >= self.stats_depth.max(1) {
+                    p.stats.pop_front();
+                }
+                p.stats.push_back((Timestamp::now(), stats));
+            }
+            BmpStationEvent::Mirroring(hdr, entry) => {
+                if self.mirror_depth == 0 {
+                    return;
+                }
+                let p = st.get_peer(&hdr);
+                while p.mirrors.len() >= self.mirror_depth {
+                    p.mirrors.pop_front();
+                }
+                p.mirrors.push_back(entry);
+            }
+        }
+    }
+}
+
+/// /api/bmp response, with `history` stats samples per peer
+pub struct BmpStationsResponse<'a> {
+    pub stations: &'a BmpStations,
+    pub history: usize,
+}
+struct BmpStationItem<'a> {
+    station: &'a BmpStation,
+    history: usize,
+}
+struct BmpPeerItem<'a> {
+    peer: &'a BmpMonitoredPeer,
+    history: usize,
+}
+struct BmpStatsItems<'a> {
+    peer: &'a BmpMonitoredPeer,
+    history: usize,
+}
+
+impl<'a> serde::Serialize for BmpStationsResponse<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let mut state = serializer.serialize_map(Some(self.stations.stations.len()))?;
+        for (k, v) in self.stations.stations.iter() {
+            state.serialize_entry(
+                &k.to_string(),
+                &BmpStationItem {
+                    station: v,
+                    history: self.history,
+                },
+            )?;
+        }
+        state.end()
+    }
+}
+impl<'a> serde::Serialize for BmpStationItem<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let mut state = serializer.serialize_struct("BmpStation", 7)?;
+        state.serialize_field("connected", &self.station.connected)?;
+        state.serialize_field("since", &self.station.since)?;
+        state.serialize_field("sys_name", &self.station.sys_name)?;
+        state.serialize_field("sys_descr", &self.station.sys_descr)?;
+        state.serialize_field("info", &self.station.info)?;
+        state.serialize_field("termination", &self.station.termination)?;
+        let peers: Vec<BmpPeerItem> = self
+            .station
+            .peers
+            .values()
+            .map(|p| BmpPeerItem {
+                peer: p,
+                history: self.history,
+            })
+            .collect();
+        state.serialize_field("peers", &peers)?;
+        state.end()
+    }
+}
+impl<'a> serde::Serialize for BmpPeerItem<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let hdr = &self.peer.header;
+        let mut state = serializer.serialize_struct("BmpMonitoredPeer", 10)?;
+        state.serialize_field("peer", &hdr.peeraddress)?;
+        state.serialize_field("rd", &hdr.peerdistinguisher.to_string())?;
+        state.serialize_field("as_num", &hdr.asnum)?;
+        state.serialize_field("router_id", &hdr.routerid)?;
+        state.serialize_field("session", &self.peer.sessionid)?;
+        state.serialize_field("up", &self.peer.up)?;
+        state.serialize_field("down_reason", &self.peer.down_reason)?;
+        match self.peer.stats.back() {
+            None => {
+                state.serialize_field("updated", &None::<Timestamp>)?;
+                state.serialize_field("stats", &None::<BTreeMap<String, u64>>)?;
+            }
+            Some((ts, st)) => {
+                state.serialize_field("updated", &Some(ts))?;
+                state.serialize_field("stats", &Some(st))?;
+            }
+        }
+        state.serialize_field(
+            "history",
+            &BmpStatsItems {
+                peer: self.peer,
+                history: self.history,
+            },
+        )?;
+        state.end()
+    }
+}
+impl<'a> serde::Serialize for BmpStatsItems<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let skip = self.peer.stats.len().saturating_sub(self.history);
+        let mut state = serializer.serialize_map(Some(self.peer.stats.len() - skip))?;
+        for (ts, st) in self.peer.stats.iter().skip(skip) {
+            state.serialize_entry(&format!("{}", ts.timestamp_millis()), st)?;
+        }
+        state.end()
+    }
+}
+
+/// /api/bmp/mirror response, last `limit` mirrored messages of matching peers
+pub struct BmpMirrorResponse<'a> {
+    pub stations: &'a BmpStations,
+    pub station: Option<IpAddr>,
+    pub peer: Option<IpAddr>,
+    pub limit: usize,
+}
+struct BmpMirrorItem<'a> {
+    station: &'a IpAddr,
+    peer: &'a BmpMonitoredPeer,
+    entry: &'a BmpMirrorEntry,
+}
+struct BmpMirroredPduItem<'a>(&'a BmpMirroredPdu);
+
+impl<'a> BmpMirrorResponse<'a> {
+    fn items(&self) -> Vec<BmpMirrorItem<'a>> {
+        let mut ret: Vec<BmpMirrorItem> = Vec::new();
+        for (station, st) in self.stations.stations.iter() {
+            if self.station.map(|s| s != *station).unwrap_or(false) {
+                continue;
+            }
+            for p in st.peers.values() {
+                if self
+                    .peer
+                    .map(|a| a != p.header.peeraddress)
+                    .unwrap_or(false)
+                {
+                    continue;
+                }
+                ret.extend(p.mirrors.iter().map(|entry| BmpMirrorItem {
+                    station,
+                    peer: p,
+                    entry,
+                }));
+            }
+        }
+        ret.sort_by_key(|a| a.entry.when);
+        let skip = ret.len().saturating_sub(self.limit);
+        ret.drain(0..skip);
+        ret
+    }
+}
+impl<'a> serde::Serialize for BmpMirrorResponse<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_seq(self.items())
+    }
+}
+impl<'a> serde::Serialize for BmpMirrorItem<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let hdr = &self.peer.header;
+        let mut state = serializer.serialize_struct("BmpMirrorEntry", 7)?;
+        state.serialize_field("station", &self.station.to_string())?;
+        state.serialize_field("peer", &hdr.peeraddress)?;
+        state.serialize_field("rd", &hdr.peerdistinguisher.to_string())?;
+        state.serialize_field("session", &self.peer.sessionid)?;
+        state.serialize_field("when", &self.entry.when)?;
+        let info: Vec<String> = self
+            .entry
+            .info
+            .iter()
+            .map(|c| mirror_info_name(*c))
+            .collect();
+        state.serialize_field("info", &info)?;
+        let pdus: Vec<BmpMirroredPduItem> =
+            self.entry.pdus.iter().map(BmpMirroredPduItem).collect();
+        state.serialize_field("pdus", &pdus)?;
+        state.end()
+    }
+}
+impl<'a> serde::Serialize for BmpMirroredPduItem<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let hex: String = self.0.raw.iter().map(|b| format!("{:02x}", b)).collect();
+        let mut state = serializer.serialize_struct("BmpMirroredPdu", 3)?;
+        state.serialize_field("hex", &hex)?;
+        match self.0.decoded {
+            Ok(ref d) => {
+                state.serialize_field("decoded", &Some(d))?;
+                state.serialize_field("error", &None::<String>)?;
+            }
+            Err(ref e) => {
+                state.serialize_field("decoded", &None::<String>)?;
+                state.serialize_field("error", &Some(e))?;
+            }
+        }
+        state.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Per-peer header of global instance peer 10.0.0.2 AS65001
+    fn peer_header() -> Vec<u8> {
+        let mut buf = vec![0u8; 42];
+        buf[20..26].copy_from_slice(&[0, 0, 10, 0, 0, 2]);
+        buf[26..30].copy_from_slice(&65001u32.to_be_bytes());
+        buf[30..34].copy_from_slice(&[10, 0, 0, 2]);
+        buf
+    }
+
+    #[test]
+    fn test_decode_stats_report() {
+        let mut buf = peer_header();
+        buf.extend_from_slice(&5u32.to_be_bytes());
+        // rejected prefixes, 32-bit counter
+        buf.extend_from_slice(&[0, 0, 0, 4, 0, 0, 0, 5]);
+        // Adj-RIB-In routes, 64-bit gauge
+        buf.extend_from_slice(&[0, 7, 0, 8, 0, 0, 0, 0, 0, 0, 3, 0xe8]);
+        // per-AFI/SAFI Adj-RIB-In routes for VPNv4
+        buf.extend_from_slice(&[0, 9, 0, 11, 0, 1, 128, 0, 0, 0, 0, 0, 0, 0, 42]);
+        // unknown type with known length and with unknown length
+        buf.extend_from_slice(&[0, 99, 0, 4, 0, 0, 0, 7]);
+        buf.extend_from_slice(&[0, 100, 0, 3, 1, 2, 3]);
+        let (peer, stats) = decode_stats_report(&buf).unwrap();
+        assert_eq!(peer.asnum, 65001);
+        assert_eq!(stats.len(), 4);
+        assert_eq!(stats["rejected_prefixes"], 5);
+        assert_eq!(stats["adj_rib_in_routes"], 1000);
+        assert_eq!(stats["adj_rib_in_routes.vpnv4u"], 42);
+        assert_eq!(stats["stat99"], 7);
+        for len in 0..buf.len() {
+            assert!(decode_stats_report(&buf[0..len]).is_err());
+        }
+    }
+
+    #[test]
+    fn test_decode_route_mirroring() {
+        let mut keepalive = vec![0xffu8; 16];
+        keepalive.extend_from_slice(&[0, 19, 4]);
+        let mut buf = peer_header();
+        // BGP message TLV with keepalive
+        buf.extend_from_slice(&[0, 0, 0, 19]);
+        buf.extend_from_slice(&keepalive);
+        // BGP message TLV with errored PDU
+        buf.extend_from_slice(&[0, 0, 0, 3, 1, 2, 3]);
+        // information TLV, messages lost
+        buf.extend_from_slice(&[0, 1, 0, 2, 0, 1]);
+        // unknown TLV is skipped
+        buf.extend_from_slice(&[0, 7, 0, 1, 0]);
+        let sess = BMPSession {
+            sessions: BTreeMap::new(),
+        };
+        let (peer, entry) = decode_route_mirroring(&buf, &sess).unwrap();
+        assert_eq!(peer.asnum, 65001);
+        assert_eq!(entry.info, vec![1]);
+        assert_eq!(entry.pdus.len(), 2);
+        assert_eq!(entry.pdus[0].raw, keepalive);
+        assert_eq!(entry.pdus[0].decoded, Ok("Keepalive".to_string()));
+        assert!(entry.pdus[1].decoded.is_err());
+        // truncated TLV header and TLV value
+        assert!(decode_route_mirroring(&buf[0..44], &sess).is_err());
+        assert!(decode_route_mirroring(&buf[0..50], &sess).is_err());
+        assert!(decode_route_mirroring(&buf[0..41], &sess).is_err());
+
+        let mut stations = BmpStations::new(10, 10);
+        let station: IpAddr = "192.0.2.1".parse().unwrap();
+        stations.handle_event(station, BmpStationEvent::Mirroring(peer, entry));
+        let rsp = BmpMirrorResponse {
+            stations: &stations,
+            station: Some(station),
+            peer: None,
+            limit: 10,
+        };
+        let v = serde_json::to_value(&rsp).unwrap();
+        assert_eq!(v[0]["station"], "192.0.2.1");
+        assert_eq!(v[0]["info"][0], "messages_lost");
+        assert_eq!(v[0]["pdus"][0]["decoded"], "Keepalive");
+        assert_eq!(v[0]["pdus"][1]["hex"], "010203");
+        assert!(v[0]["pdus"][1]["error"].is_string());
+        let rsp = BmpMirrorResponse {
+            stations: &stations,
+            station: None,
+            peer: Some("10.0.0.3".parse().unwrap()),
+            limit: 10,
+        };
+        assert_eq!(
+            serde_json::to_value(&rsp)
+                .unwrap()
+                .as_array()
+                .unwrap()
+                .len(),
+            0
+        );
+    }
+}
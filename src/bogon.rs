@@ -0,0 +1,332 @@
+//! Bogon prefixes, martian next hops and reserved ASNs of unicast routes
+use crate::bgprib::BgpRibUpdate;
+use crate::rpki::mask_addr;
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use zettabgp::prelude::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BogonKind {
+    /// prefix is within bogon prefix
+    Prefix,
+    /// AS path has reserved or private AS number
+    Asn,
+    /// next hop is martian address
+    Nexthop,
+}
+impl BogonKind {
+    pub const ALL: [BogonKind; 3] = [BogonKind::Prefix, BogonKind::Asn, BogonKind::Nexthop];
+    fn bit(&self) -> u8 {
+        match self {
+            BogonKind::Prefix => 1,
+            BogonKind::Asn => 2,
+            BogonKind::Nexthop => 4,
+        }
+    }
+}
+impl std::str::FromStr for BogonKind {
+    type Err = BgpError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "prefix" => Ok(BogonKind::Prefix),
+            "asn" => Ok(BogonKind::Asn),
+            "nexthop" => Ok(BogonKind::Nexthop),
+            _ => Err(BgpError::static_str("Invalid bogon kind")),
+        }
+    }
+}
+impl std::fmt::Display for BogonKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            BogonKind::Prefix => f.write_str("prefix"),
+            BogonKind::Asn => f.write_str("asn"),
+            BogonKind::Nexthop => f.write_str("nexthop"),
+        }
+    }
+}
+
+/// Set of bogon kinds found in route
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BogonFlags(u8);
+impl BogonFlags {
+    pub fn contains(&self, kind: BogonKind) -> bool {
+        self.0 & kind.bit() != 0
+    }
+    pub fn set(&mut self, kind: BogonKind, on: bool) {
+        if on {
+            self.0 |= kind.bit();
+        } else {
+            self.0 &= !kind.bit();
+        }
+    }
+    pub fn kinds(&self) -> Vec<BogonKind> {
+        BogonKind::ALL
+            .iter()
+            .filter(|k| self.contains(**k))
+            .cloned()
+            .collect()
+    }
+}
+impl serde::Serialize for BogonFlags {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        self.kinds().serialize(serializer)
+    }
+}
+impl<'de> serde::de::Deserialize<'de> for BogonFlags {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::de::Deserializer<'de>,
+    {
+        let mut ret = BogonFlags::default();
+        for k in Vec::<BogonKind>::deserialize(deserializer)?.into_iter() {
+            ret.set(k, true);
+        }
+        Ok(ret)
+    }
+}
+
+/// Bogon list item
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum BogonEntry {
+    Prefix(IpAddr, u8),
+    /// first and last AS number of range
+    Asn(u32, u32),
+}
+impl std::str::FromStr for BogonEntry {
+    type Err = BgpError;
+    /// prefix, AS number or range like 64512-65534 or AS64512-AS65534
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some((a, l)) = s.split_once('/') {
+            let addr: IpAddr = match a.parse() {
+                Ok(a) => a,
+                Err(_) => return Err(BgpError::static_str("Invalid bogon prefix address")),
+            };
+            return match l.parse::<u8>() {
+                Ok(l) if l <= (if addr.is_ipv4() { 32 } else { 128 }) => {
+                    Ok(BogonEntry::Prefix(mask_addr(addr, l), l))
+                }
+                _ => Err(BgpError::static_str("Invalid bogon prefix length")),
+            };
+        }
+        let asn = |v: &str| {
+            v.trim()
+                .to_uppercase()
+                .trim_start_matches("AS")
+                .parse::<u32>()
+        };
+        let (first, last) = match s.split_once('-') {
+            Some((f, l)) => (asn(f), asn(l)),
+            None => (asn(s), asn(s)),
+        };
+        match (first, last) {
+            (Ok(f), Ok(l)) if f <= l => Ok(BogonEntry::Asn(f, l)),
+            _ => Err(BgpError::static_str("Invalid bogon AS number")),
+        }
+    }
+}
+
+/// RFC 6890 special purpose and other not routable prefixes
+const BUILTIN_PREFIXES: [&str; 25] = [
+    "0.0.0.0/8",
+    "10.0.0.0/8",
+    "100.64.0.0/10",
+    "127.0.0.0/8",
+    "169.254.0.0/16",
+    "172.16.0.0/12",
+    "192.0.0.0/24",
+    "192.0.2.0/24",
+    "192.168.0.0/16",
+    "198.18.0.0/15",
+    "198.51.100.0/24",
+    "203.0.113.0/24",
+    "224.0.0.0/4",
+    "240.0.0.0/4",
+    "::/8",
+    "100::/64",
+    "2001:2::/48",
+    "2001:10::/28",
+    "2001:db8::/32",
+    "2002::/16",
+    "3ffe::/16",
+    "fc00::/7",
+    "fe80::/10",
+    "fec0::/10",
+    "ff00::/8",
+];
+/// Addresses which can not be next hop
+const MARTIAN_NEXTHOPS: [&str; 7] = [
+    "0.0.0.0/8",
+    "127.0.0.0/8",
+    "224.0.0.0/4",
+    "240.0.0.0/4",
+    "::/128",
+    "::1/128",
+    "ff00::/8",
+];
+/// AS 0, AS_TRANS, documentation, private and reserved ranges (RFC 7607, 6793, 5398, 6996, 7300)
+const BUILTIN_ASNS: [(u32, u32); 7] = [
+    (0, 0),
+    (23456, 23456),
+    (64496, 64511),
+    (64512, 65535),
+    (65536, 131071),
+    (4200000000, 4294967294),
+    (4294967295, 4294967295),
+];
+
+fn parse_builtin(v: &[&str]) -> Vec<BogonEntry> {
+    v.iter().filter_map(|s| s.parse().ok()).collect()
+}
+
+fn covered(list: &[BogonEntry], addr: IpAddr, len: u8) -> bool {
+    list.iter().any(|e| match e {
+        BogonEntry::Prefix(a, l) => {
+            a.is_ipv4() == addr.is_ipv4() && len >= *l && mask_addr(addr, *l) == *a
+        }
+        BogonEntry::Asn(_, _) => false,
+    })
+}
+
+fn within(list: &[BogonEntry], asn: u32) -> bool {
+    list.iter().any(|e| match e {
+        BogonEntry::Asn(f, l) => asn >= *f && asn <= *l,
+        BogonEntry::Prefix(_, _) => false,
+    })
+}
+
+/// Bogon table of builtin and loaded from file entries
+#[derive(Debug)]
+pub struct BogonTable {
+    /// bogon check is configured
+    pub enabled: bool,
+    builtin: Vec<BogonEntry>,
+    martians: Vec<BogonEntry>,
+    loaded: Vec<BogonEntry>,
+    /// entries excluded by file, they override builtin and loaded ones
+    excluded: Vec<BogonEntry>,
+}
+impl BogonTable {
+    /// Builtin prefixes and AS numbers are left out unless `builtin` is set,
+    /// martian next hops are always checked
+    pub fn new(enabled: bool, builtin: bool) -> BogonTable {
+        let mut entries = Vec::new();
+        if builtin {
+            entries = parse_builtin(&BUILTIN_PREFIXES);
+            entries.extend(BUILTIN_ASNS.iter().map(|(f, l)| BogonEntry::Asn(*f, *l)));
+        }
+        BogonTable {
+            enabled,
+            builtin: entries,
+            martians: parse_builtin(&MARTIAN_NEXTHOPS),
+            loaded: Vec::new(),
+            excluded: Vec::new(),
+        }
+    }
+    /// Replaces entries and exclusions loaded from file, returns true if changed
+    pub fn set(&mut self, mut entries: Vec<BogonEntry>, mut excluded: Vec<BogonEntry>) -> bool {
+        entries.sort();
+        entries.dedup();
+        excluded.sort();
+        excluded.dedup();
+        if entries == self.loaded && excluded == self.excluded {
+            return false;
+        }
+        self.loaded = entries;
+        self.excluded = excluded;
+        true
+    }
+    pub fn len(&self) -> usize {
+        self.builtin.len() + self.loaded.len()
+    }
+    pub fn prefix(&self, addr: IpAddr, len: u8) -> bool {
+        (covered(&self.builtin, addr, len) || covered(&self.loaded, addr, len))
+            && !covered(&self.excluded, addr, len)
+    }
+    pub fn asn(&self, asn: u32) -> bool {
+        (within(&self.builtin, asn) || within(&self.loaded, asn)) && !within(&self.excluded, asn)
+    }
+    pub fn aspath(&self, aspath: &BgpASpath) -> bool {
+        aspath.value.iter().any(|i| match i {
+            BgpASitem::Seq(s) => s.value.iter().any(|a| self.asn(a.value)),
+            BgpASitem::Set(s) => s.value.iter().any(|a| self.asn(a.value)),
+        })
+    }
+    pub fn nexthop(&self, nh: &BgpAddr) -> bool {
+        let addr = match nh {
+            BgpAddr::V4(a) => IpAddr::V4(*a),
+            BgpAddr::V6(a) => IpAddr::V6(*a),
+            _ => return false,
+        };
+        covered(&self.martians, addr, if addr.is_ipv4() { 32 } else { 128 })
+    }
+    /// Bogon flags of prefix with AS path and next hop
+    pub fn check(&self, addr: IpAddr, len: u8, aspath: &BgpASpath, nh: &BgpAddr) -> BogonFlags {
+        let mut ret = BogonFlags::default();
+        ret.set(BogonKind::Prefix, self.prefix(addr, len));
+        ret.set(BogonKind::Asn, self.aspath(aspath));
+        ret.set(BogonKind::Nexthop, self.nexthop(nh));
+        ret
+    }
+}
+
+/// Bogon list file with prefix, AS number or AS range per line, # starts comment,
+/// ! before entry excludes it
+pub fn parse_bogon_list(buf: &[u8]) -> Result<BgpRibUpdate, Box<dyn std::error::Error>> {
+    let mut ret = Vec::new();
+    let mut excluded = Vec::new();
+    for line in String::from_utf8_lossy(buf).lines() {
+        let item = line.split('#').next().unwrap_or("").trim();
+        if item.is_empty() {
+            continue;
+        }
+        match item.strip_prefix('!') {
+            Some(x) => excluded.push(x.trim().parse::<BogonEntry>()?),
+            None => ret.push(item.parse::<BogonEntry>()?),
+        }
+    }
+    Ok(BgpRibUpdate::Bogons(ret, excluded))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bogon_check() {
+        let mut tbl = BogonTable::new(true, true);
+        let a = |s: &str| s.parse::<IpAddr>().unwrap();
+        assert!(tbl.prefix(a("10.1.0.0"), 16));
+        assert!(!tbl.prefix(a("10.0.0.0"), 7));
+        assert!(tbl.prefix(a("2001:db8:1::"), 48));
+        assert!(!tbl.prefix(a("8.8.8.0"), 24));
+        let path = BgpASpath::from(vec![65000, 64512]);
+        let nh = BgpAddr::V4("127.0.0.1".parse().unwrap());
+        let flags = tbl.check(a("8.8.8.0"), 24, &path, &nh);
+        assert_eq!(flags.kinds(), vec![BogonKind::Asn, BogonKind::Nexthop]);
+        assert!(!tbl.aspath(&BgpASpath::from(vec![3356, 15169])));
+        let upd = parse_bogon_list(b"# list\n8.8.8.0/24\nAS3356-AS3357 # transit\n").unwrap();
+        match upd {
+            BgpRibUpdate::Bogons(v, x) => assert!(tbl.set(v, x)),
+            _ => panic!("not a bogon list"),
+        }
+        assert!(tbl.prefix(a("8.8.8.0"), 24));
+        assert!(tbl.aspath(&BgpASpath::from(vec![3356, 15169])));
+        let upd = parse_bogon_list(b"!100.64.0.0/10\n! 65000-65010\n").unwrap();
+        match upd {
+            BgpRibUpdate::Bogons(v, x) => assert!(tbl.set(v, x)),
+            _ => panic!("not a bogon list"),
+        }
+        assert!(!tbl.prefix(a("100.64.1.0"), 24));
+        assert!(tbl.prefix(a("10.0.0.0"), 8));
+        assert!(!tbl.asn(65005));
+        assert!(tbl.asn(65011));
+        let tbl = BogonTable::new(true, false);
+        assert!(!tbl.prefix(a("10.0.0.0"), 8));
+        assert!(!tbl.asn(64512));
+        assert!(tbl.nexthop(&nh));
+    }
+}
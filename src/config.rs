@@ -1,3 +1,5 @@
+use crate::alerts::OwnedPrefix;
+use crate::bgpattrs::BgpRole;
 use std::error::Error;
 use std::fmt;
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
@@ -17,6 +19,8 @@ pub enum PeerMode {
     BmpPassive,
     /// bgpexplorer connects to BMP router
     BmpActive,
+    /// bgpexplorer imports MRT dump file
+    MrtFile,
 }
 /// history store mode variations
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -38,10 +42,39 @@ pub struct ProtoPeer {
     pub flt_rd: Option<zettabgp::afi::BgpRD>,
     pub bgpsessionparams: Arc<std::sync::Mutex<Option<BgpSessionParams>>>,
     pub caps: Vec<BgpCapability>,
+    /// MRT file to import in mrtfile mode
+    pub file: Option<String>,
+    /// advertise RFC 8654 extended messages
+    pub extended_message: bool,
+    /// advertise RFC 4724 graceful restart capability in helper mode
+    pub graceful_restart: bool,
+    /// advertise RFC 4684 Route Target Constraint address family
+    pub rtc: bool,
+    /// advertise VPNv6 FlowSpec address family
+    pub fsv6u: bool,
+    /// initial reconnect delay for active modes
+    pub connect_retry: chrono::Duration,
+    /// reconnect delay limit for exponential backoff
+    pub connect_retry_max: chrono::Duration,
+    /// TCP MD5 signature key
+    pub password: Option<String>,
+    /// caps were specified explicitly
+    pub caps_configured: bool,
+    /// hold time to propose
+    pub hold_time: u16,
+    /// expected neighbor BGP identifier
+    pub remote_routerid: Option<Ipv4Addr>,
+    /// allowed neighbor prefixes for passive modes
+    pub neighbors: Vec<(IpAddr, u8)>,
+    /// RFC 9234 BGP Role to advertise
+    pub role: Option<BgpRole>,
 }
 impl PartialEq for ProtoPeer {
     fn eq(&self, other: &Self) -> bool {
-        self.routerid == other.routerid && self.mode == other.mode && self.peer == other.peer
+        self.routerid == other.routerid
+            && self.mode == other.mode
+            && self.peer == other.peer
+            && self.file == other.file
     }
 }
 impl Eq for ProtoPeer {}
@@ -58,7 +91,7 @@ impl ProtoPeer {
         let mode = match svcsection["mode"] {
             None => {
                 return Err(ErrorConfig::from_str(
-                    "No mode (bgpactive|bgppassive|bmpactive|bmppassive) specified",
+                    "No mode (bgpactive|bgppassive|bmpactive|bmppassive|mrtfile) specified",
                 ));
             }
             Some(ref s) => s,
@@ -186,6 +219,7 @@ impl ProtoPeer {
         } else {
             Some(zettabgp::afi::BgpRD::new(0, 0))
         };
+        let caps_configured = svcsection.contains_key("caps");
         let caps: Vec<BgpCapability> = if svcsection.contains_key("caps") {
             match svcsection["caps"]
                 .as_ref()
@@ -210,6 +244,10 @@ impl ProtoPeer {
                     for cs in cps {
                         match cs {
                             "ipv4u" => caps.push(BgpCapability::SafiIPv4u),
+                            "ipv4m" => caps.push(BgpCapability::SafiIPv4m),
+                            "fs4u" => caps.push(BgpCapability::SafiIPv4fu),
+                            "fsv4u" => caps.push(BgpCapability::SafiVPNv4fu),
+                            "fs6u" => caps.push(BgpCapability::SafiIPv6fu),
                             "ipv4lu" => caps.push(BgpCapability::SafiIPv4lu),
                             "vpnv4u" => caps.push(BgpCapability::SafiVPNv4u),
                             "vpnv4m" => caps.push(BgpCapability::SafiVPNv4m),
@@ -224,6 +262,12 @@ impl ProtoPeer {
                             "vpnv6m" => caps.push(BgpCapability::SafiVPNv6m),
                             "ipv6mdt" => caps.push(BgpCapability::SafiIPv6mdt),
                             "addpath" => addpath = true,
+                            "extmsg" | "gr" | "rtc" | "fsv6u" => {}
+                            "rr" => caps.push(BgpCapability::CapRR),
+                            "err" => {
+                                caps.push(BgpCapability::CapRR);
+                                caps.push(BgpCapability::CapEnhancedRR);
+                            }
                             x => warn!("Unknown capability code: {}", x),
                         }
                     }
@@ -250,6 +294,169 @@ impl ProtoPeer {
         } else {
             Self::all_caps(0)
         };
+        let capsstr = svcsection.get("caps").map(|c| c.as_deref().unwrap_or(""));
+        let extended_message = ProtoPeer::caps_option(capsstr, "extmsg", true);
+        let graceful_restart = ProtoPeer::caps_option(capsstr, "gr", true);
+        // RTC changes which VPN routes neighbor sends, so it is never implied
+        let rtc = ProtoPeer::caps_option(capsstr, "rtc", false);
+        let fsv6u = ProtoPeer::caps_option(capsstr, "fsv6u", false);
+        let file: Option<String> = if svcsection.contains_key("file") {
+            match svcsection["file"] {
+                None => {
+                    return Err(ErrorConfig::from_str("invalid file was specified"));
+                }
+                Some(ref s) => Some(s.clone()),
+            }
+        } else if peermode == PeerMode::MrtFile {
+            return Err(ErrorConfig::from_str("file was not specified"));
+        } else {
+            None
+        };
+        let connect_retry: chrono::Duration = if svcsection.contains_key("connect_retry") {
+            match svcsection["connect_retry"] {
+                None => {
+                    return Err(ErrorConfig::from_str("invalid connect_retry was specified"));
+                }
+                Some(ref s) => chrono::Duration::seconds(match s.parse() {
+                    Err(e) => {
+                        return Err(ErrorConfig::from_string(format!(
+                            "Invalid connect_retry - {}",
+                            e
+                        )));
+                    }
+                    Ok(a) => a,
+                }),
+            }
+        } else {
+            chrono::Duration::seconds(30)
+        };
+        let connect_retry_max: chrono::Duration = if svcsection.contains_key("connect_retry_max") {
+            match svcsection["connect_retry_max"] {
+                None => {
+                    return Err(ErrorConfig::from_str(
+                        "invalid connect_retry_max was specified",
+                    ));
+                }
+                Some(ref s) => chrono::Duration::seconds(match s.parse() {
+                    Err(e) => {
+                        return Err(ErrorConfig::from_string(format!(
+                            "Invalid connect_retry_max - {}",
+                            e
+                        )));
+                    }
+                    Ok(a) => a,
+                }),
+            }
+        } else {
+            chrono::Duration::minutes(10)
+        };
+        let password: Option<String> = if svcsection.contains_key("password") {
+            match svcsection["password"] {
+                None => {
+                    return Err(ErrorConfig::from_str("invalid password was specified"));
+                }
+                Some(ref s) => {
+                    if s.len() > 80 {
+                        return Err(ErrorConfig::from_str(
+                            "password should not be longer than 80 bytes",
+                        ));
+                    }
+                    Some(s.clone())
+                }
+            }
+        } else {
+            None
+        };
+        let hold_time: u16 = if svcsection.contains_key("holdtime") {
+            match svcsection["holdtime"] {
+                None => {
+                    return Err(ErrorConfig::from_str("invalid holdtime was specified"));
+                }
+                Some(ref s) => match s.parse() {
+                    Err(e) => {
+                        return Err(ErrorConfig::from_string(format!(
+                            "Invalid holdtime - {}",
+                            e
+                        )));
+                    }
+                    Ok(1) | Ok(2) => {
+                        return Err(ErrorConfig::from_str("holdtime should be 0 or at least 3"));
+                    }
+                    Ok(a) => a,
+                },
+            }
+        } else {
+            180
+        };
+        let remote_routerid: Option<Ipv4Addr> = if svcsection.contains_key("remoterouterid") {
+            match svcsection["remoterouterid"] {
+                None => {
+                    return Err(ErrorConfig::from_str(
+                        "invalid remoterouterid was specified",
+                    ));
+                }
+                Some(ref s) => match s.parse() {
+                    Err(e) => {
+                        return Err(ErrorConfig::from_string(format!(
+                            "Invalid remoterouterid - {}",
+                            e
+                        )));
+                    }
+                    Ok(a) => Some(a),
+                },
+            }
+        } else {
+            None
+        };
+        let mut neighbors: Vec<(IpAddr, u8)> = Vec::new();
+        if svcsection.contains_key("neighbors") {
+            match svcsection["neighbors"] {
+                None => {
+                    return Err(ErrorConfig::from_str("invalid neighbors was specified"));
+                }
+                Some(ref s) => {
+                    for n in s.split(',').map(|x| x.trim()).filter(|x| !x.is_empty()) {
+                        neighbors.push(match ProtoPeer::parse_prefix(n) {
+                            None => {
+                                return Err(ErrorConfig::from_string(format!(
+                                    "Invalid neighbor - {}",
+                                    n
+                                )));
+                            }
+                            Some(a) => a,
+                        });
+                    }
+                }
+            }
+        }
+        let role: Option<BgpRole> = if svcsection.contains_key("role") {
+            match svcsection["role"] {
+                None => {
+                    return Err(ErrorConfig::from_str("invalid role was specified"));
+                }
+                Some(ref s) => match s.parse() {
+                    Err(e) => {
+                        return Err(ErrorConfig::from_string(format!("Invalid role - {}", e)));
+                    }
+                    Ok(a) => Some(a),
+                },
+            }
+        } else {
+            None
+        };
+        if let (Some(SocketAddr::V4(_)), Some(_)) = (protolisten, &password) {
+            if let Some((a, l)) = neighbors.iter().find(|(a, _)| a.is_ipv6()) {
+                return Err(ErrorConfig::from_string(format!(
+                    "IPv6 neighbor {}/{} can not use password on IPv4 protolisten",
+                    a, l
+                )));
+            }
+        }
+        if connect_retry_max < connect_retry {
+            return Err(ErrorConfig::from_str(
+                "connect_retry_max should not be less than connect_retry",
+            ));
+        }
         Ok(ProtoPeer {
             routerid,
             mode: peermode,
@@ -259,11 +466,84 @@ impl ProtoPeer {
             flt_rd,
             bgpsessionparams: Arc::new(std::sync::Mutex::new(None)),
             caps,
+            file,
+            extended_message,
+            graceful_restart,
+            rtc,
+            fsv6u,
+            connect_retry,
+            connect_retry_max,
+            password,
+            caps_configured,
+            hold_time,
+            remote_routerid,
+            neighbors,
+            role,
         })
     }
+    /// Parses address or address/length
+    fn parse_prefix(s: &str) -> Option<(IpAddr, u8)> {
+        let (a, l) = match s.split_once('/') {
+            None => (s, None),
+            Some((a, l)) => (a, Some(l)),
+        };
+        let addr: IpAddr = a.parse().ok()?;
+        let maxlen = if addr.is_ipv4() { 32 } else { 128 };
+        let len: u8 = match l {
+            None => maxlen,
+            Some(l) => l.parse().ok()?,
+        };
+        if len > maxlen {
+            return None;
+        }
+        Some((addr, len))
+    }
+    /// Allowed neighbor prefixes, including peer address
+    pub fn neighbor_prefixes(&self) -> Vec<(IpAddr, u8)> {
+        let mut ret = self.neighbors.clone();
+        if let Some(p) = self.peer {
+            ret.push((p.ip(), if p.is_ipv4() { 32 } else { 128 }));
+        }
+        ret
+    }
+    /// Returns matched prefix length for neighbor address, any neighbor matches with 0 if nothing was listed
+    pub fn neighbor_match(&self, addr: &IpAddr) -> Option<u8> {
+        let addr = match addr {
+            IpAddr::V6(a) => match a.to_ipv4_mapped() {
+                Some(a4) => IpAddr::V4(a4),
+                None => *addr,
+            },
+            a => *a,
+        };
+        let prefixes = self.neighbor_prefixes();
+        if prefixes.is_empty() {
+            return Some(0);
+        }
+        prefixes
+            .iter()
+            .filter(|(p, l)| match (p, &addr) {
+                (IpAddr::V4(p), IpAddr::V4(a)) => {
+                    *l == 0 || (u32::from(*p) ^ u32::from(*a)) >> (32 - *l as u32) == 0
+                }
+                (IpAddr::V6(p), IpAddr::V6(a)) => {
+                    *l == 0 || (u128::from(*p) ^ u128::from(*a)) >> (128 - *l as u32) == 0
+                }
+                _ => false,
+            })
+            .map(|(_, l)| *l)
+            .max()
+    }
     pub fn set_session_params(&self, params: BgpSessionParams) {
         *(self.bgpsessionparams.lock().unwrap()) = Some(params);
     }
+    /// Optional capability listed in caps, or implied one for "all" and absent caps
+    fn caps_option(caps: Option<&str>, name: &str, implied: bool) -> bool {
+        match caps {
+            None | Some("all") => implied,
+            Some("" | "min" | "minimal") => false,
+            Some(c) => c.split(',').any(|c| c == name),
+        }
+    }
     pub fn all_caps(asn: u32) -> Vec<BgpCapability> {
         vec![
             BgpCapability::SafiIPv4u,
@@ -283,6 +563,8 @@ impl ProtoPeer {
             BgpCapability::SafiIPv4mdt,
             BgpCapability::SafiIPv6mdt,
             BgpCapability::CapASN32(asn),
+            BgpCapability::CapRR,
+            BgpCapability::CapEnhancedRR,
             BgpCapability::CapAddPath(vec![
                 BgpCapAddPath::new_from_cap(BgpCapability::SafiIPv4u, true, true).unwrap(),
                 BgpCapAddPath::new_from_cap(BgpCapability::SafiIPv4lu, true, true).unwrap(),
@@ -317,7 +599,7 @@ impl ProtoPeer {
         };
         let pbsp = BgpSessionParams::new(
             self.bgppeeras,
-            180,
+            self.hold_time,
             peeraddrmode,
             self.routerid,
             self.def_caps(self.bgppeeras),
@@ -345,6 +627,35 @@ pub struct SvcConfig {
     pub purge_every: chrono::Duration,
     pub snapshot_file: Option<String>,
     pub snapshot_every: Option<chrono::Duration>,
+    pub bmp_stats_depth: usize,
+    /// how many route mirroring messages to keep for each BMP monitored peer
+    pub bmp_mirror_depth: usize,
+    pub mrt_archive: Option<String>,
+    pub mrt_archive_every: chrono::Duration,
+    /// RPKI RTR cache server host:port
+    pub rpki_rtr: Option<String>,
+    /// RTR refresh interval in seconds until cache tells its own
+    pub rpki_rtr_refresh: u64,
+    /// VRP JSON file in rpki-client or Routinator format
+    pub rpki_file: Option<String>,
+    /// VRP file check interval in seconds
+    pub rpki_file_reload: u64,
+    /// ASPA JSON file in rpki-client format, reloaded like VRP file
+    pub aspa_file: Option<String>,
+    /// IRR validation whois lookups per minute, 0 turns validation off
+    pub irr_rate: u64,
+    /// IRR validation result age in seconds to look it up again
+    pub irr_recheck: u64,
+    /// prefixes with allowed origins to alert on foreign announcements
+    pub owned_prefixes: Vec<OwnedPrefix>,
+    /// JSON file to keep alert log in
+    pub alerts_file: Option<String>,
+    /// check unicast routes against builtin bogon table
+    pub bogon_check: bool,
+    /// bogon list file extending builtin table, reloaded like VRP file
+    pub bogon_file: Option<String>,
+    /// builtin bogon prefixes and AS numbers are checked
+    pub bogon_builtin: bool,
 }
 
 #[derive(Debug)]
@@ -394,6 +705,7 @@ impl FromStr for PeerMode {
             "bgppassive" => Ok(PeerMode::BgpPassive),
             "bmppassive" => Ok(PeerMode::BmpPassive),
             "bmpactive" => Ok(PeerMode::BmpActive),
+            "mrtfile" => Ok(PeerMode::MrtFile),
             _ => Err(ErrorConfig::from_str("invalid mode")),
         }
     }
@@ -577,6 +889,231 @@ impl SvcConfig {
         } else {
             chrono::Duration::minutes(5)
         };
+        let bmp_stats_depth: usize = if mainsection.contains_key("bmp_stats_depth") {
+            match mainsection["bmp_stats_depth"] {
+                None => {
+                    return Err(ErrorConfig::from_str(
+                        "invalid bmp_stats_depth was specified",
+                    ));
+                }
+                Some(ref s) => match s.parse() {
+                    Err(e) => {
+                        return Err(ErrorConfig::from_string(format!(
+                            "Invalid bmp_stats_depth - {}",
+                            e
+                        )));
+                    }
+                    Ok(a) => a,
+                },
+            }
+        } else {
+            100
+        };
+        let bmp_mirror_depth: usize = if mainsection.contains_key("bmp_mirror_depth") {
+            match mainsection["bmp_mirror_depth"] {
+                None => {
+                    return Err(ErrorConfig::from_str(
+                        "invalid bmp_mirror_depth was specified",
+                    ));
+                }
+                Some(ref s) => match s.parse() {
+                    Err(e) => {
+                        return Err(ErrorConfig::from_string(format!(
+                            "Invalid bmp_mirror_depth - {}",
+                            e
+                        )));
+                    }
+                    Ok(a) => a,
+                },
+            }
+        } else {
+            100
+        };
+        let mrt_archive = if mainsection.contains_key("mrt_archive") {
+            mainsection["mrt_archive"].as_ref().map(|s| s.to_string())
+        } else {
+            None
+        };
+        let mrt_archive_every: chrono::Duration = if mainsection.contains_key("mrt_archive_every") {
+            match mainsection["mrt_archive_every"] {
+                None => {
+                    return Err(ErrorConfig::from_str(
+                        "invalid mrt_archive_every was specified",
+                    ));
+                }
+                Some(ref s) => chrono::Duration::seconds(match s.parse() {
+                    Err(e) => {
+                        return Err(ErrorConfig::from_string(format!(
+                            "Invalid mrt_archive_every - {}",
+                            e
+                        )));
+                    }
+                    Ok(a) => a,
+                }),
+            }
+        } else {
+            chrono::Duration::minutes(15)
+        };
+        let rpki_rtr = if mainsection.contains_key("rpki_rtr") {
+            mainsection["rpki_rtr"].as_ref().map(|s| s.to_string())
+        } else {
+            None
+        };
+        let rpki_rtr_refresh: u64 = if mainsection.contains_key("rpki_rtr_refresh") {
+            match mainsection["rpki_rtr_refresh"] {
+                None => {
+                    return Err(ErrorConfig::from_str(
+                        "invalid rpki_rtr_refresh was specified",
+                    ));
+                }
+                Some(ref s) => match s.parse() {
+                    Err(e) => {
+                        return Err(ErrorConfig::from_string(format!(
+                            "Invalid rpki_rtr_refresh - {}",
+                            e
+                        )));
+                    }
+                    Ok(a) => a,
+                },
+            }
+        } else {
+            3600
+        };
+        let rpki_file = if mainsection.contains_key("rpki_file") {
+            mainsection["rpki_file"].as_ref().map(|s| s.to_string())
+        } else {
+            None
+        };
+        let aspa_file = if mainsection.contains_key("aspa_file") {
+            mainsection["aspa_file"].as_ref().map(|s| s.to_string())
+        } else {
+            None
+        };
+        let rpki_file_reload: u64 = if mainsection.contains_key("rpki_file_reload") {
+            match mainsection["rpki_file_reload"] {
+                None => {
+                    return Err(ErrorConfig::from_str(
+                        "invalid rpki_file_reload was specified",
+                    ));
+                }
+                Some(ref s) => match s.parse() {
+                    Err(e) => {
+                        return Err(ErrorConfig::from_string(format!(
+                            "Invalid rpki_file_reload - {}",
+                            e
+                        )));
+                    }
+                    Ok(a) => a,
+                },
+            }
+        } else {
+            600
+        };
+        let irr_rate: u64 = if mainsection.contains_key("irr_rate") {
+            match mainsection["irr_rate"] {
+                None => {
+                    return Err(ErrorConfig::from_str("invalid irr_rate was specified"));
+                }
+                Some(ref s) => match s.parse() {
+                    Err(e) => {
+                        return Err(ErrorConfig::from_string(format!(
+                            "Invalid irr_rate - {}",
+                            e
+                        )));
+                    }
+                    Ok(a) => a,
+                },
+            }
+        } else {
+            0
+        };
+        let irr_recheck: u64 = if mainsection.contains_key("irr_recheck") {
+            match mainsection["irr_recheck"] {
+                None => {
+                    return Err(ErrorConfig::from_str("invalid irr_recheck was specified"));
+                }
+                Some(ref s) => match s.parse() {
+                    Err(e) => {
+                        return Err(ErrorConfig::from_string(format!(
+                            "Invalid irr_recheck - {}",
+                            e
+                        )));
+                    }
+                    Ok(a) => a,
+                },
+            }
+        } else {
+            86400
+        };
+        let mut owned_prefixes: Vec<OwnedPrefix> = Vec::new();
+        if mainsection.contains_key("owned_prefixes") {
+            match mainsection["owned_prefixes"] {
+                None => {
+                    return Err(ErrorConfig::from_str(
+                        "invalid owned_prefixes was specified",
+                    ));
+                }
+                Some(ref s) => {
+                    for n in s.split(',').map(|x| x.trim()).filter(|x| !x.is_empty()) {
+                        owned_prefixes.push(match n.parse() {
+                            Err(e) => {
+                                return Err(ErrorConfig::from_string(format!(
+                                    "Invalid owned prefix {} - {}",
+                                    n, e
+                                )));
+                            }
+                            Ok(a) => a,
+                        });
+                    }
+                }
+            }
+        }
+        let alerts_file = if mainsection.contains_key("alerts_file") {
+            mainsection["alerts_file"].as_ref().map(|s| s.to_string())
+        } else {
+            None
+        };
+        let bogon_check: bool = if mainsection.contains_key("bogon_check") {
+            match mainsection["bogon_check"] {
+                None => {
+                    return Err(ErrorConfig::from_str("invalid bogon_check was specified"));
+                }
+                Some(ref s) => match s.parse() {
+                    Err(e) => {
+                        return Err(ErrorConfig::from_string(format!(
+                            "Invalid bogon_check - {}",
+                            e
+                        )));
+                    }
+                    Ok(a) => a,
+                },
+            }
+        } else {
+            false
+        };
+        let bogon_builtin: bool = if mainsection.contains_key("bogon_builtin") {
+            match mainsection["bogon_builtin"] {
+                None => {
+                    return Err(ErrorConfig::from_str("invalid bogon_builtin was specified"));
+                }
+                Some(ref s) => match s.parse() {
+                    Err(e) => {
+                        return Err(ErrorConfig::from_string(format!(
+                            "Invalid bogon_builtin - {}",
+                            e
+                        )));
+                    }
+                    Ok(a) => a,
+                },
+            }
+        } else {
+            true
+        };
+        let bogon_file = if mainsection.contains_key("bogon_file") {
+            mainsection["bogon_file"].as_ref().map(|s| s.to_string())
+        } else {
+            None
+        };
         let whoisreqtimeout: u64 = if mainsection.contains_key("whois_request_timeout") {
             match mainsection["whois_request_timeout"] {
                 Some(ref s) => s.parse().unwrap_or(30),
@@ -654,6 +1191,101 @@ impl SvcConfig {
             snapshot_file,
             snapshot_every,
             timeidx_granularity,
+            bmp_stats_depth,
+            bmp_mirror_depth,
+            mrt_archive,
+            mrt_archive_every,
+            rpki_rtr,
+            rpki_rtr_refresh,
+            rpki_file,
+            rpki_file_reload,
+            aspa_file,
+            irr_rate,
+            irr_recheck,
+            owned_prefixes,
+            alerts_file,
+            bogon_check,
+            bogon_file,
+            bogon_builtin,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn passive_peer(neighbors: Option<&str>) -> Result<ProtoPeer, ErrorConfig> {
+        let mut section = std::collections::HashMap::new();
+        section.insert("mode".to_string(), Some("bgppassive".to_string()));
+        if let Some(n) = neighbors {
+            section.insert("neighbors".to_string(), Some(n.to_string()));
+        }
+        ProtoPeer::from_ini(&section)
+    }
+
+    #[test]
+    fn test_parse_prefix() {
+        let a = |s: &str| s.parse::<IpAddr>().unwrap();
+        assert_eq!(
+            ProtoPeer::parse_prefix("10.0.0.0/8"),
+            Some((a("10.0.0.0"), 8))
+        );
+        assert_eq!(
+            ProtoPeer::parse_prefix("192.0.2.1"),
+            Some((a("192.0.2.1"), 32))
+        );
+        assert_eq!(
+            ProtoPeer::parse_prefix("2001:db8::1"),
+            Some((a("2001:db8::1"), 128))
+        );
+        assert_eq!(
+            ProtoPeer::parse_prefix("2001:db8::/33"),
+            Some((a("2001:db8::"), 33))
+        );
+        assert_eq!(ProtoPeer::parse_prefix("10.0.0.0/33"), None);
+        assert_eq!(ProtoPeer::parse_prefix("2001:db8::/129"), None);
+        assert_eq!(ProtoPeer::parse_prefix("10.0.0.0/"), None);
+        assert_eq!(ProtoPeer::parse_prefix("10.0.0.0/x"), None);
+        assert_eq!(ProtoPeer::parse_prefix("router.example.net"), None);
+        assert_eq!(ProtoPeer::parse_prefix(""), None);
+    }
+
+    #[test]
+    fn test_neighbor_match() {
+        let a = |s: &str| s.parse::<IpAddr>().unwrap();
+        let peer = passive_peer(Some("10.0.0.0/8, 10.1.0.0/16,192.0.2.1,2001:db8::/32")).unwrap();
+        assert_eq!(peer.neighbor_match(&a("10.1.2.3")), Some(16));
+        assert_eq!(peer.neighbor_match(&a("10.2.0.1")), Some(8));
+        assert_eq!(peer.neighbor_match(&a("192.0.2.1")), Some(32));
+        assert_eq!(peer.neighbor_match(&a("192.0.2.2")), None);
+        assert_eq!(peer.neighbor_match(&a("11.0.0.1")), None);
+        assert_eq!(peer.neighbor_match(&a("2001:db8:1::1")), Some(32));
+        assert_eq!(peer.neighbor_match(&a("2001:db9::1")), None);
+        // IPv4-mapped IPv6 address of dual stack listener matches IPv4 prefix
+        assert_eq!(peer.neighbor_match(&a("::ffff:10.1.0.1")), Some(16));
+        // default route of one family does not match the other
+        let peer = passive_peer(Some("0.0.0.0/0")).unwrap();
+        assert_eq!(peer.neighbor_match(&a("203.0.113.1")), Some(0));
+        assert_eq!(peer.neighbor_match(&a("2001:db8::1")), None);
+        let peer = passive_peer(None).unwrap();
+        assert_eq!(peer.neighbor_match(&a("2001:db8::1")), Some(0));
+        assert!(passive_peer(Some("10.0.0.0/8,10.0.0.0/40")).is_err());
+        assert!(passive_peer(Some("router.example.net")).is_err());
+    }
+
+    #[test]
+    fn test_password_listen_family() {
+        let keyed = |listen: &str, neighbors: &str| {
+            let mut section = std::collections::HashMap::new();
+            section.insert("mode".to_string(), Some("bgppassive".to_string()));
+            section.insert("protolisten".to_string(), Some(listen.to_string()));
+            section.insert("neighbors".to_string(), Some(neighbors.to_string()));
+            section.insert("password".to_string(), Some("secret".to_string()));
+            ProtoPeer::from_ini(&section)
+        };
+        assert!(keyed("[::]:179", "10.0.0.0/8,2001:db8::/32").is_ok());
+        assert!(keyed("0.0.0.0:179", "10.0.0.0/8").is_ok());
+        assert!(keyed("0.0.0.0:179", "10.0.0.0/8,2001:db8::/32").is_err());
+    }
+}
@@ -0,0 +1,352 @@
+//! Route flap counters and instability ranking
+use crate::bgpsvc::BgpSessionId;
+use crate::timestamp::Timestamp;
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use zettabgp::prelude::BgpError;
+
+/// RFC 2439 figure of merit increments
+const PENALTY_WITHDRAW: f64 = 1000.0;
+const PENALTY_CHANGE: f64 = 500.0;
+/// Penalty half-life, seconds
+const PENALTY_HALF_LIFE: f64 = 900.0;
+/// Event counters granularity, seconds
+const BUCKET_SECS: i64 = 60;
+/// Longest ranking window, seconds
+pub const FLAP_WINDOW_MAX: i64 = 86400;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlapEvent {
+    /// new route or re-announcement after withdraw
+    Announce,
+    Withdraw,
+    /// attributes of active route changed
+    Change,
+}
+impl FlapEvent {
+    fn index(&self) -> usize {
+        match self {
+            FlapEvent::Announce => 0,
+            FlapEvent::Withdraw => 1,
+            FlapEvent::Change => 2,
+        }
+    }
+}
+
+/// Flap counters of route from one session, independent of history depth.
+/// Counters start with the first withdrawal or change and the announcement before it,
+/// stable routes have none.
+#[derive(Debug, Clone, Default)]
+pub struct FlapStats {
+    pub announces: u64,
+    pub withdraws: u64,
+    pub changes: u64,
+    pub last_change: Option<Timestamp>,
+    penalty: f64,
+    penalty_at: i64,
+    /// events by kind per bucket start, within FLAP_WINDOW_MAX
+    buckets: VecDeque<(i64, [u32; 3])>,
+}
+impl FlapStats {
+    pub fn record(&mut self, ev: FlapEvent, when: &Timestamp) {
+        match ev {
+            FlapEvent::Announce => self.announces += 1,
+            FlapEvent::Withdraw => self.withdraws += 1,
+            FlapEvent::Change => self.changes += 1,
+        }
+        let secs = when.timestamp();
+        let add = match ev {
+            FlapEvent::Announce => 0.0,
+            FlapEvent::Withdraw => PENALTY_WITHDRAW,
+            FlapEvent::Change => PENALTY_CHANGE,
+        };
+        self.penalty = self.penalty(secs) + add;
+        self.penalty_at = secs;
+        self.last_change = Some(*when);
+        let bucket = secs - secs.rem_euclid(BUCKET_SECS);
+        match self.buckets.back_mut() {
+            Some((b, cnt)) if *b == bucket => cnt[ev.index()] += 1,
+            _ => {
+                let mut cnt = [0; 3];
+                cnt[ev.index()] = 1;
+                self.buckets.push_back((bucket, cnt));
+            }
+        }
+        self.prune(secs);
+    }
+    /// Drops buckets out of the longest window
+    fn prune(&mut self, now: i64) {
+        while let Some((b, _)) = self.buckets.front() {
+            if *b + FLAP_WINDOW_MAX > now {
+                break;
+            }
+            self.buckets.pop_front();
+        }
+    }
+    /// Prunes buckets, true if there were no events within the longest window
+    pub fn expired(&mut self, now: i64) -> bool {
+        self.prune(now);
+        self.buckets.is_empty() && self.penalty(now) < 1.0
+    }
+    /// Penalty decayed to the given time
+    pub fn penalty(&self, now: i64) -> f64 {
+        let elapsed = (now - self.penalty_at).max(0) as f64;
+        self.penalty * (-elapsed / PENALTY_HALF_LIFE).exp2()
+    }
+    /// Announcements, withdrawals and changes since given time, or total ones
+    pub fn counts(&self, since: Option<i64>) -> [u64; 3] {
+        match since {
+            None => [self.announces, self.withdraws, self.changes],
+            Some(since) => {
+                let mut ret = [0; 3];
+                for (_, cnt) in self.buckets.iter().filter(|(b, _)| b + BUCKET_SECS > since) {
+                    for i in 0..3 {
+                        ret[i] += cnt[i] as u64;
+                    }
+                }
+                ret
+            }
+        }
+    }
+}
+
+/// Instability ranking criteria
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlapMetric {
+    /// all events count
+    Flaps,
+    Announces,
+    Withdraws,
+    Changes,
+    /// current decayed penalty
+    Penalty,
+}
+impl std::str::FromStr for FlapMetric {
+    type Err = BgpError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "flaps" => Ok(FlapMetric::Flaps),
+            "announces" => Ok(FlapMetric::Announces),
+            "withdraws" => Ok(FlapMetric::Withdraws),
+            "changes" => Ok(FlapMetric::Changes),
+            "penalty" => Ok(FlapMetric::Penalty),
+            _ => Err(BgpError::static_str("Invalid flap metric")),
+        }
+    }
+}
+
+/// Window like 90, 30s, 15m, 1h or 1d in seconds, up to FLAP_WINDOW_MAX
+pub fn parse_window(s: &str) -> Option<i64> {
+    let (n, mult) = match s.char_indices().last()? {
+        (i, 's') => (&s[..i], 1),
+        (i, 'm') => (&s[..i], 60),
+        (i, 'h') => (&s[..i], 3600),
+        (i, 'd') => (&s[..i], 86400),
+        _ => (s, 1),
+    };
+    let secs = n.parse::<i64>().ok()? * mult;
+    if secs > 0 && secs <= FLAP_WINDOW_MAX {
+        Some(secs)
+    } else {
+        None
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct FlapPrefix {
+    pub prefix: String,
+    pub session: BgpSessionId,
+    pub origin: Option<u32>,
+    pub announces: u64,
+    pub withdraws: u64,
+    pub changes: u64,
+    pub penalty: f64,
+    pub last_change: Option<Timestamp>,
+    pub score: f64,
+}
+#[derive(Debug, Serialize)]
+pub struct FlapOrigin {
+    pub origin: u32,
+    pub score: f64,
+    pub prefixes: usize,
+}
+#[derive(Debug, Serialize)]
+pub struct FlapPeer {
+    pub session: BgpSessionId,
+    pub score: f64,
+    pub prefixes: usize,
+}
+#[derive(Debug, Serialize)]
+pub struct FlapTop {
+    pub by: String,
+    pub window: Option<i64>,
+    pub prefixes: Vec<FlapPrefix>,
+    pub origins: Vec<FlapOrigin>,
+    pub peers: Vec<FlapPeer>,
+}
+
+/// Collects scores of routes, prefixes with zero score are skipped
+pub struct FlapRanking {
+    by: FlapMetric,
+    window: Option<i64>,
+    now: i64,
+    /// imported sessions are ranked relative to their newest event instead of now
+    imported: HashMap<BgpSessionId, i64>,
+    prefixes: Vec<FlapPrefix>,
+    origins: HashMap<u32, (f64, usize)>,
+    peers: HashMap<BgpSessionId, (f64, usize)>,
+}
+impl FlapRanking {
+    pub fn new(
+        by: FlapMetric,
+        window: Option<i64>,
+        imported: &BTreeSet<BgpSessionId>,
+    ) -> FlapRanking {
+        FlapRanking {
+            by,
+            window,
+            now: Timestamp::now().timestamp(),
+            imported: imported.iter().map(|s| (*s, i64::MIN)).collect(),
+            prefixes: Vec::new(),
+            origins: HashMap::new(),
+            peers: HashMap::new(),
+        }
+    }
+    /// Tracks newest event of imported session, all counters are observed before add
+    pub fn observe(&mut self, session: BgpSessionId, stats: &FlapStats) {
+        if let (Some(newest), Some(t)) = (self.imported.get_mut(&session), stats.last_change) {
+            *newest = (*newest).max(t.timestamp());
+        }
+    }
+    pub fn add(
+        &mut self,
+        prefix: &impl std::fmt::Display,
+        session: BgpSessionId,
+        origin: Option<u32>,
+        stats: &FlapStats,
+    ) {
+        let now = self.imported.get(&session).copied().unwrap_or(self.now);
+        let cnt = stats.counts(self.window.map(|w| now - w));
+        let penalty = stats.penalty(now);
+        let score = match self.by {
+            FlapMetric::Flaps => (cnt[0] + cnt[1] + cnt[2]) as f64,
+            FlapMetric::Announces => cnt[0] as f64,
+            FlapMetric::Withdraws => cnt[1] as f64,
+            FlapMetric::Changes => cnt[2] as f64,
+            FlapMetric::Penalty => penalty,
+        };
+        if score < 1.0 {
+            return;
+        }
+        if let Some(o) = origin {
+            let e = self.origins.entry(o).or_default();
+            e.0 += score;
+            e.1 += 1;
+        }
+        let e = self.peers.entry(session).or_default();
+        e.0 += score;
+        e.1 += 1;
+        self.prefixes.push(FlapPrefix {
+            prefix: prefix.to_string(),
+            session,
+            origin,
+            announces: cnt[0],
+            withdraws: cnt[1],
+            changes: cnt[2],
+            penalty: penalty.round(),
+            last_change: stats.last_change,
+            score,
+        });
+    }
+    /// Top limit items of every list by descending score
+    pub fn top(self, name: &str, limit: usize) -> FlapTop {
+        let desc = |a: &f64, b: &f64| b.partial_cmp(a).unwrap_or(std::cmp::Ordering::Equal);
+        let mut prefixes = self.prefixes;
+        prefixes.sort_by(|a, b| desc(&a.score, &b.score));
+        prefixes.truncate(limit);
+        let mut origins: Vec<FlapOrigin> = self
+            .origins
+            .into_iter()
+            .map(|(origin, (score, prefixes))| FlapOrigin {
+                origin,
+                score,
+                prefixes,
+            })
+            .collect();
+        origins.sort_by(|a, b| desc(&a.score, &b.score));
+        origins.truncate(limit);
+        let mut peers: Vec<FlapPeer> = self
+            .peers
+            .into_iter()
+            .map(|(session, (score, prefixes))| FlapPeer {
+                session,
+                score,
+                prefixes,
+            })
+            .collect();
+        peers.sort_by(|a, b| desc(&a.score, &b.score));
+        peers.truncate(limit);
+        FlapTop {
+            by: name.to_string(),
+            window: self.window,
+            prefixes,
+            origins,
+            peers,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flap_stats() {
+        assert_eq!(parse_window("1h"), Some(3600));
+        assert_eq!(parse_window("90"), Some(90));
+        assert_eq!(parse_window("2d"), None);
+        assert_eq!(parse_window("h"), None);
+        let now = Timestamp::now();
+        let old: Timestamp = (*now - chrono::Duration::hours(2)).into();
+        let mut st = FlapStats::default();
+        st.record(FlapEvent::Announce, &old);
+        st.record(FlapEvent::Withdraw, &old);
+        st.record(FlapEvent::Announce, &now);
+        st.record(FlapEvent::Change, &now);
+        assert_eq!(st.counts(None), [2, 1, 1]);
+        assert_eq!(st.counts(Some(now.timestamp() - 3600)), [1, 0, 1]);
+        // withdraw penalty decayed by 8 half-lives
+        let p = st.penalty(now.timestamp());
+        assert!(p > 503.0 && p < 504.0);
+        let mut rank = FlapRanking::new(FlapMetric::Flaps, Some(3600), &BTreeSet::new());
+        rank.add(&"10.0.0.0/24", 1, Some(65001), &st);
+        rank.add(&"10.0.1.0/24", 2, Some(65001), &FlapStats::default());
+        let top = rank.top("flaps", 10);
+        assert_eq!(top.prefixes.len(), 1);
+        assert_eq!(top.prefixes[0].score, 2.0);
+        assert_eq!(top.origins[0].prefixes, 1);
+        assert_eq!(top.peers[0].session, 1);
+        assert!(!st.expired(now.timestamp()));
+        assert!(st.expired(now.timestamp() + FLAP_WINDOW_MAX));
+        // imported session is ranked relative to its newest event
+        let mut old = FlapStats::default();
+        let t: Timestamp = (*now - chrono::Duration::days(30)).into();
+        old.record(FlapEvent::Withdraw, &t);
+        old.record(FlapEvent::Announce, &t);
+        let mut rank = FlapRanking::new(FlapMetric::Penalty, Some(3600), &BTreeSet::from([2]));
+        for sess in [1, 2] {
+            rank.observe(sess, &old);
+        }
+        for sess in [1, 2] {
+            rank.add(&"10.0.2.0/24", sess, None, &old);
+        }
+        let top = rank.top("penalty", 10);
+        assert_eq!(top.prefixes.len(), 1);
+        assert_eq!(top.prefixes[0].session, 2);
+        assert_eq!(top.prefixes[0].penalty, 1000.0);
+        assert_eq!(
+            (top.prefixes[0].announces, top.prefixes[0].withdraws),
+            (1, 1)
+        );
+    }
+}
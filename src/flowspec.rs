@@ -0,0 +1,211 @@
+//! VPNv6 FlowSpec (RFC 8955, RFC 8956) NLRI, which is unknown to zettabgp
+use crate::rtc::extract_mp;
+use serde::{Deserialize, Serialize};
+use zettabgp::prelude::*;
+
+/// VPNv6 FlowSpec address family
+pub const FSV6U_AFI: u16 = 2;
+pub const FSV6U_SAFI: u8 = 134;
+
+/// VPNv6 FlowSpec rule, route distinguisher and all its components
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct BgpFlowSpecVpn6 {
+    pub rd: BgpRD,
+    pub components: Vec<BgpFlowSpec<FS6>>,
+}
+impl BgpFlowSpecVpn6 {
+    /// Decodes NLRI, returns rule and consumed length
+    pub fn decode_from(buf: &[u8]) -> Result<(BgpFlowSpecVpn6, usize), BgpError> {
+        if buf.is_empty() {
+            return Err(BgpError::insufficient_buffer_size());
+        }
+        let (pos, len) = if buf[0] & 0xf0 == 0xf0 {
+            if buf.len() < 2 {
+                return Err(BgpError::insufficient_buffer_size());
+            }
+            (2, (getn_u16(buf) & 0xfff) as usize)
+        } else {
+            (1, buf[0] as usize)
+        };
+        if buf.len() < pos + len || len < 8 {
+            return Err(BgpError::static_str("Invalid VPNv6 FlowSpec NLRI length"));
+        }
+        let nlri = &buf[pos..pos + len];
+        let (rd, mut cpos) = BgpRD::decode_rd_from(nlri)?;
+        let mut components = Vec::new();
+        while cpos < nlri.len() {
+            let (c, sz) = BgpFlowSpecVpn6::decode_component(&nlri[cpos..])?;
+            components.push(c);
+            cpos += sz;
+        }
+        Ok((BgpFlowSpecVpn6 { rd, components }, pos + len))
+    }
+    /// Decodes one component, returns it and consumed length
+    fn decode_component(buf: &[u8]) -> Result<(BgpFlowSpec<FS6>, usize), BgpError> {
+        match buf[0] {
+            1 | 2 => {
+                // prefix length, offset and pattern bits from offset to length
+                if buf.len() < 3 || buf[1] > 128 || buf[2] > buf[1] {
+                    return Err(BgpError::static_str("Invalid VPNv6 FlowSpec prefix"));
+                }
+                let bytes = (buf[1] - buf[2]).div_ceil(8) as usize;
+                if buf.len() < 3 + bytes {
+                    return Err(BgpError::insufficient_buffer_size());
+                }
+                let mut v = [0u8; 16];
+                v[0..bytes].copy_from_slice(&buf[3..3 + bytes]);
+                let addr = std::net::Ipv6Addr::from(u128::from_be_bytes(v) >> buf[2]);
+                let p = FS6::new(buf[2], BgpAddrV6::new(addr, buf[1]));
+                Ok((
+                    if buf[0] == 1 {
+                        BgpFlowSpec::PrefixDst(p)
+                    } else {
+                        BgpFlowSpec::PrefixSrc(p)
+                    },
+                    3 + bytes,
+                ))
+            }
+            3..=13 => {
+                // operator and value pairs until end-of-list bit
+                let mut pos = 1;
+                loop {
+                    if pos >= buf.len() {
+                        return Err(BgpError::insufficient_buffer_size());
+                    }
+                    let op = buf[pos];
+                    pos += 1 + (1usize << ((op >> 4) & 3));
+                    if op & 0x80 != 0 {
+                        break;
+                    }
+                }
+                if pos > buf.len() || pos > 255 {
+                    return Err(BgpError::static_str("Invalid VPNv6 FlowSpec component"));
+                }
+                // zettabgp decodes length-prefixed single component
+                let mut item = Vec::with_capacity(pos + 1);
+                item.push(pos as u8);
+                item.extend_from_slice(&buf[0..pos]);
+                let (c, _) = BgpFlowSpec::<FS6>::decode_from(BgpTransportMode::IPv6, &item)?;
+                Ok((c, pos))
+            }
+            _ => Err(BgpError::static_str(
+                "Unknown VPNv6 FlowSpec component type",
+            )),
+        }
+    }
+    /// Destination and source prefixes of the rule
+    pub fn prefixes(&self) -> impl Iterator<Item = &BgpAddrV6> {
+        self.components.iter().filter_map(|c| match c {
+            BgpFlowSpec::PrefixDst(p) | BgpFlowSpec::PrefixSrc(p) => Some(&p.ipv6),
+            _ => None,
+        })
+    }
+}
+impl std::fmt::Display for BgpFlowSpecVpn6 {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.rd)?;
+        for c in self.components.iter() {
+            write!(f, " {}", c)?;
+        }
+        Ok(())
+    }
+}
+
+/// VPNv6 FlowSpec part of UPDATE message
+#[derive(Debug, Clone)]
+pub struct FsV6uUpdate {
+    pub nexthop: BgpAddr,
+    pub updates: Vec<BgpFlowSpecVpn6>,
+    pub withdraws: Vec<BgpFlowSpecVpn6>,
+    /// UPDATE was End-of-RIB marker for VPNv6 FlowSpec
+    pub end_of_rib: bool,
+}
+impl FsV6uUpdate {
+    fn decode_nlri(buf: &[u8], v: &mut Vec<BgpFlowSpecVpn6>) -> Result<(), BgpError> {
+        let mut pos = 0;
+        while pos < buf.len() {
+            let (r, sz) = BgpFlowSpecVpn6::decode_from(&buf[pos..])?;
+            v.push(r);
+            pos += sz;
+        }
+        Ok(())
+    }
+    /// Next hop of MP_REACH_NLRI, usually empty for FlowSpec, may be prepended with RD
+    fn decode_nexthop(nh: &[u8]) -> Result<BgpAddr, BgpError> {
+        Ok(match nh.len() {
+            4 => BgpAddr::V4(decode_addrv4_from(nh)?),
+            12 => BgpAddr::V4(decode_addrv4_from(&nh[8..])?),
+            16 | 32 => BgpAddr::V6(decode_addrv6_from(&nh[0..16])?),
+            24 | 48 => BgpAddr::V6(decode_addrv6_from(&nh[8..24])?),
+            _ => BgpAddr::None,
+        })
+    }
+}
+
+/// Extracts VPNv6 FlowSpec NLRI from UPDATE message body.
+/// Returns rules and message body without VPNv6 FlowSpec attributes, or None if there are none.
+pub fn extract_fsv6u(buf: &[u8]) -> Result<Option<(FsV6uUpdate, Vec<u8>)>, BgpError> {
+    let (mp, body) = match extract_mp(buf, FSV6U_AFI, FSV6U_SAFI)? {
+        None => return Ok(None),
+        Some(r) => r,
+    };
+    let mut ret = FsV6uUpdate {
+        nexthop: BgpAddr::None,
+        updates: Vec::new(),
+        withdraws: Vec::new(),
+        end_of_rib: mp.end_of_rib,
+    };
+    if let Some(ref reach) = mp.reach {
+        if reach.is_empty() || reach.len() < 2 + reach[0] as usize {
+            return Err(BgpError::static_str(
+                "Invalid VPNv6 FlowSpec MP_REACH_NLRI length",
+            ));
+        }
+        let nhlen = reach[0] as usize;
+        ret.nexthop = FsV6uUpdate::decode_nexthop(&reach[1..1 + nhlen])?;
+        FsV6uUpdate::decode_nlri(&reach[2 + nhlen..], &mut ret.updates)?;
+    }
+    if let Some(ref unreach) = mp.unreach {
+        FsV6uUpdate::decode_nlri(unreach, &mut ret.withdraws)?;
+    }
+    Ok(Some((ret, body)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_fsv6u() {
+        // ORIGIN, MP_REACH_NLRI 2/134 without next hop, RD 65000:1,
+        // destination 2001:db8::/32, source ::1:0:0:0/80 with offset 64, TCP
+        let nlri = [
+            23, 0, 0, 0xfd, 0xe8, 0, 0, 0, 1, 1, 32, 0, 0x20, 0x01, 0x0d, 0xb8, 2, 80, 64, 0, 1, 3,
+            0x81, 6,
+        ];
+        let mut attr = vec![0, 2, 134, 0, 0];
+        attr.extend_from_slice(&nlri);
+        let mut buf = vec![0, 0];
+        let mut attrs = vec![0x40, 1, 1, 0, 0x80, 14, attr.len() as u8];
+        attrs.extend_from_slice(&attr);
+        buf.extend_from_slice(&(attrs.len() as u16).to_be_bytes());
+        buf.extend_from_slice(&attrs);
+        let (fs, body) = extract_fsv6u(&buf).unwrap().unwrap();
+        assert_eq!(body, vec![0, 0, 0, 4, 0x40, 1, 1, 0]);
+        assert_eq!(fs.nexthop, BgpAddr::None);
+        assert!(!fs.end_of_rib);
+        assert_eq!(fs.updates.len(), 1);
+        let rule = &fs.updates[0];
+        assert_eq!(rule.rd, BgpRD::new(0xfde8, 1));
+        assert_eq!(rule.components.len(), 3);
+        let prefixes: Vec<String> = rule.prefixes().map(|p| p.to_string()).collect();
+        assert_eq!(prefixes, vec!["2001:db8::/32", "::1:0:0:0/80"]);
+        assert!(matches!(rule.components[2], BgpFlowSpec::Proto(_)));
+        assert!(extract_fsv6u(&body).unwrap().is_none());
+        let (fs, body) = extract_fsv6u(&[0, 0, 0, 6, 0x80, 15, 3, 0, 2, 134])
+            .unwrap()
+            .unwrap();
+        assert!(fs.end_of_rib);
+        assert_eq!(body, vec![0, 0, 0, 0]);
+    }
+}
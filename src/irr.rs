@@ -0,0 +1,236 @@
+//! IRR route object validation of unicast routes through whois service
+use crate::bgprib::BgpRibUpdate;
+use crate::bgpsvc::BgpSvr;
+use crate::whoissvc::WhoisSvr;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::select;
+use zettabgp::prelude::*;
+
+/// IRR route object state of prefix and origin AS
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IrrState {
+    /// route or route6 object with the origin exists
+    Valid,
+    Missing,
+}
+impl std::str::FromStr for IrrState {
+    type Err = BgpError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "valid" => Ok(IrrState::Valid),
+            "missing" => Ok(IrrState::Missing),
+            _ => Err(BgpError::static_str("Invalid IRR state")),
+        }
+    }
+}
+impl std::fmt::Display for IrrState {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            IrrState::Valid => f.write_str("valid"),
+            IrrState::Missing => f.write_str("missing"),
+        }
+    }
+}
+
+/// Prefix, prefix length and origin AS
+pub type IrrKey = (IpAddr, u8, u32);
+
+/// Routes count to collect for lookups at once
+const IRR_BATCH: usize = 100;
+
+fn parse_prefix(s: &str) -> Option<(IpAddr, u8)> {
+    let (a, l) = s.split_once('/')?;
+    Some((a.trim().parse().ok()?, l.trim().parse().ok()?))
+}
+
+/// Prefixes and origins of route and route6 objects in whois response
+pub fn parse_route_objects(s: &str) -> Vec<IrrKey> {
+    let mut ret = Vec::new();
+    let mut route = None;
+    for line in s.lines() {
+        if line.trim().is_empty() {
+            route = None;
+            continue;
+        }
+        let (k, v) = match line.split_once(':') {
+            Some(kv) => kv,
+            None => continue,
+        };
+        match k.trim().to_lowercase().as_str() {
+            "route" | "route6" => route = parse_prefix(v),
+            "origin" => {
+                let asn = v.trim().to_uppercase();
+                if let (Some((a, l)), Ok(n)) = (route, asn.trim_start_matches("AS").parse()) {
+                    ret.push((a, l, n));
+                }
+            }
+            _ => {}
+        }
+    }
+    ret
+}
+
+/// Validation results with check time and queue of routes to look up
+#[derive(Debug)]
+pub struct IrrTable {
+    /// IRR validation is configured
+    pub enabled: bool,
+    /// result age to check it again
+    pub recheck: Duration,
+    items: HashMap<IrrKey, (IrrState, Instant)>,
+    pending: VecDeque<IrrKey>,
+    queued: HashSet<IrrKey>,
+    /// routes present before validator start were queued
+    pub scanned: bool,
+}
+impl IrrTable {
+    pub fn new(enabled: bool, recheck: Duration) -> IrrTable {
+        IrrTable {
+            enabled,
+            recheck,
+            items: HashMap::new(),
+            pending: VecDeque::new(),
+            queued: HashSet::new(),
+            scanned: false,
+        }
+    }
+    pub fn get(&self, key: &IrrKey) -> Option<IrrState> {
+        self.items.get(key).map(|(s, _)| *s)
+    }
+    pub fn set(&mut self, key: IrrKey, state: IrrState) {
+        self.items.insert(key, (state, Instant::now()));
+    }
+    pub fn needs_check(&self, key: &IrrKey) -> bool {
+        match self.items.get(key) {
+            None => true,
+            Some((_, checked)) => checked.elapsed() > self.recheck,
+        }
+    }
+    /// Queues route for lookup unless it is queued already or has fresh result
+    pub fn enqueue(&mut self, key: IrrKey) {
+        if self.enabled && self.needs_check(&key) && self.queued.insert(key) {
+            self.pending.push_back(key);
+        }
+    }
+    /// Takes up to limit queued routes
+    pub fn take(&mut self, limit: usize) -> Vec<IrrKey> {
+        let n = limit.min(self.pending.len());
+        let ret: Vec<IrrKey> = self.pending.drain(0..n).collect();
+        for k in ret.iter() {
+            self.queued.remove(k);
+        }
+        ret
+    }
+    pub fn is_idle(&self) -> bool {
+        self.pending.is_empty()
+    }
+    /// Removes results older than recheck age, returns their keys
+    pub fn expire(&mut self) -> Vec<IrrKey> {
+        let recheck = self.recheck;
+        let mut ret = Vec::new();
+        self.items.retain(|k, (_, checked)| {
+            if checked.elapsed() > recheck {
+                ret.push(*k);
+                false
+            } else {
+                true
+            }
+        });
+        ret
+    }
+}
+
+/// Looks up route objects for unchecked routes, at most per_minute whois queries
+pub async fn run_irr(
+    bgp: Arc<BgpSvr>,
+    whois: Arc<WhoisSvr>,
+    per_minute: u64,
+    cancel: tokio_util::sync::CancellationToken,
+) {
+    let pause = Duration::from_millis(60000 / per_minute.max(1));
+    let check4 = Arc::new(Some(Regex::new(r"route:").unwrap()));
+    let check6 = Arc::new(Some(Regex::new(r"route6:").unwrap()));
+    // failed lookups are queued again when queue is empty
+    let mut failed: Vec<IrrKey> = Vec::new();
+    loop {
+        let keys = bgp.rib.rib.write().await.irr_candidates(IRR_BATCH);
+        if keys.is_empty() {
+            if !failed.is_empty() {
+                let mut rib = bgp.rib.rib.write().await;
+                failed.drain(..).for_each(|k| rib.irr.enqueue(k));
+            }
+            select! {
+                _ = cancel.cancelled() => return,
+                _ = tokio::time::sleep(Duration::from_secs(60)) => {}
+            }
+            continue;
+        }
+        for key in keys.into_iter() {
+            select! {
+                _ = cancel.cancelled() => return,
+                _ = tokio::time::sleep(pause) => {}
+            }
+            let check = if key.0.is_ipv4() { &check4 } else { &check6 };
+            let res = whois
+                .query_whois(format!("{}/{}", key.0, key.1), check.clone())
+                .await;
+            let state = match res {
+                Ok(s) => {
+                    if parse_route_objects(&s).contains(&key) {
+                        IrrState::Valid
+                    } else {
+                        IrrState::Missing
+                    }
+                }
+                Err(e) => {
+                    debug!("IRR lookup {}/{} error: {:?}", key.0, key.1, e);
+                    failed.push(key);
+                    continue;
+                }
+            };
+            if !bgp.queue_rib_update(BgpRibUpdate::Irr(key, state)).await {
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_irr_queue() {
+        let mut tbl = IrrTable::new(true, Duration::from_secs(0));
+        let a: IpAddr = "10.0.0.0".parse().unwrap();
+        tbl.enqueue((a, 24, 65001));
+        tbl.enqueue((a, 24, 65001));
+        tbl.enqueue((a, 24, 65002));
+        assert_eq!(tbl.take(1), vec![(a, 24, 65001)]);
+        tbl.set((a, 24, 65001), IrrState::Valid);
+        assert_eq!(tbl.take(10), vec![(a, 24, 65002)]);
+        assert!(tbl.is_idle());
+        std::thread::sleep(Duration::from_millis(1));
+        assert_eq!(tbl.expire(), vec![(a, 24, 65001)]);
+        assert_eq!(tbl.get(&(a, 24, 65001)), None);
+    }
+
+    #[test]
+    fn test_parse_route_objects() {
+        let s = "% comment\n\nroute:          10.0.0.0/24\ndescr:          test\norigin:         AS65001\n\n\
+                 route6:   2001:db8::/32\norigin: as65002\n\nmnt-by: X\norigin: AS65003\n";
+        assert_eq!(
+            parse_route_objects(s),
+            vec![
+                ("10.0.0.0".parse().unwrap(), 24, 65001),
+                ("2001:db8::".parse().unwrap(), 32, 65002)
+            ]
+        );
+    }
+}
@@ -26,11 +26,15 @@ use tokio::fs::File;
 use tokio::*;
 use tokio_util::codec::{BytesCodec, Decoder, Framed, FramedRead};
 
+mod alerts;
 mod bgpattrs;
 mod bgppeer;
 mod bgprib;
+mod bogon;
 use bgprib::*;
 mod bmppeer;
+mod bmpstats;
+mod mrt;
 mod service;
 use service::*;
 mod bgpsvc;
@@ -38,10 +42,16 @@ use bgpsvc::*;
 mod whoissvc;
 use whoissvc::*;
 mod config;
+mod flaps;
+mod flowspec;
+mod irr;
 use config::*;
 mod ribfilter;
 mod ribservice;
+mod rpki;
+mod rtc;
 mod subscriber;
+mod tcpmd5;
 mod timestamp;
 
 use std::sync::Arc;
@@ -56,6 +66,15 @@ fn not_found() -> Response<Body> {
         .unwrap()
 }
 
+/// HTTP status code 405 for state changing API called without POST
+fn method_not_allowed() -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::METHOD_NOT_ALLOWED)
+        .header("Allow", "POST")
+        .body("Method Not Allowed".into())
+        .unwrap()
+}
+
 async fn simple_file_send(filename: &str) -> Result<Response<Body>, hyper::Error> {
     if let Ok(file) = File::open(filename).await {
         let stream = FramedRead::new(file, BytesCodec::new());
@@ -135,11 +154,13 @@ impl Svc {
         Ok(res)
     }
     pub async fn response_fn(&self, req: Request<Body>) -> Result<Response<Body>, hyper::Error> {
-        if req.method() != Method::GET {
+        let requri = req.uri().path();
+        let api = requri.len() > 5 && requri[..5] == "/api/"[..5];
+        // POST is for state changing API calls only
+        if req.method() != Method::GET && !(api && req.method() == Method::POST) {
             return Ok(not_found());
         }
-        let requri = req.uri().path();
-        if requri.len() > 5 && requri[..5] == "/api/"[..5] {
+        if api {
             let urlparts: Vec<&str> = requri.split('/').collect();
             if urlparts.len() > 2 {
                 match urlparts[2] {
@@ -190,11 +211,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let mut svr = BgpSvr::new(conf.clone(), token.clone());
     svr.start_updates().await;
     let msvr = Arc::new(svr);
-    let svc = Svc::new(
-        Arc::new(conf.httproot.clone()),
-        msvr.clone(),
-        Arc::new(WhoisSvr::new(&conf)),
-    );
+    let whois = Arc::new(WhoisSvr::new(&conf));
+    let svc = Svc::new(Arc::new(conf.httproot.clone()), msvr.clone(), whois.clone());
 
     let tck1 = {
         let mut _svr = msvr.clone();
@@ -202,6 +220,14 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             _svr.run().await;
         })
     };
+    if conf.irr_rate > 0 {
+        tokio::spawn(irr::run_irr(
+            msvr.clone(),
+            whois,
+            conf.irr_rate,
+            token.clone(),
+        ));
+    }
     let (tx, mut rx) = tokio::sync::mpsc::channel::<()>(10);
     #[cfg(unix)]
     {
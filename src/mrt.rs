@@ -0,0 +1,953 @@
+use crate::bgpattrs::BgpAttrs;
+use crate::bgprib::{BgpRIBKey, BgpRIBSafi};
+use crate::bgpsvc::*;
+use crate::config::ProtoPeer;
+use crate::timestamp::Timestamp;
+use std::collections::BTreeMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::Arc;
+use tokio::io::AsyncReadExt;
+use zettabgp::prelude::*;
+
+/// MRT record types (RFC 6396)
+const MRT_TABLE_DUMP_V2: u16 = 13;
+const MRT_BGP4MP: u16 = 16;
+const MRT_BGP4MP_ET: u16 = 17;
+/// sanity limit for a single record
+const MRT_MAX_RECORD: usize = 16 * 1024 * 1024;
+
+/// Peer as seen in PEER_INDEX_TABLE or BGP4MP header
+#[derive(Debug, Clone)]
+pub struct MrtPeer {
+    pub router_id: Ipv4Addr,
+    pub addr: IpAddr,
+    pub asn: u32,
+}
+impl MrtPeer {
+    pub fn from_desc(desc: &BgpPeerDesc) -> MrtPeer {
+        MrtPeer {
+            router_id: desc.bom.router_id,
+            addr: desc.addr,
+            asn: desc.bom.as_num,
+        }
+    }
+    pub fn desc(&self) -> BgpPeerDesc {
+        let mut bom = BgpOpenMessage::new();
+        bom.as_num = self.asn;
+        bom.router_id = self.router_id;
+        BgpPeerDesc::new(self.addr, bom)
+    }
+}
+
+/// Decoded MRT record content
+pub enum MrtEntry {
+    /// TABLE_DUMP_V2 peer index table - collector id and peers
+    PeerIndex(Ipv4Addr, Vec<MrtPeer>),
+    /// TABLE_DUMP_V2 route - peer index, originated time and update
+    Rib(u16, Timestamp, BgpUpdateMessage),
+    /// BGP4MP update - local, remote and update
+    Message(MrtPeer, MrtPeer, BgpUpdateMessage),
+    /// BGP4MP state change - local, remote, old and new state
+    StateChange(MrtPeer, MrtPeer, u16, u16),
+}
+
+fn slice(buf: &[u8], from: usize, to: usize) -> Result<&[u8], BgpError> {
+    if to > buf.len() || from > to {
+        return Err(BgpError::insufficient_buffer_size());
+    }
+    Ok(&buf[from..to])
+}
+
+fn decode_ip(ipv6: bool, buf: &[u8]) -> Result<(IpAddr, usize), BgpError> {
+    if ipv6 {
+        let mut a = [0u8; 16];
+        a.copy_from_slice(slice(buf, 0, 16)?);
+        Ok((IpAddr::V6(Ipv6Addr::from(a)), 16))
+    } else {
+        let mut a = [0u8; 4];
+        a.copy_from_slice(slice(buf, 0, 4)?);
+        Ok((IpAddr::V4(Ipv4Addr::from(a)), 4))
+    }
+}
+
+fn decode_nexthop(buf: &[u8]) -> BgpAddr {
+    match buf.len() {
+        4 => BgpAddr::V4(Ipv4Addr::new(buf[0], buf[1], buf[2], buf[3])),
+        16 | 32 => {
+            let mut a = [0u8; 16];
+            a.copy_from_slice(&buf[0..16]);
+            BgpAddr::V6(Ipv6Addr::from(a))
+        }
+        _ => BgpAddr::None,
+    }
+}
+
+fn decode_peer_index(buf: &[u8]) -> Result<MrtEntry, BgpError> {
+    let collector = Ipv4Addr::from(getn_u32(slice(buf, 0, 4)?));
+    let viewlen = getn_u16(slice(buf, 4, 6)?) as usize;
+    let mut pos = 6 + viewlen;
+    let count = getn_u16(slice(buf, pos, pos + 2)?);
+    pos += 2;
+    let mut peers = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let peertype = slice(buf, pos, pos + 1)?[0];
+        let router_id = Ipv4Addr::from(getn_u32(slice(buf, pos + 1, pos + 5)?));
+        pos += 5;
+        let (addr, n) = decode_ip((peertype & 1) != 0, &buf[pos..])?;
+        pos += n;
+        let asn = if (peertype & 2) != 0 {
+            pos += 4;
+            getn_u32(slice(buf, pos - 4, pos)?)
+        } else {
+            pos += 2;
+            getn_u16(slice(buf, pos - 2, pos)?) as u32
+        };
+        peers.push(MrtPeer {
+            router_id,
+            addr,
+            asn,
+        });
+    }
+    Ok(MrtEntry::PeerIndex(collector, peers))
+}
+
+/// Decodes TABLE_DUMP_V2 attributes, where MP_REACH_NLRI holds next hop only
+fn decode_rib_update(
+    params: &BgpSessionParams,
+    buf: &[u8],
+    addrs: BgpAddrs,
+) -> Result<BgpUpdateMessage, BgpError> {
+    let mut upd = BgpUpdateMessage::new();
+    let mut mpnexthop: Option<BgpAddr> = None;
+    let mut pos: usize = 0;
+    while pos < buf.len() {
+        let flags = slice(buf, pos, pos + 3)?[0];
+        let tc = buf[pos + 1];
+        let attrlen = if (flags & 16) > 0 {
+            pos += 4;
+            getn_u16(slice(buf, pos - 2, pos)?) as usize
+        } else {
+            pos += 3;
+            buf[pos - 1] as usize
+        };
+        let v = slice(buf, pos, pos + attrlen)?;
+        if tc == 14 {
+            // RFC 6396 4.3.4, though some writers keep full encoding
+            mpnexthop = Some(if !v.is_empty() && v[0] as usize + 1 == v.len() {
+                decode_nexthop(&v[1..])
+            } else if v.len() >= 4 {
+                decode_nexthop(slice(v, 4, 4 + v[3] as usize)?)
+            } else {
+                BgpAddr::None
+            });
+        } else {
+            upd.attrs
+                .push(BgpAttrItem::decode_from(params, tc, flags, attrlen, v)?);
+        }
+        pos += attrlen;
+    }
+    match mpnexthop {
+        None if matches!(addrs, BgpAddrs::IPV4U(_) | BgpAddrs::IPV4UP(_)) => {
+            upd.updates = addrs;
+        }
+        nexthop => {
+            upd.attrs.push(BgpAttrItem::MPUpdates(BgpMPUpdates {
+                nexthop: nexthop.unwrap_or(BgpAddr::None),
+                addrs,
+            }));
+        }
+    }
+    Ok(upd)
+}
+
+fn decode_rib(subtype: u16, buf: &[u8]) -> Result<Vec<MrtEntry>, BgpError> {
+    let ipv6 = matches!(subtype, 4 | 5 | 10 | 11);
+    let addpath = subtype >= 8;
+    let prefixlen = slice(buf, 4, 5)?[0];
+    let nbytes = (prefixlen as usize).div_ceil(8);
+    let pfx = slice(buf, 5, 5 + nbytes)?;
+    let mut pos = 5 + nbytes;
+    let prefix = if ipv6 {
+        if prefixlen > 128 {
+            return Err(BgpError::static_str("Invalid IPv6 prefix length"));
+        }
+        let mut a = [0u8; 16];
+        a[0..nbytes].copy_from_slice(pfx);
+        BgpNet::V6(BgpAddrV6::new(Ipv6Addr::from(a), prefixlen))
+    } else {
+        if prefixlen > 32 {
+            return Err(BgpError::static_str("Invalid IPv4 prefix length"));
+        }
+        let mut a = [0u8; 4];
+        a[0..nbytes].copy_from_slice(pfx);
+        BgpNet::V4(BgpAddrV4::new(Ipv4Addr::from(a), prefixlen))
+    };
+    // attributes in TABLE_DUMP_V2 always use 4-octet AS numbers
+    let params = BgpSessionParams::new(
+        0,
+        180,
+        if ipv6 {
+            BgpTransportMode::IPv6
+        } else {
+            BgpTransportMode::IPv4
+        },
+        Ipv4Addr::new(0, 0, 0, 0),
+        vec![BgpCapability::CapASN32(0)],
+    );
+    let count = getn_u16(slice(buf, pos, pos + 2)?);
+    pos += 2;
+    let mut ret = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let peeridx = getn_u16(slice(buf, pos, pos + 2)?);
+        let originated = Timestamp::from_unix(getn_u32(slice(buf, pos + 2, pos + 6)?) as i64, 0);
+        pos += 6;
+        let pathid = if addpath {
+            pos += 4;
+            getn_u32(slice(buf, pos - 4, pos)?)
+        } else {
+            0
+        };
+        let attrlen = getn_u16(slice(buf, pos, pos + 2)?) as usize;
+        pos += 2;
+        let addrs = match (subtype, &prefix) {
+            (2, BgpNet::V4(a)) => BgpAddrs::IPV4U(vec![a.clone()]),
+            (3, BgpNet::V4(a)) => BgpAddrs::IPV4M(vec![a.clone()]),
+            (4, BgpNet::V6(a)) => BgpAddrs::IPV6U(vec![a.clone()]),
+            (5, BgpNet::V6(a)) => BgpAddrs::IPV6M(vec![a.clone()]),
+            (8, BgpNet::V4(a)) => BgpAddrs::IPV4UP(vec![WithPathId::new(pathid, a.clone())]),
+            (9, BgpNet::V4(a)) => BgpAddrs::IPV4MP(vec![WithPathId::new(pathid, a.clone())]),
+            (10, BgpNet::V6(a)) => BgpAddrs::IPV6UP(vec![WithPathId::new(pathid, a.clone())]),
+            (11, BgpNet::V6(a)) => BgpAddrs::IPV6MP(vec![WithPathId::new(pathid, a.clone())]),
+            _ => BgpAddrs::None,
+        };
+        let upd = decode_rib_update(&params, slice(buf, pos, pos + attrlen)?, addrs)?;
+        pos += attrlen;
+        ret.push(MrtEntry::Rib(peeridx, originated, upd));
+    }
+    Ok(ret)
+}
+
+fn decode_bgp4mp(subtype: u16, buf: &[u8]) -> Result<Vec<MrtEntry>, BgpError> {
+    let as4 = matches!(subtype, 4 | 5 | 7 | 9 | 11);
+    let addpath = subtype >= 8;
+    let (peer_as, local_as, mut pos) = if as4 {
+        (getn_u32(slice(buf, 0, 4)?), getn_u32(slice(buf, 4, 8)?), 8)
+    } else {
+        (
+            getn_u16(slice(buf, 0, 2)?) as u32,
+            getn_u16(slice(buf, 2, 4)?) as u32,
+            4,
+        )
+    };
+    // interface index is skipped
+    let afi = getn_u16(slice(buf, pos + 2, pos + 4)?);
+    pos += 4;
+    let (peer_ip, n) = decode_ip(afi == 2, &buf[pos..])?;
+    pos += n;
+    let (local_ip, n) = decode_ip(afi == 2, &buf[pos..])?;
+    pos += n;
+    let remote = MrtPeer {
+        router_id: Ipv4Addr::new(0, 0, 0, 0),
+        addr: peer_ip,
+        asn: peer_as,
+    };
+    let local = MrtPeer {
+        router_id: Ipv4Addr::new(0, 0, 0, 0),
+        addr: local_ip,
+        asn: local_as,
+    };
+    match subtype {
+        0 | 5 => {
+            let st = slice(buf, pos, pos + 4)?;
+            Ok(vec![MrtEntry::StateChange(
+                local,
+                remote,
+                getn_u16(st),
+                getn_u16(&st[2..]),
+            )])
+        }
+        // *_LOCAL messages were sent by the collector itself, not received from the peer
+        6 | 7 | 10 | 11 => Ok(Vec::new()),
+        1 | 4 | 8 | 9 => {
+            let mut params = BgpSessionParams::new(
+                peer_as,
+                180,
+                if afi == 2 {
+                    BgpTransportMode::IPv6
+                } else {
+                    BgpTransportMode::IPv4
+                },
+                Ipv4Addr::new(0, 0, 0, 0),
+                if addpath {
+                    ProtoPeer::all_caps(peer_as)
+                } else {
+                    vec![BgpCapability::CapASN32(peer_as)]
+                },
+            );
+            params.has_as32bit = as4;
+            params.fuzzy_pathid = false;
+            if getn_u16(slice(buf, pos + 16, pos + 18)?) < 19 {
+                return Err(BgpError::static_str("Invalid BGP4MP message length"));
+            }
+            let msg = params.decode_message_head(&buf[pos..])?;
+            if msg.0 != BgpMessageType::Update {
+                return Ok(Vec::new());
+            }
+            let mut upd = BgpUpdateMessage::new();
+            upd.decode_from(&params, slice(buf, pos + 19, pos + 19 + msg.1)?)?;
+            Ok(vec![MrtEntry::Message(local, remote, upd)])
+        }
+        _ => Ok(Vec::new()),
+    }
+}
+
+/// Decodes MRT record body
+pub fn decode_record(rtype: u16, subtype: u16, buf: &[u8]) -> Result<Vec<MrtEntry>, BgpError> {
+    match (rtype, subtype) {
+        (MRT_TABLE_DUMP_V2, 1) => Ok(vec![decode_peer_index(buf)?]),
+        (MRT_TABLE_DUMP_V2, 2..=5) | (MRT_TABLE_DUMP_V2, 8..=11) => decode_rib(subtype, buf),
+        (MRT_BGP4MP, _) => decode_bgp4mp(subtype, buf),
+        (MRT_BGP4MP_ET, _) => decode_bgp4mp(subtype, slice(buf, 4, buf.len())?),
+        _ => Ok(Vec::new()),
+    }
+}
+
+/// MRT file importer, feeds routes with original timestamps
+pub struct MrtFile<'a, H: BgpUpdateHandler> {
+    peer: Arc<ProtoPeer>,
+    collector: Option<MrtPeer>,
+    peers: Vec<MrtPeer>,
+    rib_sessids: BTreeMap<u16, BgpSessionId>,
+    sessids: BTreeMap<(IpAddr, IpAddr), BgpSessionId>,
+    update_handler: &'a H,
+}
+
+impl<'a, H: BgpUpdateHandler> MrtFile<'a, H> {
+    pub fn new(peer: Arc<ProtoPeer>, handler: &'a H) -> MrtFile<'a, H> {
+        MrtFile {
+            peer,
+            collector: None,
+            peers: Vec::new(),
+            rib_sessids: BTreeMap::new(),
+            sessids: BTreeMap::new(),
+            update_handler: handler,
+        }
+    }
+    async fn register(&self, local: &MrtPeer, remote: &MrtPeer) -> BgpSessionId {
+        let mut desc = BgpSessionDesc::new(local.desc(), remote.desc());
+        desc.imported = true;
+        let sessid = self.update_handler.register_session(Arc::new(desc)).await;
+        info!("Register session id {} for MRT peer {:?}", sessid, remote);
+        sessid
+    }
+    async fn rib_session(&mut self, peeridx: u16) -> Option<BgpSessionId> {
+        if let Some(sessid) = self.rib_sessids.get(&peeridx) {
+            return Some(*sessid);
+        }
+        let remote = self.peers.get(peeridx as usize)?;
+        let local = self.collector.as_ref()?;
+        let sessid = self.register(local, remote).await;
+        self.rib_sessids.insert(peeridx, sessid);
+        Some(sessid)
+    }
+    async fn bgp4mp_session(&mut self, local: &MrtPeer, remote: &MrtPeer) -> BgpSessionId {
+        if let Some(sessid) = self.sessids.get(&(local.addr, remote.addr)) {
+            return *sessid;
+        }
+        let sessid = self.register(local, remote).await;
+        self.sessids.insert((local.addr, remote.addr), sessid);
+        sessid
+    }
+    pub async fn processentry(&mut self, when: Timestamp, entry: MrtEntry) {
+        match entry {
+            MrtEntry::PeerIndex(collector, peers) => {
+                info!(
+                    "MRT peer index table from {} with {} peers",
+                    collector,
+                    peers.len()
+                );
+                self.collector = Some(MrtPeer {
+                    router_id: collector,
+                    addr: IpAddr::V4(collector),
+                    asn: 0,
+                });
+                self.peers = peers;
+                self.rib_sessids.clear();
+            }
+            MrtEntry::Rib(peeridx, originated, upd) => match self.rib_session(peeridx).await {
+                None => warn!("MRT RIB entry for unknown peer index {}", peeridx),
+                Some(sessid) => {
+                    self.update_handler
+                        .handle_update(sessid, upd, originated)
+                        .await
+                }
+            },
+            MrtEntry::Message(local, remote, upd) => {
+                let sessid = self.bgp4mp_session(&local, &remote).await;
+                self.update_handler.handle_update(sessid, upd, when).await;
+            }
+            MrtEntry::StateChange(local, remote, oldstate, newstate) => {
+                info!(
+                    "{} MRT peer {}->{} state {}->{}",
+                    when, local.addr, remote.addr, oldstate, newstate
+                );
+            }
+        }
+    }
+    pub async fn lifecycle(
+        &mut self,
+        cancel: tokio_util::sync::CancellationToken,
+    ) -> std::io::Result<()> {
+        let filename = match self.peer.file {
+            None => return Err(std::io::Error::other("No file parameter")),
+            Some(ref s) => s.clone(),
+        };
+        info!("Importing MRT file {}", filename);
+        let mut rdr = tokio::io::BufReader::new(tokio::fs::File::open(&filename).await?);
+        let mut hdr = [0u8; 12];
+        let mut buf: Vec<u8> = Vec::new();
+        let mut records: usize = 0;
+        while !cancel.is_cancelled() {
+            if let Err(e) = rdr.read_exact(&mut hdr).await {
+                if e.kind() == std::io::ErrorKind::UnexpectedEof {
+                    break;
+                }
+                return Err(e);
+            }
+            let secs = getn_u32(&hdr[0..4]);
+            let rtype = getn_u16(&hdr[4..6]);
+            let subtype = getn_u16(&hdr[6..8]);
+            let length = getn_u32(&hdr[8..12]) as usize;
+            if length > MRT_MAX_RECORD {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("MRT record too long: {}", length),
+                ));
+            }
+            buf.resize(length, 0);
+            rdr.read_exact(&mut buf).await?;
+            records += 1;
+            let when = if rtype == MRT_BGP4MP_ET && length >= 4 {
+                Timestamp::from_unix(secs as i64, getn_u32(&buf[0..4]))
+            } else {
+                Timestamp::from_unix(secs as i64, 0)
+            };
+            match decode_record(rtype, subtype, &buf) {
+                Err(e) => warn!(
+                    "MRT record {} type {}/{} decode error: {:?}",
+                    records, rtype, subtype, e
+                ),
+                Ok(entries) => {
+                    for entry in entries.into_iter() {
+                        self.processentry(when, entry).await;
+                    }
+                }
+            }
+        }
+        info!("MRT file {} done, {} records", filename, records);
+        Ok(())
+    }
+}
+
+/// Prefix encoding for TABLE_DUMP_V2 RIB records
+pub trait MrtPrefix {
+    fn mrt_ipv6() -> bool;
+    fn mrt_prefix(&self, out: &mut Vec<u8>);
+}
+impl MrtPrefix for BgpAddrV4 {
+    fn mrt_ipv6() -> bool {
+        false
+    }
+    fn mrt_prefix(&self, out: &mut Vec<u8>) {
+        out.push(self.prefixlen);
+        out.extend_from_slice(&self.addr.octets()[0..(self.prefixlen as usize).div_ceil(8)]);
+    }
+}
+impl MrtPrefix for BgpAddrV6 {
+    fn mrt_ipv6() -> bool {
+        true
+    }
+    fn mrt_prefix(&self, out: &mut Vec<u8>) {
+        out.push(self.prefixlen);
+        out.extend_from_slice(&self.addr.octets()[0..(self.prefixlen as usize).div_ceil(8)]);
+    }
+}
+
+fn is_pathid_addrs(addrs: &BgpAddrs) -> bool {
+    matches!(
+        addrs,
+        BgpAddrs::IPV4UP(_)
+            | BgpAddrs::IPV4MP(_)
+            | BgpAddrs::IPV4LUP(_)
+            | BgpAddrs::VPNV4UP(_)
+            | BgpAddrs::VPNV4MP(_)
+            | BgpAddrs::IPV6UP(_)
+            | BgpAddrs::IPV6MP(_)
+            | BgpAddrs::IPV6LUP(_)
+            | BgpAddrs::VPNV6UP(_)
+            | BgpAddrs::VPNV6MP(_)
+    )
+}
+
+/// Update carries ADD-PATH NLRI, so it goes as BGP4MP_MESSAGE_AS4_ADDPATH
+fn has_pathid(upd: &BgpUpdateMessage) -> bool {
+    is_pathid_addrs(&upd.updates)
+        || is_pathid_addrs(&upd.withdraws)
+        || upd.attrs.iter().any(|a| match a {
+            BgpAttrItem::MPUpdates(n) => is_pathid_addrs(&n.addrs),
+            BgpAttrItem::MPWithdraws(n) => is_pathid_addrs(&n.addrs),
+            _ => false,
+        })
+}
+
+fn put_ip(out: &mut Vec<u8>, ip: &IpAddr) {
+    match ip {
+        IpAddr::V4(a) => out.extend_from_slice(&a.octets()),
+        IpAddr::V6(a) => out.extend_from_slice(&a.octets()),
+    }
+}
+
+fn put_record(out: &mut Vec<u8>, when: &Timestamp, rtype: u16, subtype: u16, body: &[u8]) {
+    out.extend_from_slice(&(when.timestamp() as u32).to_be_bytes());
+    out.extend_from_slice(&rtype.to_be_bytes());
+    out.extend_from_slice(&subtype.to_be_bytes());
+    out.extend_from_slice(&(body.len() as u32).to_be_bytes());
+    out.extend_from_slice(body);
+}
+
+/// Path attributes of the route, except next hop
+fn attrs_items(attrs: &BgpAttrs) -> Vec<BgpAttrItem> {
+    let mut ret = vec![
+        BgpAttrItem::Origin(BgpOrigin {
+            value: attrs.origin,
+        }),
+        BgpAttrItem::ASPath((*attrs.aspath).clone()),
+    ];
+    if let Some(v) = attrs.med {
+        ret.push(BgpAttrItem::MED(BgpMED { value: v }));
+    }
+    if let Some(v) = attrs.localpref {
+        ret.push(BgpAttrItem::LocalPref(BgpLocalpref { value: v }));
+    }
+    if let Some(v) = attrs.atomicaggregate {
+        ret.push(BgpAttrItem::AtomicAggregate(BgpAtomicAggregate {
+            value: v,
+        }));
+    }
+    if let Some(ref v) = attrs.aggregatoras {
+        ret.push(BgpAttrItem::AggregatorAS(v.clone()));
+    }
+    if !attrs.comms.value.is_empty() {
+        ret.push(BgpAttrItem::CommunityList((*attrs.comms).clone()));
+    }
+    if let Some(v) = attrs.originator {
+        ret.push(BgpAttrItem::OriginatorID(BgpOriginatorID { value: v }));
+    }
+    if let Some(ref v) = attrs.clusterlist {
+        ret.push(BgpAttrItem::ClusterList((**v).clone()));
+    }
+    if !attrs.extcomms.value.is_empty() {
+        ret.push(BgpAttrItem::ExtCommunityList((*attrs.extcomms).clone()));
+    }
+    if !attrs.lcomms.value.is_empty() {
+        ret.push(BgpAttrItem::LargeCommunityList((*attrs.lcomms).clone()));
+    }
+    if let Some(ref v) = attrs.pmsi_ta {
+        ret.push(BgpAttrItem::PMSITunnel((**v).clone()));
+    }
+    ret
+}
+
+/// Encodes route attributes the TABLE_DUMP_V2 way, next hop in abbreviated MP_REACH_NLRI
+fn encode_rib_attrs(
+    params: &BgpSessionParams,
+    attrs: &BgpAttrs,
+    ipv6: bool,
+    buf: &mut [u8],
+) -> Result<usize, BgpError> {
+    let mut pos: usize = 0;
+    for item in attrs_items(attrs).iter() {
+        pos += item.encode_to(params, &mut buf[pos..])?;
+    }
+    let nh: Option<IpAddr> = match attrs.nexthop {
+        BgpAddr::V4(a) => Some(IpAddr::V4(a)),
+        BgpAddr::V6(a) => Some(IpAddr::V6(a)),
+        _ => None,
+    };
+    match nh {
+        Some(IpAddr::V4(a)) if !ipv6 => {
+            pos += BgpAttrItem::NextHop(BgpNextHop {
+                value: IpAddr::V4(a),
+            })
+            .encode_to(params, &mut buf[pos..])?;
+        }
+        Some(a) => {
+            let mut nhbuf = Vec::new();
+            put_ip(&mut nhbuf, &a);
+            let b = slice_mut(buf, pos, pos + 4 + nhbuf.len())?;
+            b[0] = 0x80;
+            b[1] = 14;
+            b[2] = (nhbuf.len() + 1) as u8;
+            b[3] = nhbuf.len() as u8;
+            b[4..].copy_from_slice(&nhbuf);
+            pos += 4 + nhbuf.len();
+        }
+        None => {}
+    }
+    Ok(pos)
+}
+
+fn slice_mut(buf: &mut [u8], from: usize, to: usize) -> Result<&mut [u8], BgpError> {
+    if to > buf.len() || from > to {
+        return Err(BgpError::insufficient_buffer_size());
+    }
+    Ok(&mut buf[from..to])
+}
+
+/// TABLE_DUMP_V2 snapshot of the active routes of RIB table, encoded in parts
+pub struct TableDump {
+    now: Timestamp,
+    multicast: bool,
+    peeridx: BTreeMap<BgpSessionId, u16>,
+    params: BgpSessionParams,
+    abuf: Vec<u8>,
+    seq: u32,
+}
+impl TableDump {
+    /// Returns dumper and PEER_INDEX_TABLE record
+    pub fn new(
+        multicast: bool,
+        viewname: &str,
+        collector: Ipv4Addr,
+        sessions: &[(BgpSessionId, MrtPeer)],
+    ) -> (TableDump, Vec<u8>) {
+        let now = Timestamp::now();
+        let mut out: Vec<u8> = Vec::new();
+        let mut body: Vec<u8> = Vec::new();
+        body.extend_from_slice(&collector.octets());
+        body.extend_from_slice(&(viewname.len() as u16).to_be_bytes());
+        body.extend_from_slice(viewname.as_bytes());
+        body.extend_from_slice(&(sessions.len() as u16).to_be_bytes());
+        let mut peeridx: BTreeMap<BgpSessionId, u16> = BTreeMap::new();
+        for (i, (sessid, peer)) in sessions.iter().enumerate() {
+            peeridx.insert(*sessid, i as u16);
+            body.push(if peer.addr.is_ipv6() { 3 } else { 2 });
+            body.extend_from_slice(&peer.router_id.octets());
+            put_ip(&mut body, &peer.addr);
+            body.extend_from_slice(&peer.asn.to_be_bytes());
+        }
+        put_record(&mut out, &now, MRT_TABLE_DUMP_V2, 1, &body);
+        (
+            TableDump {
+                now,
+                multicast,
+                peeridx,
+                params: BgpSessionParams::new(
+                    0,
+                    180,
+                    BgpTransportMode::IPv4,
+                    Ipv4Addr::new(0, 0, 0, 0),
+                    vec![BgpCapability::CapASN32(0)],
+                ),
+                abuf: vec![0u8; 65536],
+                seq: 0,
+            },
+            out,
+        )
+    }
+    /// Encodes RIB records of up to limit prefixes following after.
+    /// Returns records and last visited prefix, None when table is done.
+    pub fn chunk<T: BgpRIBKey + MrtPrefix>(
+        &mut self,
+        safi: &BgpRIBSafi<T>,
+        after: Option<&T>,
+        limit: usize,
+    ) -> Result<(Vec<u8>, Option<T>), BgpError> {
+        use std::ops::Bound;
+        let ipv6 = T::mrt_ipv6();
+        let mut out: Vec<u8> = Vec::new();
+        let mut body: Vec<u8> = Vec::new();
+        let from: Bound<&T> = match after {
+            None => Bound::Unbounded,
+            Some(a) => Bound::Excluded(a),
+        };
+        let range = safi.items.range::<T, _>((from, Bound::Unbounded));
+        let mut last = None;
+        for (prefix, sessentry) in range.take(limit) {
+            last = Some(prefix);
+            let mut entries: Vec<(u16, u32, BgpPathId, Vec<u8>)> = Vec::new();
+            for (sessid, pathentry) in sessentry.items.iter() {
+                let idx = match self.peeridx.get(sessid) {
+                    None => continue,
+                    Some(i) => *i,
+                };
+                for (pathid, hist) in pathentry.items.iter() {
+                    let (ts, ae) = match hist.items.iter().last() {
+                        Some((ts, ae)) if ae.active => (ts, ae),
+                        _ => continue,
+                    };
+                    let alen = encode_rib_attrs(&self.params, &ae.attrs, ipv6, &mut self.abuf)?;
+                    entries.push((
+                        idx,
+                        ts.timestamp() as u32,
+                        *pathid,
+                        self.abuf[0..alen].to_vec(),
+                    ));
+                }
+            }
+            if entries.is_empty() {
+                continue;
+            }
+            let addpath = entries.iter().any(|e| e.2 != 0);
+            let subtype: u16 = match (ipv6, self.multicast) {
+                (false, false) => 2,
+                (false, true) => 3,
+                (true, false) => 4,
+                (true, true) => 5,
+            } + if addpath { 6 } else { 0 };
+            body.clear();
+            body.extend_from_slice(&self.seq.to_be_bytes());
+            prefix.mrt_prefix(&mut body);
+            body.extend_from_slice(&(entries.len() as u16).to_be_bytes());
+            for (idx, ts, pathid, attrs) in entries.iter() {
+                body.extend_from_slice(&idx.to_be_bytes());
+                body.extend_from_slice(&ts.to_be_bytes());
+                if addpath {
+                    body.extend_from_slice(&pathid.to_be_bytes());
+                }
+                body.extend_from_slice(&(attrs.len() as u16).to_be_bytes());
+                body.extend_from_slice(attrs);
+            }
+            put_record(&mut out, &self.now, MRT_TABLE_DUMP_V2, subtype, &body);
+            self.seq = self.seq.wrapping_add(1);
+        }
+        Ok((out, last.cloned()))
+    }
+}
+
+/// Appends BGP4MP_MESSAGE_AS4 records to rotating files
+pub struct MrtArchive {
+    prefix: String,
+    every: chrono::Duration,
+    current: Option<(Timestamp, std::fs::File)>,
+}
+impl MrtArchive {
+    pub fn new(prefix: String, every: chrono::Duration) -> MrtArchive {
+        MrtArchive {
+            prefix,
+            every,
+            current: None,
+        }
+    }
+    fn file(&mut self, when: &Timestamp) -> std::io::Result<&mut std::fs::File> {
+        let period = when.cut_millis(self.every.num_milliseconds().max(1000) as u64);
+        if self.current.as_ref().map(|c| c.0) != Some(period) {
+            let filename = format!("{}.{}", self.prefix, period.format("%Y%m%d.%H%M"));
+            info!("MRT archive file {}", filename);
+            let f = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(filename)?;
+            self.current = Some((period, f));
+        }
+        Ok(&mut self.current.as_mut().unwrap().1)
+    }
+    /// Writes queued records in own thread until channel is closed
+    pub fn run(
+        mut self,
+        mut rx: tokio::sync::mpsc::Receiver<(Timestamp, Vec<u8>)>,
+    ) -> std::thread::JoinHandle<()> {
+        std::thread::Builder::new()
+            .name("mrt_archive_writer".into())
+            .spawn(move || {
+                use std::io::Write;
+                while let Some((when, rec)) = rx.blocking_recv() {
+                    if let Err(e) = self.file(&when).and_then(|f| f.write_all(&rec)) {
+                        warn!("MRT archive write error: {}", e);
+                    }
+                }
+            })
+            .unwrap()
+    }
+    /// Encodes update received from remote peer as archive record
+    pub fn encode_update(
+        when: &Timestamp,
+        local: &BgpPeerDesc,
+        remote: &BgpPeerDesc,
+        upd: &BgpUpdateMessage,
+    ) -> std::io::Result<Vec<u8>> {
+        let params = BgpSessionParams::new(
+            remote.bom.as_num,
+            180,
+            if remote.addr.is_ipv6() {
+                BgpTransportMode::IPv6
+            } else {
+                BgpTransportMode::IPv4
+            },
+            Ipv4Addr::new(0, 0, 0, 0),
+            vec![BgpCapability::CapASN32(remote.bom.as_num)],
+        );
+        let mut msgbuf = vec![255u8; 65536 + 19];
+        let msglen = match upd.encode_to(&params, &mut msgbuf[19..]) {
+            Ok(l) => l,
+            Err(e) => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("{:?}", e),
+                ))
+            }
+        };
+        if msglen + 19 > 65535 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "Update too long",
+            ));
+        }
+        msgbuf[16..18].copy_from_slice(&((msglen + 19) as u16).to_be_bytes());
+        msgbuf[18] = 2;
+        let localaddr = match (remote.addr, local.addr) {
+            (IpAddr::V4(_), IpAddr::V6(_)) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            (IpAddr::V6(_), IpAddr::V4(_)) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+            (_, a) => a,
+        };
+        let mut body: Vec<u8> = Vec::with_capacity(msglen + 64);
+        body.extend_from_slice(&remote.bom.as_num.to_be_bytes());
+        body.extend_from_slice(&local.bom.as_num.to_be_bytes());
+        body.extend_from_slice(&0u16.to_be_bytes());
+        body.extend_from_slice(&(if remote.addr.is_ipv6() { 2u16 } else { 1u16 }).to_be_bytes());
+        put_ip(&mut body, &remote.addr);
+        put_ip(&mut body, &localaddr);
+        body.extend_from_slice(&msgbuf[0..msglen + 19]);
+        let mut out: Vec<u8> = Vec::with_capacity(body.len() + 12);
+        put_record(
+            &mut out,
+            when,
+            MRT_BGP4MP,
+            if has_pathid(upd) { 9 } else { 4 },
+            &body,
+        );
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// BGP4MP record body with UPDATE from 192.0.2.1 to 192.0.2.2
+    fn bgp4mp(as4: bool, update: &[u8]) -> Vec<u8> {
+        let mut buf = Vec::new();
+        if as4 {
+            buf.extend_from_slice(&[0, 0, 0xfd, 0xe9, 0, 0, 0xfd, 0xe8]);
+        } else {
+            buf.extend_from_slice(&[0xfd, 0xe9, 0xfd, 0xe8]);
+        }
+        buf.extend_from_slice(&[0, 0, 0, 1, 192, 0, 2, 1, 192, 0, 2, 2]);
+        buf.extend_from_slice(&[255; 16]);
+        buf.extend_from_slice(&((update.len() + 19) as u16).to_be_bytes());
+        buf.push(2);
+        buf.extend_from_slice(update);
+        buf
+    }
+
+    fn aspath(upd: &BgpUpdateMessage) -> Vec<u32> {
+        upd.attrs
+            .iter()
+            .find_map(|a| match a {
+                BgpAttrItem::ASPath(p) => Some(
+                    p.value
+                        .iter()
+                        .flat_map(|i| match i {
+                            BgpASitem::Seq(s) => s.value.iter().map(|a| a.value).collect(),
+                            BgpASitem::Set(s) => {
+                                s.value.iter().map(|a| a.value).collect::<Vec<_>>()
+                            }
+                        })
+                        .collect(),
+                ),
+                _ => None,
+            })
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn test_decode_table_dump() {
+        // collector 10.0.0.1, view "v", peer 10.0.0.2 192.0.2.1 AS65001 with 4-octet AS
+        let index = [
+            10, 0, 0, 1, 0, 1, b'v', 0, 1, 2, 10, 0, 0, 2, 192, 0, 2, 1, 0, 0, 0xfd, 0xe9,
+        ];
+        match decode_record(MRT_TABLE_DUMP_V2, 1, &index).unwrap().pop() {
+            Some(MrtEntry::PeerIndex(collector, peers)) => {
+                assert_eq!(collector, Ipv4Addr::new(10, 0, 0, 1));
+                assert_eq!(peers.len(), 1);
+                assert_eq!(peers[0].asn, 65001);
+                assert_eq!(peers[0].addr, "192.0.2.1".parse::<IpAddr>().unwrap());
+            }
+            _ => panic!("not a peer index"),
+        }
+        // 198.51.100.0/24 via 192.0.2.1, AS path 65001 4200000000
+        let rib = [
+            0, 0, 0, 0, 24, 198, 51, 100, 0, 1, 0, 0, 0x60, 0, 0, 0, 0, 24, 0x40, 1, 1, 0, 0x40, 2,
+            10, 2, 2, 0, 0, 0xfd, 0xe9, 0xfa, 0x56, 0xea, 0, 0x40, 3, 4, 192, 0, 2, 1,
+        ];
+        match decode_record(MRT_TABLE_DUMP_V2, 2, &rib).unwrap().pop() {
+            Some(MrtEntry::Rib(idx, _, upd)) => {
+                assert_eq!(idx, 0);
+                assert_eq!(aspath(&upd), vec![65001, 4200000000]);
+                match upd.updates {
+                    BgpAddrs::IPV4U(v) => {
+                        assert_eq!(v, vec![BgpAddrV4::new(Ipv4Addr::new(198, 51, 100, 0), 24)])
+                    }
+                    _ => panic!("not an IPv4 unicast"),
+                }
+            }
+            _ => panic!("not a RIB entry"),
+        }
+        for len in 0..rib.len() {
+            assert!(decode_record(MRT_TABLE_DUMP_V2, 2, &rib[0..len]).is_err());
+        }
+        for len in 0..index.len() {
+            assert!(decode_record(MRT_TABLE_DUMP_V2, 1, &index[0..len]).is_err());
+        }
+    }
+
+    #[test]
+    fn test_decode_bgp4mp() {
+        // ORIGIN, AS_PATH 65001 with 2-octet AS, NEXT_HOP and 198.51.100.0/24
+        let upd2 = [
+            0, 0, 0, 18, 0x40, 1, 1, 0, 0x40, 2, 4, 2, 1, 0xfd, 0xe9, 0x40, 3, 4, 192, 0, 2, 1, 24,
+            198, 51, 100,
+        ];
+        let rec = bgp4mp(false, &upd2);
+        match decode_record(MRT_BGP4MP, 1, &rec).unwrap().pop() {
+            Some(MrtEntry::Message(local, remote, upd)) => {
+                assert_eq!(local.asn, 65000);
+                assert_eq!(remote.asn, 65001);
+                assert_eq!(aspath(&upd), vec![65001]);
+            }
+            _ => panic!("not a BGP4MP message"),
+        }
+        // same with 4-octet AS and ADD-PATH path id 7
+        let upd4 = [
+            0, 0, 0, 20, 0x40, 1, 1, 0, 0x40, 2, 6, 2, 1, 0, 0, 0xfd, 0xe9, 0x40, 3, 4, 192, 0, 2,
+            1, 0, 0, 0, 7, 24, 198, 51, 100,
+        ];
+        let rec = bgp4mp(true, &upd4);
+        match decode_record(MRT_BGP4MP, 9, &rec).unwrap().pop() {
+            Some(MrtEntry::Message(_, remote, upd)) => {
+                assert_eq!(remote.asn, 65001);
+                assert_eq!(aspath(&upd), vec![65001]);
+                match upd.updates {
+                    BgpAddrs::IPV4UP(v) => assert_eq!(v[0].pathid, 7),
+                    _ => panic!("not an ADD-PATH IPv4 unicast"),
+                }
+            }
+            _ => panic!("not a BGP4MP message"),
+        }
+        // messages sent by the collector are skipped
+        assert!(decode_record(MRT_BGP4MP, 11, &rec).unwrap().is_empty());
+        for len in 0..rec.len() {
+            assert!(decode_record(MRT_BGP4MP, 9, &rec[0..len]).is_err());
+        }
+    }
+}
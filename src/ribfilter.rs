@@ -1,7 +1,12 @@
-use crate::bgpattrs::BgpAttrs;
+use crate::bgpattrs::{BgpAttrs, BGP_ATTR_OTC};
 use crate::bgprib::{BgpRIBKey, BgpRIBSafi, BgpSessionEntry, ClonableIterator};
+use crate::bogon::BogonKind;
 use crate::clone_iter;
+use crate::flowspec::BgpFlowSpecVpn6;
+use crate::irr::IrrState;
 use crate::ribservice::RibResponseFilter;
+use crate::rpki::{AspaState, RpkiState};
+use crate::rtc::BgpRtc;
 use crate::service::*;
 use regex::Regex;
 use std::collections::BTreeSet;
@@ -173,6 +178,18 @@ pub enum FilterItem {
     Num(u64),
     Regexp(FilterRegex),
     ExtCommunity(FilterExtComm),
+    /// path attribute type code without dedicated decoder
+    AttrType(u8),
+    /// route leak flag by RFC 9234 ingress rules
+    Leak(bool),
+    /// route origin validation state
+    Rpki(RpkiState),
+    /// AS path verification state
+    Aspa(AspaState),
+    /// IRR route object state
+    Irr(IrrState),
+    /// bogon prefix, AS number or next hop
+    Bogon(BogonKind),
 }
 impl FilterItem {
     pub fn kind(&self) -> FilterItemKind {
@@ -451,7 +468,90 @@ impl FilterMatchRoute for BgpMdtV6 {
         FilterItemMatchResult::multi(&[self.addr.match_item(fi), fi.match_addr_v6(&self.group)])
     }
 }
-impl FilterMatchRoute for BgpFlowSpec<BgpAddrV4> {}
+impl FilterMatchRoute for BgpFlowSpec<BgpAddrV4> {
+    fn match_item(&self, fi: &FilterItem) -> FilterItemMatchResult {
+        match self {
+            BgpFlowSpec::PrefixDst(p) | BgpFlowSpec::PrefixSrc(p) => p.match_item(fi),
+            _ => FilterItemMatchResult::Unknown,
+        }
+    }
+    fn match_super_item(&self, fi: &FilterItem) -> FilterItemMatchResult {
+        match self {
+            BgpFlowSpec::PrefixDst(p) | BgpFlowSpec::PrefixSrc(p) => p.match_super_item(fi),
+            _ => FilterItemMatchResult::Unknown,
+        }
+    }
+}
+impl FilterMatchRoute for BgpFlowSpec<FS6> {
+    fn match_item(&self, fi: &FilterItem) -> FilterItemMatchResult {
+        match self {
+            BgpFlowSpec::PrefixDst(p) | BgpFlowSpec::PrefixSrc(p) => p.ipv6.match_item(fi),
+            _ => FilterItemMatchResult::Unknown,
+        }
+    }
+    fn match_super_item(&self, fi: &FilterItem) -> FilterItemMatchResult {
+        match self {
+            BgpFlowSpec::PrefixDst(p) | BgpFlowSpec::PrefixSrc(p) => p.ipv6.match_super_item(fi),
+            _ => FilterItemMatchResult::Unknown,
+        }
+    }
+}
+impl FilterMatchRoute for BgpFlowSpec<FSV4U> {
+    fn match_item(&self, fi: &FilterItem) -> FilterItemMatchResult {
+        match self {
+            BgpFlowSpec::PrefixDst(p) | BgpFlowSpec::PrefixSrc(p) => p.prefix.match_item(fi),
+            _ => FilterItemMatchResult::Unknown,
+        }
+    }
+    fn match_super_item(&self, fi: &FilterItem) -> FilterItemMatchResult {
+        match self {
+            BgpFlowSpec::PrefixDst(p) | BgpFlowSpec::PrefixSrc(p) => p.prefix.match_super_item(fi),
+            _ => FilterItemMatchResult::Unknown,
+        }
+    }
+}
+impl FilterMatchRoute for BgpFlowSpecVpn6 {
+    fn match_item(&self, fi: &FilterItem) -> FilterItemMatchResult {
+        match fi.match_rd(&self.rd) {
+            FilterItemMatchResult::Unknown => {
+                BgpFlowSpecVpn6::match_prefixes(self.prefixes().map(|p| p.match_item(fi)))
+            }
+            n => n,
+        }
+    }
+    fn match_super_item(&self, fi: &FilterItem) -> FilterItemMatchResult {
+        match fi.match_rd(&self.rd) {
+            FilterItemMatchResult::Unknown => {
+                BgpFlowSpecVpn6::match_prefixes(self.prefixes().map(|p| p.match_super_item(fi)))
+            }
+            n => n,
+        }
+    }
+}
+impl BgpFlowSpecVpn6 {
+    /// Rule matches if destination or source prefix matches
+    fn match_prefixes(
+        results: impl Iterator<Item = FilterItemMatchResult>,
+    ) -> FilterItemMatchResult {
+        let mut ret = FilterItemMatchResult::Unknown;
+        for r in results {
+            match r {
+                FilterItemMatchResult::Yes => return r,
+                FilterItemMatchResult::No => ret = r,
+                FilterItemMatchResult::Unknown => {}
+            }
+        }
+        ret
+    }
+}
+impl FilterMatchRoute for BgpRtc {
+    fn match_item(&self, fi: &FilterItem) -> FilterItemMatchResult {
+        match fi {
+            FilterItem::ExtCommunity(_) => self.rt.match_item(fi),
+            _ => FilterItemMatchResult::Unknown,
+        }
+    }
+}
 impl<T: BgpItem<T> + FilterMatchRoute + Clone> FilterMatchRoute for WithRd<T> {
     fn match_item(&self, fi: &FilterItem) -> FilterItemMatchResult {
         //eprintln!("WithRd::match_item {:?} - {}", fi, self);
@@ -587,28 +687,35 @@ impl<'a, 'b, T: FilterMatchRoute + BgpRIBKey> std::iter::Iterator
             match self.srcitr.next() {
                 None => break,
                 Some(q) => {
-                    if q.1.items.iter().any(|ssitr| {
-                        ssitr
-                            .1
-                            .items
-                            .iter()
-                            .filter(|x| self.filter.respflt.filter_path_e(x.1))
-                            .any(|pitr| {
-                                pitr.1
-                                    .items
-                                    .iter()
-                                    .filter(|hr| self.filter.respflt.filter_ah(hr.0, hr.1))
-                                    .skip(if pitr.1.items.len() > self.filter.respflt.maxdepth {
-                                        pitr.1.items.len() - self.filter.respflt.maxdepth
-                                    } else {
-                                        0
-                                    })
-                                    .any(|histitem| {
-                                        self.filter.filter.match_route(q.0, &histitem.1.attrs)
-                                            == FilterItemMatchResult::Yes
-                                    })
-                            })
-                    }) {
+                    if q.1
+                        .items
+                        .iter()
+                        .filter(|ssitr| self.filter.respflt.filter_session(ssitr.0))
+                        .any(|ssitr| {
+                            ssitr
+                                .1
+                                .items
+                                .iter()
+                                .filter(|x| self.filter.respflt.filter_path_e(x.1))
+                                .any(|pitr| {
+                                    pitr.1
+                                        .items
+                                        .iter()
+                                        .filter(|hr| self.filter.respflt.filter_ah(hr.0, hr.1))
+                                        .skip(
+                                            if pitr.1.items.len() > self.filter.respflt.maxdepth {
+                                                pitr.1.items.len() - self.filter.respflt.maxdepth
+                                            } else {
+                                                0
+                                            },
+                                        )
+                                        .any(|histitem| {
+                                            self.filter.filter.match_route(q.0, &histitem.1.attrs)
+                                                == FilterItemMatchResult::Yes
+                                        })
+                                })
+                        })
+                    {
                         return Some(q);
                     }
                 }
@@ -651,28 +758,37 @@ impl<'a, 'b, T: FilterMatchRoute + BgpRIBKey> std::iter::Iterator
             match self.srcitr.next() {
                 None => break,
                 Some(q) => {
-                    if q.1.items.iter().any(|ssitr| {
-                        ssitr
-                            .1
-                            .items
-                            .iter()
-                            .filter(|pitr| self.filter.respflt.filter_path_e(pitr.1))
-                            .any(|pitr| {
-                                pitr.1
-                                    .items
-                                    .iter()
-                                    .filter(|hr| self.filter.respflt.filter_ah(hr.0, hr.1))
-                                    .skip(if pitr.1.items.len() > self.filter.respflt.maxdepth {
-                                        pitr.1.items.len() - self.filter.respflt.maxdepth
-                                    } else {
-                                        0
-                                    })
-                                    .any(|histitem| {
-                                        self.filter.filter.match_super_route(q.0, &histitem.1.attrs)
-                                            == FilterItemMatchResult::Yes
-                                    })
-                            })
-                    }) {
+                    if q.1
+                        .items
+                        .iter()
+                        .filter(|ssitr| self.filter.respflt.filter_session(ssitr.0))
+                        .any(|ssitr| {
+                            ssitr
+                                .1
+                                .items
+                                .iter()
+                                .filter(|pitr| self.filter.respflt.filter_path_e(pitr.1))
+                                .any(|pitr| {
+                                    pitr.1
+                                        .items
+                                        .iter()
+                                        .filter(|hr| self.filter.respflt.filter_ah(hr.0, hr.1))
+                                        .skip(
+                                            if pitr.1.items.len() > self.filter.respflt.maxdepth {
+                                                pitr.1.items.len() - self.filter.respflt.maxdepth
+                                            } else {
+                                                0
+                                            },
+                                        )
+                                        .any(|histitem| {
+                                            self.filter
+                                                .filter
+                                                .match_super_route(q.0, &histitem.1.attrs)
+                                                == FilterItemMatchResult::Yes
+                                        })
+                                })
+                        })
+                    {
                         return Some(q);
                     }
                 }
@@ -957,6 +1073,12 @@ impl FilterItem {
             static ref RE_C: Regex = Regex::new(r"^c[^:]*:([0-9]+):([0-9]+)$").unwrap();
             static ref RE_NUM: Regex = Regex::new(r"^([0-9]+)$").unwrap();
             static ref RE_RE: Regex = Regex::new(r"^re:(.*)$").unwrap();
+            static ref RE_ATTR: Regex = Regex::new(r"^attr:([0-9]+)$").unwrap();
+            static ref RE_LEAK: Regex = Regex::new(r"^leak:(yes|no)$").unwrap();
+            static ref RE_RPKI: Regex = Regex::new(r"^rpki:(valid|invalid|notfound)$").unwrap();
+            static ref RE_ASPA: Regex = Regex::new(r"^aspa:(valid|invalid|unknown)$").unwrap();
+            static ref RE_IRR: Regex = Regex::new(r"^irr:(valid|missing)$").unwrap();
+            static ref RE_BOGON: Regex = Regex::new(r"^bogon:(prefix|asn|nexthop)$").unwrap();
             static ref RE_RT_N: Regex = Regex::new(r"^(rt|target|ext-target):([0-9]+)$").unwrap();
             static ref RE_RT_P: Regex =
                 Regex::new(r"^(rt|target|ext-target):([0-9]+):([0-9]+)$").unwrap();
@@ -1199,6 +1321,38 @@ impl FilterItem {
             }
             _ => {}
         };
+        if let Some(caps) = RE_ATTR.captures(itemstr) {
+            if let Some(n) = caps.get(1) {
+                if let Ok(tc) = n.as_str().parse() {
+                    return FilterItem::AttrType(tc);
+                }
+            }
+        };
+        if let Some(caps) = RE_LEAK.captures(itemstr) {
+            if let Some(n) = caps.get(1) {
+                return FilterItem::Leak(n.as_str() == "yes");
+            }
+        };
+        if let Some(caps) = RE_RPKI.captures(itemstr) {
+            if let Some(Ok(state)) = caps.get(1).map(|n| n.as_str().parse()) {
+                return FilterItem::Rpki(state);
+            }
+        };
+        if let Some(caps) = RE_ASPA.captures(itemstr) {
+            if let Some(Ok(state)) = caps.get(1).map(|n| n.as_str().parse()) {
+                return FilterItem::Aspa(state);
+            }
+        };
+        if let Some(caps) = RE_IRR.captures(itemstr) {
+            if let Some(Ok(state)) = caps.get(1).map(|n| n.as_str().parse()) {
+                return FilterItem::Irr(state);
+            }
+        };
+        if let Some(caps) = RE_BOGON.captures(itemstr) {
+            if let Some(Ok(kind)) = caps.get(1).map(|n| n.as_str().parse()) {
+                return FilterItem::Bogon(kind);
+            }
+        };
         match RE_RT_N.captures(itemstr) {
             Some(caps) => {
                 match caps.get(2) {
@@ -1440,6 +1594,27 @@ impl FilterItem {
                 _ => FilterItemMatchResult::Unknown,
             },
             FilterItem::Community(cflt) => attr.comms.value.contains(cflt).into(),
+            FilterItem::AttrType(tc) => ((*tc == BGP_ATTR_OTC && attr.otc.is_some())
+                || attr
+                    .rawattrs
+                    .as_ref()
+                    .map(|r| r.contains(*tc))
+                    .unwrap_or(false))
+            .into(),
+            FilterItem::Leak(leak) => (attr.leak == *leak).into(),
+            FilterItem::Rpki(state) => match attr.rpki {
+                Some(ref s) => (s == state).into(),
+                None => FilterItemMatchResult::Unknown,
+            },
+            FilterItem::Aspa(state) => match attr.aspa {
+                Some(ref s) => (s == state).into(),
+                None => FilterItemMatchResult::Unknown,
+            },
+            FilterItem::Irr(state) => match attr.irr {
+                Some(ref s) => (s == state).into(),
+                None => FilterItemMatchResult::Unknown,
+            },
+            FilterItem::Bogon(kind) => attr.bogon.contains(*kind).into(),
             FilterItem::ExtCommunity(_) => {
                 let mut ret = FilterItemMatchResult::Unknown;
                 if attr.extcomms.value.is_empty() {
@@ -1650,7 +1825,10 @@ impl FilterTerm {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::bgpattrs::{BgpRawAttr, BgpRawAttrs, BgpRole};
+    use crate::bogon::BogonFlags;
     use crate::config::*;
+    use crate::timestamp::Timestamp;
 
     #[test]
     fn test_ribfilter_fi_ipv4_host() {
@@ -1873,6 +2051,7 @@ mod tests {
                 BgpAddrV4::new(std::net::Ipv4Addr::new(10, 0, 0, 0), 24),
             ],
             attrs,
+            Timestamp::now(),
         );
         assert_eq!(safi.len(), 3);
         let mut flt = RouteFilter::new();
@@ -1915,6 +2094,7 @@ mod tests {
                 ),
             ],
             attrs,
+            Timestamp::now(),
         );
         assert_eq!(safi.len(), 4);
         let mut flt = RouteFilter::new();
@@ -1967,6 +2147,7 @@ mod tests {
                 ),
             ],
             attrs,
+            Timestamp::now(),
         );
         assert_eq!(safi.len(), 4);
         let mut flt = RouteFilter::new();
@@ -1989,6 +2170,13 @@ mod tests {
                 originator: None,
                 clusterlist: None,
                 pmsi_ta: None,
+                rawattrs: None,
+                otc: None,
+                leak: false,
+                rpki: None,
+                aspa: None,
+                irr: None,
+                bogon: BogonFlags::default(),
                 aspath: Arc::new(BgpASpath::new()),
                 comms: Arc::new(BgpCommunityList::new()),
                 lcomms: Arc::new(BgpLargeCommunityList::new()),
@@ -2022,6 +2210,7 @@ mod tests {
                     ),
                 ],
                 Arc::new(attrs1),
+                Timestamp::now(),
             );
             let attrs2 = BgpAttrs {
                 origin: BgpAttrOrigin::Incomplete,
@@ -2042,6 +2231,13 @@ mod tests {
                 clusterlist: None,
                 originator: None,
                 pmsi_ta: None,
+                rawattrs: None,
+                otc: None,
+                leak: false,
+                rpki: None,
+                aspa: None,
+                irr: None,
+                bogon: BogonFlags::default(),
             };
             safi.handle_updates_afi(
                 0,
@@ -2064,6 +2260,7 @@ mod tests {
                     ),
                 ],
                 Arc::new(attrs2),
+                Timestamp::now(),
             );
         }
         assert_eq!(safi.len(), 8);
@@ -2106,6 +2303,13 @@ mod tests {
             originator: None,
             clusterlist: None,
             pmsi_ta: None,
+            rawattrs: None,
+            otc: None,
+            leak: false,
+            rpki: None,
+            aspa: None,
+            irr: None,
+            bogon: BogonFlags::default(),
             aspath: Arc::new(BgpASpath::new()),
             comms: Arc::new(BgpCommunityList::new()),
             lcomms: Arc::new(BgpLargeCommunityList::new()),
@@ -2128,6 +2332,7 @@ mod tests {
                 BgpAddrV4::new(std::net::Ipv4Addr::new(10, 0, 0, 255), 32),
             ],
             Arc::new(attrs1),
+            Timestamp::now(),
         );
         assert_eq!(safi.len(), 5);
         let mut flt = RouteFilter::new();
@@ -2164,6 +2369,13 @@ mod tests {
             originator: None,
             clusterlist: None,
             pmsi_ta: None,
+            rawattrs: None,
+            otc: None,
+            leak: false,
+            rpki: None,
+            aspa: None,
+            irr: None,
+            bogon: BogonFlags::default(),
             aspath: Arc::new(BgpASpath::new()),
             comms: Arc::new(BgpCommunityList::new()),
             lcomms: Arc::new(BgpLargeCommunityList::new()),
@@ -2186,6 +2398,7 @@ mod tests {
                 BgpAddrV4::new(std::net::Ipv4Addr::new(10, 0, 0, 255), 32),
             ],
             Arc::new(attrs1),
+            Timestamp::now(),
         );
         assert_eq!(safi.len(), 5);
         let mut flt = RouteFilter::new();
@@ -2206,6 +2419,13 @@ mod tests {
             originator: None,
             clusterlist: None,
             pmsi_ta: None,
+            rawattrs: None,
+            otc: None,
+            leak: false,
+            rpki: None,
+            aspa: None,
+            irr: None,
+            bogon: BogonFlags::default(),
             aspath: Arc::new(BgpASpath::new()),
             comms: Arc::new(BgpCommunityList::new()),
             lcomms: Arc::new(BgpLargeCommunityList::new()),
@@ -2279,6 +2499,7 @@ mod tests {
                 ),
             ],
             Arc::new(attrs1),
+            Timestamp::now(),
         );
         assert_eq!(safi.len(), 8);
         let rflt = RibResponseFilter::new(10, false);
@@ -2317,6 +2538,13 @@ mod tests {
             originator: None,
             clusterlist: None,
             pmsi_ta: None,
+            rawattrs: None,
+            otc: None,
+            leak: false,
+            rpki: None,
+            aspa: None,
+            irr: None,
+            bogon: BogonFlags::default(),
             aspath: Arc::new(BgpASpath::new()),
             comms: Arc::new(BgpCommunityList::new()),
             lcomms: Arc::new(BgpLargeCommunityList::new()),
@@ -2334,6 +2562,13 @@ mod tests {
             originator: None,
             clusterlist: None,
             pmsi_ta: None,
+            rawattrs: None,
+            otc: None,
+            leak: false,
+            rpki: None,
+            aspa: None,
+            irr: None,
+            bogon: BogonFlags::default(),
             aspath: Arc::new(BgpASpath::new()),
             comms: Arc::new(BgpCommunityList::new()),
             lcomms: Arc::new(BgpLargeCommunityList::new()),
@@ -2369,6 +2604,73 @@ mod tests {
         assert!(rf.match_super_route(&r2, &attrs2) != FilterItemMatchResult::Yes);
     }
     #[test]
+    fn test_ribfilter_attr_type() {
+        let mut attrs = BgpAttrs::new();
+        attrs.rawattrs = Some(Arc::new(BgpRawAttrs {
+            items: vec![BgpRawAttr {
+                flags: 0xc0,
+                typecode: 35,
+                value: vec![0, 0, 0xfd, 0xe8],
+            }],
+        }));
+        assert_eq!(FilterItem::parse("attr:35"), FilterItem::AttrType(35));
+        let rf = RouteFilter::fromstr("attr:35");
+        assert_eq!(rf.match_attr(&attrs), FilterItemMatchResult::Yes);
+        assert_eq!(rf.match_attr(&BgpAttrs::new()), FilterItemMatchResult::No);
+        let rf = RouteFilter::fromstr("attr:26");
+        assert_eq!(rf.match_attr(&attrs), FilterItemMatchResult::No);
+    }
+    #[test]
+    fn test_ribfilter_flowspec() {
+        let attrs = BgpAttrs::new();
+        let dst6 = BgpFlowSpec::<FS6>::PrefixDst(FS6::new(
+            0,
+            BgpAddrV6::new("2001:db8:1::".parse().unwrap(), 48),
+        ));
+        let src6 = BgpFlowSpec::<FS6>::PrefixSrc(FS6::new(
+            0,
+            BgpAddrV6::new("2001:db8:2::1".parse().unwrap(), 128),
+        ));
+        let rf = RouteFilter::fromstr("2001:db8::/32");
+        assert_eq!(rf.match_route(&dst6, &attrs), FilterItemMatchResult::Yes);
+        assert_eq!(rf.match_route(&src6, &attrs), FilterItemMatchResult::Yes);
+        let rf = RouteFilter::fromstr("2001:db8:2::/48");
+        assert!(rf.match_route(&dst6, &attrs) != FilterItemMatchResult::Yes);
+        assert_eq!(rf.match_route(&src6, &attrs), FilterItemMatchResult::Yes);
+        let vpn = BgpFlowSpec::<FSV4U>::PrefixDst(FSV4U::new(WithRd::<BgpAddrV4>::new(
+            BgpRD::new(100, 1),
+            BgpAddrV4::new(std::net::Ipv4Addr::new(10, 1, 0, 0), 16),
+        )));
+        let rf = RouteFilter::fromstr("10.0.0.0/8 rd:100:1");
+        assert_eq!(rf.match_route(&vpn, &attrs), FilterItemMatchResult::Yes);
+        let rf = RouteFilter::fromstr("10.0.0.0/8 rd:100:2");
+        assert!(rf.match_route(&vpn, &attrs) != FilterItemMatchResult::Yes);
+    }
+    #[test]
+    fn test_ribfilter_leak() {
+        // OTC from customer is always a leak, from peer only if it is not peer AS
+        assert!(BgpRole::Provider.is_leak(Some(65001), 65001));
+        assert!(!BgpRole::Provider.is_leak(None, 65001));
+        assert!(!BgpRole::Peer.is_leak(Some(65001), 65001));
+        assert!(BgpRole::Peer.is_leak(Some(65002), 65001));
+        assert!(!BgpRole::Customer.is_leak(Some(65002), 65001));
+        let mut attrs = BgpAttrs::new();
+        attrs.otc = Some(65002);
+        attrs.leak = BgpRole::Peer.is_leak(attrs.otc, 65001);
+        assert_eq!(FilterItem::parse("leak:yes"), FilterItem::Leak(true));
+        assert_eq!(
+            FilterItem::parse("bogon:nexthop"),
+            FilterItem::Bogon(BogonKind::Nexthop)
+        );
+        let rf = RouteFilter::fromstr("leak:yes");
+        assert_eq!(rf.match_attr(&attrs), FilterItemMatchResult::Yes);
+        assert_eq!(rf.match_attr(&BgpAttrs::new()), FilterItemMatchResult::No);
+        let rf = RouteFilter::fromstr("leak:no");
+        assert_eq!(rf.match_attr(&BgpAttrs::new()), FilterItemMatchResult::Yes);
+        let rf = RouteFilter::fromstr("attr:35");
+        assert_eq!(rf.match_attr(&attrs), FilterItemMatchResult::Yes);
+    }
+    #[test]
     fn test_ribfilter_mvpn_1() {
         let attrs1 = BgpAttrs {
             origin: BgpAttrOrigin::Incomplete,
@@ -2378,6 +2680,13 @@ mod tests {
             originator: None,
             clusterlist: None,
             pmsi_ta: None,
+            rawattrs: None,
+            otc: None,
+            leak: false,
+            rpki: None,
+            aspa: None,
+            irr: None,
+            bogon: BogonFlags::default(),
             aspath: Arc::new(BgpASpath::new()),
             comms: Arc::new(BgpCommunityList::new()),
             lcomms: Arc::new(BgpLargeCommunityList::new()),
@@ -2395,6 +2704,13 @@ mod tests {
             originator: None,
             clusterlist: None,
             pmsi_ta: None,
+            rawattrs: None,
+            otc: None,
+            leak: false,
+            rpki: None,
+            aspa: None,
+            irr: None,
+            bogon: BogonFlags::default(),
             aspath: Arc::new(BgpASpath::new()),
             comms: Arc::new(BgpCommunityList::new()),
             lcomms: Arc::new(BgpLargeCommunityList::new()),
@@ -1,16 +1,22 @@
+use crate::alerts::AlertKind;
 use crate::bgprib::*;
+use crate::bogon::BogonKind;
+use crate::flaps::{parse_window, FlapMetric, FlapRanking};
+use crate::rpki::RpkiState;
+use crate::rtc::BgpRtc;
 use crate::service::*;
 use crate::timestamp::Timestamp;
 use crate::*;
 use chrono::prelude::*;
 use futures::executor::block_on;
-use std::collections::HashMap;
+use serde::ser::{SerializeMap, SerializeStruct};
+use std::collections::{BTreeSet, HashMap};
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::mpsc::*;
 use tokio::sync::RwLock;
 use tokio::time::timeout;
-use zettabgp::prelude::*;
+use zettabgp::prelude::BgpExtCommunity;
 
 const HTTP_CONTENT_TYPE: &'static str = "Content-Type";
 const HTTP_CT_TEXT_PLAIN: &'static str = "text/plain";
@@ -22,6 +28,7 @@ pub struct RibResponseFilter {
     pub onlyactive: bool,
     pub changed_before: Option<Timestamp>,
     pub changed_after: Option<Timestamp>,
+    pub sessions: Option<Arc<BTreeSet<BgpSessionId>>>,
 }
 impl RibResponseFilter {
     pub fn new(maxdepth: usize, onlyactive: bool) -> RibResponseFilter {
@@ -30,6 +37,7 @@ impl RibResponseFilter {
             onlyactive,
             changed_before: None,
             changed_after: None,
+            sessions: None,
         }
     }
     pub fn extract_params(&mut self, hashmap: &HashMap<String, String>) {
@@ -46,6 +54,12 @@ impl RibResponseFilter {
             self.changed_after = Some(n);
         };
     }
+    pub fn filter_session(&self, sess: &BgpSessionId) -> bool {
+        match self.sessions {
+            None => true,
+            Some(ref s) => s.contains(sess),
+        }
+    }
     pub fn filter_path_e(&self, bp: &BgpAttrHistory) -> bool {
         if let Some(cb) = self.changed_before.as_ref() {
             if bp
@@ -113,6 +127,112 @@ impl RibResponseParams {
     }
 }
 
+/// /api/rtc response, route target membership and routes which carry the route target
+struct RtcResponse<'a> {
+    rib: &'a BgpRIB,
+    rt: BgpExtCommunity,
+    limit: usize,
+}
+struct RtcMember<'a> {
+    rtc: &'a BgpRtc,
+    session: BgpSessionId,
+    changed: &'a Timestamp,
+    entry: &'a crate::bgpattrs::BgpAttrEntry,
+}
+struct RtcRoutes {
+    count: usize,
+    items: Vec<String>,
+}
+impl RtcRoutes {
+    fn new<T: BgpRIBKey + std::string::ToString>(
+        rib: &BgpRIBSafi<T>,
+        rt: &BgpExtCommunity,
+        limit: usize,
+    ) -> RtcRoutes {
+        let v = rib.rt_routes(rt);
+        RtcRoutes {
+            count: v.len(),
+            items: v.into_iter().take(limit).map(|r| r.to_string()).collect(),
+        }
+    }
+}
+impl<'a> serde::Serialize for RtcResponse<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let members: Vec<RtcMember> = self
+            .rib
+            .rt_members(&self.rt)
+            .into_iter()
+            .map(|(rtc, session, changed, entry)| RtcMember {
+                rtc,
+                session,
+                changed,
+                entry,
+            })
+            .collect();
+        let (rt, rib, limit) = (&self.rt, self.rib, self.limit);
+        let routes = [
+            ("vpnv4u", RtcRoutes::new(&rib.vpnv4u, rt, limit)),
+            ("vpnv4m", RtcRoutes::new(&rib.vpnv4m, rt, limit)),
+            ("vpnv6u", RtcRoutes::new(&rib.vpnv6u, rt, limit)),
+            ("vpnv6m", RtcRoutes::new(&rib.vpnv6m, rt, limit)),
+            ("l2vpls", RtcRoutes::new(&rib.l2vpls, rt, limit)),
+            ("mvpn", RtcRoutes::new(&rib.mvpn, rt, limit)),
+            ("evpn", RtcRoutes::new(&rib.evpn, rt, limit)),
+            ("fsv4u", RtcRoutes::new(&rib.fsv4u, rt, limit)),
+            ("fsv6u", RtcRoutes::new(&rib.fsv6u, rt, limit)),
+            ("ipv4mdt", RtcRoutes::new(&rib.ipv4mdt, rt, limit)),
+            ("ipv6mdt", RtcRoutes::new(&rib.ipv6mdt, rt, limit)),
+        ];
+        let mut state = serializer.serialize_struct("RtcResponse", 3)?;
+        state.serialize_field("rt", &self.rt.to_string())?;
+        state.serialize_field("members", &members)?;
+        state.serialize_field("routes", &RtcRoutesMap(&routes))?;
+        state.end()
+    }
+}
+struct RtcRoutesMap<'a>(&'a [(&'static str, RtcRoutes)]);
+impl<'a> serde::Serialize for RtcRoutesMap<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let mut state = serializer.serialize_map(None)?;
+        for (kind, r) in self.0.iter().filter(|(_, r)| r.count > 0) {
+            state.serialize_entry(kind, r)?;
+        }
+        state.end()
+    }
+}
+impl serde::Serialize for RtcRoutes {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let mut state = serializer.serialize_struct("RtcRoutes", 2)?;
+        state.serialize_field("count", &self.count)?;
+        state.serialize_field("items", &self.items)?;
+        state.end()
+    }
+}
+impl<'a> serde::Serialize for RtcMember<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let mut state = serializer.serialize_struct("RtcMember", 6)?;
+        state.serialize_field("rtc", &self.rtc.to_string())?;
+        state.serialize_field("origin_as", &self.rtc.origin_as)?;
+        state.serialize_field("session", &self.session)?;
+        state.serialize_field("nexthop", &self.entry.attrs.nexthop)?;
+        state.serialize_field("changed", self.changed)?;
+        state.serialize_field("stale", &self.entry.stale)?;
+        state.end()
+    }
+}
+
 pub struct BgpRIBts {
     pub locktimeout: Duration,
     pub rib: Arc<RwLock<BgpRIB>>,
@@ -127,15 +247,15 @@ impl BgpRIBts {
     pub async fn shutdown(&self) {
         self.rib.read().await.shutdown().await;
     }
-    pub fn run(
-        &self,
-        mut rx: Receiver<Option<(BgpSessionId, BgpUpdateMessage)>>,
-    ) -> std::thread::JoinHandle<()> {
+    pub fn run(&self, mut rx: Receiver<Option<BgpRibUpdate>>) -> std::thread::JoinHandle<()> {
         let ribc = self.rib.clone();
         let builderp = std::thread::Builder::new().name("bgp_garbage_collector".into());
         builderp
             .spawn(move || loop {
                 std::thread::sleep(time::Duration::from_secs(10));
+                if let Err(e) = block_on(ribc.read()).alerts.store() {
+                    warn!("Alerts store error: {}", e);
+                }
                 if !block_on(ribc.read()).needs_purge() {
                     continue;
                 }
@@ -148,9 +268,10 @@ impl BgpRIBts {
             .spawn(move || {
                 while let Some(updmsg) = rx.blocking_recv() {
                     match updmsg {
-                        Some(updm) => {
+                        Some(BgpRibUpdate::Update(sessid, upd, when)) => {
                             let time_started = Local::now();
-                            if let Err(e) = block_on(ribc.write()).handle_update(updm.0, updm.1) {
+                            if let Err(e) = block_on(ribc.write()).handle_update(sessid, upd, when)
+                            {
                                 warn!("RIB handle_update: {:?}", e);
                             };
                             let time_done = Local::now();
@@ -159,6 +280,57 @@ impl BgpRIBts {
                                 warn!("{} Warning: BGP update took {}", time_started, took);
                             }
                         }
+                        Some(BgpRibUpdate::SessionDown(sessid)) => {
+                            block_on(ribc.write()).handle_session_down(sessid);
+                        }
+                        Some(BgpRibUpdate::RouteRefresh(sessid, kind, begin, when)) => {
+                            block_on(ribc.write()).handle_route_refresh(sessid, kind, begin, when);
+                        }
+                        Some(BgpRibUpdate::SessionStale(sessid, retain, since)) => {
+                            block_on(ribc.write()).handle_session_stale(sessid, &retain, since);
+                        }
+                        Some(BgpRibUpdate::StaleTimeout(sessid, since)) => {
+                            block_on(ribc.write()).handle_stale_timeout(sessid, since);
+                        }
+                        Some(BgpRibUpdate::SessionRestart(sessid, previous, forwarding)) => {
+                            block_on(ribc.write()).handle_session_restart(
+                                sessid,
+                                &previous,
+                                forwarding.as_deref(),
+                            );
+                        }
+                        Some(BgpRibUpdate::SessionRole(sessid, role, peer_as)) => {
+                            block_on(ribc.write()).handle_session_role(sessid, role, peer_as);
+                        }
+                        Some(BgpRibUpdate::SessionImported(sessid)) => {
+                            block_on(ribc.write()).imported.insert(sessid);
+                        }
+                        Some(BgpRibUpdate::Rtc(sessid, upd, rtc, when)) => {
+                            if let Err(e) =
+                                block_on(ribc.write()).handle_rtc(sessid, upd, rtc, when)
+                            {
+                                warn!("RIB handle_rtc: {:?}", e);
+                            };
+                        }
+                        Some(BgpRibUpdate::FsV6u(sessid, upd, fs, when)) => {
+                            if let Err(e) =
+                                block_on(ribc.write()).handle_fsv6u(sessid, upd, fs, when)
+                            {
+                                warn!("RIB handle_fsv6u: {:?}", e);
+                            };
+                        }
+                        Some(BgpRibUpdate::Vrps(source, vrps)) => {
+                            block_on(ribc.write()).handle_vrps(source, vrps);
+                        }
+                        Some(BgpRibUpdate::Aspas(aspas)) => {
+                            block_on(ribc.write()).handle_aspas(aspas);
+                        }
+                        Some(BgpRibUpdate::Irr(key, state)) => {
+                            block_on(ribc.write()).handle_irr(key, state);
+                        }
+                        Some(BgpRibUpdate::Bogons(entries, excluded)) => {
+                            block_on(ribc.write()).handle_bogons(entries, excluded);
+                        }
                         None => break,
                     }
                 }
@@ -175,7 +347,7 @@ impl BgpRIBts {
                     .body("Operation timed out".into());
             }
         };
-        let mut rsp: std::collections::HashMap<&str, std::collections::HashMap<&str, u64>> =
+        let mut rsp: std::collections::HashMap<&str, serde_json::Value> =
             std::collections::HashMap::new();
         let mut m: std::collections::HashMap<&str, u64> = std::collections::HashMap::new();
         m.insert("pathes", rib.pathes.len() as u64);
@@ -184,7 +356,8 @@ impl BgpRIBts {
         m.insert("extcomms", rib.extcomms.len() as u64);
         m.insert("attrs", rib.attrs.len() as u64);
         m.insert("clusters", rib.clusters.len() as u64);
-        rsp.insert("stores", m);
+        m.insert("rawattrs", rib.rawattrs.len() as u64);
+        rsp.insert("stores", serde_json::json!(m));
         let mut m: std::collections::HashMap<&str, u64> = std::collections::HashMap::new();
         m.insert("ipv4u", rib.ipv4u.len() as u64);
         m.insert("ipv4m", rib.ipv4m.len() as u64);
@@ -192,6 +365,7 @@ impl BgpRIBts {
         m.insert("vpnv4u", rib.vpnv4u.len() as u64);
         m.insert("vpnv4m", rib.vpnv4m.len() as u64);
         m.insert("ipv6u", rib.ipv6u.len() as u64);
+        m.insert("ipv6m", rib.ipv6m.len() as u64);
         m.insert("ipv6lu", rib.ipv6lu.len() as u64);
         m.insert("vpnv6u", rib.vpnv6u.len() as u64);
         m.insert("vpnv6m", rib.vpnv6m.len() as u64);
@@ -199,13 +373,234 @@ impl BgpRIBts {
         m.insert("mvpn", rib.mvpn.len() as u64);
         m.insert("evpn", rib.evpn.len() as u64);
         m.insert("fs4u", rib.fs4u.len() as u64);
+        m.insert("fs6u", rib.fs6u.len() as u64);
+        m.insert("fsv4u", rib.fsv4u.len() as u64);
         m.insert("ipv4mdt", rib.ipv4mdt.len() as u64);
         m.insert("ipv6mdt", rib.ipv6mdt.len() as u64);
-        rsp.insert("ribs", m);
+        m.insert("rtc", rib.rtc.len() as u64);
+        m.insert("fsv6u", rib.fsv6u.len() as u64);
+        rsp.insert("ribs", serde_json::json!(m));
         let mut m: std::collections::HashMap<&str, u64> = std::collections::HashMap::new();
         m.insert("updates", rib.cnt_updates);
         m.insert("withdraws", rib.cnt_withdraws);
-        rsp.insert("counters", m);
+        rsp.insert("counters", serde_json::json!(m));
+        if rib.vrps.enabled {
+            let counts = rib.rpki_counts();
+            let mut m: std::collections::HashMap<String, u64> = std::collections::HashMap::new();
+            for state in [RpkiState::Valid, RpkiState::Invalid, RpkiState::NotFound] {
+                m.insert(
+                    state.to_string(),
+                    counts.get(&state).cloned().unwrap_or(0) as u64,
+                );
+            }
+            m.insert("vrps".to_string(), rib.vrps.len() as u64);
+            rsp.insert("rpki", serde_json::json!(m));
+        }
+        if rib.bogons.enabled {
+            let counts = rib.bogon_counts();
+            let mut m: std::collections::HashMap<String, u64> = std::collections::HashMap::new();
+            for kind in BogonKind::ALL {
+                m.insert(
+                    kind.to_string(),
+                    counts.get(&kind).cloned().unwrap_or(0) as u64,
+                );
+            }
+            m.insert("entries".to_string(), rib.bogons.len() as u64);
+            rsp.insert("bogon", serde_json::json!(m));
+        }
+        match serde_json::to_vec(&rsp) {
+            Ok(v) => Response::builder()
+                .status(StatusCode::OK)
+                .header(HTTP_CONTENT_TYPE, HTTP_CT_TEXT_JSON)
+                .body(v.into()),
+            Err(e) => Response::builder()
+                .status(StatusCode::from_u16(500).unwrap())
+                .header(HTTP_CONTENT_TYPE, HTTP_CT_TEXT_PLAIN)
+                .body(format!("Error: {:?}", e).into()),
+        }
+    }
+    /// Alert log query or acknowledge of alert by id
+    pub async fn say_alerts(
+        &self,
+        urlparts: &[&str],
+        req: &Request<Body>,
+    ) -> Result<Response<Body>, hyper::http::Error> {
+        if urlparts.len() > 4 && urlparts[4] == "ack" {
+            if req.method() != Method::POST {
+                return Ok(method_not_allowed());
+            }
+            let id: u64 = match urlparts[3] {
+                "all" => 0,
+                s => match s.parse() {
+                    Ok(id) => id,
+                    Err(_) => {
+                        return Response::builder()
+                            .status(StatusCode::BAD_REQUEST)
+                            .header(HTTP_CONTENT_TYPE, HTTP_CT_TEXT_PLAIN)
+                            .body(format!("Invalid alert id: {}", s).into());
+                    }
+                },
+            };
+            let mut rib = match timeout(self.locktimeout, self.rib.write()).await {
+                Ok(r) => r,
+                Err(_) => {
+                    return Response::builder()
+                        .status(StatusCode::from_u16(408).unwrap())
+                        .header(HTTP_CONTENT_TYPE, HTTP_CT_TEXT_PLAIN)
+                        .body("Operation timed out".into());
+                }
+            };
+            let acked = rib.alerts.ack(id);
+            return Response::builder()
+                .status(StatusCode::OK)
+                .header(HTTP_CONTENT_TYPE, HTTP_CT_TEXT_JSON)
+                .body(format!("{{\"acked\":{}}}", acked).into());
+        }
+        let params = get_url_params(req);
+        let kind = match get_url_param::<String>(&params, "kind") {
+            None => None,
+            Some(k) => match k.parse::<AlertKind>() {
+                Ok(k) => Some(k),
+                Err(_) => {
+                    return Response::builder()
+                        .status(StatusCode::BAD_REQUEST)
+                        .header(HTTP_CONTENT_TYPE, HTTP_CT_TEXT_PLAIN)
+                        .body(format!("Invalid alert kind: {}", k).into());
+                }
+            },
+        };
+        let acked: Option<bool> = get_url_param(&params, "acked");
+        let limit: usize = get_url_param(&params, "limit").unwrap_or(100);
+        let rib = match timeout(self.locktimeout, self.rib.read()).await {
+            Ok(r) => r,
+            Err(_) => {
+                return Response::builder()
+                    .status(StatusCode::from_u16(408).unwrap())
+                    .header(HTTP_CONTENT_TYPE, HTTP_CT_TEXT_PLAIN)
+                    .body("Operation timed out".into());
+            }
+        };
+        match serde_json::to_vec(&rib.alerts.list(acked, kind, limit)) {
+            Ok(v) => Response::builder()
+                .status(StatusCode::OK)
+                .header(HTTP_CONTENT_TYPE, HTTP_CT_TEXT_JSON)
+                .body(v.into()),
+            Err(e) => Response::builder()
+                .status(StatusCode::from_u16(500).unwrap())
+                .header(HTTP_CONTENT_TYPE, HTTP_CT_TEXT_PLAIN)
+                .body(format!("Error: {:?}", e).into()),
+        }
+    }
+    /// Most unstable prefixes, origins and peers of RIB
+    pub async fn say_top(
+        &self,
+        queryrib: &str,
+        req: &Request<Body>,
+    ) -> Result<Response<Body>, hyper::http::Error> {
+        let params = get_url_params(req);
+        let by = get_url_param::<String>(&params, "by").unwrap_or_else(|| "flaps".to_string());
+        let metric = match by.parse::<FlapMetric>() {
+            Ok(m) => m,
+            Err(_) => {
+                return Response::builder()
+                    .status(StatusCode::BAD_REQUEST)
+                    .header(HTTP_CONTENT_TYPE, HTTP_CT_TEXT_PLAIN)
+                    .body(format!("Invalid ranking: {}", by).into());
+            }
+        };
+        let window = match get_url_param::<String>(&params, "window") {
+            None => None,
+            Some(w) => match parse_window(&w) {
+                Some(w) => Some(w),
+                None => {
+                    return Response::builder()
+                        .status(StatusCode::BAD_REQUEST)
+                        .header(HTTP_CONTENT_TYPE, HTTP_CT_TEXT_PLAIN)
+                        .body(format!("Invalid window: {}", w).into());
+                }
+            },
+        };
+        let limit: usize = get_url_param(&params, "limit").unwrap_or(20);
+        let rib = match timeout(self.locktimeout, self.rib.read()).await {
+            Ok(r) => r,
+            Err(_) => {
+                return Response::builder()
+                    .status(StatusCode::from_u16(408).unwrap())
+                    .header(HTTP_CONTENT_TYPE, HTTP_CT_TEXT_PLAIN)
+                    .body("Operation timed out".into());
+            }
+        };
+        let mut ranking = FlapRanking::new(metric, window, &rib.imported);
+        match queryrib {
+            "ipv4u" => rib.ipv4u.rank_flaps(&mut ranking),
+            "ipv4m" => rib.ipv4m.rank_flaps(&mut ranking),
+            "ipv4lu" => rib.ipv4lu.rank_flaps(&mut ranking),
+            "vpnv4u" => rib.vpnv4u.rank_flaps(&mut ranking),
+            "vpnv4m" => rib.vpnv4m.rank_flaps(&mut ranking),
+            "ipv6u" => rib.ipv6u.rank_flaps(&mut ranking),
+            "ipv6m" => rib.ipv6m.rank_flaps(&mut ranking),
+            "ipv6lu" => rib.ipv6lu.rank_flaps(&mut ranking),
+            "vpnv6u" => rib.vpnv6u.rank_flaps(&mut ranking),
+            "vpnv6m" => rib.vpnv6m.rank_flaps(&mut ranking),
+            "l2vpls" => rib.l2vpls.rank_flaps(&mut ranking),
+            "mvpn" => rib.mvpn.rank_flaps(&mut ranking),
+            "evpn" => rib.evpn.rank_flaps(&mut ranking),
+            "fs4u" => rib.fs4u.rank_flaps(&mut ranking),
+            "fs6u" => rib.fs6u.rank_flaps(&mut ranking),
+            "fsv4u" => rib.fsv4u.rank_flaps(&mut ranking),
+            "ipv4mdt" => rib.ipv4mdt.rank_flaps(&mut ranking),
+            "ipv6mdt" => rib.ipv6mdt.rank_flaps(&mut ranking),
+            "rtc" => rib.rtc.rank_flaps(&mut ranking),
+            "fsv6u" => rib.fsv6u.rank_flaps(&mut ranking),
+            _ => {
+                return Response::builder()
+                    .status(StatusCode::BAD_REQUEST)
+                    .header(HTTP_CONTENT_TYPE, HTTP_CT_TEXT_PLAIN)
+                    .body(format!("Invalid RIB: {}", queryrib).into());
+            }
+        }
+        match serde_json::to_vec(&ranking.top(&by, limit)) {
+            Ok(v) => Response::builder()
+                .status(StatusCode::OK)
+                .header(HTTP_CONTENT_TYPE, HTTP_CT_TEXT_JSON)
+                .body(v.into()),
+            Err(e) => Response::builder()
+                .status(StatusCode::from_u16(500).unwrap())
+                .header(HTTP_CONTENT_TYPE, HTTP_CT_TEXT_PLAIN)
+                .body(format!("Error: {:?}", e).into()),
+        }
+    }
+    /// PEs which asked for the route target and routes which carry it
+    pub async fn say_rtc(
+        &self,
+        rtstr: &str,
+        req: &Request<Body>,
+    ) -> Result<Response<Body>, hyper::http::Error> {
+        let mut filter = ribfilter::RouteFilter::new();
+        filter.parse(format!("rt:{}", rtstr).as_str());
+        let rt = match filter.find_extcommunity_item().into_iter().next() {
+            Some(rt) => rt,
+            None => {
+                return Response::builder()
+                    .status(StatusCode::BAD_REQUEST)
+                    .header(HTTP_CONTENT_TYPE, HTTP_CT_TEXT_PLAIN)
+                    .body(format!("Invalid route target: {}", rtstr).into());
+            }
+        };
+        let rib = match timeout(self.locktimeout, self.rib.read()).await {
+            Ok(r) => r,
+            Err(_) => {
+                return Response::builder()
+                    .status(StatusCode::from_u16(408).unwrap())
+                    .header(HTTP_CONTENT_TYPE, HTTP_CT_TEXT_PLAIN)
+                    .body("Operation timed out".into());
+            }
+        };
+        let rsp = RtcResponse {
+            rib: &rib,
+            rt,
+            limit: get_url_param(&get_url_params(req), "limit").unwrap_or(1000),
+        };
         match serde_json::to_vec(&rsp) {
             Ok(v) => Response::builder()
                 .status(StatusCode::OK)
@@ -240,6 +635,7 @@ impl BgpRIBts {
         &self,
         queryrib: &str,
         req: &Request<Body>,
+        sessions: Option<BTreeSet<BgpSessionId>>,
     ) -> Result<Response<Body>, hyper::http::Error> {
         let rib = match timeout(self.locktimeout, self.rib.read()).await {
             Ok(r) => r,
@@ -254,6 +650,7 @@ impl BgpRIBts {
         let mut filter = ribfilter::RouteFilter::new();
         let paramshm = get_url_params(req);
         params.extract_params(&paramshm);
+        params.filter.sessions = sessions.map(Arc::new);
         if let Some(s) = get_url_param::<String>(&paramshm, "filter") {
             filter.parse(s.as_str());
         };
@@ -264,6 +661,7 @@ impl BgpRIBts {
             "vpnv4u" => BgpRIBts::jsontabrib(&rib.vpnv4u, &filter, params),
             "vpnv4m" => BgpRIBts::jsontabrib(&rib.vpnv4m, &filter, params),
             "ipv6u" => BgpRIBts::jsontabrib(&rib.ipv6u, &filter, params),
+            "ipv6m" => BgpRIBts::jsontabrib(&rib.ipv6m, &filter, params),
             "ipv6lu" => BgpRIBts::jsontabrib(&rib.ipv6lu, &filter, params),
             "vpnv6u" => BgpRIBts::jsontabrib(&rib.vpnv6u, &filter, params),
             "vpnv6m" => BgpRIBts::jsontabrib(&rib.vpnv6m, &filter, params),
@@ -271,8 +669,12 @@ impl BgpRIBts {
             "mvpn" => BgpRIBts::jsontabrib(&rib.mvpn, &filter, params),
             "evpn" => BgpRIBts::jsontabrib(&rib.evpn, &filter, params),
             "fs4u" => BgpRIBts::jsontabrib(&rib.fs4u, &filter, params),
+            "fs6u" => BgpRIBts::jsontabrib(&rib.fs6u, &filter, params),
+            "fsv4u" => BgpRIBts::jsontabrib(&rib.fsv4u, &filter, params),
             "ipv4mdt" => BgpRIBts::jsontabrib(&rib.ipv4mdt, &filter, params),
             "ipv6mdt" => BgpRIBts::jsontabrib(&rib.ipv6mdt, &filter, params),
+            "rtc" => BgpRIBts::jsontabrib(&rib.rtc, &filter, params),
+            "fsv6u" => BgpRIBts::jsontabrib(&rib.fsv6u, &filter, params),
             _ => BgpRIBts::jsontabrib(&rib.ipv4u, &filter, params),
         }
     }
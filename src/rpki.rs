@@ -0,0 +1,861 @@
+//! RPKI route origin validation (RFC 6811) with VRPs from RTR cache (RFC 8210) or JSON file
+use crate::bgprib::BgpRibUpdate;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::select;
+use tokio::sync::mpsc::{channel, Receiver, Sender};
+use zettabgp::prelude::*;
+
+/// Route origin validation state
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RpkiState {
+    Valid,
+    Invalid,
+    NotFound,
+}
+impl std::str::FromStr for RpkiState {
+    type Err = BgpError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "valid" => Ok(RpkiState::Valid),
+            "invalid" => Ok(RpkiState::Invalid),
+            "notfound" => Ok(RpkiState::NotFound),
+            _ => Err(BgpError::static_str("Invalid RPKI state")),
+        }
+    }
+}
+impl std::fmt::Display for RpkiState {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            RpkiState::Valid => f.write_str("valid"),
+            RpkiState::Invalid => f.write_str("invalid"),
+            RpkiState::NotFound => f.write_str("notfound"),
+        }
+    }
+}
+
+/// Validated ROA payload
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Vrp {
+    pub prefix: IpAddr,
+    pub prefixlen: u8,
+    pub maxlen: u8,
+    pub asn: u32,
+}
+impl Vrp {
+    pub fn new(prefix: IpAddr, prefixlen: u8, maxlen: u8, asn: u32) -> Vrp {
+        Vrp {
+            prefix: mask_addr(prefix, prefixlen),
+            prefixlen,
+            maxlen,
+            asn,
+        }
+    }
+}
+
+/// Where VRPs came from, each source keeps its own set
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum VrpSource {
+    Rtr,
+    File,
+}
+
+pub fn mask_addr(addr: IpAddr, len: u8) -> IpAddr {
+    match addr {
+        IpAddr::V4(a) => {
+            let m = if len == 0 {
+                0
+            } else {
+                u32::MAX << (32 - len.min(32))
+            };
+            IpAddr::V4(Ipv4Addr::from(u32::from(a) & m))
+        }
+        IpAddr::V6(a) => {
+            let m = if len == 0 {
+                0
+            } else {
+                u128::MAX << (128 - len.min(128))
+            };
+            IpAddr::V6(Ipv6Addr::from(u128::from(a) & m))
+        }
+    }
+}
+
+/// Origin AS is the last AS of AS_SEQUENCE, there is none when path ends with AS_SET
+pub fn origin_as(aspath: &BgpASpath) -> Option<u32> {
+    match aspath.value.last() {
+        Some(BgpASitem::Seq(s)) => s.value.last().map(|a| a.value),
+        _ => None,
+    }
+}
+
+/// VRPs of all sources indexed by prefix
+#[derive(Debug, Default)]
+pub struct VrpTable {
+    /// origin validation is configured
+    pub enabled: bool,
+    sources: BTreeMap<VrpSource, BTreeSet<Vrp>>,
+    index: HashMap<(IpAddr, u8), Vec<(u8, u32)>>,
+}
+impl VrpTable {
+    pub fn new(enabled: bool) -> VrpTable {
+        VrpTable {
+            enabled,
+            sources: BTreeMap::new(),
+            index: HashMap::new(),
+        }
+    }
+    /// Replaces VRP set of the source, returns true if VRPs were changed
+    pub fn set(&mut self, source: VrpSource, vrps: Vec<Vrp>) -> bool {
+        let vrps: BTreeSet<Vrp> = vrps.into_iter().collect();
+        if self.sources.get(&source) == Some(&vrps) {
+            return false;
+        }
+        self.sources.insert(source, vrps);
+        self.index.clear();
+        for v in self.sources.values().flat_map(|s| s.iter()) {
+            let e = self.index.entry((v.prefix, v.prefixlen)).or_default();
+            if !e.contains(&(v.maxlen, v.asn)) {
+                e.push((v.maxlen, v.asn));
+            }
+        }
+        true
+    }
+    /// Distinct VRPs count
+    pub fn len(&self) -> usize {
+        self.index.values().map(|v| v.len()).sum()
+    }
+    pub fn validate(&self, prefix: IpAddr, prefixlen: u8, origin: Option<u32>) -> RpkiState {
+        let mut covered = false;
+        for l in 0..=prefixlen {
+            if let Some(v) = self.index.get(&(mask_addr(prefix, l), l)) {
+                covered = true;
+                if v.iter()
+                    .any(|(maxlen, asn)| prefixlen <= *maxlen && *asn != 0 && origin == Some(*asn))
+                {
+                    return RpkiState::Valid;
+                }
+            }
+        }
+        if covered {
+            RpkiState::Invalid
+        } else {
+            RpkiState::NotFound
+        }
+    }
+}
+
+/// Parses rpki-client or Routinator JSON output
+pub fn parse_vrp_json(buf: &[u8]) -> Result<Vec<Vrp>, Box<dyn std::error::Error>> {
+    let js: serde_json::Value = serde_json::from_slice(buf)?;
+    let roas = match js.get("roas").and_then(|r| r.as_array()) {
+        Some(r) => r,
+        None => return Err(Box::new(BgpError::static_str("No roas array"))),
+    };
+    let mut ret = Vec::with_capacity(roas.len());
+    for roa in roas.iter() {
+        let prefix = roa.get("prefix").and_then(|p| p.as_str());
+        let maxlen = roa.get("maxLength").and_then(|p| p.as_u64());
+        let asn = roa.get("asn").and_then(json_asn);
+        let (prefix, maxlen, asn) = match (prefix, maxlen, asn) {
+            (Some(p), Some(m), Some(a)) => (p, m, a),
+            _ => {
+                warn!("Invalid ROA: {}", roa);
+                continue;
+            }
+        };
+        let (addr, len) = match prefix.split_once('/') {
+            Some((a, l)) => (a.parse::<IpAddr>(), l.parse::<u8>()),
+            None => {
+                warn!("Invalid ROA prefix: {}", prefix);
+                continue;
+            }
+        };
+        match (addr, len) {
+            (Ok(a), Ok(l)) => ret.push(Vrp::new(a, l, maxlen as u8, asn)),
+            _ => warn!("Invalid ROA prefix: {}", prefix),
+        }
+    }
+    Ok(ret)
+}
+
+/// AS path verification state
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AspaState {
+    Valid,
+    Invalid,
+    Unknown,
+}
+impl std::str::FromStr for AspaState {
+    type Err = BgpError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "valid" => Ok(AspaState::Valid),
+            "invalid" => Ok(AspaState::Invalid),
+            "unknown" => Ok(AspaState::Unknown),
+            _ => Err(BgpError::static_str("Invalid ASPA state")),
+        }
+    }
+}
+impl std::fmt::Display for AspaState {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            AspaState::Valid => f.write_str("valid"),
+            AspaState::Invalid => f.write_str("invalid"),
+            AspaState::Unknown => f.write_str("unknown"),
+        }
+    }
+}
+
+/// Autonomous System Provider Authorization of customer AS
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Aspa {
+    pub customer: u32,
+    pub providers: Vec<u32>,
+}
+
+/// Provider authorization of the hop from customer to provider
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AspaHop {
+    NoAttestation,
+    ProviderPlus,
+    NotProviderPlus,
+}
+
+/// ASPA records indexed by customer AS
+#[derive(Debug, Default)]
+pub struct AspaTable {
+    /// AS path verification is configured
+    pub enabled: bool,
+    providers: HashMap<u32, BTreeSet<u32>>,
+}
+impl AspaTable {
+    pub fn new(enabled: bool) -> AspaTable {
+        AspaTable {
+            enabled,
+            providers: HashMap::new(),
+        }
+    }
+    /// Replaces ASPA records, returns true if they were changed
+    pub fn set(&mut self, aspas: Vec<Aspa>) -> bool {
+        let mut providers: HashMap<u32, BTreeSet<u32>> = HashMap::new();
+        for a in aspas.into_iter() {
+            providers.entry(a.customer).or_default().extend(a.providers);
+        }
+        if providers == self.providers {
+            return false;
+        }
+        self.providers = providers;
+        true
+    }
+    pub fn len(&self) -> usize {
+        self.providers.len()
+    }
+    fn hop(&self, customer: u32, provider: u32) -> AspaHop {
+        match self.providers.get(&customer) {
+            None => AspaHop::NoAttestation,
+            Some(p) if p.contains(&provider) => AspaHop::ProviderPlus,
+            Some(_) => AspaHop::NotProviderPlus,
+        }
+    }
+    /// Upstream or downstream verification of AS path
+    pub fn verify(&self, aspath: &BgpASpath, downstream: bool) -> AspaState {
+        // origin first, prepends collapsed
+        let mut path: Vec<u32> = Vec::new();
+        for item in aspath.value.iter() {
+            match item {
+                BgpASitem::Seq(s) => {
+                    for a in s.value.iter() {
+                        if path.last() != Some(&a.value) {
+                            path.push(a.value);
+                        }
+                    }
+                }
+                BgpASitem::Set(_) => return AspaState::Invalid,
+            }
+        }
+        path.reverse();
+        let n = path.len();
+        if n == 0 {
+            return AspaState::Unknown;
+        }
+        // up-ramp from origin, with and without ASes lacking attestation
+        let ramp = |hops: &mut dyn Iterator<Item = AspaHop>| -> (usize, usize) {
+            let (mut max, mut min, mut strict) = (1, 1, true);
+            for h in hops {
+                if h == AspaHop::NotProviderPlus {
+                    break;
+                }
+                strict &= h == AspaHop::ProviderPlus;
+                max += 1;
+                if strict {
+                    min += 1;
+                }
+            }
+            (max, min)
+        };
+        let (max_up, min_up) = ramp(&mut path.windows(2).map(|w| self.hop(w[0], w[1])));
+        let (max_down, min_down) = if downstream {
+            ramp(&mut path.windows(2).rev().map(|w| self.hop(w[1], w[0])))
+        } else {
+            (0, 0)
+        };
+        if max_up + max_down < n {
+            AspaState::Invalid
+        } else if min_up + min_down >= n {
+            AspaState::Valid
+        } else {
+            AspaState::Unknown
+        }
+    }
+}
+
+fn json_asn(v: &serde_json::Value) -> Option<u32> {
+    match v {
+        serde_json::Value::Number(n) => n.as_u64().map(|n| n as u32),
+        serde_json::Value::String(s) => s.trim_start_matches("AS").parse().ok(),
+        _ => None,
+    }
+}
+
+/// Parses aspas array of rpki-client JSON output
+pub fn parse_aspa_json(buf: &[u8]) -> Result<Vec<Aspa>, Box<dyn std::error::Error>> {
+    let js: serde_json::Value = serde_json::from_slice(buf)?;
+    let aspas = match js.get("aspas").and_then(|r| r.as_array()) {
+        Some(r) => r,
+        None => return Err(Box::new(BgpError::static_str("No aspas array"))),
+    };
+    let mut ret = Vec::with_capacity(aspas.len());
+    for aspa in aspas.iter() {
+        let customer = aspa
+            .get("customer_asid")
+            .or_else(|| aspa.get("customer"))
+            .and_then(json_asn);
+        let providers = aspa
+            .get("providers")
+            .and_then(|p| p.as_array())
+            .map(|p| p.iter().filter_map(json_asn).collect());
+        match (customer, providers) {
+            (Some(customer), Some(providers)) => ret.push(Aspa {
+                customer,
+                providers,
+            }),
+            _ => warn!("Invalid ASPA: {}", aspa),
+        }
+    }
+    Ok(ret)
+}
+
+/// Makes RIB update from RPKI file contents
+pub type RpkiFileParser = fn(&[u8]) -> Result<BgpRibUpdate, Box<dyn std::error::Error>>;
+
+/// Reloads RPKI JSON or bogon list file periodically
+pub async fn run_rpki_file(
+    path: String,
+    every: std::time::Duration,
+    parse: RpkiFileParser,
+    upd: Sender<Option<BgpRibUpdate>>,
+    cancel: tokio_util::sync::CancellationToken,
+) {
+    let mut modified = None;
+    loop {
+        let mtime = tokio::fs::metadata(&path)
+            .await
+            .and_then(|m| m.modified())
+            .ok();
+        if mtime.is_none() || mtime != modified {
+            let ribupd = match tokio::fs::read(&path).await {
+                Ok(buf) => match parse(&buf) {
+                    Ok(u) => Some(u),
+                    Err(e) => {
+                        warn!("File {} parse error: {}", path, e);
+                        None
+                    }
+                },
+                Err(e) => {
+                    warn!("File {} read error: {}", path, e);
+                    None
+                }
+            };
+            if let Some(u) = ribupd {
+                info!("File {} loaded", path);
+                modified = mtime;
+                if upd.send(Some(u)).await.is_err() {
+                    return;
+                }
+            }
+        }
+        select! {
+            _ = cancel.cancelled() => return,
+            _ = tokio::time::sleep(every) => {}
+        }
+    }
+}
+
+/// RTR PDU types
+const RTR_SERIAL_NOTIFY: u8 = 0;
+const RTR_SERIAL_QUERY: u8 = 1;
+const RTR_RESET_QUERY: u8 = 2;
+const RTR_CACHE_RESPONSE: u8 = 3;
+const RTR_IPV4_PREFIX: u8 = 4;
+const RTR_IPV6_PREFIX: u8 = 6;
+const RTR_END_OF_DATA: u8 = 7;
+const RTR_CACHE_RESET: u8 = 8;
+const RTR_ERROR_REPORT: u8 = 10;
+/// Error Report code for unsupported protocol version
+const RTR_ERR_BAD_VERSION: u16 = 4;
+/// Largest PDU accepted from cache
+const RTR_MAX_PDU: usize = 65536;
+
+/// RTR protocol data unit
+#[derive(Debug, Clone, PartialEq)]
+pub struct RtrPdu {
+    pub version: u8,
+    pub pdutype: u8,
+    /// session id, error code or zero, depending on type
+    pub session: u16,
+    pub body: Vec<u8>,
+}
+impl RtrPdu {
+    pub fn new(version: u8, pdutype: u8, session: u16, body: Vec<u8>) -> RtrPdu {
+        RtrPdu {
+            version,
+            pdutype,
+            session,
+            body,
+        }
+    }
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(8 + self.body.len());
+        buf.push(self.version);
+        buf.push(self.pdutype);
+        buf.extend_from_slice(&self.session.to_be_bytes());
+        buf.extend_from_slice(&((8 + self.body.len()) as u32).to_be_bytes());
+        buf.extend_from_slice(&self.body);
+        buf
+    }
+    pub async fn read_from<R: AsyncReadExt + Unpin>(rd: &mut R) -> Result<RtrPdu, BgpError> {
+        let mut hdr = [0u8; 8];
+        if let Err(e) = rd.read_exact(&mut hdr).await {
+            return Err(BgpError::from_string(format!("RTR read error: {}", e)));
+        }
+        let len = getn_u32(&hdr[4..8]) as usize;
+        if !(8..=RTR_MAX_PDU).contains(&len) {
+            return Err(BgpError::static_str("Invalid RTR PDU length"));
+        }
+        let mut body = vec![0u8; len - 8];
+        if let Err(e) = rd.read_exact(&mut body).await {
+            return Err(BgpError::from_string(format!("RTR read error: {}", e)));
+        }
+        Ok(RtrPdu::new(hdr[0], hdr[1], getn_u16(&hdr[2..4]), body))
+    }
+    /// Announced (true) or withdrawn VRP of IPv4 Prefix and IPv6 Prefix PDUs
+    pub fn prefix(&self) -> Result<(bool, Vrp), BgpError> {
+        let alen = match self.pdutype {
+            RTR_IPV4_PREFIX => 4,
+            RTR_IPV6_PREFIX => 16,
+            _ => return Err(BgpError::static_str("Not a prefix PDU")),
+        };
+        if self.body.len() != 8 + alen {
+            return Err(BgpError::static_str("Invalid RTR prefix PDU length"));
+        }
+        let addr = if alen == 4 {
+            IpAddr::V4(decode_addrv4_from(&self.body[4..8])?)
+        } else {
+            IpAddr::V6(decode_addrv6_from(&self.body[4..20])?)
+        };
+        Ok((
+            self.body[0] & 1 != 0,
+            Vrp::new(
+                addr,
+                self.body[1],
+                self.body[2],
+                getn_u32(&self.body[4 + alen..]),
+            ),
+        ))
+    }
+}
+
+/// RTR client state between PDUs
+struct RtrClient {
+    version: u8,
+    session: Option<u16>,
+    serial: u32,
+    refresh: u64,
+    vrps: BTreeSet<Vrp>,
+    /// VRPs being received since Cache Response
+    pending: Option<BTreeSet<Vrp>>,
+}
+impl RtrClient {
+    fn query(&self) -> RtrPdu {
+        match self.session {
+            Some(s) => RtrPdu::new(
+                self.version,
+                RTR_SERIAL_QUERY,
+                s,
+                self.serial.to_be_bytes().to_vec(),
+            ),
+            None => RtrPdu::new(self.version, RTR_RESET_QUERY, 0, Vec::new()),
+        }
+    }
+    /// Handles PDU from cache, returns query to send and whether new VRP set is complete
+    fn handle(&mut self, pdu: RtrPdu) -> Result<(Option<RtrPdu>, bool), BgpError> {
+        match pdu.pdutype {
+            RTR_SERIAL_NOTIFY if self.pending.is_none() => {
+                return Ok((Some(self.query()), false));
+            }
+            RTR_CACHE_RESPONSE => {
+                // incremental update for known session, full set otherwise
+                self.pending = Some(if self.session == Some(pdu.session) {
+                    self.vrps.clone()
+                } else {
+                    BTreeSet::new()
+                });
+            }
+            RTR_IPV4_PREFIX | RTR_IPV6_PREFIX => {
+                let (announce, vrp) = pdu.prefix()?;
+                if let Some(p) = self.pending.as_mut() {
+                    if announce {
+                        p.insert(vrp);
+                    } else {
+                        p.remove(&vrp);
+                    }
+                }
+            }
+            RTR_END_OF_DATA => {
+                if pdu.body.len() < 4 {
+                    return Err(BgpError::static_str("Invalid RTR End of Data length"));
+                }
+                self.session = Some(pdu.session);
+                self.serial = getn_u32(&pdu.body[0..4]);
+                if pdu.body.len() >= 8 {
+                    self.refresh = getn_u32(&pdu.body[4..8]) as u64;
+                }
+                if let Some(p) = self.pending.take() {
+                    self.vrps = p;
+                    return Ok((None, true));
+                }
+            }
+            RTR_CACHE_RESET => {
+                self.session = None;
+                self.pending = None;
+                return Ok((Some(self.query()), false));
+            }
+            RTR_ERROR_REPORT => {
+                if pdu.session == RTR_ERR_BAD_VERSION && self.version > 0 {
+                    self.version -= 1;
+                    self.session = None;
+                }
+                return Err(BgpError::from_string(format!(
+                    "RTR cache error report code {}",
+                    pdu.session
+                )));
+            }
+            _ => {}
+        }
+        Ok((None, false))
+    }
+}
+
+async fn rtr_reader(mut rd: tokio::net::tcp::OwnedReadHalf, tx: Sender<Result<RtrPdu, BgpError>>) {
+    loop {
+        let r = RtrPdu::read_from(&mut rd).await;
+        let stop = r.is_err();
+        if tx.send(r).await.is_err() || stop {
+            return;
+        }
+    }
+}
+
+async fn rtr_session(
+    cache: &str,
+    client: &mut RtrClient,
+    upd: &Sender<Option<BgpRibUpdate>>,
+) -> Result<(), BgpError> {
+    let stream = match tokio::net::TcpStream::connect(cache).await {
+        Ok(s) => s,
+        Err(e) => return Err(BgpError::from_string(format!("RTR connect error: {}", e))),
+    };
+    info!("RTR connected to {} version {}", cache, client.version);
+    let (rd, mut wr) = stream.into_split();
+    let (tx, mut rx): (_, Receiver<Result<RtrPdu, BgpError>>) = channel(256);
+    let reader = tokio::spawn(rtr_reader(rd, tx));
+    let mut query = Some(client.query());
+    let ret = loop {
+        if let Some(q) = query.take() {
+            if let Err(e) = wr.write_all(&q.encode()).await {
+                break Err(BgpError::from_string(format!("RTR write error: {}", e)));
+            }
+        }
+        let refresh = std::time::Duration::from_secs(client.refresh.max(1));
+        let pdu = select! {
+            p = rx.recv() => p,
+            _ = tokio::time::sleep(refresh) => {
+                query = Some(client.query());
+                continue;
+            }
+        };
+        let pdu = match pdu {
+            Some(Ok(p)) => p,
+            Some(Err(e)) => break Err(e),
+            None => break Err(BgpError::static_str("RTR connection closed")),
+        };
+        match client.handle(pdu) {
+            Err(e) => break Err(e),
+            Ok((q, done)) => {
+                query = q;
+                if done {
+                    info!(
+                        "RTR {} serial {}, {} VRPs",
+                        cache,
+                        client.serial,
+                        client.vrps.len()
+                    );
+                    let vrps = client.vrps.iter().cloned().collect();
+                    if upd
+                        .send(Some(BgpRibUpdate::Vrps(VrpSource::Rtr, vrps)))
+                        .await
+                        .is_err()
+                    {
+                        break Ok(());
+                    }
+                }
+            }
+        }
+    };
+    reader.abort();
+    ret
+}
+
+/// Keeps RTR session to cache server, reconnecting on errors
+pub async fn run_rtr(
+    cache: String,
+    refresh: u64,
+    upd: Sender<Option<BgpRibUpdate>>,
+    cancel: tokio_util::sync::CancellationToken,
+) {
+    let mut client = RtrClient {
+        version: 1,
+        session: None,
+        serial: 0,
+        refresh,
+        vrps: BTreeSet::new(),
+        pending: None,
+    };
+    loop {
+        select! {
+            _ = cancel.cancelled() => return,
+            r = rtr_session(&cache, &mut client, &upd) => {
+                match r {
+                    Ok(_) => return,
+                    Err(e) => warn!("RTR {}: {}", cache, e),
+                }
+            }
+        }
+        client.pending = None;
+        select! {
+            _ = cancel.cancelled() => return,
+            _ = tokio::time::sleep(std::time::Duration::from_secs(10)) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rpki_validate() {
+        let vrps = parse_vrp_json(
+            br#"{"roas":[{"prefix":"10.0.0.0/16","maxLength":24,"asn":"AS65001"},
+            {"prefix":"2001:db8::/32","maxLength":32,"asn":65002}]}"#,
+        )
+        .unwrap();
+        let mut tbl = VrpTable::new(true);
+        assert!(tbl.set(VrpSource::File, vrps.clone()));
+        assert!(!tbl.set(VrpSource::File, vrps));
+        assert_eq!(tbl.len(), 2);
+        let pfx: IpAddr = "10.0.1.0".parse().unwrap();
+        assert_eq!(tbl.validate(pfx, 24, Some(65001)), RpkiState::Valid);
+        assert_eq!(tbl.validate(pfx, 25, Some(65001)), RpkiState::Invalid);
+        assert_eq!(tbl.validate(pfx, 24, Some(65003)), RpkiState::Invalid);
+        assert_eq!(tbl.validate(pfx, 24, None), RpkiState::Invalid);
+        let pfx: IpAddr = "10.1.0.0".parse().unwrap();
+        assert_eq!(tbl.validate(pfx, 16, Some(65001)), RpkiState::NotFound);
+        let pfx: IpAddr = "2001:db8::".parse().unwrap();
+        assert_eq!(tbl.validate(pfx, 32, Some(65002)), RpkiState::Valid);
+        let mut path = BgpASpath::new();
+        path.value.push(BgpASitem::Seq(BgpASseq {
+            value: vec![BgpAS::new(65010), BgpAS::new(65001)],
+        }));
+        assert_eq!(origin_as(&path), Some(65001));
+    }
+    #[test]
+    fn test_aspa_verify() {
+        let aspas = parse_aspa_json(
+            br#"{"aspas":[{"customer_asid":65001,"providers":[65002]},
+            {"customer_asid":65002,"providers":["AS65003"]},
+            {"customer_asid":65004,"providers":[65003]}]}"#,
+        )
+        .unwrap();
+        let mut tbl = AspaTable::new(true);
+        assert!(tbl.set(aspas));
+        // neighbor first, origin last
+        let path = |v: Vec<u32>| BgpASpath::from(v);
+        assert_eq!(
+            tbl.verify(&path(vec![65003, 65002, 65001, 65001]), false),
+            AspaState::Valid
+        );
+        assert_eq!(
+            tbl.verify(&path(vec![65005, 65002, 65001]), false),
+            AspaState::Invalid
+        );
+        assert_eq!(
+            tbl.verify(&path(vec![65006, 65005, 65001]), false),
+            AspaState::Invalid
+        );
+        assert_eq!(
+            tbl.verify(&path(vec![65006, 65003, 65002, 65001]), false),
+            AspaState::Unknown
+        );
+        // valley free path received from provider 65004 via top 65003
+        assert_eq!(
+            tbl.verify(&path(vec![65004, 65003, 65002, 65001]), true),
+            AspaState::Valid
+        );
+        assert_eq!(
+            tbl.verify(&path(vec![65004, 65003, 65002, 65001]), false),
+            AspaState::Unknown
+        );
+        assert_eq!(
+            tbl.verify(&path(vec![65004, 65002, 65003, 65001]), true),
+            AspaState::Invalid
+        );
+        assert_eq!(
+            tbl.verify(&path(vec![65007, 65005, 65003, 65002, 65001]), true),
+            AspaState::Unknown
+        );
+    }
+    #[test]
+    fn test_rtr_client() {
+        let mut client = RtrClient {
+            version: 1,
+            session: None,
+            serial: 0,
+            refresh: 3600,
+            vrps: BTreeSet::new(),
+            pending: None,
+        };
+        assert_eq!(client.query().encode(), vec![1, 2, 0, 0, 0, 0, 0, 8]);
+        let pfx = |flags: u8| {
+            RtrPdu::new(
+                1,
+                RTR_IPV4_PREFIX,
+                0,
+                vec![flags, 24, 24, 0, 192, 0, 2, 0, 0, 0, 0xfd, 0xe9],
+            )
+        };
+        let eod = |serial: u8| {
+            RtrPdu::new(
+                1,
+                RTR_END_OF_DATA,
+                7,
+                vec![0, 0, 0, serial, 0, 0, 0, 60, 0, 0, 2, 88, 0, 0, 28, 32],
+            )
+        };
+        assert_eq!(
+            client
+                .handle(RtrPdu::new(1, RTR_CACHE_RESPONSE, 7, Vec::new()))
+                .unwrap(),
+            (None, false)
+        );
+        assert_eq!(client.handle(pfx(1)).unwrap(), (None, false));
+        assert_eq!(client.handle(eod(1)).unwrap(), (None, true));
+        assert_eq!(client.refresh, 60);
+        let vrp = Vrp::new("192.0.2.0".parse().unwrap(), 24, 24, 65001);
+        assert!(client.vrps.contains(&vrp));
+        let q = client
+            .handle(RtrPdu::new(1, RTR_SERIAL_NOTIFY, 7, vec![0, 0, 0, 2]))
+            .unwrap()
+            .0
+            .unwrap();
+        assert_eq!(q.encode(), vec![1, 1, 0, 7, 0, 0, 0, 12, 0, 0, 0, 1]);
+        client
+            .handle(RtrPdu::new(1, RTR_CACHE_RESPONSE, 7, Vec::new()))
+            .unwrap();
+        client.handle(pfx(0)).unwrap();
+        assert_eq!(client.handle(eod(2)).unwrap(), (None, true));
+        assert!(client.vrps.is_empty());
+        assert!(client
+            .handle(RtrPdu::new(1, RTR_ERROR_REPORT, 4, Vec::new()))
+            .is_err());
+        assert_eq!(client.version, 0);
+    }
+    async fn rtr_vrps(rx: &mut Receiver<Option<BgpRibUpdate>>) -> Vec<Vrp> {
+        match rx.recv().await {
+            Some(Some(BgpRibUpdate::Vrps(VrpSource::Rtr, v))) => v,
+            _ => panic!("VRPs expected"),
+        }
+    }
+    #[tokio::test]
+    async fn test_rtr_session() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let cache = listener.local_addr().unwrap().to_string();
+        let (tx, mut rx) = channel(4);
+        let session = tokio::spawn(async move {
+            let mut client = RtrClient {
+                version: 1,
+                session: None,
+                serial: 0,
+                refresh: 3600,
+                vrps: BTreeSet::new(),
+                pending: None,
+            };
+            rtr_session(&cache, &mut client, &tx).await
+        });
+        let (mut sock, _) = listener.accept().await.unwrap();
+        let pfx = |flags: u8| {
+            RtrPdu::new(
+                1,
+                RTR_IPV4_PREFIX,
+                0,
+                vec![flags, 24, 24, 0, 192, 0, 2, 0, 0, 0, 0xfd, 0xe9],
+            )
+        };
+        let eod = |serial: u8| RtrPdu::new(1, RTR_END_OF_DATA, 7, vec![0, 0, 0, serial]);
+        // full set after Reset Query
+        let q = RtrPdu::read_from(&mut sock).await.unwrap();
+        assert_eq!(q, RtrPdu::new(1, RTR_RESET_QUERY, 0, Vec::new()));
+        for p in [
+            RtrPdu::new(1, RTR_CACHE_RESPONSE, 7, Vec::new()),
+            pfx(1),
+            eod(1),
+        ] {
+            sock.write_all(&p.encode()).await.unwrap();
+        }
+        assert_eq!(
+            rtr_vrps(&mut rx).await,
+            vec![Vrp::new("192.0.2.0".parse().unwrap(), 24, 24, 65001)]
+        );
+        // incremental update after Serial Notify
+        sock.write_all(&RtrPdu::new(1, RTR_SERIAL_NOTIFY, 7, vec![0, 0, 0, 2]).encode())
+            .await
+            .unwrap();
+        let q = RtrPdu::read_from(&mut sock).await.unwrap();
+        assert_eq!(q, RtrPdu::new(1, RTR_SERIAL_QUERY, 7, vec![0, 0, 0, 1]));
+        for p in [
+            RtrPdu::new(1, RTR_CACHE_RESPONSE, 7, Vec::new()),
+            pfx(0),
+            eod(2),
+        ] {
+            sock.write_all(&p.encode()).await.unwrap();
+        }
+        assert!(rtr_vrps(&mut rx).await.is_empty());
+        drop(sock);
+        assert!(session.await.unwrap().is_err());
+    }
+}
@@ -0,0 +1,298 @@
+//! Route Target Constraint (RFC 4684) NLRI, which is unknown to zettabgp
+use serde::{Deserialize, Serialize};
+use zettabgp::prelude::*;
+
+/// RTC address family
+pub const RTC_AFI: u16 = 1;
+pub const RTC_SAFI: u8 = 132;
+/// MP_REACH_NLRI and MP_UNREACH_NLRI attribute type codes
+const BGP_ATTR_MP_REACH: u8 = 14;
+const BGP_ATTR_MP_UNREACH: u8 = 15;
+
+/// Route Target membership NLRI, origin AS and route target prefix
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct BgpRtc {
+    pub origin_as: u32,
+    /// route target, bits beyond prefix length are zero
+    pub rt: BgpExtCommunity,
+    /// prefix length in bits, 0 for default route target
+    pub prefixlen: u8,
+}
+impl BgpRtc {
+    pub fn new(origin_as: u32, rt: BgpExtCommunity) -> BgpRtc {
+        BgpRtc {
+            origin_as,
+            rt,
+            prefixlen: 96,
+        }
+    }
+    /// Decodes NLRI, returns item and consumed length
+    pub fn decode_from(buf: &[u8]) -> Result<(BgpRtc, usize), BgpError> {
+        if buf.is_empty() {
+            return Err(BgpError::insufficient_buffer_size());
+        }
+        let bits = buf[0] as usize;
+        if bits > 96 {
+            return Err(BgpError::static_str("Invalid RTC prefix length"));
+        }
+        let len = bits.div_ceil(8);
+        if buf.len() < 1 + len {
+            return Err(BgpError::insufficient_buffer_size());
+        }
+        let mut v = [0u8; 12];
+        v[0..len].copy_from_slice(&buf[1..1 + len]);
+        if !bits.is_multiple_of(8) {
+            v[len - 1] &= 0xffu8 << (8 - bits % 8);
+        }
+        Ok((
+            BgpRtc {
+                origin_as: getn_u32(&v[0..4]),
+                rt: BgpExtCommunity::decode_from(&v[4..12])?,
+                prefixlen: bits as u8,
+            },
+            1 + len,
+        ))
+    }
+    /// Full route target, not a prefix
+    pub fn is_exact(&self) -> bool {
+        self.prefixlen == 96
+    }
+    /// Checks that membership covers the route target
+    pub fn covers(&self, rt: &BgpExtCommunity) -> bool {
+        if self.prefixlen <= 32 {
+            return true;
+        }
+        let mut a = [0u8; 8];
+        let mut b = [0u8; 8];
+        if self.rt.encode_to(&mut a).is_err() || rt.encode_to(&mut b).is_err() {
+            return false;
+        }
+        let shift = 96 - self.prefixlen as u32;
+        (u64::from_be_bytes(a) >> shift) == (u64::from_be_bytes(b) >> shift)
+    }
+}
+impl std::fmt::Display for BgpRtc {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if self.prefixlen == 0 {
+            return f.write_str("default");
+        }
+        write!(f, "{} {}/{}", self.origin_as, self.rt, self.prefixlen)
+    }
+}
+
+/// Route Target membership part of UPDATE message
+#[derive(Debug, Clone)]
+pub struct RtcUpdate {
+    pub nexthop: BgpAddr,
+    pub updates: Vec<BgpRtc>,
+    pub withdraws: Vec<BgpRtc>,
+    /// UPDATE was End-of-RIB marker for RTC
+    pub end_of_rib: bool,
+}
+impl RtcUpdate {
+    fn new() -> RtcUpdate {
+        RtcUpdate {
+            nexthop: BgpAddr::None,
+            updates: Vec::new(),
+            withdraws: Vec::new(),
+            end_of_rib: false,
+        }
+    }
+    fn decode_nlri(buf: &[u8], v: &mut Vec<BgpRtc>) -> Result<(), BgpError> {
+        let mut pos = 0;
+        while pos < buf.len() {
+            let (r, sz) = BgpRtc::decode_from(&buf[pos..])?;
+            v.push(r);
+            pos += sz;
+        }
+        Ok(())
+    }
+    /// Decodes MP_REACH_NLRI after AFI and SAFI
+    fn decode_reach(&mut self, buf: &[u8]) -> Result<(), BgpError> {
+        if buf.is_empty() || buf.len() < 2 + buf[0] as usize {
+            return Err(BgpError::static_str("Invalid RTC MP_REACH_NLRI length"));
+        }
+        let nh = &buf[1..1 + buf[0] as usize];
+        self.nexthop = match nh.len() {
+            4 => BgpAddr::V4(decode_addrv4_from(nh)?),
+            16 | 32 => BgpAddr::V6(decode_addrv6_from(&nh[0..16])?),
+            _ => BgpAddr::None,
+        };
+        RtcUpdate::decode_nlri(&buf[2 + nh.len()..], &mut self.updates)
+    }
+}
+
+/// UPDATE message body announcing default Route Target membership (RFC 4684 section 4),
+/// so neighbor sends VPN routes of all route targets
+pub fn encode_rtc_default(
+    as_num: u32,
+    as32: bool,
+    ebgp: bool,
+    nexthop: std::net::IpAddr,
+) -> Vec<u8> {
+    let mut attrs = vec![0x40, 1, 1, 0];
+    if ebgp {
+        if as32 {
+            attrs.extend_from_slice(&[0x40, 2, 6, 2, 1]);
+            attrs.extend_from_slice(&as_num.to_be_bytes());
+        } else {
+            attrs.extend_from_slice(&[0x40, 2, 4, 2, 1]);
+            attrs.extend_from_slice(&(as_num as u16).to_be_bytes());
+        }
+    } else {
+        attrs.extend_from_slice(&[0x40, 2, 0, 0x40, 5, 4, 0, 0, 0, 100]);
+    }
+    let nh = match nexthop {
+        std::net::IpAddr::V4(a) => a.octets().to_vec(),
+        std::net::IpAddr::V6(a) => a.octets().to_vec(),
+    };
+    attrs.extend_from_slice(&[0x80, BGP_ATTR_MP_REACH, 6 + nh.len() as u8]);
+    attrs.extend_from_slice(&RTC_AFI.to_be_bytes());
+    attrs.extend_from_slice(&[RTC_SAFI, nh.len() as u8]);
+    attrs.extend_from_slice(&nh);
+    // no SNPA, default route target has zero prefix length
+    attrs.extend_from_slice(&[0, 0]);
+    let mut body = vec![0, 0];
+    body.extend_from_slice(&(attrs.len() as u16).to_be_bytes());
+    body.extend_from_slice(&attrs);
+    body
+}
+
+/// MP_REACH_NLRI and MP_UNREACH_NLRI values of one address family, without AFI and SAFI
+#[derive(Debug, Default)]
+pub struct MpNlri {
+    pub reach: Option<Vec<u8>>,
+    pub unreach: Option<Vec<u8>>,
+    /// UPDATE was End-of-RIB marker for the address family
+    pub end_of_rib: bool,
+}
+
+/// Extracts MP_REACH_NLRI and MP_UNREACH_NLRI of address family from UPDATE message body.
+/// Returns attribute values and message body without them, or None if there are no such attributes.
+pub fn extract_mp(buf: &[u8], afi: u16, safi: u8) -> Result<Option<(MpNlri, Vec<u8>)>, BgpError> {
+    if buf.len() < 4 {
+        return Err(BgpError::insufficient_buffer_size());
+    }
+    let apos = 2 + getn_u16(buf) as usize;
+    if buf.len() < apos + 2 {
+        return Err(BgpError::insufficient_buffer_size());
+    }
+    let aend = apos + 2 + getn_u16(&buf[apos..]) as usize;
+    if buf.len() < aend {
+        return Err(BgpError::insufficient_buffer_size());
+    }
+    let mut ret = MpNlri::default();
+    let mut attrs = Vec::with_capacity(aend - apos - 2);
+    let mut pos = apos + 2;
+    while pos < aend {
+        if pos + 3 > aend {
+            return Err(BgpError::insufficient_buffer_size());
+        }
+        let (hdr, len) = if buf[pos] & 0x10 != 0 {
+            if pos + 4 > aend {
+                return Err(BgpError::insufficient_buffer_size());
+            }
+            (4, getn_u16(&buf[pos + 2..]) as usize)
+        } else {
+            (3, buf[pos + 2] as usize)
+        };
+        if pos + hdr + len > aend {
+            return Err(BgpError::insufficient_buffer_size());
+        }
+        let tc = buf[pos + 1];
+        let val = &buf[pos + hdr..pos + hdr + len];
+        if (tc == BGP_ATTR_MP_REACH || tc == BGP_ATTR_MP_UNREACH)
+            && val.len() >= 3
+            && getn_u16(val) == afi
+            && val[2] == safi
+        {
+            if tc == BGP_ATTR_MP_REACH {
+                ret.reach = Some(val[3..].to_vec());
+            } else {
+                ret.unreach = Some(val[3..].to_vec());
+            }
+        } else {
+            attrs.extend_from_slice(&buf[pos..pos + hdr + len]);
+        }
+        pos += hdr + len;
+    }
+    if ret.reach.is_none() && ret.unreach.is_none() {
+        return Ok(None);
+    }
+    ret.end_of_rib = ret.reach.is_none()
+        && ret.unreach.as_ref().is_some_and(|u| u.is_empty())
+        && attrs.is_empty()
+        && apos == 2
+        && aend == buf.len();
+    let mut body = Vec::with_capacity(buf.len());
+    body.extend_from_slice(&buf[0..apos]);
+    body.extend_from_slice(&(attrs.len() as u16).to_be_bytes());
+    body.extend_from_slice(&attrs);
+    body.extend_from_slice(&buf[aend..]);
+    Ok(Some((ret, body)))
+}
+
+/// Extracts RTC NLRI from UPDATE message body.
+/// Returns membership and message body without RTC attributes, or None if there is no RTC.
+pub fn extract_rtc(buf: &[u8]) -> Result<Option<(RtcUpdate, Vec<u8>)>, BgpError> {
+    let (mp, body) = match extract_mp(buf, RTC_AFI, RTC_SAFI)? {
+        None => return Ok(None),
+        Some(r) => r,
+    };
+    let mut ret = RtcUpdate::new();
+    if let Some(ref reach) = mp.reach {
+        ret.decode_reach(reach)?;
+    }
+    if let Some(ref unreach) = mp.unreach {
+        RtcUpdate::decode_nlri(unreach, &mut ret.withdraws)?;
+    }
+    ret.end_of_rib = mp.end_of_rib;
+    Ok(Some((ret, body)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_rtc() {
+        // ORIGIN, MP_REACH_NLRI 1/132 with nexthop 10.0.0.1, 65000 target:100:1 and default
+        let buf = [
+            0, 0, 0, 31, 0x40, 1, 1, 0, 0x90, 14, 0, 23, 0, 1, 132, 4, 10, 0, 0, 1, 0, 96, 0, 0,
+            0xfd, 0xe8, 0, 2, 0, 100, 0, 0, 0, 1, 0,
+        ];
+        let (rtc, body) = extract_rtc(&buf).unwrap().unwrap();
+        assert_eq!(
+            rtc.nexthop,
+            BgpAddr::V4(std::net::Ipv4Addr::new(10, 0, 0, 1))
+        );
+        let rt = BgpExtCommunity::rt_asn(100, 1);
+        assert_eq!(
+            rtc.updates,
+            vec![
+                BgpRtc::new(65000, rt.clone()),
+                BgpRtc::decode_from(&[0]).unwrap().0
+            ]
+        );
+        assert!(rtc.updates.iter().all(|r| r.covers(&rt)));
+        assert!(!rtc.updates[0].covers(&BgpExtCommunity::rt_asn(100, 2)));
+        assert!(!rtc.end_of_rib);
+        assert_eq!(body, vec![0, 0, 0, 4, 0x40, 1, 1, 0]);
+        assert!(extract_rtc(&body).unwrap().is_none());
+        let (rtc, body) = extract_rtc(&[0, 0, 0, 6, 0x80, 15, 3, 0, 1, 132])
+            .unwrap()
+            .unwrap();
+        assert!(rtc.end_of_rib);
+        assert_eq!(body, vec![0, 0, 0, 0]);
+        let nh = std::net::IpAddr::V4(std::net::Ipv4Addr::new(10, 0, 0, 2));
+        let (rtc, body) = extract_rtc(&encode_rtc_default(65000, true, true, nh))
+            .unwrap()
+            .unwrap();
+        assert_eq!(rtc.updates.len(), 1);
+        assert_eq!(rtc.updates[0].prefixlen, 0);
+        assert_eq!(
+            body,
+            vec![0, 0, 0, 13, 0x40, 1, 1, 0, 0x40, 2, 6, 2, 1, 0, 0, 0xfd, 0xe8]
+        );
+    }
+}
@@ -1,3 +1,4 @@
+use crate::alerts::Alert;
 use crate::bgpattrs::BgpAttrs;
 use crate::bgprib::*;
 use crate::bgpsvc::BgpSessionId;
@@ -55,6 +56,19 @@ impl serde::Serialize for EventWithdraw {
         map.end()
     }
 }
+struct EventAlert {
+    alert: Arc<Alert>,
+}
+impl serde::Serialize for EventAlert {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let mut map = serializer.serialize_struct("Alert", 1)?;
+        map.serialize_field("alert", self.alert.as_ref())?;
+        map.end()
+    }
+}
 pub async fn on_subscriber_client(
     mut rcv: tokio::sync::broadcast::Receiver<BgpEvent>,
     mut client: Framed<Upgraded, MessageCodec>,
@@ -90,6 +104,11 @@ pub async fn on_subscriber_client(
                                     }
                                 }
                             }
+                            BgpEvent::Alert(alert) => {
+                                if let Ok(vl) = serde_json::to_string(&EventAlert{alert}) {
+                                    let _ = client.send(Message::text(vl)).await;
+                                }
+                            }
                         }
                     }
                 }
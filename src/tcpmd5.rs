@@ -0,0 +1,168 @@
+//! TCP MD5 signature option (RFC 2385) for BGP sessions
+//! TCP Authentication Option (RFC 5925) is not implemented.
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+
+#[cfg(target_os = "linux")]
+mod sys {
+    use std::io;
+    use std::net::IpAddr;
+    use std::os::unix::io::RawFd;
+
+    const TCP_MD5SIG_FLAG_PREFIX: u8 = 1;
+
+    /// struct tcp_md5sig from linux/tcp.h
+    #[repr(C)]
+    struct TcpMd5Sig {
+        addr: libc::sockaddr_storage,
+        flags: u8,
+        prefixlen: u8,
+        keylen: u16,
+        ifindex: i32,
+        key: [u8; libc::TCP_MD5SIG_MAXKEYLEN],
+    }
+
+    pub fn set_md5sig(
+        fd: RawFd,
+        addr: &IpAddr,
+        prefixlen: Option<u8>,
+        key: &[u8],
+    ) -> io::Result<()> {
+        if key.len() > libc::TCP_MD5SIG_MAXKEYLEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "TCP MD5 key is too long",
+            ));
+        }
+        let mut sig: TcpMd5Sig = unsafe { std::mem::zeroed() };
+        match addr {
+            IpAddr::V4(a) => {
+                let sa = &mut sig.addr as *mut libc::sockaddr_storage as *mut libc::sockaddr_in;
+                unsafe {
+                    (*sa).sin_family = libc::AF_INET as libc::sa_family_t;
+                    (*sa).sin_addr.s_addr = u32::from_ne_bytes(a.octets());
+                }
+            }
+            IpAddr::V6(a) => {
+                let sa = &mut sig.addr as *mut libc::sockaddr_storage as *mut libc::sockaddr_in6;
+                unsafe {
+                    (*sa).sin6_family = libc::AF_INET6 as libc::sa_family_t;
+                    (*sa).sin6_addr.s6_addr = a.octets();
+                }
+            }
+        }
+        let opt = match prefixlen {
+            None => libc::TCP_MD5SIG,
+            Some(l) => {
+                sig.flags = TCP_MD5SIG_FLAG_PREFIX;
+                // kernel takes IPv4 prefix length for IPv4-mapped addresses
+                sig.prefixlen = match addr {
+                    IpAddr::V6(a) if a.to_ipv4_mapped().is_some() => l.saturating_sub(96),
+                    _ => l,
+                };
+                libc::TCP_MD5SIG_EXT
+            }
+        };
+        sig.keylen = key.len() as u16;
+        sig.key[0..key.len()].copy_from_slice(key);
+        let ret = unsafe {
+            libc::setsockopt(
+                fd,
+                libc::IPPROTO_TCP,
+                opt,
+                &sig as *const TcpMd5Sig as *const libc::c_void,
+                std::mem::size_of::<TcpMd5Sig>() as libc::socklen_t,
+            )
+        };
+        if ret != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+/// Maps neighbor prefix to key address and prefix length for listen socket family,
+/// IPv4 neighbors on IPv6 listener are keyed as IPv4-mapped addresses
+pub fn listen_key(listen: &SocketAddr, addr: &IpAddr, len: u8) -> Option<(IpAddr, u8)> {
+    match (listen, addr) {
+        (SocketAddr::V4(_), IpAddr::V4(_)) | (SocketAddr::V6(_), IpAddr::V6(_)) => {
+            Some((*addr, len))
+        }
+        (SocketAddr::V6(_), IpAddr::V4(a)) => Some((IpAddr::V6(a.to_ipv6_mapped()), len + 96)),
+        (SocketAddr::V4(_), IpAddr::V6(_)) => None,
+    }
+}
+
+/// Installs MD5 key for neighbor address, or for neighbor prefix when prefixlen is specified
+#[cfg(target_os = "linux")]
+pub fn set_tcp_md5(
+    sock: &impl std::os::unix::io::AsRawFd,
+    addr: &IpAddr,
+    prefixlen: Option<u8>,
+    password: &str,
+) -> io::Result<()> {
+    sys::set_md5sig(sock.as_raw_fd(), addr, prefixlen, password.as_bytes())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn set_tcp_md5<T>(
+    _sock: &T,
+    _addr: &IpAddr,
+    _prefixlen: Option<u8>,
+    _password: &str,
+) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "TCP MD5 signature is supported only on Linux",
+    ))
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+    use std::time::Duration;
+    use tokio::net::TcpSocket;
+
+    async fn connect(port: u16, password: &str) -> io::Result<tokio::net::TcpStream> {
+        let socket = TcpSocket::new_v4()?;
+        set_tcp_md5(&socket, &IpAddr::V4(Ipv4Addr::LOCALHOST), None, password)?;
+        socket.connect((Ipv4Addr::LOCALHOST, port).into()).await
+    }
+
+    #[tokio::test]
+    async fn test_md5_loopback() {
+        let listen = TcpSocket::new_v4().unwrap();
+        set_tcp_md5(&listen, &IpAddr::V4(Ipv4Addr::LOCALHOST), None, "secret").unwrap();
+        listen.bind((Ipv4Addr::LOCALHOST, 0).into()).unwrap();
+        let port = listen.local_addr().unwrap().port();
+        let listener = listen.listen(4).unwrap();
+        let client = connect(port, "secret").await.unwrap();
+        let (server, _) = listener.accept().await.unwrap();
+        assert_eq!(server.peer_addr().unwrap(), client.local_addr().unwrap());
+        // segments with wrong signature are silently dropped
+        let wrong = tokio::time::timeout(Duration::from_millis(500), connect(port, "wrong")).await;
+        assert!(wrong.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_md5_dual_stack() {
+        let any6: SocketAddr = (std::net::Ipv6Addr::UNSPECIFIED, 0).into();
+        let (addr, len) = listen_key(&any6, &IpAddr::V4(Ipv4Addr::new(127, 0, 0, 0)), 8).unwrap();
+        assert_eq!(addr, "::ffff:127.0.0.0".parse::<IpAddr>().unwrap());
+        assert_eq!(len, 104);
+        let any4: SocketAddr = (Ipv4Addr::UNSPECIFIED, 0).into();
+        assert!(listen_key(&any4, &"2001:db8::".parse().unwrap(), 32).is_none());
+        let listen = TcpSocket::new_v6().unwrap();
+        set_tcp_md5(&listen, &addr, Some(len), "secret").unwrap();
+        listen.bind(any6).unwrap();
+        let port = listen.local_addr().unwrap().port();
+        let listener = listen.listen(4).unwrap();
+        let client = connect(port, "secret").await.unwrap();
+        let (server, _) = listener.accept().await.unwrap();
+        assert_eq!(server.local_addr().unwrap().port(), port);
+        drop(client);
+        let wrong = tokio::time::timeout(Duration::from_millis(500), connect(port, "wrong")).await;
+        assert!(wrong.is_err());
+    }
+}
@@ -7,6 +7,14 @@ impl Timestamp {
     pub fn now() -> Self {
         Timestamp(Local::now())
     }
+    /// From unix seconds and microseconds, as found in MRT records
+    pub fn from_unix(secs: i64, micros: u32) -> Self {
+        match Local.timestamp_opt(secs, micros.min(999_999) * 1000) {
+            LocalResult::Single(dt) => Timestamp(dt),
+            LocalResult::Ambiguous(t1, _) => Timestamp(t1),
+            LocalResult::None => Timestamp(Local::now()),
+        }
+    }
     pub fn timestamp_millis(&self) -> i64 {
         self.0.timestamp_millis()
     }